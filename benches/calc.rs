@@ -0,0 +1,84 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use v4v::pc20::calc::{
+    compute_sat_recipients, fee_recipients_to_splits, use_remote_splits, GenericRecipient,
+};
+
+const SIZES: [usize; 4] = [10, 100, 1000, 10000];
+
+/// Deterministic, uneven splits, so that rounding leaves a balance to distribute.
+fn splits(num_recipients: usize) -> Vec<u64> {
+    (0..num_recipients as u64)
+        .map(|i| (i * 7919) % 97 + 1)
+        .collect()
+}
+
+fn bench_compute_sat_recipients(c: &mut Criterion) {
+    let mut group = c.benchmark_group("compute_sat_recipients");
+    for num_recipients in SIZES {
+        let splits = splits(num_recipients);
+        // Enough sats for every recipient, leaving a positive balance after rounding down.
+        group.bench_with_input(
+            BenchmarkId::new("abundant", num_recipients),
+            &splits,
+            |b, splits| b.iter(|| compute_sat_recipients(black_box(splits), black_box(1_000_003))),
+        );
+        // Fewer sats than recipients, so the one-sat minimums must be taken back.
+        group.bench_with_input(
+            BenchmarkId::new("scarce", num_recipients),
+            &splits,
+            |b, splits| {
+                b.iter(|| {
+                    compute_sat_recipients(black_box(splits), black_box(num_recipients as u64 / 2))
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+fn bench_fee_recipients_to_splits(c: &mut Criterion) {
+    let mut group = c.benchmark_group("fee_recipients_to_splits");
+    for num_recipients in SIZES {
+        let recipients: Vec<GenericRecipient> = splits(num_recipients)
+            .into_iter()
+            .enumerate()
+            .map(|(i, split)| {
+                if i % 10 == 0 {
+                    GenericRecipient::PercentageBased { percentage: 0 }
+                } else {
+                    GenericRecipient::ShareBased { num_shares: split }
+                }
+            })
+            .collect();
+        group.bench_with_input(
+            BenchmarkId::from_parameter(num_recipients),
+            &recipients,
+            |b, recipients| b.iter(|| fee_recipients_to_splits(black_box(recipients))),
+        );
+    }
+    group.finish();
+}
+
+fn bench_use_remote_splits(c: &mut Criterion) {
+    let mut group = c.benchmark_group("use_remote_splits");
+    for num_recipients in SIZES {
+        let local_splits = splits(num_recipients / 2);
+        let remote_splits = splits(num_recipients - num_recipients / 2);
+        group.bench_with_input(
+            BenchmarkId::from_parameter(num_recipients),
+            &(local_splits, remote_splits),
+            |b, (local_splits, remote_splits)| {
+                b.iter(|| use_remote_splits(black_box(local_splits), black_box(remote_splits), 90))
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_compute_sat_recipients,
+    bench_fee_recipients_to_splits,
+    bench_use_remote_splits
+);
+criterion_main!(benches);
@@ -1,5 +1,8 @@
-pub use crate::alby::helpers::RequestError;
-use crate::alby::helpers::{make_request, ErrorResponse, RequestArgs};
+use crate::alby::helpers::{make_request, RequestArgs};
+pub use crate::alby::helpers::{
+    ErrorResponse, RateLimiter, RequestError, RetryPolicy, Timeouts, DEFAULT_BASE_URL,
+    DEFAULT_RETRYABLE_STATUSES,
+};
 use chrono::{DateTime, Utc};
 use std::collections::HashMap;
 
@@ -27,6 +30,12 @@ pub mod account {
         pub user_agent: &'a str,
         /// Bearer token for authentication.
         pub token: &'a str,
+        /// Base URL of the Alby API (e.g., of a mock server), [DEFAULT_BASE_URL] if `None`.
+        pub base_url: Option<&'a str>,
+        /// Timeouts of the request.
+        pub timeouts: Timeouts,
+        /// Policy for retrying failed requests. Not retried if `None`.
+        pub retry: Option<RetryPolicy>,
     }
 
     /// Get Alby account balance.
@@ -34,9 +43,13 @@ pub mod account {
         let request_args = RequestArgs {
             user_agent: args.user_agent,
             method: reqwest::Method::GET,
-            url: "https://api.getalby.com/balance",
+            base_url: args.base_url,
+            path: "/balance",
             token: args.token,
             body: None,
+            rate_limiter: None,
+            timeouts: args.timeouts,
+            retry: args.retry,
         };
 
         make_request(request_args).await
@@ -56,6 +69,8 @@ pub mod invoices {
         pub user_agent: &'a str,
         /// Bearer token for authentication.
         pub token: &'a str,
+        /// Base URL of the Alby API (e.g., of a mock server), [DEFAULT_BASE_URL] if `None`.
+        pub base_url: Option<&'a str>,
         /// The amount of sats.
         pub num_sats: u64,
         /// Arbitary metadata.
@@ -64,6 +79,10 @@ pub mod invoices {
         pub description: Option<String>,
         /// Name of payer (not included in the BOLT11 invoice)
         pub payer_name: Option<String>,
+        /// Timeouts of the request.
+        pub timeouts: Timeouts,
+        /// Policy for retrying failed requests. Not retried if `None`.
+        pub retry: Option<RetryPolicy>,
     }
 
     /// Request body for [create_invoice].
@@ -108,13 +127,230 @@ pub mod invoices {
         let request_args = RequestArgs {
             user_agent: args.user_agent,
             method: reqwest::Method::POST,
-            url: "https://api.getalby.com/invoices",
+            base_url: args.base_url,
+            path: "/invoices",
             token: args.token,
             body: Some(&body),
+            rate_limiter: None,
+            timeouts: args.timeouts,
+            retry: args.retry,
         };
 
         make_request(request_args).await
     }
+
+    /// Arguments for [list_invoices].
+    pub struct ListInvoicesArgs<'a> {
+        /// User agent string.
+        pub user_agent: &'a str,
+        /// Bearer token for authentication.
+        pub token: &'a str,
+        /// Base URL of the Alby API (e.g., of a mock server), [DEFAULT_BASE_URL] if `None`.
+        pub base_url: Option<&'a str>,
+        /// Page number, starting at 1. Alby's default (the first page) if `None`.
+        pub page: Option<u32>,
+        /// Number of invoices per page. Alby's default if `None`.
+        pub items: Option<u32>,
+        /// Only list invoices created after this time.
+        pub created_after: Option<DateTime<Utc>>,
+        /// Only list invoices created before this time.
+        pub created_before: Option<DateTime<Utc>>,
+        /// Timeouts of the request.
+        pub timeouts: Timeouts,
+        /// Policy for retrying failed requests. Not retried if `None`.
+        pub retry: Option<RetryPolicy>,
+    }
+
+    /// List invoices using the Alby API, e.g., for reconciling payments received via webhooks.
+    ///
+    /// The invoices are paginated: request increasing [page](ListInvoicesArgs::page) numbers until
+    /// an empty list is returned.
+    pub async fn list_invoices(
+        args: ListInvoicesArgs<'_>,
+    ) -> Result<Vec<crate::alby::webhooks::AlbyInvoice>, RequestError> {
+        let mut query = url::form_urlencoded::Serializer::new(String::new());
+        if let Some(page) = args.page {
+            query.append_pair("page", &page.to_string());
+        }
+        if let Some(items) = args.items {
+            query.append_pair("items", &items.to_string());
+        }
+        if let Some(created_after) = args.created_after {
+            query.append_pair("q[created_at_gt]", &created_after.timestamp().to_string());
+        }
+        if let Some(created_before) = args.created_before {
+            query.append_pair("q[created_at_lt]", &created_before.timestamp().to_string());
+        }
+        let query = query.finish();
+
+        let path = match query.as_str() {
+            "" => "/invoices".to_string(),
+            query => format!("/invoices?{}", query),
+        };
+
+        let request_args = RequestArgs {
+            user_agent: args.user_agent,
+            method: reqwest::Method::GET,
+            base_url: args.base_url,
+            path: &path,
+            token: args.token,
+            body: None,
+            rate_limiter: None,
+            timeouts: args.timeouts,
+            retry: args.retry,
+        };
+
+        make_request(request_args).await
+    }
+
+    /// Arguments for [get_invoice].
+    pub struct GetInvoiceArgs<'a> {
+        /// User agent string.
+        pub user_agent: &'a str,
+        /// Bearer token for authentication.
+        pub token: &'a str,
+        /// Base URL of the Alby API (e.g., of a mock server), [DEFAULT_BASE_URL] if `None`.
+        pub base_url: Option<&'a str>,
+        /// The payment hash of the invoice.
+        pub payment_hash: &'a str,
+        /// Timeouts of the request.
+        pub timeouts: Timeouts,
+        /// Policy for retrying failed requests. Not retried if `None`.
+        pub retry: Option<RetryPolicy>,
+    }
+
+    /// Get an invoice by its payment hash using the Alby API, e.g., to poll its state if the
+    /// webhook was missed.
+    ///
+    /// Fails with [RequestError::NotFound] if there is no such invoice.
+    pub async fn get_invoice(
+        args: GetInvoiceArgs<'_>,
+    ) -> Result<crate::alby::webhooks::AlbyInvoice, RequestError> {
+        let path = format!(
+            "/invoices/{}",
+            url::form_urlencoded::byte_serialize(args.payment_hash.as_bytes()).collect::<String>()
+        );
+
+        let request_args = RequestArgs {
+            user_agent: args.user_agent,
+            method: reqwest::Method::GET,
+            base_url: args.base_url,
+            path: &path,
+            token: args.token,
+            body: None,
+            rate_limiter: None,
+            timeouts: args.timeouts,
+            retry: args.retry,
+        };
+
+        make_request(request_args).await
+    }
+
+    /// Arguments for [decode_invoice].
+    pub struct DecodeInvoiceArgs<'a> {
+        /// User agent string.
+        pub user_agent: &'a str,
+        /// Bearer token for authentication.
+        pub token: &'a str,
+        /// Base URL of the Alby API (e.g., of a mock server), [DEFAULT_BASE_URL] if `None`.
+        pub base_url: Option<&'a str>,
+        /// BOLT11 invoice to decode.
+        pub invoice: &'a str,
+        /// Timeouts of the request.
+        pub timeouts: Timeouts,
+        /// Policy for retrying failed requests. Not retried if `None`.
+        pub retry: Option<RetryPolicy>,
+    }
+
+    /// Response body of the decode endpoint.
+    #[derive(Debug, serde::Deserialize)]
+    struct DecodeInvoiceResponse {
+        /// The amount of sats, 0 if not specified.
+        #[serde(default)]
+        amount: u64,
+        /// Description.
+        #[serde(default)]
+        description: Option<String>,
+        /// Hex-encoded sha256 hash of the description, if the invoice commits to one.
+        #[serde(default)]
+        description_hash: Option<String>,
+        /// The payment hash of the invoice.
+        payment_hash: String,
+        /// Creation time (Unix timestamp).
+        created_at: i64,
+        /// Seconds after creation until the invoice expires.
+        expiry: i64,
+    }
+
+    /// Decoded BOLT11 invoice.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct DecodedInvoice {
+        /// The amount of sats, `None` for an invoice without an amount.
+        pub num_sats: Option<u64>,
+        /// Description.
+        pub description: Option<String>,
+        /// Hex-encoded sha256 hash of the description, if the invoice commits to one (e.g., an
+        /// invoice from an LNURL-pay service).
+        pub description_hash: Option<String>,
+        /// The payment hash of the invoice.
+        pub payment_hash: String,
+        /// The expiration time of the invoice.
+        pub expires_at: DateTime<Utc>,
+    }
+
+    /// Decode a BOLT11 invoice using the Alby API, without paying it.
+    ///
+    /// Fails with [RequestError::InvalidArgs] if the invoice contains characters outside the
+    /// bech32 charset.
+    pub async fn decode_invoice(
+        args: DecodeInvoiceArgs<'_>,
+    ) -> Result<DecodedInvoice, RequestError> {
+        // The invoice may come from a third party (e.g., an LNURL service) and goes into the path
+        // of an authenticated request, so only the bech32 charset is allowed.
+        if args.invoice.is_empty() || !args.invoice.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return Err(RequestError::InvalidArgs(format!(
+                "invalid BOLT11 invoice: {}",
+                args.invoice
+            )));
+        }
+        let path = format!("/decode/bolt11/{}", args.invoice);
+
+        let request_args = RequestArgs {
+            user_agent: args.user_agent,
+            method: reqwest::Method::GET,
+            base_url: args.base_url,
+            path: &path,
+            token: args.token,
+            body: None,
+            rate_limiter: None,
+            timeouts: args.timeouts,
+            retry: args.retry,
+        };
+
+        let response: DecodeInvoiceResponse = make_request(request_args).await?;
+
+        let expires_at = DateTime::from_timestamp(response.created_at, 0)
+            .and_then(|created_at| {
+                created_at.checked_add_signed(chrono::Duration::seconds(response.expiry))
+            })
+            .ok_or_else(|| {
+                RequestError::Unexpected(format!(
+                    "Invalid invoice expiry: created at {}, expiry {}",
+                    response.created_at, response.expiry
+                ))
+            })?;
+
+        Ok(DecodedInvoice {
+            num_sats: match response.amount {
+                0 => None,
+                amount => Some(amount),
+            },
+            description: response.description.filter(|d| !d.is_empty()),
+            description_hash: response.description_hash.filter(|d| !d.is_empty()),
+            payment_hash: response.payment_hash,
+            expires_at,
+        })
+    }
 }
 
 /// Alby API functions related to payments.
@@ -130,12 +366,22 @@ pub mod payments {
         pub user_agent: &'a str,
         /// Bearer token for authentication.
         pub token: &'a str,
+        /// Base URL of the Alby API (e.g., of a mock server), [DEFAULT_BASE_URL] if `None`.
+        pub base_url: Option<&'a str>,
         /// The amount of sats.
         pub num_sats: u64,
         /// Destination node pubkey.
         pub dest_pubkey: &'a str,
         /// Custom records.
         pub custom_records: HashMap<String, String>,
+        /// Optional client-side rate limiter. Off if `None`.
+        pub rate_limiter: Option<&'a RateLimiter>,
+        /// Timeouts of the request.
+        pub timeouts: Timeouts,
+        /// Policy for retrying failed requests. Not retried if `None`.
+        ///
+        /// **Retrying a keysend can pay twice**; see [RetryPolicy#paying-twice].
+        pub retry: Option<RetryPolicy>,
     }
 
     /// Request body for [keysend].
@@ -175,6 +421,17 @@ pub mod payments {
         pub payment_preimage: String,
     }
 
+    impl KeysendResponse {
+        /// Parses [payment_preimage](KeysendResponse::payment_preimage) for verifying it against
+        /// [payment_hash](KeysendResponse::payment_hash).
+        pub fn preimage(
+            &self,
+        ) -> Result<crate::alby::types::Preimage, crate::alby::types::InvalidPreimageError>
+        {
+            crate::alby::types::Preimage::from_hex(&self.payment_preimage)
+        }
+    }
+
     /// Send a keysend payment using the Alby API.
     pub async fn keysend(args: KeysendArgs<'_>) -> Result<KeysendResponse, RequestError> {
         let request_body = KeysendRequest {
@@ -189,9 +446,106 @@ pub mod payments {
         let request_args = RequestArgs {
             user_agent: args.user_agent,
             method: reqwest::Method::POST,
-            url: "https://api.getalby.com/payments/keysend",
+            base_url: args.base_url,
+            path: "/payments/keysend",
+            token: args.token,
+            body: Some(&body),
+            rate_limiter: args.rate_limiter,
+            timeouts: args.timeouts,
+            retry: args.retry,
+        };
+
+        make_request(request_args).await
+    }
+
+    /// Arguments for [pay_invoice].
+    pub struct PayInvoiceArgs<'a> {
+        /// User agent string.
+        pub user_agent: &'a str,
+        /// Bearer token for authentication.
+        pub token: &'a str,
+        /// Base URL of the Alby API (e.g., of a mock server), [DEFAULT_BASE_URL] if `None`.
+        pub base_url: Option<&'a str>,
+        /// BOLT11 invoice to pay.
+        pub invoice: &'a str,
+        /// The amount of sats. Only needed for invoices without an amount.
+        pub num_sats: Option<u64>,
+        /// Optional client-side rate limiter. Off if `None`.
+        pub rate_limiter: Option<&'a RateLimiter>,
+        /// Timeouts of the request.
+        pub timeouts: Timeouts,
+        /// Policy for retrying failed requests. Not retried if `None`.
+        ///
+        /// Alby won't pay the same invoice twice, but a retried request may still fail with an
+        /// error instead of returning the original payment.
+        pub retry: Option<RetryPolicy>,
+    }
+
+    /// Request body for [pay_invoice].
+    #[derive(Debug, serde::Serialize)]
+    struct PayInvoiceRequest {
+        /// BOLT11 invoice.
+        pub invoice: String,
+        /// The amount of sats.
+        #[serde(rename = "amount", skip_serializing_if = "Option::is_none")]
+        pub num_sats: Option<u64>,
+    }
+
+    /// Response for a successfully paid invoice.
+    #[derive(Debug, serde::Deserialize)]
+    pub struct PayInvoiceResponse {
+        /// The amount of sats.
+        #[serde(rename = "amount")]
+        pub num_sats: u64,
+        /// Description.
+        #[serde(default)]
+        pub description: Option<String>,
+        /// Destination node pubkey.
+        #[serde(rename = "destination", default)]
+        pub dest_pubkey: Option<String>,
+        /// Fee in sats.
+        #[serde(rename = "fee")]
+        pub fee_in_sats: u64,
+        /// Payment hash.
+        pub payment_hash: String,
+        /// Payment preimage.
+        pub payment_preimage: String,
+        /// The paid BOLT11 invoice.
+        #[serde(default)]
+        pub payment_request: Option<String>,
+    }
+
+    impl PayInvoiceResponse {
+        /// Parses [payment_preimage](PayInvoiceResponse::payment_preimage) for verifying it against
+        /// [payment_hash](PayInvoiceResponse::payment_hash).
+        pub fn preimage(
+            &self,
+        ) -> Result<crate::alby::types::Preimage, crate::alby::types::InvalidPreimageError>
+        {
+            crate::alby::types::Preimage::from_hex(&self.payment_preimage)
+        }
+    }
+
+    /// Pay a BOLT11 invoice using the Alby API.
+    pub async fn pay_invoice(args: PayInvoiceArgs<'_>) -> Result<PayInvoiceResponse, RequestError> {
+        let request_body = PayInvoiceRequest {
+            invoice: args.invoice.to_string(),
+            num_sats: args.num_sats,
+        };
+
+        let body = serde_json::to_string(&request_body)
+            .map_err(|e| RequestError::Unexpected(e.to_string()))?;
+
+        let request_args = RequestArgs {
+            user_agent: args.user_agent,
+            method: reqwest::Method::POST,
+            base_url: args.base_url,
+            path: "/payments/bolt11",
             token: args.token,
             body: Some(&body),
+            rate_limiter: args.rate_limiter,
+            timeouts: args.timeouts,
+            retry: args.retry,
         };
 
         make_request(request_args).await
@@ -205,12 +559,48 @@ pub mod payments {
     }
 
     /// Keysend item response for [multi_keysend].
+    ///
+    /// Alby sets either the keysend response or the error; the other one is missing or empty.
     #[derive(Debug, serde::Deserialize)]
     pub struct MultiKeysendItemResponse {
-        /// Error.
-        pub error: ErrorResponse,
-        /// Keysend response.
-        pub keysend: KeysendResponse,
+        /// Error, if the keysend failed.
+        #[serde(default, deserialize_with = "deserialize_empty_as_none")]
+        pub error: Option<ErrorResponse>,
+        /// Keysend response, if the keysend succeeded.
+        #[serde(default, deserialize_with = "deserialize_empty_as_none")]
+        pub keysend: Option<KeysendResponse>,
+    }
+
+    impl MultiKeysendItemResponse {
+        /// The keysend response if the keysend succeeded, the error otherwise.
+        pub fn into_result(self) -> Result<KeysendResponse, ErrorResponse> {
+            match (self.keysend, self.error) {
+                (Some(keysend), None) => Ok(keysend),
+                (_, Some(error)) => Err(error),
+                (None, None) => Err(ErrorResponse {
+                    code: 0,
+                    error: true,
+                    message: "Neither keysend nor error in the response".to_string(),
+                }),
+            }
+        }
+    }
+
+    /// Deserializes `null` and `{}` as `None`.
+    fn deserialize_empty_as_none<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+        T: serde::de::DeserializeOwned,
+    {
+        use serde::Deserialize;
+
+        match serde_json::Value::deserialize(deserializer)? {
+            serde_json::Value::Null => Ok(None),
+            serde_json::Value::Object(object) if object.is_empty() => Ok(None),
+            value => serde_json::from_value(value)
+                .map(Some)
+                .map_err(serde::de::Error::custom),
+        }
     }
 
     /// Response for a successful [multi_keysend].
@@ -221,6 +611,7 @@ pub mod payments {
     }
 
     /// [multi_keysend] keysend item.
+    #[derive(Debug, Clone)]
     pub struct MultiKeysendItemArgs<'a> {
         /// The amount of sats.
         pub num_sats: u64,
@@ -236,8 +627,18 @@ pub mod payments {
         pub user_agent: &'a str,
         /// Bearer token for authentication.
         pub token: &'a str,
+        /// Base URL of the Alby API (e.g., of a mock server), [DEFAULT_BASE_URL] if `None`.
+        pub base_url: Option<&'a str>,
         /// Keysend items.
         pub keysends: Vec<MultiKeysendItemArgs<'a>>,
+        /// Optional client-side rate limiter. Off if `None`.
+        pub rate_limiter: Option<&'a RateLimiter>,
+        /// Timeouts of the request.
+        pub timeouts: Timeouts,
+        /// Policy for retrying failed requests. Not retried if `None`.
+        ///
+        /// **Retrying a keysend can pay twice**; see [RetryPolicy#paying-twice].
+        pub retry: Option<RetryPolicy>,
     }
 
     /// Send multiple keysend payments using the Alby API.
@@ -264,9 +665,13 @@ pub mod payments {
         let request_args = RequestArgs {
             user_agent: args.user_agent,
             method: reqwest::Method::POST,
-            url: "https://api.getalby.com/payments/keysend/multi",
+            base_url: args.base_url,
+            path: "/payments/keysend/multi",
             token: args.token,
             body: Some(&body),
+            rate_limiter: args.rate_limiter,
+            timeouts: args.timeouts,
+            retry: args.retry,
         };
 
         make_request(request_args).await
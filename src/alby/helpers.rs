@@ -17,6 +17,8 @@ pub struct ErrorResponse {
 pub enum RequestError {
     /// Unexpected error.
     Unexpected(String),
+    /// Arguments were rejected before sending the request.
+    InvalidArgs(String),
     /// Failed to create auth header.
     AuthHeaderCreation(reqwest::header::InvalidHeaderValue),
     /// Failed to create reqwest client.
@@ -31,6 +33,18 @@ pub enum RequestError {
     BadRequest(ErrorResponse),
     /// Internal server error (500).
     InternalServerError(ErrorResponse),
+    /// Resource not found (404).
+    NotFound {
+        /// Response body.
+        body: String,
+    },
+    /// Rate limited by the API (429).
+    RateLimited {
+        /// How long to wait before retrying, from the `Retry-After` header (if present and valid).
+        retry_after: Option<std::time::Duration>,
+        /// Response body.
+        body: String,
+    },
     /// Unexpected status code.
     UnexpectedStatus {
         /// Status code.
@@ -38,12 +52,18 @@ pub enum RequestError {
         /// Response body.
         body: String,
     },
+    /// An LNURL service returned an error or an unusable response.
+    Lnurl(String),
+    /// A Nostr Wallet Connect request failed, e.g., the relay was unreachable or the wallet
+    /// service returned an error.
+    Nwc(String),
 }
 
 impl fmt::Display for RequestError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             RequestError::Unexpected(e) => write!(f, "Unexpected error: {}", e),
+            RequestError::InvalidArgs(e) => write!(f, "Invalid arguments: {}", e),
             RequestError::AuthHeaderCreation(e) => write!(f, "Failed to create auth header: {}", e),
             RequestError::ClientCreation(e) => write!(f, "Failed to create reqwest client: {}", e),
             RequestError::RequestSend(e) => write!(f, "Failed to send request: {}", e),
@@ -59,9 +79,25 @@ impl fmt::Display for RequestError {
                 "Internal server error (500): {} (code: {})",
                 e.message, e.code
             ),
+            RequestError::NotFound { body } => write!(f, "Not found (404). Body: {}", body),
+            RequestError::RateLimited {
+                retry_after: Some(retry_after),
+                body,
+            } => write!(
+                f,
+                "Rate limited (429), retry after {}s. Body: {}",
+                retry_after.as_secs(),
+                body
+            ),
+            RequestError::RateLimited {
+                retry_after: None,
+                body,
+            } => write!(f, "Rate limited (429). Body: {}", body),
             RequestError::UnexpectedStatus { status, body } => {
                 write!(f, "Unexpected status code: {}. Body: {}", status, body)
             }
+            RequestError::Lnurl(e) => write!(f, "LNURL error: {}", e),
+            RequestError::Nwc(e) => write!(f, "Nostr Wallet Connect error: {}", e),
         }
     }
 }
@@ -69,7 +105,7 @@ impl fmt::Display for RequestError {
 impl std::error::Error for RequestError {
     fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
         match self {
-            RequestError::Unexpected(_) => None,
+            RequestError::Unexpected(_) | RequestError::InvalidArgs(_) => None,
             RequestError::AuthHeaderCreation(e) => Some(e),
             RequestError::ClientCreation(e) => Some(e),
             RequestError::RequestSend(e) => Some(e),
@@ -77,7 +113,33 @@ impl std::error::Error for RequestError {
             RequestError::ResponseParse(e, _body) => Some(e),
             RequestError::BadRequest(_)
             | RequestError::InternalServerError(_)
-            | RequestError::UnexpectedStatus { .. } => None,
+            | RequestError::NotFound { .. }
+            | RequestError::RateLimited { .. }
+            | RequestError::UnexpectedStatus { .. }
+            | RequestError::Lnurl(_)
+            | RequestError::Nwc(_) => None,
+        }
+    }
+}
+
+impl RequestError {
+    /// Whether the error may be transient according to the [default](RetryPolicy::default)
+    /// [RetryPolicy]; see [RetryPolicy::is_retryable].
+    pub fn is_retryable(&self) -> bool {
+        RetryPolicy::default().is_retryable(self)
+    }
+
+    /// Status code of the response, if the error is due to an unsuccessful one.
+    pub fn status(&self) -> Option<reqwest::StatusCode> {
+        match self {
+            RequestError::BadRequest(_) => Some(reqwest::StatusCode::BAD_REQUEST),
+            RequestError::InternalServerError(_) => {
+                Some(reqwest::StatusCode::INTERNAL_SERVER_ERROR)
+            }
+            RequestError::NotFound { .. } => Some(reqwest::StatusCode::NOT_FOUND),
+            RequestError::RateLimited { .. } => Some(reqwest::StatusCode::TOO_MANY_REQUESTS),
+            RequestError::UnexpectedStatus { status, .. } => Some(*status),
+            _ => None,
         }
     }
 }
@@ -100,18 +162,257 @@ impl From<reqwest::Error> for RequestError {
     }
 }
 
+/// Client-side token-bucket rate limiter for Alby API requests.
+///
+/// Pass the same limiter to every call that should share the limit, e.g., with
+/// [MakePaymentArgs::rate_limiter](crate::pc20::payments::MakePaymentArgs::rate_limiter), so that
+/// requests are paced before Alby starts rejecting them. Bursts of up to one second's worth of
+/// requests are allowed.
+///
+/// ## Example
+/// ```rust
+/// # tokio::runtime::Builder::new_current_thread().enable_time().build().unwrap().block_on(async {
+/// let rate_limiter = v4v::alby::api::RateLimiter::new(2.0);
+/// let start = std::time::Instant::now();
+/// for _ in 0..3 {
+///     rate_limiter.acquire().await;
+/// }
+/// // The third request had to wait for a token.
+/// assert!(start.elapsed() >= std::time::Duration::from_millis(400));
+/// # });
+/// ```
+#[derive(Debug)]
+pub struct RateLimiter {
+    max_requests_per_second: f64,
+    capacity: f64,
+    state: std::sync::Mutex<RateLimiterState>,
+}
+
+#[derive(Debug)]
+struct RateLimiterState {
+    tokens: f64,
+    refilled_at: std::time::Instant,
+}
+
+impl RateLimiter {
+    /// Creates a rate limiter allowing `max_requests_per_second` requests per second on average.
+    ///
+    /// Panics if `max_requests_per_second` is not positive and finite.
+    pub fn new(max_requests_per_second: f64) -> Self {
+        assert!(
+            max_requests_per_second.is_finite() && max_requests_per_second > 0.0,
+            "max_requests_per_second must be positive and finite"
+        );
+        let capacity = max_requests_per_second.ceil();
+        Self {
+            max_requests_per_second,
+            capacity,
+            state: std::sync::Mutex::new(RateLimiterState {
+                tokens: capacity,
+                refilled_at: std::time::Instant::now(),
+            }),
+        }
+    }
+
+    /// Waits until a request may be sent and consumes a token.
+    pub async fn acquire(&self) {
+        loop {
+            let wait = {
+                let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+                let now = std::time::Instant::now();
+                let elapsed = now.duration_since(state.refilled_at).as_secs_f64();
+                state.tokens =
+                    (state.tokens + elapsed * self.max_requests_per_second).min(self.capacity);
+                state.refilled_at = now;
+
+                if state.tokens >= 1.0 {
+                    state.tokens -= 1.0;
+                    return;
+                }
+                (1.0 - state.tokens) / self.max_requests_per_second
+            };
+
+            tokio::time::sleep(std::time::Duration::from_secs_f64(wait)).await;
+        }
+    }
+}
+
+/// Timeouts of Alby API requests.
+///
+/// The connect timeout only bounds establishing the connection, so an unreachable Alby fails
+/// fast. The overall timeout bounds the whole request, from connecting until the response body
+/// has been read, so it should allow for slow responses, e.g., to a large multi-keysend. Both
+/// default to 10 seconds.
+///
+/// ## Example
+/// ```rust
+/// let timeouts = v4v::alby::api::Timeouts {
+///     connect_timeout: std::time::Duration::from_secs(2),
+///     timeout: std::time::Duration::from_secs(60),
+/// };
+/// assert_eq!(v4v::alby::api::Timeouts::default().timeout, std::time::Duration::from_secs(10));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timeouts {
+    /// Timeout for connecting to Alby.
+    pub connect_timeout: std::time::Duration,
+    /// Timeout for the whole request, including connecting.
+    pub timeout: std::time::Duration,
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Self {
+            connect_timeout: std::time::Duration::from_secs(10),
+            timeout: std::time::Duration::from_secs(10),
+        }
+    }
+}
+
+/// Base URL of the Alby API.
+pub const DEFAULT_BASE_URL: &str = "https://api.getalby.com";
+
+/// Joins a base URL and an API path, tolerating slashes on either side.
+fn join_url(base_url: &str, path: &str) -> String {
+    format!(
+        "{}/{}",
+        base_url.trim_end_matches('/'),
+        path.trim_start_matches('/')
+    )
+}
+
+/// Status codes retried by the [default](RetryPolicy::default) [RetryPolicy]: 429, 500, 502, 503,
+/// and 504. Other 5xx codes, e.g., 501, are not transient.
+pub const DEFAULT_RETRYABLE_STATUSES: &[reqwest::StatusCode] = &[
+    reqwest::StatusCode::TOO_MANY_REQUESTS,
+    reqwest::StatusCode::INTERNAL_SERVER_ERROR,
+    reqwest::StatusCode::BAD_GATEWAY,
+    reqwest::StatusCode::SERVICE_UNAVAILABLE,
+    reqwest::StatusCode::GATEWAY_TIMEOUT,
+];
+
+/// Policy for retrying [retryable](RetryPolicy::is_retryable) request errors with exponential
+/// backoff.
+///
+/// The delay before the `n`-th retry is `base_delay * 2^(n - 1)`, capped at `max_delay`, with
+/// random jitter of up to half the delay subtracted. For [RequestError::RateLimited] with a
+/// `Retry-After` header, that delay (capped at `max_delay`) is used instead.
+///
+/// ## Paying twice
+///
+/// Retrying a payment can pay the recipients twice: a request that failed on the client's side
+/// (e.g., timed out) may still have been processed by Alby. Only retry keysends if double
+/// payments are acceptable or are deduplicated downstream, e.g., by the
+/// [boost_id](crate::pc20::payments::PaymentInfo::boost_id) in the bLIP-10 record.
+///
+/// ## Example
+/// ```rust
+/// let retry = v4v::alby::api::RetryPolicy {
+///     max_attempts: 5,
+///     ..Default::default()
+/// };
+/// assert_eq!(retry.base_delay, std::time::Duration::from_millis(500));
+///
+/// // Don't retry when rate limited.
+/// let retry = v4v::alby::api::RetryPolicy {
+///     retryable_statuses: &[reqwest::StatusCode::SERVICE_UNAVAILABLE],
+///     ..Default::default()
+/// };
+/// let rate_limited = v4v::alby::api::RequestError::RateLimited {
+///     retry_after: None,
+///     body: String::new(),
+/// };
+/// assert!(!retry.is_retryable(&rate_limited));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Maximum number of attempts, including the first one.
+    pub max_attempts: u32,
+    /// Delay before the first retry.
+    pub base_delay: std::time::Duration,
+    /// Maximum delay between attempts.
+    pub max_delay: std::time::Duration,
+    /// Status codes of responses that are retried, [DEFAULT_RETRYABLE_STATUSES] by default.
+    pub retryable_statuses: &'static [reqwest::StatusCode],
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            base_delay: std::time::Duration::from_millis(500),
+            max_delay: std::time::Duration::from_secs(10),
+            retryable_statuses: DEFAULT_RETRYABLE_STATUSES,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Whether `error` may be transient, so that the request is worth retrying: failing to send
+    /// the request or read the response, or a response with one of the
+    /// [retryable_statuses](RetryPolicy::retryable_statuses).
+    ///
+    /// A retryable error doesn't mean that the request wasn't processed, e.g., a request may time
+    /// out after Alby has sent a keysend. Retrying keysends is therefore only safe if the
+    /// recipients can deduplicate them by an idempotency key, such as
+    /// [boost_id](crate::pc20::payments::PaymentInfo::boost_id).
+    pub fn is_retryable(&self, error: &RequestError) -> bool {
+        match error {
+            RequestError::RequestSend(_) | RequestError::ResponseBodyRead(_) => true,
+            _ => error
+                .status()
+                .is_some_and(|status| self.retryable_statuses.contains(&status)),
+        }
+    }
+
+    /// Delay before retrying after the `attempt`-th attempt (starting at 1) failed with `error`.
+    fn delay(&self, attempt: u32, error: &RequestError) -> std::time::Duration {
+        if let RequestError::RateLimited {
+            retry_after: Some(retry_after),
+            ..
+        } = error
+        {
+            return (*retry_after).min(self.max_delay);
+        }
+
+        let delay = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)))
+            .min(self.max_delay);
+        delay.saturating_sub(delay.mul_f64(random_fraction() / 2.0))
+    }
+}
+
+/// Random number in `[0, 1)` for jitter; doesn't need to be of high quality.
+fn random_fraction() -> f64 {
+    use std::hash::{BuildHasher, Hasher};
+
+    let random = std::collections::hash_map::RandomState::new()
+        .build_hasher()
+        .finish();
+    (random >> 11) as f64 / (1u64 << 53) as f64
+}
+
 /// Arguments for making a request.
 pub struct RequestArgs<'a> {
     /// User agent string.
     pub user_agent: &'a str,
     /// HTTP method.
     pub method: reqwest::Method,
-    /// URL to send the request to.
-    pub url: &'a str,
+    /// Base URL of the API, [DEFAULT_BASE_URL] if `None`.
+    pub base_url: Option<&'a str>,
+    /// Path of the endpoint, e.g., `/balance`.
+    pub path: &'a str,
     /// Bearer token for authentication.
     pub token: &'a str,
     /// Optional request body.
     pub body: Option<&'a str>,
+    /// Optional rate limiter to wait on before sending the request.
+    pub rate_limiter: Option<&'a RateLimiter>,
+    /// Timeouts of the request.
+    pub timeouts: Timeouts,
+    /// Policy for retrying failed requests. Not retried if `None`.
+    pub retry: Option<RetryPolicy>,
 }
 
 pub async fn make_request<T: DeserializeOwned>(args: RequestArgs<'_>) -> Result<T, RequestError> {
@@ -130,21 +431,67 @@ pub async fn make_request<T: DeserializeOwned>(args: RequestArgs<'_>) -> Result<
     let client = reqwest::Client::builder()
         .default_headers(headers)
         .user_agent(args.user_agent)
-        .timeout(std::time::Duration::from_secs(10))
+        .connect_timeout(args.timeouts.connect_timeout)
+        .timeout(args.timeouts.timeout)
         .build()?;
 
+    let url = join_url(args.base_url.unwrap_or(DEFAULT_BASE_URL), args.path);
+    let max_attempts = args.retry.map_or(1, |retry| retry.max_attempts.max(1));
+
+    let mut attempt = 1;
+    loop {
+        if let Some(rate_limiter) = args.rate_limiter {
+            rate_limiter.acquire().await;
+        }
+
+        let result = send_request(&client, args.method.clone(), &url, args.body).await;
+        match (result, args.retry) {
+            (Err(e), Some(retry)) if retry.is_retryable(&e) && attempt < max_attempts => {
+                let delay = retry.delay(attempt, &e);
+                log::debug!(
+                    "Alby API request failed (attempt {}/{}), retrying in {:?}: {}",
+                    attempt,
+                    max_attempts,
+                    delay,
+                    e
+                );
+                tokio::time::sleep(delay).await;
+                attempt += 1;
+            }
+            (result, _) => return result,
+        }
+    }
+}
+
+/// Sends a single request and parses the response.
+async fn send_request<T: DeserializeOwned>(
+    client: &reqwest::Client,
+    method: reqwest::Method,
+    url: &str,
+    body: Option<&str>,
+) -> Result<T, RequestError> {
     let response = client
-        .request(args.method, args.url)
-        .body(args.body.unwrap_or_default().to_string())
+        .request(method, url)
+        .body(body.unwrap_or_default().to_string())
         .send()
         .await?;
 
     let status = response.status();
+    let headers = response.headers().clone();
     let body = response.text().await?;
-    println!("{}", body);
+    log::debug!("Alby API response ({}): {}", status, body);
 
+    parse_response(status, &headers, body)
+}
+
+/// Deserializes a response body according to its status; any 2xx status is a success.
+fn parse_response<T: DeserializeOwned>(
+    status: reqwest::StatusCode,
+    headers: &reqwest::header::HeaderMap,
+    body: String,
+) -> Result<T, RequestError> {
     match status.as_u16() {
-        200..299 => {
+        200..=299 => {
             Ok(serde_json::from_str(&body).map_err(|e| RequestError::ResponseParse(e, body))?)
         }
         400 => {
@@ -157,6 +504,180 @@ pub async fn make_request<T: DeserializeOwned>(args: RequestArgs<'_>) -> Result<
                 .map_err(|e| RequestError::ResponseParse(e, body.clone()))?;
             Err(RequestError::InternalServerError(error_response))
         }
+        404 => Err(RequestError::NotFound { body }),
+        429 => Err(RequestError::RateLimited {
+            retry_after: headers
+                .get(reqwest::header::RETRY_AFTER)
+                .and_then(|value| value.to_str().ok())
+                .and_then(parse_retry_after),
+            body,
+        }),
         _ => Err(RequestError::UnexpectedStatus { status, body }),
     }
 }
+
+/// Parses a `Retry-After` header value, either delay seconds or an HTTP date.
+fn parse_retry_after(value: &str) -> Option<std::time::Duration> {
+    let value = value.trim();
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(std::time::Duration::from_secs(secs));
+    }
+
+    let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    // A date in the past means no wait.
+    Some(
+        (date.with_timezone(&chrono::Utc) - chrono::Utc::now())
+            .to_std()
+            .unwrap_or_default(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, serde::Deserialize, PartialEq)]
+    struct Created {
+        id: u64,
+    }
+
+    #[test]
+    fn join_url_handles_slashes() {
+        for base_url in ["http://localhost:8080", "http://localhost:8080/"] {
+            assert_eq!(
+                join_url(base_url, "/payments/keysend"),
+                "http://localhost:8080/payments/keysend"
+            );
+            assert_eq!(
+                join_url(base_url, "payments/keysend"),
+                "http://localhost:8080/payments/keysend"
+            );
+        }
+        assert_eq!(
+            join_url("https://example.com/alby/", "/balance"),
+            "https://example.com/alby/balance"
+        );
+    }
+
+    #[test]
+    fn parse_response_accepts_all_2xx() {
+        for code in [200, 201, 202, 299] {
+            let status = reqwest::StatusCode::from_u16(code).unwrap();
+            let created: Created =
+                parse_response(status, &Default::default(), r#"{"id": 1}"#.to_string()).unwrap();
+            assert_eq!(created, Created { id: 1 });
+        }
+    }
+
+    #[test]
+    fn parse_response_rejects_unexpected_status() {
+        let result: Result<Created, _> = parse_response(
+            reqwest::StatusCode::from_u16(300).unwrap(),
+            &Default::default(),
+            "{}".to_string(),
+        );
+        assert!(matches!(
+            result,
+            Err(RequestError::UnexpectedStatus { status, .. }) if status.as_u16() == 300
+        ));
+    }
+
+    #[test]
+    fn parse_response_rate_limited() {
+        let mut headers = reqwest::header::HeaderMap::new();
+        headers.insert(
+            reqwest::header::RETRY_AFTER,
+            reqwest::header::HeaderValue::from_static("2"),
+        );
+        let result: Result<Created, _> = parse_response(
+            reqwest::StatusCode::TOO_MANY_REQUESTS,
+            &headers,
+            "slow down".to_string(),
+        );
+        assert!(matches!(
+            result,
+            Err(RequestError::RateLimited { retry_after: Some(retry_after), body })
+                if retry_after == std::time::Duration::from_secs(2) && body == "slow down"
+        ));
+
+        let result: Result<Created, _> = parse_response(
+            reqwest::StatusCode::TOO_MANY_REQUESTS,
+            &Default::default(),
+            String::new(),
+        );
+        assert!(matches!(
+            result,
+            Err(RequestError::RateLimited {
+                retry_after: None,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn retry_policy_delay() {
+        let retry = RetryPolicy {
+            max_attempts: 10,
+            base_delay: std::time::Duration::from_millis(100),
+            max_delay: std::time::Duration::from_millis(500),
+            ..Default::default()
+        };
+        let error = RequestError::Unexpected(String::new());
+        for (attempt, max_delay_ms) in [(1, 100), (2, 200), (3, 400), (4, 500), (9, 500)] {
+            let delay = retry.delay(attempt, &error);
+            let max_delay = std::time::Duration::from_millis(max_delay_ms);
+            assert!(delay <= max_delay && delay >= max_delay / 2, "{:?}", delay);
+        }
+
+        let rate_limited = |secs| RequestError::RateLimited {
+            retry_after: Some(std::time::Duration::from_secs(secs)),
+            body: String::new(),
+        };
+        assert_eq!(retry.delay(1, &rate_limited(0)), std::time::Duration::ZERO);
+        assert_eq!(retry.delay(1, &rate_limited(60)), retry.max_delay);
+    }
+
+    #[test]
+    fn retry_policy_retryable_statuses() {
+        let unexpected_status = |code| RequestError::UnexpectedStatus {
+            status: reqwest::StatusCode::from_u16(code).unwrap(),
+            body: String::new(),
+        };
+        let internal_server_error = RequestError::InternalServerError(ErrorResponse {
+            code: 500,
+            error: true,
+            message: String::new(),
+        });
+
+        let retry = RetryPolicy::default();
+        for code in [502, 503, 504] {
+            assert!(retry.is_retryable(&unexpected_status(code)), "{}", code);
+        }
+        assert!(retry.is_retryable(&internal_server_error));
+        assert!(!retry.is_retryable(&unexpected_status(501)));
+        assert!(!retry.is_retryable(&RequestError::NotFound {
+            body: String::new()
+        }));
+        assert!(!retry.is_retryable(&RequestError::InvalidArgs(String::new())));
+
+        let retry = RetryPolicy {
+            retryable_statuses: &[reqwest::StatusCode::NOT_IMPLEMENTED],
+            ..Default::default()
+        };
+        assert!(retry.is_retryable(&unexpected_status(501)));
+        assert!(!retry.is_retryable(&internal_server_error));
+    }
+
+    #[test]
+    fn parse_retry_after_formats() {
+        assert_eq!(
+            parse_retry_after("120"),
+            Some(std::time::Duration::from_secs(120))
+        );
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT"),
+            Some(std::time::Duration::ZERO)
+        );
+        assert_eq!(parse_retry_after("soon"), None);
+    }
+}
@@ -1,10 +1,192 @@
 /// Keysend address.
-#[derive(Debug, Default, serde::Serialize, serde::Deserialize, Clone)]
+#[derive(
+    Debug, Default, serde::Serialize, serde::Deserialize, Clone, PartialEq, Eq, PartialOrd, Ord,
+)]
 #[serde(rename_all = "camelCase")]
 pub struct KeysendAddress {
     /// Node's public key.
     pub pubkey: String,
     /// Custom data (key -> value), usually used to identify a wallet hosted at a node.
+    ///
+    /// When sending Podcasting 2.0 payments, custom data using the bLIP-10 record key
+    /// ([TLV_RECORD_KEY](crate::pc20::tlv::TLV_RECORD_KEY)) is dropped in favour of the bLIP-10
+    /// record.
     #[serde(default)]
     pub custom_data: Option<(String, String)>,
 }
+
+/// Error returned when a node's public key is not a valid compressed secp256k1 public key in hex.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidPubkeyError {
+    /// The rejected public key.
+    pub pubkey: String,
+}
+
+impl std::fmt::Display for InvalidPubkeyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Invalid node public key: {}", self.pubkey)
+    }
+}
+
+impl std::error::Error for InvalidPubkeyError {}
+
+impl KeysendAddress {
+    /// Creates a keysend address of a node.
+    ///
+    /// The public key must be a compressed secp256k1 public key encoded as 66 hex characters.
+    ///
+    /// ## Example
+    /// ```rust
+    /// let address = v4v::alby::types::KeysendAddress::new(
+    ///     "030a58b8653d32b99200a2334cfe913e51dc7d155aa0116c176657a4f1722677a3",
+    /// ).unwrap();
+    /// assert_eq!(address.custom_data, None);
+    ///
+    /// assert!(v4v::alby::types::KeysendAddress::new("not-a-pubkey").is_err());
+    /// ```
+    pub fn new(pubkey: &str) -> Result<Self, InvalidPubkeyError> {
+        let is_valid = pubkey.len() == 66
+            && (pubkey.starts_with("02") || pubkey.starts_with("03"))
+            && pubkey.chars().all(|c| c.is_ascii_hexdigit());
+
+        if !is_valid {
+            return Err(InvalidPubkeyError {
+                pubkey: pubkey.to_string(),
+            });
+        }
+
+        Ok(Self {
+            pubkey: pubkey.to_ascii_lowercase(),
+            custom_data: None,
+        })
+    }
+
+    /// Creates a keysend address of a wallet identified by custom data at a node.
+    ///
+    /// ## Example
+    /// ```rust
+    /// let address = v4v::alby::types::KeysendAddress::with_custom(
+    ///     "030a58b8653d32b99200a2334cfe913e51dc7d155aa0116c176657a4f1722677a3",
+    ///     "696969",
+    ///     "017rsl75kNnSke4mMHYE",
+    /// ).unwrap();
+    /// assert_eq!(
+    ///     address.custom_data,
+    ///     Some(("696969".to_string(), "017rsl75kNnSke4mMHYE".to_string())),
+    /// );
+    /// ```
+    pub fn with_custom(pubkey: &str, key: &str, value: &str) -> Result<Self, InvalidPubkeyError> {
+        Ok(Self {
+            custom_data: Some((key.to_string(), value.to_string())),
+            ..Self::new(pubkey)?
+        })
+    }
+
+    /// Creates a keysend address of a wallet hosted at a node, identified by custom data under
+    /// [WALLET_ID_KEY](crate::pc20::tlv::WALLET_ID_KEY).
+    ///
+    /// ## Example
+    /// ```rust
+    /// let address = v4v::alby::types::KeysendAddress::with_wallet_id(
+    ///     "030a58b8653d32b99200a2334cfe913e51dc7d155aa0116c176657a4f1722677a3",
+    ///     "017rsl75kNnSke4mMHYE",
+    /// ).unwrap();
+    /// assert_eq!(address.wallet_id(), Some("017rsl75kNnSke4mMHYE"));
+    /// ```
+    pub fn with_wallet_id(pubkey: &str, wallet_id: &str) -> Result<Self, InvalidPubkeyError> {
+        Self::with_custom(pubkey, crate::pc20::tlv::WALLET_ID_KEY, wallet_id)
+    }
+
+    /// Returns the ID of the hosted wallet, if the custom data uses
+    /// [WALLET_ID_KEY](crate::pc20::tlv::WALLET_ID_KEY).
+    pub fn wallet_id(&self) -> Option<&str> {
+        match &self.custom_data {
+            Some((key, value)) if key == crate::pc20::tlv::WALLET_ID_KEY => Some(value),
+            _ => None,
+        }
+    }
+}
+
+/// Error returned when a payment preimage is not 32 bytes encoded as 64 hex characters.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidPreimageError {
+    /// The rejected preimage.
+    pub preimage: String,
+}
+
+impl std::fmt::Display for InvalidPreimageError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Invalid payment preimage: {}", self.preimage)
+    }
+}
+
+impl std::error::Error for InvalidPreimageError {}
+
+/// Payment preimage, e.g., from
+/// [KeysendResponse::payment_preimage](crate::alby::api::payments::KeysendResponse::payment_preimage).
+///
+/// A payment hash is the sha256 hash of the preimage, and only the payer learns the preimage when
+/// the payment settles, so it serves as proof of payment.
+///
+/// ## Example
+/// ```rust
+/// use v4v::alby::types::Preimage;
+///
+/// let preimage = Preimage::from_hex(
+///     "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f",
+/// ).unwrap();
+/// assert!(preimage.verifies("630dcd2966c4336691125448bbb25b4ff412a49c732db2c8abc1b8581bd710dd"));
+/// assert!(!preimage.verifies("66687aadf862bd776c8fc18b8e9f8e20089714856ee233b3902a591d0d5f2925"));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Preimage([u8; 32]);
+
+impl Preimage {
+    /// Parses a preimage from 64 hex characters (case-insensitive).
+    pub fn from_hex(preimage: &str) -> Result<Self, InvalidPreimageError> {
+        let error = || InvalidPreimageError {
+            preimage: preimage.to_string(),
+        };
+
+        crate::hex::decode(preimage)
+            .and_then(|bytes| bytes.try_into().ok())
+            .map(Self)
+            .ok_or_else(error)
+    }
+
+    /// Raw bytes of the preimage.
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+
+    /// Lowercase hex encoding of the preimage.
+    pub fn to_hex(&self) -> String {
+        crate::hex::encode(&self.0)
+    }
+
+    /// Whether the hex-encoded `payment_hash` is the sha256 hash of this preimage.
+    pub fn verifies(&self, payment_hash: &str) -> bool {
+        let hash = hmac_sha256::Hash::hash(&self.0);
+        crate::hex::encode(&hash).eq_ignore_ascii_case(payment_hash)
+    }
+}
+
+impl std::fmt::Display for Preimage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.to_hex())
+    }
+}
+
+impl From<[u8; 32]> for Preimage {
+    fn from(bytes: [u8; 32]) -> Self {
+        Self(bytes)
+    }
+}
+
+impl std::str::FromStr for Preimage {
+    type Err = InvalidPreimageError;
+
+    fn from_str(preimage: &str) -> Result<Self, Self::Err> {
+        Self::from_hex(preimage)
+    }
+}
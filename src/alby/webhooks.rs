@@ -1,3 +1,5 @@
+use std::collections::HashMap;
+
 use serde_json::Value;
 
 use crate::pc20::tlv::Record;
@@ -5,6 +7,33 @@ pub use crate::svix::webhooks::{HeaderMap, WebhookError as Error};
 
 /// Verifies Alby webhook requests.
 ///
+/// `headers` can be an `http::HeaderMap` from either `http` 0.2 or `http` 1.x, the latter being
+/// the one re-exported by `axum` (`axum::http::HeaderMap`).
+///
+/// ## Example
+/// ```rust
+/// use base64::{engine::general_purpose::STANDARD, Engine as _};
+///
+/// let secret = "whsec_MfKQ9r8GKYqrTwjUPD8ILPZIo2LaLaSw";
+/// let payload = br#"{"type":"incoming","state":"SETTLED"}"#;
+///
+/// // Sign the payload the same way Alby does.
+/// let msg_id = "msg_p5jXN8AQM9LWM0D4loKWxJek";
+/// let timestamp = chrono::Utc::now().timestamp();
+/// let key = STANDARD.decode(secret.trim_start_matches("whsec_")).unwrap();
+/// let to_sign = format!("{msg_id}.{timestamp}.{}", std::str::from_utf8(payload).unwrap());
+/// let signature = STANDARD.encode(hmac_sha256::HMAC::mac(to_sign.as_bytes(), &key));
+///
+/// // Same type as `axum::http::HeaderMap`.
+/// let mut headers = http1::HeaderMap::new();
+/// headers.insert("svix-id", msg_id.parse().unwrap());
+/// headers.insert("svix-timestamp", timestamp.to_string().parse().unwrap());
+/// headers.insert("svix-signature", format!("v1,{signature}").parse().unwrap());
+///
+/// assert!(v4v::alby::webhooks::verify_signature(secret, payload, &headers).is_ok());
+/// assert!(v4v::alby::webhooks::verify_signature(secret, b"{}", &headers).is_err());
+/// ```
+///
 /// ## Example Axum usage
 /// ```ignore
 /// use axum::{
@@ -51,22 +80,36 @@ pub fn verify_signature<HM: HeaderMap>(
     crate::svix::webhooks::Webhook::new(secret)?.verify(payload, headers)
 }
 
+/// Same as [verify_signature], but checks the webhook's timestamp against `now` instead of the
+/// system clock.
+///
+/// Useful for deterministic tests, or for verifying a stored request at the time it was
+/// received.
+pub fn verify_signature_at<HM: HeaderMap>(
+    secret: &str,
+    payload: &[u8],
+    headers: &HM,
+    now: chrono::DateTime<chrono::Utc>,
+) -> Result<(), Error> {
+    crate::svix::webhooks::Webhook::new(secret)?.verify_at(payload, headers, now.timestamp())
+}
+
 /// Alby invoice obtained via webhook request.
 #[derive(Debug, serde::Deserialize, Clone)]
 pub struct AlbyInvoice {
     /// 24 alphanumeric characters
     pub identifier: String,
 
-    /// e.g., "incoming" or "outgoing"
+    /// Type of the invoice, e.g., incoming.
     #[serde(rename = "type")]
-    pub type_: String,
+    pub kind: InvoiceKind,
 
     /// Description.
     #[serde(default)]
     pub memo: Option<String>,
 
-    /// State of the invoice, e.g., "SETTLED">
-    pub state: String,
+    /// State of the invoice, e.g., settled.
+    pub state: InvoiceState,
 
     /// Arbitrary data added during the invoice creation.
     #[serde(default)]
@@ -91,7 +134,399 @@ pub struct AlbyInvoice {
     pub boostagram: Option<Record>,
 }
 
+/// Type of an [AlbyInvoice].
+///
+/// Deserialized from the `type` string; any other value is kept in [InvoiceKind::Unknown], so
+/// that new types don't break deserialization.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Deserialize)]
+#[serde(from = "String")]
+pub enum InvoiceKind {
+    /// Payment received by the account ("incoming").
+    Incoming,
+    /// Payment sent by the account ("outgoing").
+    Outgoing,
+    /// Any other type, as sent by Alby.
+    Unknown(String),
+}
+
+impl InvoiceKind {
+    /// The type as sent by Alby, e.g., "incoming".
+    pub fn as_str(&self) -> &str {
+        match self {
+            InvoiceKind::Incoming => "incoming",
+            InvoiceKind::Outgoing => "outgoing",
+            InvoiceKind::Unknown(kind) => kind,
+        }
+    }
+}
+
+impl From<String> for InvoiceKind {
+    fn from(kind: String) -> Self {
+        match kind.as_str() {
+            "incoming" => InvoiceKind::Incoming,
+            "outgoing" => InvoiceKind::Outgoing,
+            _ => InvoiceKind::Unknown(kind),
+        }
+    }
+}
+
+impl std::fmt::Display for InvoiceKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// State of an [AlbyInvoice].
+///
+/// Deserialized from the `state` string; any other value is kept in [InvoiceState::Unknown], so
+/// that new states don't break deserialization.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Deserialize)]
+#[serde(from = "String")]
+pub enum InvoiceState {
+    /// The invoice was created but not paid yet ("CREATED").
+    Created,
+    /// The invoice was paid ("SETTLED").
+    Settled,
+    /// Any other state, as sent by Alby.
+    Unknown(String),
+}
+
+impl InvoiceState {
+    /// The state as sent by Alby, e.g., "SETTLED".
+    pub fn as_str(&self) -> &str {
+        match self {
+            InvoiceState::Created => "CREATED",
+            InvoiceState::Settled => "SETTLED",
+            InvoiceState::Unknown(state) => state,
+        }
+    }
+}
+
+impl From<String> for InvoiceState {
+    fn from(state: String) -> Self {
+        match state.as_str() {
+            "CREATED" => InvoiceState::Created,
+            "SETTLED" => InvoiceState::Settled,
+            _ => InvoiceState::Unknown(state),
+        }
+    }
+}
+
+impl std::fmt::Display for InvoiceState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl AlbyInvoice {
+    /// Whether the invoice was paid.
+    pub fn is_settled(&self) -> bool {
+        self.state == InvoiceState::Settled
+    }
+
+    /// Whether the invoice is for a payment received by the account.
+    pub fn is_incoming(&self) -> bool {
+        self.kind == InvoiceKind::Incoming
+    }
+
+    /// Verifies the Nostr signature of the [boostagram](AlbyInvoice::boostagram), authenticating
+    /// its claimed sender.
+    ///
+    /// See [Record::verify_signature] for details. Available with the `nostr` feature.
+    #[cfg(feature = "nostr")]
+    pub fn verify_boostagram_signature(&self) -> Result<bool, crate::pc20::tlv::VerifyError> {
+        self.boostagram
+            .as_ref()
+            .ok_or(crate::pc20::tlv::VerifyError::NoBoostagram)?
+            .verify_signature()
+    }
+}
+
+/// Reads the type of a webhook event (e.g., "incoming" or "outgoing") without deserializing the
+/// whole body.
+///
+/// Looks at the `type` field, which is what [AlbyInvoice] deserializes.
+///
+/// ## Example
+/// ```rust
+/// let body = serde_json::json!({"type": "incoming", "amount": 21});
+/// assert_eq!(v4v::alby::webhooks::peek_event_type(&body), Some("incoming".to_string()));
+/// assert_eq!(v4v::alby::webhooks::peek_event_type(&serde_json::json!({})), None);
+/// ```
+pub fn peek_event_type(body: &Value) -> Option<String> {
+    body.get("type").and_then(Value::as_str).map(str::to_string)
+}
+
 /// Extracts an Alby invoice from a webhook request body.
 pub fn extract_alby_invoice(body: &Value) -> Result<AlbyInvoice, String> {
     serde_json::from_value(body.clone()).map_err(|e| e.to_string())
 }
+
+/// Error returned when a webhook request body cannot be interpreted.
+#[derive(Debug)]
+pub enum ExtractError {
+    /// Body doesn't have the expected shape.
+    InvalidBody(serde_json::Error),
+    /// Body describes a payment that is not a keysend.
+    NotKeysend,
+}
+
+impl std::fmt::Display for ExtractError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ExtractError::InvalidBody(e) => write!(f, "invalid webhook body: {}", e),
+            ExtractError::NotKeysend => write!(f, "payment is not a keysend"),
+        }
+    }
+}
+
+impl std::error::Error for ExtractError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ExtractError::InvalidBody(e) => Some(e),
+            ExtractError::NotKeysend => None,
+        }
+    }
+}
+
+/// Extracts Alby invoices from a webhook request body that contains either a single invoice or an
+/// array of them (batched delivery).
+///
+/// Fails if any of the invoices can't be parsed. Use [extract_alby_invoice] when the body is known
+/// to contain a single invoice.
+///
+/// ## Example
+/// ```rust
+/// let invoice = serde_json::json!({
+///     "identifier": "MAYm6eWC7TKUyixZWAKUM3su",
+///     "type": "incoming",
+///     "state": "SETTLED",
+///     "amount": 21,
+///     "created_at": "2024-08-01T12:00:00.000Z",
+/// });
+/// let body = serde_json::json!([invoice, invoice]);
+/// let invoices = v4v::alby::webhooks::extract_alby_invoices(&body).unwrap();
+/// assert_eq!(invoices.len(), 2);
+/// ```
+pub fn extract_alby_invoices(body: &Value) -> Result<Vec<AlbyInvoice>, ExtractError> {
+    match body {
+        Value::Array(invoices) => invoices
+            .iter()
+            .map(|invoice| {
+                serde_json::from_value(invoice.clone()).map_err(ExtractError::InvalidBody)
+            })
+            .collect(),
+        _ => serde_json::from_value(body.clone())
+            .map(|invoice| vec![invoice])
+            .map_err(ExtractError::InvalidBody),
+    }
+}
+
+/// Keysend payment received directly (rather than via an invoice) obtained via webhook request.
+#[derive(Debug, serde::Deserialize, Clone)]
+pub struct IncomingKeysend {
+    /// 24 alphanumeric characters
+    pub identifier: String,
+
+    /// Type of the payment, e.g., incoming.
+    #[serde(rename = "type")]
+    pub kind: InvoiceKind,
+
+    /// State of the payment, e.g., settled.
+    pub state: InvoiceState,
+
+    /// Amount in sats.
+    #[serde(rename = "amount")]
+    pub num_sats: u64,
+
+    /// Custom records (TLV type -> value) attached to the keysend.
+    #[serde(default)]
+    pub custom_records: HashMap<String, String>,
+
+    /// When the payment was received.
+    pub created_at: chrono::DateTime<chrono::Utc>,
+
+    /// bLIP-10 TLV record.
+    ///
+    /// Parsed from the [TLV_RECORD_KEY](crate::pc20::tlv::TLV_RECORD_KEY) custom record, whose
+    /// value may be either JSON or base64-encoded JSON. If the custom record is missing, Alby's
+    /// parsed `boostagram` field is used instead.
+    #[serde(
+        default,
+        deserialize_with = "crate::pc20::tlv::deserialize_untrusted_tlv_record"
+    )]
+    pub boostagram: Option<Record>,
+}
+
+impl IncomingKeysend {
+    /// Returns the value of a custom record, e.g., one of the keys in [crate::pc20::tlv].
+    pub fn custom_record(&self, key: &str) -> Option<&str> {
+        self.custom_records.get(key).map(String::as_str)
+    }
+
+    /// Returns the plain-text message sent under
+    /// [KEYSEND_MESSAGE_KEY](crate::pc20::tlv::KEYSEND_MESSAGE_KEY), if any.
+    ///
+    /// Unlike [boostagram](IncomingKeysend::boostagram), this is not part of bLIP-10, but some
+    /// keysend apps only send this.
+    pub fn keysend_message(&self) -> Option<&str> {
+        self.custom_record(crate::pc20::tlv::KEYSEND_MESSAGE_KEY)
+    }
+}
+
+/// Extracts a received keysend payment from a webhook request body.
+///
+/// ## Example
+/// ```rust
+/// let body = serde_json::json!({
+///     "identifier": "MAYm6eWC7TKUyixZWAKUM3su",
+///     "type": "incoming",
+///     "state": "SETTLED",
+///     "amount": 21,
+///     "keysend": true,
+///     "created_at": "2024-08-01T12:00:00.000Z",
+///     "custom_records": {
+///         "7629169": r#"{"action":"boost","message":"Hi!","value_msat_total":21000}"#,
+///     },
+/// });
+///
+/// let keysend = v4v::alby::webhooks::extract_incoming_keysend(&body).unwrap();
+/// assert_eq!(keysend.num_sats, 21);
+/// assert_eq!(keysend.keysend_message(), None);
+/// assert_eq!(keysend.boostagram.unwrap().message, Some("Hi!".to_string()));
+/// ```
+pub fn extract_incoming_keysend(body: &Value) -> Result<IncomingKeysend, ExtractError> {
+    if body.get("keysend").and_then(Value::as_bool) == Some(false) {
+        return Err(ExtractError::NotKeysend);
+    }
+
+    let mut keysend: IncomingKeysend =
+        serde_json::from_value(body.clone()).map_err(ExtractError::InvalidBody)?;
+
+    if let Some(record) = crate::pc20::tlv::parse_tlv_from_custom_records(&keysend.custom_records) {
+        keysend.boostagram = Some(record);
+    }
+
+    Ok(keysend)
+}
+
+/// Webhook event sent by Alby, as returned by [process].
+#[derive(Debug, Clone)]
+pub enum AlbyEvent {
+    /// Keysend payment received directly (the body has `"keysend": true`).
+    Keysend(IncomingKeysend),
+    /// Any other invoice, incoming or outgoing.
+    Invoice(AlbyInvoice),
+}
+
+/// Error returned by [process].
+#[derive(Debug)]
+pub enum WebhookProcessError {
+    /// The request could not be verified as coming from Alby. Usually maps to
+    /// `400 Bad Request` or `401 Unauthorized`.
+    InvalidSignature(Error),
+    /// The request was signed correctly, but its body could not be interpreted.
+    MalformedBody(serde_json::Error),
+}
+
+impl std::fmt::Display for WebhookProcessError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            WebhookProcessError::InvalidSignature(e) => {
+                write!(f, "invalid webhook signature: {:?}", e)
+            }
+            WebhookProcessError::MalformedBody(e) => write!(f, "malformed webhook body: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for WebhookProcessError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            WebhookProcessError::InvalidSignature(_) => None,
+            WebhookProcessError::MalformedBody(e) => Some(e),
+        }
+    }
+}
+
+/// Verifies an Alby webhook request and parses its body into an [AlbyEvent].
+///
+/// Combines [verify_signature] with [extract_incoming_keysend] or [extract_alby_invoice], so
+/// that a handler only needs a single call. The signature is always checked first, so a
+/// [WebhookProcessError::MalformedBody] error means that Alby did send the request.
+///
+/// ## Example
+/// ```rust
+/// use base64::{engine::general_purpose::STANDARD, Engine as _};
+/// use v4v::alby::webhooks::{process, AlbyEvent, WebhookProcessError};
+///
+/// let secret = "whsec_MfKQ9r8GKYqrTwjUPD8ILPZIo2LaLaSw";
+/// let sign = |payload: &[u8]| {
+///     let msg_id = "msg_p5jXN8AQM9LWM0D4loKWxJek";
+///     let timestamp = chrono::Utc::now().timestamp();
+///     let key = STANDARD.decode(secret.trim_start_matches("whsec_")).unwrap();
+///     let to_sign = format!("{msg_id}.{timestamp}.{}", std::str::from_utf8(payload).unwrap());
+///     let signature = STANDARD.encode(hmac_sha256::HMAC::mac(to_sign.as_bytes(), &key));
+///
+///     let mut headers = http1::HeaderMap::new();
+///     headers.insert("svix-id", msg_id.parse().unwrap());
+///     headers.insert("svix-timestamp", timestamp.to_string().parse().unwrap());
+///     headers.insert("svix-signature", format!("v1,{signature}").parse().unwrap());
+///     headers
+/// };
+///
+/// let payload = br#"{
+///     "identifier": "MAYm6eWC7TKUyixZWAKUM3su",
+///     "type": "incoming",
+///     "state": "SETTLED",
+///     "amount": 21,
+///     "created_at": "2024-08-01T12:00:00.000Z"
+/// }"#;
+/// let headers = sign(payload);
+///
+/// match process(secret, payload, &headers) {
+///     Ok(AlbyEvent::Invoice(invoice)) => assert_eq!(invoice.num_sats, 21),
+///     other => panic!("unexpected result: {other:?}"),
+/// }
+/// assert!(matches!(
+///     process(secret, b"{}", &headers),
+///     Err(WebhookProcessError::InvalidSignature(_)),
+/// ));
+/// assert!(matches!(
+///     process(secret, b"{}", &sign(b"{}")),
+///     Err(WebhookProcessError::MalformedBody(_)),
+/// ));
+/// ```
+///
+/// ## Example Axum usage
+/// ```ignore
+/// async fn webhook_handler(headers: axum::http::HeaderMap, body: axum::body::Bytes) -> StatusCode {
+///     match v4v::alby::webhooks::process(&secret, &body, &headers) {
+///         Ok(event) => handle_event(event).await,
+///         Err(WebhookProcessError::InvalidSignature(_)) => StatusCode::UNAUTHORIZED,
+///         Err(WebhookProcessError::MalformedBody(_)) => StatusCode::UNPROCESSABLE_ENTITY,
+///     }
+/// }
+/// ```
+pub fn process<HM: HeaderMap>(
+    secret: &str,
+    payload: &[u8],
+    headers: &HM,
+) -> Result<AlbyEvent, WebhookProcessError> {
+    verify_signature(secret, payload, headers).map_err(WebhookProcessError::InvalidSignature)?;
+
+    let body: Value =
+        serde_json::from_slice(payload).map_err(WebhookProcessError::MalformedBody)?;
+
+    if body.get("keysend").and_then(Value::as_bool) == Some(true) {
+        match extract_incoming_keysend(&body) {
+            Ok(keysend) => Ok(AlbyEvent::Keysend(keysend)),
+            Err(ExtractError::InvalidBody(e)) => Err(WebhookProcessError::MalformedBody(e)),
+            Err(ExtractError::NotKeysend) => unreachable!("`keysend` is true"),
+        }
+    } else {
+        serde_json::from_value(body)
+            .map(AlbyEvent::Invoice)
+            .map_err(WebhookProcessError::MalformedBody)
+    }
+}
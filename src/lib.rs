@@ -14,6 +14,9 @@ pub mod alby {
     pub mod webhooks;
 }
 
+/// Hex encoding helpers.
+mod hex;
+
 /// This is forked from <https://github.com/svix/svix-webhooks/blob/main/rust/src/webhooks.rs> to
 /// minimize the amount of dependencies.
 mod svix {
@@ -22,6 +25,8 @@ mod svix {
 
 /// Podcasting-related helpers.
 pub mod pc20 {
+    /// Batching of streaming payments.
+    pub mod batching;
     /// Functions related to sat caculations.
     pub mod calc;
     /// Utilities enabling to forward BOLT11 payments to keysend recipients.
@@ -73,6 +78,11 @@ pub mod pc20 {
     ///             token: ALBY_TOKEN,
     ///             payment_info: body.payment_info,
     ///             recipients: body.recipients,
+    ///             max_sats: None,
+    ///             invoice_memo: None,
+    ///             base_url: None,
+    ///             timeouts: Default::default(),
+    ///             retry: None,
     ///         }).await {
     ///             Ok(resp) => resp,
     ///             Err(e) => {
@@ -90,7 +100,7 @@ pub mod pc20 {
     ///     headers: http::header::HeaderMap,
     ///     Json(body): Json<serde_json::Value>,
     /// ) -> StatusCode {
-    ///     match v4v::verify_signature(&secret, body.to_string().as_bytes(), &headers) {
+    ///     match v4v::alby::webhooks::verify_signature(&secret, body.to_string().as_bytes(), &headers) {
     ///         Ok(()) => {}
     ///         Err(e) => {
     ///             log::error!("Failed to verify webhook: {:?}", e);
@@ -99,7 +109,7 @@ pub mod pc20 {
     ///     };
     ///
     ///     let alby_invoice = v4v::alby::webhooks::extract_alby_invoice(&body)?;
-    ///     if alby_invoice.status != "SETTLED".to_string() {
+    ///     if !alby_invoice.is_settled() {
     ///         return StatusCode::OK;
     ///     }
     ///
@@ -120,6 +130,7 @@ pub mod pc20 {
     ///         token: ALBY_TOKEN,
     ///         payment_info,
     ///         recipients,
+    ///         ..Default::default()
     ///         }).await {
     ///             Ok(_) => StatusCode::NO_CONTENT,
     ///             Err(e) => {
@@ -130,8 +141,36 @@ pub mod pc20 {
     /// }
     /// ```
     pub mod forwarding;
+    /// Lightning address resolution and LNURL-pay payments.
+    pub mod lightning_address;
+    /// LND REST API payment provider.
+    #[cfg(feature = "lnd")]
+    pub mod lnd;
+    /// Nostr Wallet Connect payment provider.
+    #[cfg(feature = "nwc")]
+    pub mod nwc;
     /// Podcasting-related payment utilities.
     pub mod payments;
+    /// Payment backends used to send keysends and create invoices.
+    pub mod provider;
     /// Utilities related to Podcasting 2.0 TLV records.
     pub mod tlv;
 }
+
+/// Expands to the name and version of the crate it is used in, as a `(&str, &str)` tuple.
+///
+/// Useful for setting the app name and version sent in bLIP-10 records, e.g., with
+/// [MakePaymentArgsBuilder::default_app](crate::pc20::payments::MakePaymentArgsBuilder::default_app).
+///
+/// ## Example
+/// ```rust
+/// let (name, version) = v4v::app_info!();
+/// assert_eq!(name, env!("CARGO_PKG_NAME"));
+/// assert_eq!(version, env!("CARGO_PKG_VERSION"));
+/// ```
+#[macro_export]
+macro_rules! app_info {
+    () => {
+        (env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"))
+    };
+}
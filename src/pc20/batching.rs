@@ -0,0 +1,373 @@
+use std::time::{Duration, Instant};
+
+use super::payments::{
+    make_payment, MakePaymentArgs, PaymentInfo, PaymentOutcome, PaymentRecipientInfo,
+};
+use crate::alby::api::RequestError;
+
+/// Configuration of [BatchingSender].
+#[derive(Debug, Clone)]
+pub struct BatchingConfig {
+    /// How long sats are accumulated before they are sent.
+    pub window: Duration,
+    /// Number of accumulated sats at which the batch should be sent even if the window hasn't
+    /// elapsed yet.
+    pub flush_threshold_sats: u64,
+    /// Minimum number of sats a recipient has to accumulate to be paid. Smaller amounts are
+    /// carried over to the next batch.
+    pub min_payout_sats: u64,
+    /// Base URL of the Alby API (e.g., of a mock server),
+    /// [DEFAULT_BASE_URL](crate::alby::api::DEFAULT_BASE_URL) if `None`.
+    pub base_url: Option<String>,
+}
+
+impl Default for BatchingConfig {
+    fn default() -> Self {
+        Self {
+            window: Duration::from_secs(60),
+            flush_threshold_sats: 1000,
+            min_payout_sats: 1,
+            base_url: None,
+        }
+    }
+}
+
+/// Accumulates streaming payments and sends them in batches.
+///
+/// Instead of sending keysends every time a listener streams a few sats, amounts intended for
+/// each recipient are accumulated and sent together in a single payment once the
+/// [window](BatchingConfig::window) elapses or the
+/// [threshold](BatchingConfig::flush_threshold_sats) is crossed. Recipients that haven't
+/// accumulated [enough sats](BatchingConfig::min_payout_sats) are carried over to the next batch.
+///
+/// If the sender is dropped inside a Tokio runtime while it still has payable sats, the remaining
+/// batch is sent in a spawned task. Call [BatchingSender::flush] before shutting down to know
+/// whether the last batch was sent successfully.
+///
+/// ## Example usage
+/// ```ignore
+/// let mut sender = v4v::pc20::batching::BatchingSender::new(
+///     USER_AGENT,
+///     ALBY_TOKEN,
+///     v4v::pc20::batching::BatchingConfig::default(),
+/// );
+///
+/// loop {
+///     // Every few seconds of playback:
+///     sender.push(payment_info.clone(), &recipients);
+///     if let Err(e) = sender.maybe_flush().await {
+///         log::error!("Failed to send streamed sats: {}", e);
+///     }
+/// }
+/// ```
+#[derive(Debug)]
+pub struct BatchingSender {
+    user_agent: String,
+    token: String,
+    config: BatchingConfig,
+    payment_info: Option<PaymentInfo>,
+    pending: Vec<PaymentRecipientInfo>,
+    unconfirmed: Vec<PaymentRecipientInfo>,
+    window_started_at: Option<Instant>,
+    flush_on_drop: bool,
+}
+
+impl BatchingSender {
+    /// Creates a sender with no pending payments.
+    pub fn new(user_agent: &str, token: &str, config: BatchingConfig) -> Self {
+        Self {
+            user_agent: user_agent.to_string(),
+            token: token.to_string(),
+            config,
+            payment_info: None,
+            pending: vec![],
+            unconfirmed: vec![],
+            window_started_at: None,
+            flush_on_drop: true,
+        }
+    }
+
+    /// Adds a payment to the batch.
+    ///
+    /// Sats of recipients with the same address are added up. The most recent payment
+    /// information (e.g., with the latest playback position) is used when the batch is sent.
+    pub fn push(&mut self, payment_info: PaymentInfo, recipients: &[PaymentRecipientInfo]) {
+        for recipient in recipients {
+            let existing = self
+                .pending
+                .iter_mut()
+                .find(|pending| pending.address == recipient.address);
+
+            match existing {
+                Some(pending) => pending.num_sats += recipient.num_sats,
+                None => self.pending.push(recipient.clone()),
+            }
+        }
+
+        self.payment_info = Some(payment_info);
+        self.window_started_at.get_or_insert_with(Instant::now);
+    }
+
+    /// Recipients and the sats accumulated for them so far.
+    pub fn pending(&self) -> &[PaymentRecipientInfo] {
+        &self.pending
+    }
+
+    /// Takes the recipients whose sats were removed from the batch without knowing whether they
+    /// were sent, so that the caller can reconcile them (e.g., by checking the wallet's payment
+    /// history) instead of paying them twice.
+    ///
+    /// See [BatchingSender::flush] for when this happens.
+    pub fn take_unconfirmed(&mut self) -> Vec<PaymentRecipientInfo> {
+        std::mem::take(&mut self.unconfirmed)
+    }
+
+    /// Total number of sats accumulated so far.
+    pub fn pending_sats(&self) -> u64 {
+        self.pending
+            .iter()
+            .map(|recipient| recipient.num_sats)
+            .sum()
+    }
+
+    /// Whether the window has elapsed or the threshold has been crossed.
+    pub fn should_flush(&self) -> bool {
+        let window_elapsed = self
+            .window_started_at
+            .is_some_and(|started_at| started_at.elapsed() >= self.config.window);
+
+        window_elapsed || self.pending_sats() >= self.config.flush_threshold_sats
+    }
+
+    /// Sends the batch if [BatchingSender::should_flush] is true.
+    pub async fn maybe_flush(&mut self) -> Result<Option<Vec<PaymentOutcome>>, RequestError> {
+        if self.should_flush() {
+            self.flush().await
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Sends the accumulated sats of every recipient that has reached the minimum payout.
+    ///
+    /// Returns `None` if there was nothing to send. The sats of the individual keysends that fail
+    /// remain pending, and so do all sats if the request fails in a way that shows that nothing
+    /// was sent (e.g., invalid arguments, a failed connection or a 4xx response).
+    ///
+    /// If the request fails after Alby may already have processed it (e.g., a timeout or an
+    /// unparsable response), or if the future is dropped while the request is in flight, the sats
+    /// being sent are removed from the batch and moved to
+    /// [take_unconfirmed](BatchingSender::take_unconfirmed); they are never sent twice.
+    pub async fn flush(&mut self) -> Result<Option<Vec<PaymentOutcome>>, RequestError> {
+        let (payable, residual): (Vec<_>, Vec<_>) = std::mem::take(&mut self.pending)
+            .into_iter()
+            .partition(|recipient| {
+                recipient.num_sats > 0 && recipient.num_sats >= self.config.min_payout_sats
+            });
+        self.pending = residual;
+
+        if payable.is_empty() {
+            return Ok(None);
+        }
+
+        let payment_info = PaymentInfo {
+            total_num_sats: payable.iter().map(|recipient| recipient.num_sats).sum(),
+            ..self.payment_info.clone().unwrap_or_default()
+        };
+
+        // Recorded as unconfirmed until the outcome is known, in case the future is dropped.
+        let num_unconfirmed = self.unconfirmed.len();
+        self.unconfirmed.extend(payable.iter().cloned());
+
+        let result = make_payment(MakePaymentArgs {
+            user_agent: &self.user_agent,
+            token: &self.token,
+            payment_info,
+            recipients: payable.clone(),
+            base_url: self.config.base_url.as_deref(),
+            ..Default::default()
+        })
+        .await;
+
+        if !matches!(&result, Err(e) if !nothing_sent(e)) {
+            self.unconfirmed.truncate(num_unconfirmed);
+        }
+
+        match result {
+            Ok(outcomes) => {
+                self.window_started_at = if self.pending.is_empty() {
+                    None
+                } else {
+                    Some(Instant::now())
+                };
+
+                // Alby rejected these keysends, so they weren't sent: retry with the next batch.
+                let failed: Vec<_> = outcomes
+                    .iter()
+                    .filter(|outcome| !outcome.is_success())
+                    .map(|outcome| payable[outcome.recipient_index].clone())
+                    .collect();
+                if !failed.is_empty() {
+                    self.push(self.payment_info.clone().unwrap_or_default(), &failed);
+                }
+
+                Ok(Some(outcomes))
+            }
+            Err(e) if nothing_sent(&e) => {
+                // Nothing is lost: put the sats back so they are retried with the next batch.
+                let residual = std::mem::replace(&mut self.pending, payable);
+                self.push(self.payment_info.clone().unwrap_or_default(), &residual);
+                Err(e)
+            }
+            // The sats may have been sent, so they are left unconfirmed.
+            Err(e) => Err(e),
+        }
+    }
+
+    fn has_payable(&self) -> bool {
+        self.pending.iter().any(|recipient| {
+            recipient.num_sats > 0 && recipient.num_sats >= self.config.min_payout_sats
+        })
+    }
+}
+
+/// Whether `error` shows that the request didn't reach Alby or was rejected by it, so that no
+/// keysend was sent.
+fn nothing_sent(error: &RequestError) -> bool {
+    match error {
+        RequestError::InvalidArgs(_)
+        | RequestError::AuthHeaderCreation(_)
+        | RequestError::ClientCreation(_) => true,
+        RequestError::RequestSend(e) => e.is_connect(),
+        _ => error
+            .status()
+            .is_some_and(|status| status.is_client_error()),
+    }
+}
+
+impl Drop for BatchingSender {
+    fn drop(&mut self) {
+        if !self.flush_on_drop || !self.has_payable() {
+            return;
+        }
+
+        let Ok(handle) = tokio::runtime::Handle::try_current() else {
+            return;
+        };
+
+        let mut sender = BatchingSender {
+            user_agent: std::mem::take(&mut self.user_agent),
+            token: std::mem::take(&mut self.token),
+            config: self.config.clone(),
+            payment_info: self.payment_info.take(),
+            pending: std::mem::take(&mut self.pending),
+            unconfirmed: vec![],
+            window_started_at: self.window_started_at.take(),
+            // The final flush is only attempted once.
+            flush_on_drop: false,
+        };
+
+        handle.spawn(async move {
+            let _ = sender.flush().await;
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alby::types::KeysendAddress;
+
+    fn recipient(pubkey: &str, num_sats: u64) -> PaymentRecipientInfo {
+        PaymentRecipientInfo {
+            address: KeysendAddress {
+                pubkey: pubkey.to_string(),
+                custom_data: None,
+            }
+            .into(),
+            num_sats,
+            ..Default::default()
+        }
+    }
+
+    fn sender(config: BatchingConfig) -> BatchingSender {
+        let mut sender = BatchingSender::new("test", "token", config);
+        sender.flush_on_drop = false;
+        sender
+    }
+
+    #[test]
+    fn push_merges_recipients() {
+        let mut sender = sender(BatchingConfig::default());
+        sender.push(
+            PaymentInfo::default(),
+            &[recipient("alice", 3), recipient("bob", 1)],
+        );
+        sender.push(
+            PaymentInfo::default(),
+            &[recipient("alice", 2), recipient("carol", 4)],
+        );
+
+        let pending: Vec<_> = sender
+            .pending()
+            .iter()
+            .map(|r| (r.address.to_string(), r.num_sats))
+            .collect();
+        assert_eq!(
+            pending,
+            vec![
+                ("alice".to_string(), 5),
+                ("bob".to_string(), 1),
+                ("carol".to_string(), 4)
+            ]
+        );
+        assert_eq!(sender.pending_sats(), 10);
+    }
+
+    #[test]
+    fn should_flush_at_threshold() {
+        let mut sender = sender(BatchingConfig {
+            window: Duration::from_secs(3600),
+            flush_threshold_sats: 10,
+            min_payout_sats: 1,
+            ..Default::default()
+        });
+        assert!(!sender.should_flush());
+
+        sender.push(PaymentInfo::default(), &[recipient("alice", 9)]);
+        assert!(!sender.should_flush());
+
+        sender.push(PaymentInfo::default(), &[recipient("bob", 1)]);
+        assert!(sender.should_flush());
+    }
+
+    #[test]
+    fn should_flush_after_window() {
+        let mut sender = sender(BatchingConfig {
+            window: Duration::ZERO,
+            flush_threshold_sats: u64::MAX,
+            min_payout_sats: 1,
+            ..Default::default()
+        });
+        assert!(!sender.should_flush());
+
+        sender.push(PaymentInfo::default(), &[recipient("alice", 1)]);
+        assert!(sender.should_flush());
+    }
+
+    #[tokio::test]
+    async fn flush_carries_residuals_below_floor() {
+        let mut sender = sender(BatchingConfig {
+            min_payout_sats: 5,
+            ..Default::default()
+        });
+        sender.push(
+            PaymentInfo::default(),
+            &[recipient("alice", 2), recipient("bob", 0)],
+        );
+
+        assert!(sender.flush().await.unwrap().is_none());
+        assert_eq!(sender.pending_sats(), 2);
+        assert_eq!(sender.pending().len(), 2);
+    }
+}
@@ -31,7 +31,235 @@ use std::cmp::Ordering;
 /// // There is only 1 sat available to distribute, so the recipient with the larger split gets it:
 /// assert_eq!(v4v::pc20::calc::compute_sat_recipients(&splits, total_sats), vec![0, 1]);
 /// ```
+///
+/// ## Performance
+/// Runs in linear time, unless one-sat minimums have to be taken back from other recipients, in
+/// which case the recipients are sorted, taking `O(n log n)` time. Either way, it takes well under
+/// a millisecond for 10000 recipients (see `benches/calc.rs`).
 pub fn compute_sat_recipients(splits: &[u64], total_sats: u64) -> Vec<u64> {
+    compute_sat_recipients_detailed(splits, total_sats).amounts
+}
+
+/// Result of [compute_sat_recipients_detailed].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SatAllocation {
+    /// Sats for each recipient, as returned by [compute_sat_recipients].
+    pub amounts: Vec<u64>,
+    /// Sats that couldn't be assigned to any recipient. For [compute_sat_recipients_detailed],
+    /// only nonzero if there are no recipients; for [compute_sat_recipients_capped_detailed], also
+    /// nonzero if the caps add up to less than the total.
+    pub undistributed: u64,
+    /// Number of recipients that receive no sats, e.g., because there weren't enough sats for
+    /// everyone. Apps can use it to warn users before sending.
+    pub num_zero_recipients: usize,
+}
+
+/// Same as [compute_sat_recipients], but also reports how many recipients end up with nothing
+/// and how many sats couldn't be distributed.
+///
+/// ## Example
+/// ```rust
+/// let allocation = v4v::pc20::calc::compute_sat_recipients_detailed(&[1, 99], 1);
+/// assert_eq!(allocation.amounts, vec![0, 1]);
+/// assert_eq!(allocation.undistributed, 0);
+/// assert_eq!(allocation.num_zero_recipients, 1);
+/// ```
+pub fn compute_sat_recipients_detailed(splits: &[u64], total_sats: u64) -> SatAllocation {
+    let amounts = compute_sat_recipients_with_floor(splits, total_sats, 1);
+    let distributed: u64 = amounts.iter().sum();
+
+    SatAllocation {
+        undistributed: total_sats - distributed,
+        num_zero_recipients: amounts.iter().filter(|&&sats| sats == 0).count(),
+        amounts,
+    }
+}
+
+/// How [compute_sat_recipients_mode] rounds sats to whole numbers.
+///
+/// Separate from [RoundingMode], which applies to percentages.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SatRoundingMode {
+    /// Amounts are rounded down, and the remaining sats go to the recipients with the largest
+    /// remainders ([Hamilton's method](https://en.wikipedia.org/wiki/Largest_remainder_method)),
+    /// ties going to larger splits. Recipients left with nothing then get a sat each, taken from
+    /// the recipients with the most sats, as long as there are enough sats.
+    LargestRemainder,
+    /// The rounding of [compute_sat_recipients]: remaining sats go to the recipients with the
+    /// largest splits.
+    #[default]
+    CurrentHeuristic,
+}
+
+/// Same as [compute_sat_recipients], but with a choice of how amounts are rounded.
+///
+/// ## Example
+/// ```rust
+/// use v4v::pc20::calc::{compute_sat_recipients_mode, SatRoundingMode};
+///
+/// // The exact amounts are 4, 3.5 and 2.5 sats.
+/// let splits = vec![40, 35, 25];
+/// assert_eq!(compute_sat_recipients_mode(&splits, 10, SatRoundingMode::LargestRemainder), vec![4, 4, 2]);
+/// assert_eq!(compute_sat_recipients_mode(&splits, 10, SatRoundingMode::CurrentHeuristic), vec![5, 3, 2]);
+/// ```
+pub fn compute_sat_recipients_mode(
+    splits: &[u64],
+    total_sats: u64,
+    mode: SatRoundingMode,
+) -> Vec<u64> {
+    match mode {
+        SatRoundingMode::LargestRemainder => {
+            compute_sat_recipients_largest_remainder(splits, total_sats)
+        }
+        SatRoundingMode::CurrentHeuristic => compute_sat_recipients(splits, total_sats),
+    }
+}
+
+fn compute_sat_recipients_largest_remainder(splits: &[u64], total_sats: u64) -> Vec<u64> {
+    let num_recipients = splits.len();
+
+    if splits.is_empty() || total_sats == 0 {
+        return vec![0; num_recipients];
+    }
+
+    // If all splits are zero, treat them as equal.
+    let all_zero = splits.iter().all(|&split| split == 0);
+    let split_at = |index: usize| if all_zero { 1 } else { splits[index] as u128 };
+    let total_split: u128 = (0..num_recipients).map(split_at).sum();
+
+    let mut sat_amounts: Vec<u64> = (0..num_recipients)
+        .map(|index| (split_at(index) * total_sats as u128 / total_split) as u64)
+        .collect();
+    let balance = total_sats - sat_amounts.iter().sum::<u64>();
+
+    // Largest remainders first, then larger splits, then earlier recipients.
+    let mut by_remainder: Vec<usize> = (0..num_recipients).collect();
+    by_remainder.sort_by(|&a, &b| {
+        let remainder = |index: usize| split_at(index) * total_sats as u128 % total_split;
+        remainder(b)
+            .cmp(&remainder(a))
+            .then(split_at(b).cmp(&split_at(a)))
+            .then(a.cmp(&b))
+    });
+    for &index in by_remainder.iter().take(balance as usize) {
+        sat_amounts[index] += 1;
+    }
+
+    // Give a sat to recipients left with nothing, larger splits first, taking it from the
+    // recipients with the most sats.
+    let mut empty: Vec<usize> = (0..num_recipients)
+        .filter(|&index| sat_amounts[index] == 0)
+        .collect();
+    empty.sort_by(|&a, &b| splits[b].cmp(&splits[a]).then(a.cmp(&b)));
+    let mut donors: std::collections::BinaryHeap<(u64, std::cmp::Reverse<usize>)> = (0
+        ..num_recipients)
+        .filter(|&index| sat_amounts[index] >= 2)
+        .map(|index| (sat_amounts[index], std::cmp::Reverse(index)))
+        .collect();
+    for index in empty {
+        let Some((amount, std::cmp::Reverse(donor))) = donors.pop() else {
+            break;
+        };
+        sat_amounts[donor] = amount - 1;
+        sat_amounts[index] += 1;
+        if sat_amounts[donor] >= 2 {
+            donors.push((sat_amounts[donor], std::cmp::Reverse(donor)));
+        }
+    }
+
+    sat_amounts
+}
+
+/// Same as [compute_sat_recipients], but every recipient that receives sats gets at least
+/// `min_sat` sats, e.g., to avoid keysends too small to route.
+///
+/// Recipients whose proportional amount would fall below the floor get nothing, starting with
+/// the lowest splits, and their sats are redistributed among the rest. With `min_sat` of 0,
+/// recipients with zero splits get nothing, and the others get their proportional amounts.
+/// With `min_sat` of 1, this is the same as [compute_sat_recipients].
+///
+/// The result always sums to `total_sats`: if it is less than `min_sat`, everything goes to the
+/// recipient with the largest split.
+///
+/// ## Example
+/// ```rust
+/// use v4v::pc20::calc::compute_sat_recipients_with_floor;
+///
+/// // 100 sats aren't enough to give 10 sats to all three recipients proportionally, so the
+/// // smallest split is dropped:
+/// assert_eq!(compute_sat_recipients_with_floor(&[60, 35, 5], 100, 10), vec![64, 36, 0]);
+/// assert_eq!(compute_sat_recipients_with_floor(&[60, 35, 5], 100, 1), vec![60, 35, 5]);
+/// ```
+pub fn compute_sat_recipients_with_floor(
+    splits: &[u64],
+    total_sats: u64,
+    min_sat: u64,
+) -> Vec<u64> {
+    if min_sat == 1 {
+        compute_sat_recipients_with_one_sat_floor(splits, total_sats)
+    } else {
+        compute_sat_recipients_with_any_floor(splits, total_sats, min_sat)
+    }
+}
+
+fn compute_sat_recipients_with_any_floor(
+    splits: &[u64],
+    total_sats: u64,
+    min_sat: u64,
+) -> Vec<u64> {
+    let num_recipients = splits.len();
+    let mut sat_amounts = vec![0; num_recipients];
+
+    if splits.is_empty() || total_sats == 0 {
+        return sat_amounts;
+    }
+
+    // If all splits are zero, treat them as equal.
+    let all_zero = splits.iter().all(|&split| split == 0);
+    let split_at = |index: usize| if all_zero { 1 } else { splits[index] as u128 };
+
+    // Decreasing splits, ties broken by increasing index. Recipients are dropped from the end.
+    let mut order: Vec<usize> = (0..num_recipients)
+        .filter(|&index| split_at(index) > 0)
+        .collect();
+    order.sort_by(|&a, &b| split_at(b).cmp(&split_at(a)).then(a.cmp(&b)));
+
+    // The smallest proportional amount among the first `k` recipients only decreases as `k`
+    // grows, so keep the longest prefix in which everyone reaches the floor.
+    let mut num_eligible = 0;
+    let mut total_split: u128 = 0;
+    for (k, &index) in order.iter().enumerate() {
+        let split = split_at(index);
+        if split * total_sats as u128 / (total_split + split) < min_sat as u128 {
+            break;
+        }
+        total_split += split;
+        num_eligible = k + 1;
+    }
+
+    if num_eligible == 0 {
+        // Not even the largest split can reach the floor.
+        sat_amounts[order[0]] = total_sats;
+        return sat_amounts;
+    }
+
+    let eligible = &order[..num_eligible];
+    let mut balance = total_sats;
+    for &index in eligible {
+        let amount = (split_at(index) * total_sats as u128 / total_split) as u64;
+        sat_amounts[index] = amount;
+        balance -= amount;
+    }
+
+    // Rounding down loses less than a sat per recipient, and the largest splits come first.
+    for &index in eligible.iter().take(balance as usize) {
+        sat_amounts[index] += 1;
+    }
+
+    sat_amounts
+}
+
+fn compute_sat_recipients_with_one_sat_floor(splits: &[u64], total_sats: u64) -> Vec<u64> {
     let num_recipients = splits.len();
 
     if splits.is_empty() || total_sats == 0 {
@@ -140,10 +368,19 @@ pub fn compute_sat_recipients(splits: &[u64], total_sats: u64) -> Vec<u64> {
         }
         Ordering::Equal => {}
         Ordering::Greater => {
-            // Sort by decreasing splits for positive balance
-            indexed_splits.sort_by_key(|&(_, split)| std::cmp::Reverse(split));
+            // Rounding down loses less than a sat per recipient, so the balance is less than
+            // the number of recipients. Only the `balance` recipients with the largest splits
+            // (ties broken by increasing index) get an extra sat, so select them instead of
+            // sorting everyone.
+            let by_decreasing_split = |&(i1, s1): &(usize, u128), &(i2, s2): &(usize, u128)| {
+                s2.cmp(&s1).then(i1.cmp(&i2))
+            };
+            let num_extra = (balance as usize).min(num_recipients);
+            if num_extra < num_recipients {
+                indexed_splits.select_nth_unstable_by(num_extra, by_decreasing_split);
+                indexed_splits.truncate(num_extra);
+            }
 
-            // Single iteration: add sats to recipients
             for &(index, _) in &indexed_splits {
                 if balance > 0 {
                     sat_amounts[index] += 1;
@@ -158,13 +395,498 @@ pub fn compute_sat_recipients(splits: &[u64], total_sats: u64) -> Vec<u64> {
     sat_amounts
 }
 
+/// Splits and the total number of sats of a single payment, used by [DistributeExt].
+///
+/// Implemented for `(splits, total_sats)` tuples and references to them.
+pub trait DistributionInput {
+    /// Splits of the recipients.
+    fn splits(&self) -> &[u64];
+    /// Total number of sats to distribute.
+    fn total_sats(&self) -> u64;
+}
+
+impl<S: AsRef<[u64]>> DistributionInput for (S, u64) {
+    fn splits(&self) -> &[u64] {
+        self.0.as_ref()
+    }
+
+    fn total_sats(&self) -> u64 {
+        self.1
+    }
+}
+
+impl<T: DistributionInput + ?Sized> DistributionInput for &T {
+    fn splits(&self) -> &[u64] {
+        (**self).splits()
+    }
+
+    fn total_sats(&self) -> u64 {
+        (**self).total_sats()
+    }
+}
+
+/// Iterator returned by [DistributeExt::map_distribute].
+#[derive(Debug, Clone)]
+pub struct Distribute<I> {
+    iter: I,
+}
+
+impl<I> Iterator for Distribute<I>
+where
+    I: Iterator,
+    I::Item: DistributionInput,
+{
+    type Item = Vec<u64>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter
+            .next()
+            .map(|item| compute_sat_recipients(item.splits(), item.total_sats()))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+/// Extension trait for lazily distributing sats of many payments with [compute_sat_recipients].
+///
+/// ## Example
+/// ```rust
+/// use v4v::pc20::calc::DistributeExt;
+///
+/// let boosts = vec![(vec![60, 40], 1000), (vec![1, 99], 10)];
+/// let distributions: Vec<Vec<u64>> = boosts.iter().map_distribute().collect();
+/// assert_eq!(distributions, vec![vec![600, 400], vec![1, 9]]);
+/// ```
+pub trait DistributeExt: Iterator + Sized {
+    /// Maps every `(splits, total_sats)` item to the sats received by each recipient.
+    fn map_distribute(self) -> Distribute<Self>;
+}
+
+impl<I> DistributeExt for I
+where
+    I: Iterator,
+    I::Item: DistributionInput,
+{
+    fn map_distribute(self) -> Distribute<Self> {
+        Distribute { iter: self }
+    }
+}
+
+/// Distributes a refund back to the parties of a payment proportionally to the amounts they
+/// originally received.
+///
+/// The same calculation as [compute_sat_recipients] with the original amounts as splits, so the
+/// refund adds up to `refund_sats`, parties that received more refund more, and every party that
+/// received sats refunds at least one if there are enough sats. Parties that received nothing
+/// refund nothing (unless nobody received anything, in which case the refund is split evenly).
+///
+/// ## Example
+/// ```rust
+/// // A 1000-sat payment was split 600/300/100, and half of it is refunded:
+/// assert_eq!(v4v::pc20::calc::compute_refund(&[600, 300, 100], 500), vec![300, 150, 50]);
+/// ```
+pub fn compute_refund(original_amounts: &[u64], refund_sats: u64) -> Vec<u64> {
+    let paid_indices: Vec<usize> = (0..original_amounts.len())
+        .filter(|&i| original_amounts[i] > 0)
+        .collect();
+    if paid_indices.is_empty() {
+        return compute_sat_recipients(original_amounts, refund_sats);
+    }
+
+    let paid_amounts: Vec<u64> = paid_indices.iter().map(|&i| original_amounts[i]).collect();
+    let mut refunds = vec![0; original_amounts.len()];
+    for (&index, refund) in paid_indices
+        .iter()
+        .zip(compute_sat_recipients(&paid_amounts, refund_sats))
+    {
+        refunds[index] = refund;
+    }
+
+    refunds
+}
+
+/// Error returned by the calculations that validate their input.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CalcError {
+    /// Weight at the given index is negative, NaN or infinite.
+    InvalidWeight {
+        /// Index of the weight.
+        index: usize,
+    },
+    /// There are sats to distribute but no recipients to receive them.
+    NoRecipients,
+}
+
+impl std::fmt::Display for CalcError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CalcError::InvalidWeight { index } => {
+                write!(f, "Weight at index {} is negative or not finite", index)
+            }
+            CalcError::NoRecipients => write!(f, "No recipients to distribute sats to"),
+        }
+    }
+}
+
+impl std::error::Error for CalcError {}
+
+/// Same as [compute_sat_recipients], but returns [CalcError::NoRecipients] instead of silently
+/// dropping the sats if `total_sats` is non-zero and `splits` is empty, e.g., because the value
+/// block failed to load.
+///
+/// ## Example
+/// ```rust
+/// use v4v::pc20::calc::{compute_sat_recipients_strict, CalcError};
+///
+/// assert_eq!(compute_sat_recipients_strict(&[60, 40], 100), Ok(vec![60, 40]));
+/// assert_eq!(compute_sat_recipients_strict(&[], 100), Err(CalcError::NoRecipients));
+/// assert_eq!(compute_sat_recipients_strict(&[], 0), Ok(vec![]));
+/// ```
+pub fn compute_sat_recipients_strict(
+    splits: &[u64],
+    total_sats: u64,
+) -> Result<Vec<u64>, CalcError> {
+    if splits.is_empty() && total_sats > 0 {
+        return Err(CalcError::NoRecipients);
+    }
+
+    Ok(compute_sat_recipients(splits, total_sats))
+}
+
+/// Same as [compute_sat_recipients], but no recipient receives more than its cap.
+///
+/// `caps` is parallel to `splits`, with `None` (or a missing entry) meaning no cap. When a
+/// recipient would exceed its cap, it gets exactly its cap, and the rest of the sats are
+/// distributed among the other recipients by their splits. This repeats until no recipient
+/// exceeds its cap, so overflow can cascade through several recipients.
+///
+/// If every recipient is capped and the caps add up to less than `total_sats`, every recipient
+/// gets its cap and the result sums to less than `total_sats`. Use
+/// [compute_sat_recipients_capped_detailed] to get the shortfall, and decide what to do with it
+/// (e.g., not charge it, or pay it to someone else).
+///
+/// ## Example
+/// ```rust
+/// use v4v::pc20::calc::compute_sat_recipients_capped;
+///
+/// assert_eq!(compute_sat_recipients_capped(&[80, 20], 100, &[Some(50), None]), vec![50, 50]);
+/// ```
+pub fn compute_sat_recipients_capped(
+    splits: &[u64],
+    total_sats: u64,
+    caps: &[Option<u64>],
+) -> Vec<u64> {
+    compute_sat_recipients_capped_detailed(splits, total_sats, caps).amounts
+}
+
+/// Same as [compute_sat_recipients_capped], but also reports how many recipients end up with
+/// nothing and how many sats couldn't be distributed because of the caps.
+///
+/// ## Example
+/// ```rust
+/// use v4v::pc20::calc::compute_sat_recipients_capped_detailed;
+///
+/// // 10 sats can't be paid out:
+/// let allocation = compute_sat_recipients_capped_detailed(&[80, 20], 100, &[Some(50), Some(40)]);
+/// assert_eq!(allocation.amounts, vec![50, 40]);
+/// assert_eq!(allocation.undistributed, 10);
+/// ```
+pub fn compute_sat_recipients_capped_detailed(
+    splits: &[u64],
+    total_sats: u64,
+    caps: &[Option<u64>],
+) -> SatAllocation {
+    let cap_at = |index: usize| caps.get(index).copied().flatten();
+
+    let mut sat_amounts = vec![0; splits.len()];
+    let mut pinned = vec![false; splits.len()];
+    let mut active: Vec<usize> = (0..splits.len()).collect();
+    let mut remaining = total_sats;
+
+    while !active.is_empty() {
+        let active_splits: Vec<u64> = active.iter().map(|&index| splits[index]).collect();
+        let amounts = compute_sat_recipients(&active_splits, remaining);
+
+        let over_cap: Vec<usize> = active
+            .iter()
+            .zip(&amounts)
+            .filter(|&(&index, &amount)| cap_at(index).is_some_and(|cap| amount > cap))
+            .map(|(&index, _)| index)
+            .collect();
+
+        if over_cap.is_empty() {
+            for (&index, amount) in active.iter().zip(amounts) {
+                sat_amounts[index] = amount;
+            }
+            break;
+        }
+
+        // Pin the recipients exceeding their caps and redistribute the rest.
+        for &index in &over_cap {
+            let cap = cap_at(index).unwrap_or_default();
+            sat_amounts[index] = cap;
+            remaining -= cap;
+            pinned[index] = true;
+        }
+        active.retain(|&index| !pinned[index]);
+    }
+
+    let distributed: u64 = sat_amounts.iter().sum();
+    SatAllocation {
+        undistributed: total_sats - distributed,
+        num_zero_recipients: sat_amounts.iter().filter(|&&sats| sats == 0).count(),
+        amounts: sat_amounts,
+    }
+}
+
+/// Similar to [compute_sat_recipients] but takes fractional weights (e.g., 2.5 shares).
+///
+/// Weights are scaled to integer splits with 53 bits of precision relative to the largest weight,
+/// so the at-least-one-sat and priority rules of [compute_sat_recipients] apply as usual. Any
+/// positive weight is treated as a non-zero split, however small.
+///
+/// ## Example
+/// ```rust
+/// use v4v::pc20::calc::compute_sat_recipients_weighted;
+///
+/// assert_eq!(compute_sat_recipients_weighted(&[2.5, 7.5], 100), Ok(vec![25, 75]));
+/// assert!(compute_sat_recipients_weighted(&[1.0, f64::NAN], 100).is_err());
+/// ```
+pub fn compute_sat_recipients_weighted(
+    weights: &[f64],
+    total_sats: u64,
+) -> Result<Vec<u64>, CalcError> {
+    if let Some(index) = weights.iter().position(|w| !w.is_finite() || *w < 0.0) {
+        return Err(CalcError::InvalidWeight { index });
+    }
+
+    // Largest weight is mapped to 2^53, the largest integer below which every integer is exactly
+    // representable as f64.
+    const SCALE: f64 = (1u64 << 53) as f64;
+
+    let max_weight = weights.iter().copied().fold(0.0, f64::max);
+    let splits: Vec<u64> = weights
+        .iter()
+        .map(|&weight| {
+            if weight == 0.0 {
+                0
+            } else {
+                ((weight / max_weight * SCALE).round() as u64).max(1)
+            }
+        })
+        .collect();
+
+    Ok(compute_sat_recipients(&splits, total_sats))
+}
+
+/// Converts a fiat amount (e.g., dollars) to sats given an exchange rate in sats per fiat unit.
+///
+/// The product is rounded to the nearest sat, with ties rounded to even (e.g., 2.5 sats becomes
+/// 2 and 3.5 becomes 4), so that rounding doesn't systematically favor the sender or the
+/// recipients. Negative and NaN results become 0, and results too large for `u64` saturate at
+/// `u64::MAX`.
+///
+/// Combine it with [compute_sat_recipients] to distribute a fiat tip.
+///
+/// ## Example
+/// ```rust
+/// use v4v::pc20::calc::{compute_sat_recipients, fiat_to_sats};
+///
+/// // $1 at 1,500 sats per dollar.
+/// let total_sats = fiat_to_sats(1.0, 1500.0);
+/// assert_eq!(total_sats, 1500);
+/// assert_eq!(compute_sat_recipients(&[90, 10], total_sats), vec![1350, 150]);
+/// ```
+pub fn fiat_to_sats(fiat_amount: f64, sats_per_unit: f64) -> u64 {
+    let sats = (fiat_amount * sats_per_unit).round_ties_even();
+    if sats.is_nan() || sats <= 0.0 {
+        return 0;
+    }
+    // Casting saturates at `u64::MAX`.
+    sats as u64
+}
+
+/// Same as [compute_sat_recipients], but returns `(index, sats)` pairs sorted by sats in
+/// decreasing order, e.g., for leaderboards.
+///
+/// `index` is the position of the recipient in `splits`. Recipients receiving the same number of
+/// sats keep their original order.
+///
+/// ## Example
+/// ```rust
+/// assert_eq!(
+///     v4v::pc20::calc::compute_sat_recipients_ranked(&[10, 60, 30], 100),
+///     vec![(1, 60), (2, 30), (0, 10)],
+/// );
+/// ```
+pub fn compute_sat_recipients_ranked(splits: &[u64], total_sats: u64) -> Vec<(usize, u64)> {
+    let mut ranked: Vec<(usize, u64)> = compute_sat_recipients(splits, total_sats)
+        .into_iter()
+        .enumerate()
+        .collect();
+    // Stable sort, so ties stay in input order.
+    ranked.sort_by_key(|&(_, sats)| std::cmp::Reverse(sats));
+    ranked
+}
+
+/// Why a recipient received the amount in a [RecipientOutcome].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutcomeReason {
+    /// The recipient receives at least one sat.
+    Paid,
+    /// There weren't enough sats for every recipient, and recipients with higher splits were
+    /// prioritized.
+    DroppedLowSplit,
+    /// There were no sats to distribute.
+    DroppedInsufficient,
+}
+
+/// Amount a recipient would receive and why, as returned by [preview_distribution].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecipientOutcome {
+    /// Number of sats the recipient would receive.
+    pub sats: u64,
+    /// Why the recipient would receive `sats`.
+    pub reason: OutcomeReason,
+}
+
+/// Same as [compute_sat_recipients], but also explains why recipients would receive no sats,
+/// e.g., to preview a small boost before sending it.
+///
+/// ## Example
+/// ```rust
+/// use v4v::pc20::calc::{preview_distribution, OutcomeReason, RecipientOutcome};
+///
+/// assert_eq!(
+///     preview_distribution(&[1, 99], 1),
+///     vec![
+///         RecipientOutcome { sats: 0, reason: OutcomeReason::DroppedLowSplit },
+///         RecipientOutcome { sats: 1, reason: OutcomeReason::Paid },
+///     ],
+/// );
+/// ```
+pub fn preview_distribution(splits: &[u64], total_sats: u64) -> Vec<RecipientOutcome> {
+    compute_sat_recipients(splits, total_sats)
+        .into_iter()
+        .map(|sats| RecipientOutcome {
+            sats,
+            reason: match sats {
+                0 if total_sats == 0 => OutcomeReason::DroppedInsufficient,
+                // Every recipient gets at least one sat when there are enough sats, so a recipient
+                // only misses out because of the priority rules.
+                0 => OutcomeReason::DroppedLowSplit,
+                _ => OutcomeReason::Paid,
+            },
+        })
+        .collect()
+}
+
+/// Computes the smallest total number of sats at which every recipient with a non-zero split
+/// earns at least one sat proportionally.
+///
+/// That is the smallest `total_sats` for which `split * total_sats / sum(splits)` is at least one
+/// for the smallest non-zero split, i.e., `ceil(sum(splits) / min_nonzero_split)`.
+/// [compute_sat_recipients] already tops up recipients to one sat whenever there are at least as
+/// many sats as recipients, but below this amount the top-ups come out of the larger recipients'
+/// shares. Useful as a "minimum boost" hint.
+///
+/// Returns 0 if there are no non-zero splits, and saturates at `u64::MAX`.
+///
+/// ## Example
+/// ```rust
+/// assert_eq!(v4v::pc20::calc::minimum_satisfying_amount(&[1, 99]), 100);
+/// assert_eq!(v4v::pc20::calc::minimum_satisfying_amount(&[50, 50]), 2);
+/// assert_eq!(v4v::pc20::calc::minimum_satisfying_amount(&[0, 0]), 0);
+/// ```
+pub fn minimum_satisfying_amount(splits: &[u64]) -> u64 {
+    let Some(min_split) = splits.iter().copied().filter(|&split| split > 0).min() else {
+        return 0;
+    };
+    let total_split: u128 = splits.iter().map(|&split| split as u128).sum();
+
+    total_split
+        .div_ceil(min_split as u128)
+        .min(u64::MAX as u128) as u64
+}
+
 /// Similar to [compute_sat_recipients] but allows to use it with any type that uses splits.
 pub fn compute_sat_recipients_generic<T: HasSplit + Clone>(
     values: &[T],
     total_sats: u64,
 ) -> Vec<u64> {
     let splits: Vec<u64> = values.iter().map(|v| v.get_split()).collect();
-    compute_sat_recipients(&splits, total_sats)
+    let sats = compute_sat_recipients(&splits, total_sats);
+    debug_assert_eq!(sats.len(), values.len());
+    sats
+}
+
+/// Recipient whose payout priority is determined by its tier.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TieredRecipient {
+    /// Split of the recipient.
+    pub split: u64,
+    /// Tier of the recipient. Lower tiers are paid first.
+    pub tier: u8,
+}
+
+/// Distributes sats to tiered recipients, satisfying lower tiers before any sats flow to higher
+/// ones.
+///
+/// A tier is considered satisfied once it has received its proportional share of `total_sats`
+/// (rounded up), but no less than one sat per recipient in the tier. Within a tier, sats are
+/// distributed with [compute_sat_recipients]. When there are enough sats for every tier, the
+/// result is the same as distributing proportionally; when there aren't, higher tiers receive
+/// whatever is left after the lower tiers.
+///
+/// ## Example
+/// ```rust
+/// use v4v::pc20::calc::{compute_tiered, TieredRecipient};
+///
+/// let recipients = vec![
+///     TieredRecipient { split: 10, tier: 0 },
+///     TieredRecipient { split: 10, tier: 0 },
+///     TieredRecipient { split: 80, tier: 1 },
+/// ];
+/// assert_eq!(compute_tiered(&recipients, 100), vec![10, 10, 80]);
+/// // There are only enough sats for the first tier:
+/// assert_eq!(compute_tiered(&recipients, 2), vec![1, 1, 0]);
+/// ```
+pub fn compute_tiered(recipients: &[TieredRecipient], total_sats: u64) -> Vec<u64> {
+    let mut result = vec![0; recipients.len()];
+
+    // If all splits are zero, every recipient is weighed equally.
+    let all_zero = recipients.iter().all(|r| r.split == 0);
+    let weight = |r: &TieredRecipient| if all_zero { 1 } else { r.split as u128 };
+    let total_weight: u128 = recipients.iter().map(weight).sum();
+
+    let mut tiers: Vec<u8> = recipients.iter().map(|r| r.tier).collect();
+    tiers.sort_unstable();
+    tiers.dedup();
+
+    let mut remaining_sats = total_sats;
+    for tier in tiers {
+        let indices: Vec<usize> = (0..recipients.len())
+            .filter(|&i| recipients[i].tier == tier)
+            .collect();
+
+        let tier_weight: u128 = indices.iter().map(|&i| weight(&recipients[i])).sum();
+        let proportional_sats = (total_sats as u128 * tier_weight).div_ceil(total_weight);
+        let entitled_sats = proportional_sats.max(indices.len() as u128);
+        let tier_sats = entitled_sats.min(remaining_sats as u128) as u64;
+        remaining_sats -= tier_sats;
+
+        let splits: Vec<u64> = indices.iter().map(|&i| recipients[i].split).collect();
+        for (&index, sats) in indices
+            .iter()
+            .zip(compute_sat_recipients(&splits, tier_sats))
+        {
+            result[index] = sats;
+        }
+    }
+
+    result
 }
 
 /// Represents a share- or percentage-based recipient.
@@ -195,8 +917,168 @@ fn gcd(a: u128, b: u128) -> u128 {
     }
 }
 
+/// Divides all splits by their greatest common divisor, e.g., to store or display splits from
+/// [fee_recipients_to_splits] in their smallest form.
+///
+/// Zeros stay zeros, and the ratios between the splits don't change.
+///
+/// ## Example
+/// ```rust
+/// assert_eq!(v4v::pc20::calc::normalize_splits(&[9900, 9900, 200]), vec![99, 99, 2]);
+/// assert_eq!(v4v::pc20::calc::normalize_splits(&[50, 0, 25]), vec![2, 0, 1]);
+/// ```
+pub fn normalize_splits(splits: &[u64]) -> Vec<u64> {
+    let gcd_value = splits
+        .iter()
+        .filter(|&&x| x != 0)
+        .fold(0u128, |acc, &x| gcd(acc, x as u128));
+
+    if gcd_value <= 1 {
+        return splits.to_vec();
+    }
+
+    splits
+        .iter()
+        .map(|&x| (x as u128 / gcd_value) as u64)
+        .collect()
+}
+
+/// Checks whether two lists of splits describe the same ratios.
+///
+/// Splits are compared position by position after dividing each list by its greatest common
+/// divisor, so they would result in the same payouts.
+///
+/// ## Example
+/// ```rust
+/// use v4v::pc20::calc::splits_equivalent;
+///
+/// assert!(splits_equivalent(&[2, 2], &[1, 1]));
+/// assert!(splits_equivalent(&[90, 10], &[9, 1]));
+/// assert!(!splits_equivalent(&[90, 10], &[1, 9]));
+/// ```
+pub fn splits_equivalent(a: &[u64], b: &[u64]) -> bool {
+    a.len() == b.len() && normalize_splits(a) == normalize_splits(b)
+}
+
+/// Checks whether two value blocks would result in the same payouts.
+///
+/// Each recipient is a `(key, split)` pair, where the key identifies the recipient (e.g., its
+/// node's public key, possibly together with custom data). Unlike [splits_equivalent], the
+/// order of recipients doesn't matter.
+///
+/// ## Example
+/// ```rust
+/// use v4v::pc20::calc::value_blocks_equivalent;
+///
+/// let old = vec![("02aaa", 50), ("03bbb", 50)];
+/// let new = vec![("03bbb", 1), ("02aaa", 1)];
+/// assert!(value_blocks_equivalent(&old, &new));
+///
+/// let new = vec![("03bbb", 1), ("02ccc", 1)];
+/// assert!(!value_blocks_equivalent(&old, &new));
+/// ```
+pub fn value_blocks_equivalent<K: Ord>(a: &[(K, u64)], b: &[(K, u64)]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+
+    fn normalize<K: Ord>(recipients: &[(K, u64)]) -> Vec<(&K, u64)> {
+        let splits: Vec<u64> = recipients.iter().map(|(_, split)| *split).collect();
+        let mut normalized: Vec<(&K, u64)> = recipients
+            .iter()
+            .map(|(key, _)| key)
+            .zip(normalize_splits(&splits))
+            .collect();
+        normalized.sort();
+        normalized
+    }
+
+    normalize(a) == normalize(b)
+}
+
+/// Per-recipient change in sats between two distributions, e.g., from [compute_sat_recipients]
+/// before and after a split change.
+///
+/// Distributions are compared position by position; a recipient missing from one of them is
+/// treated as receiving 0 sats. Deltas are clamped to the range of `i64`.
+///
+/// ## Example
+/// ```rust
+/// let before = vec![600, 400];
+/// let after = vec![500, 500];
+/// assert_eq!(v4v::pc20::calc::diff_distributions(&before, &after), vec![-100, 100]);
+/// ```
+pub fn diff_distributions(before: &[u64], after: &[u64]) -> Vec<i64> {
+    (0..before.len().max(after.len()))
+        .map(|i| {
+            let before = before.get(i).copied().unwrap_or(0) as i128;
+            let after = after.get(i).copied().unwrap_or(0) as i128;
+            (after - before).clamp(i64::MIN as i128, i64::MAX as i128) as i64
+        })
+        .collect()
+}
+
+/// Summary of the change between two distributions, as returned by [DistributionDiff::new].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DistributionDiff {
+    /// Per-recipient deltas, as returned by [diff_distributions].
+    pub deltas: Vec<i64>,
+    /// Sum of the sats gained by recipients receiving more.
+    pub sats_gained: u64,
+    /// Sum of the sats lost by recipients receiving less.
+    pub sats_lost: u64,
+}
+
+impl DistributionDiff {
+    /// Compares the distributions `before` and `after`.
+    ///
+    /// ## Example
+    /// ```rust
+    /// use v4v::pc20::calc::DistributionDiff;
+    ///
+    /// // The total grew by 100 sats, and 50 sats moved from the first recipient to the second.
+    /// let diff = DistributionDiff::new(&[600, 400], &[550, 550]);
+    /// assert_eq!(diff.deltas, vec![-50, 150]);
+    /// assert_eq!(diff.total_moved(), 50);
+    /// assert_eq!(diff.net_change(), 100);
+    /// ```
+    pub fn new(before: &[u64], after: &[u64]) -> Self {
+        let deltas = diff_distributions(before, after);
+        let sats_gained = deltas
+            .iter()
+            .filter(|&&delta| delta > 0)
+            .fold(0u64, |acc, &delta| acc.saturating_add(delta.unsigned_abs()));
+        let sats_lost = deltas
+            .iter()
+            .filter(|&&delta| delta < 0)
+            .fold(0u64, |acc, &delta| acc.saturating_add(delta.unsigned_abs()));
+
+        Self {
+            deltas,
+            sats_gained,
+            sats_lost,
+        }
+    }
+
+    /// Sats that moved from some recipients to others, i.e., the change not explained by a
+    /// different total.
+    pub fn total_moved(&self) -> u64 {
+        self.sats_gained.min(self.sats_lost)
+    }
+
+    /// Change of the total: positive if more sats are distributed after than before.
+    pub fn net_change(&self) -> i128 {
+        self.sats_gained as i128 - self.sats_lost as i128
+    }
+
+    /// Whether no recipient's amount changed.
+    pub fn is_unchanged(&self) -> bool {
+        self.deltas.iter().all(|&delta| delta == 0)
+    }
+}
+
 /// Represents an error when converting a list of share- or percentage-based recipients into a list of share-like splits.
-#[derive(PartialEq)]
+#[derive(Clone, Copy, PartialEq, Eq)]
 pub enum RecipientsToSplitsError {
     /// The total fee exceeds 100%.
     TotalFeeExceeds100,
@@ -221,6 +1103,8 @@ impl std::fmt::Debug for RecipientsToSplitsError {
     }
 }
 
+impl std::error::Error for RecipientsToSplitsError {}
+
 /// Converts a list of [generic recipients](GenericRecipient) into a list of share-like splits.
 ///
 /// Share-based recipients maintain the same ratios between themselves after percentage-based
@@ -315,6 +1199,85 @@ pub fn fee_recipients_to_splits(
     }
 }
 
+/// Effective percentage of the total each recipient ends up with after
+/// [fee_recipients_to_splits], e.g., to show "Host: 49.5%, Host: 49.5%, Platform fee: 1%" in a
+/// dashboard.
+///
+/// The percentages add up to 100 (up to floating-point error), unless the list is empty. Returns
+/// the same errors as [fee_recipients_to_splits]. If all share-based recipients have zero shares,
+/// they split the non-fee percentage equally; if only percentage-based recipients are present,
+/// they split 100% in proportion to their percentages.
+///
+/// ## Example
+/// ```rust
+/// use v4v::pc20::calc::{preview_percentages, GenericRecipient};
+///
+/// let recipients = vec![
+///     GenericRecipient::ShareBased { num_shares: 50 },
+///     GenericRecipient::ShareBased { num_shares: 50 },
+///     GenericRecipient::PercentageBased { percentage: 1 },
+/// ];
+/// assert_eq!(preview_percentages(&recipients), Ok(vec![49.5, 49.5, 1.0]));
+/// ```
+pub fn preview_percentages(
+    recipients: &[GenericRecipient],
+) -> Result<Vec<f64>, RecipientsToSplitsError> {
+    // Validates the recipients.
+    let splits = fee_recipients_to_splits(recipients)?;
+
+    let total_split: f64 = splits.iter().map(|&split| split as f64).sum();
+    if total_split > 0.0 {
+        return Ok(splits
+            .iter()
+            .map(|&split| split as f64 / total_split * 100.0)
+            .collect());
+    }
+
+    // Every split is zero: either there are no shares, or all the percentages are zero too.
+    let num_share_recipients = recipients
+        .iter()
+        .filter(|r| matches!(r, GenericRecipient::ShareBased { .. }))
+        .count();
+    let total_percentage: u64 = recipients
+        .iter()
+        .map(|r| match r {
+            GenericRecipient::PercentageBased { percentage } => *percentage,
+            GenericRecipient::ShareBased { .. } => 0,
+        })
+        .sum();
+
+    Ok(recipients
+        .iter()
+        .map(|r| match r {
+            _ if num_share_recipients == 0 => 100.0 / recipients.len() as f64,
+            GenericRecipient::ShareBased { .. } => {
+                (100 - total_percentage) as f64 / num_share_recipients as f64
+            }
+            GenericRecipient::PercentageBased { percentage } => *percentage as f64,
+        })
+        .collect())
+}
+
+/// Converts [generic recipients](GenericRecipient) into splits with [fee_recipients_to_splits] and
+/// distributes `total_sats` among them with [compute_sat_recipients].
+///
+/// ## Example
+/// ```rust
+/// let recipients = vec![
+///     v4v::pc20::calc::GenericRecipient::ShareBased { num_shares: 50 },
+///     v4v::pc20::calc::GenericRecipient::ShareBased { num_shares: 50 },
+///     v4v::pc20::calc::GenericRecipient::PercentageBased { percentage: 2 },
+/// ];
+/// assert_eq!(v4v::pc20::calc::recipients_to_sats(&recipients, 1000), Ok(vec![490, 490, 20]));
+/// ```
+pub fn recipients_to_sats(
+    recipients: &[GenericRecipient],
+    total_sats: u64,
+) -> Result<Vec<u64>, RecipientsToSplitsError> {
+    let splits = fee_recipients_to_splits(recipients)?;
+    Ok(compute_sat_recipients(&splits, total_sats))
+}
+
 /// Similar to [fee_recipients_to_splits] but allows to use it with any type that uses splits and
 /// implements `Into<crate::GenericRecipient>`.
 pub fn fee_recipients_to_splits_generic<T: Into<GenericRecipient> + HasSplit + Clone>(
@@ -325,19 +1288,16 @@ pub fn fee_recipients_to_splits_generic<T: Into<GenericRecipient> + HasSplit + C
 
     let splits = fee_recipients_to_splits(&generic_recipients)?;
 
-    let mut result = Vec::with_capacity(recipients.len());
-    for (recipient, &split) in recipients.iter().zip(splits.iter()) {
-        let mut recipient = (*recipient).clone();
-        recipient.set_split(split);
-        result.push(recipient);
-    }
-
-    Ok(result)
+    Ok(assign_splits(recipients, &splits)
+        .expect("fee_recipients_to_splits must return one split per recipient"))
 }
 
 /// Scales the splits such that `remote_splits` would constitute `remote_percentage` of the total,
 /// and `local_splits` would constitute the rest.
 ///
+/// If `remote_percentage` is 0, the local splits are only divided by their greatest common
+/// divisor, and every remote split is 0.
+///
 /// ## Example
 /// ```rust
 /// let local_splits = vec![50, 50];
@@ -357,6 +1317,12 @@ pub fn use_remote_splits(
 
     // Calculate total splits
     let total_local: u128 = local_splits.iter().map(|&x| x as u128).sum();
+
+    // Nothing goes to the remote recipients, so the local splits only need to be reduced.
+    if remote_percentage == 0 && total_local != 0 {
+        return (normalize_splits(local_splits), vec![0; remote_splits.len()]);
+    }
+
     let total_remote: u128 = remote_splits.iter().map(|&x| x as u128).sum();
 
     // If either total is 0, we need to handle this specially
@@ -426,6 +1392,139 @@ pub fn use_remote_splits(
     (final_local, final_remote)
 }
 
+/// Same as [use_remote_splits], but for several remote value blocks, e.g., referenced in
+/// different `<podcast:valueTimeSplit>` tags, each with its own remote percentage.
+///
+/// Each remote is `(remote_splits, remote_percentage)`. The remote percentages are applied in
+/// order and their total is capped at 100: once it is reached, the remaining remotes get
+/// nothing. The local splits get the rest. A group whose splits are all zero gets nothing, and
+/// its share goes to the other groups proportionally (if they all have a zero percentage, the
+/// splits are used as they are). All the resulting splits are divided by their greatest common
+/// divisor.
+///
+/// ## Example
+/// ```rust
+/// let local_splits = vec![50, 50];
+/// let remotes = vec![(vec![1], 30), (vec![1, 1], 20)];
+/// // Local recipients get 25% each, the first remote 30%, and the second remote 10% each.
+/// assert_eq!(
+///     v4v::pc20::calc::use_remote_splits_multi(&local_splits, &remotes),
+///     (vec![5, 5], vec![vec![6], vec![2, 2]]),
+/// );
+/// ```
+pub fn use_remote_splits_multi(
+    local_splits: &[u64],
+    remotes: &[(Vec<u64>, u64)],
+) -> (Vec<u64>, Vec<Vec<u64>>) {
+    let mut remaining_percentage = 100u64;
+    let mut groups: Vec<(&[u64], u128)> = Vec::with_capacity(remotes.len() + 1);
+    let mut remote_groups = Vec::with_capacity(remotes.len());
+    for (remote_splits, remote_percentage) in remotes {
+        let remote_percentage = (*remote_percentage).min(remaining_percentage);
+        remaining_percentage -= remote_percentage;
+        remote_groups.push((remote_splits.as_slice(), remote_percentage as u128));
+    }
+    groups.push((local_splits, remaining_percentage as u128));
+    groups.extend(remote_groups);
+
+    let group_total = |splits: &[u64]| splits.iter().map(|&x| x as u128).sum::<u128>();
+
+    // Scale every group to the least common multiple of the totals, so that a recipient's split
+    // is `percentage * split / total` of the common denominator.
+    let common_total = groups
+        .iter()
+        .map(|&(splits, _)| group_total(splits))
+        .filter(|&total| total != 0)
+        .try_fold(1u128, |acc, total| {
+            (acc / gcd(acc, total)).checked_mul(total)
+        });
+
+    let exact: Option<Vec<Vec<u128>>> = common_total.and_then(|common_total| {
+        groups
+            .iter()
+            .map(|&(splits, percentage)| {
+                let total = group_total(splits);
+                splits
+                    .iter()
+                    .map(|&split| match total {
+                        0 => Some(0),
+                        _ => (percentage * split as u128).checked_mul(common_total / total),
+                    })
+                    .collect()
+            })
+            .collect()
+    });
+
+    let mut scaled: Vec<Vec<u128>> = exact.unwrap_or_else(|| {
+        // The exact values don't fit, so approximate with 53 bits of precision, like
+        // compute_sat_recipients_weighted.
+        let shares: Vec<Vec<f64>> = groups
+            .iter()
+            .map(|&(splits, percentage)| {
+                let total = group_total(splits) as f64;
+                splits
+                    .iter()
+                    .map(|&split| match total {
+                        0.0 => 0.0,
+                        _ => percentage as f64 * split as f64 / total,
+                    })
+                    .collect()
+            })
+            .collect();
+        let max_share = shares.iter().flatten().copied().fold(0.0, f64::max);
+        shares
+            .into_iter()
+            .map(|group| {
+                group
+                    .into_iter()
+                    .map(|share| match max_share {
+                        0.0 => 0,
+                        _ => (share / max_share * (1u64 << 53) as f64).round() as u128,
+                    })
+                    .collect()
+            })
+            .collect()
+    });
+
+    // If only groups with a zero percentage have non-zero splits, ignore the percentages, like
+    // use_remote_splits does.
+    if scaled.iter().flatten().all(|&x| x == 0) {
+        scaled = groups
+            .iter()
+            .map(|&(splits, _)| splits.iter().map(|&split| split as u128).collect())
+            .collect();
+    }
+
+    let gcd_value = scaled
+        .iter()
+        .flatten()
+        .filter(|&&x| x != 0)
+        .fold(0u128, |acc, &x| gcd(acc, x))
+        .max(1);
+    let max_value = scaled.iter().flatten().copied().max().unwrap_or(0) / gcd_value;
+    let to_split = |x: u128| {
+        let x = x / gcd_value;
+        if max_value > u64::MAX as u128 {
+            (x as f64 * (u64::MAX as f64 / max_value as f64)).round() as u64
+        } else {
+            x as u64
+        }
+    };
+
+    let mut scaled = scaled.into_iter();
+    let local = scaled
+        .next()
+        .unwrap_or_default()
+        .into_iter()
+        .map(to_split)
+        .collect();
+    let remotes = scaled
+        .map(|group| group.into_iter().map(to_split).collect())
+        .collect();
+
+    (local, remotes)
+}
+
 /// Trait for types that have a split.
 pub trait HasSplit {
     /// Set split.
@@ -445,17 +1544,316 @@ pub fn use_remote_splits_generic<T: HasSplit + Clone>(
     let (new_local_splits, new_remote_splits) =
         use_remote_splits(&local_splits, &remote_splits, remote_percentage);
 
-    let mut result = Vec::with_capacity(local_values.len() + remote_values.len());
-    for (value, &split) in local_values.iter().zip(new_local_splits.iter()) {
-        let mut value = value.clone();
-        value.set_split(split);
-        result.push(value);
+    let mut result = assign_splits(local_values, &new_local_splits)
+        .expect("use_remote_splits must return one split per local value");
+    result.extend(
+        assign_splits(remote_values, &new_remote_splits)
+            .expect("use_remote_splits must return one split per remote value"),
+    );
+
+    result
+}
+
+/// Value block referenced in a `<podcast:valueTimeSplit>` tag, used by
+/// [recipients_at_timestamp].
+#[derive(Debug, Clone, PartialEq)]
+pub struct RemoteBlock<T> {
+    /// Recipients of the remote value block.
+    pub recipients: Vec<T>,
+    /// Percentage of the payment going to the remote recipients (the `remotePercentage`
+    /// attribute).
+    pub remote_percentage: u64,
+}
+
+/// Recipients in effect at a playback position, as returned by [recipients_at_timestamp].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResolvedBlock<T> {
+    /// Local recipients, with their splits scaled if a time split is active.
+    pub local: Vec<T>,
+    /// Remote recipients of the active time split, if any, with their splits scaled.
+    pub remote: Vec<T>,
+    /// Index of the active time split.
+    pub active_time_split: Option<usize>,
+}
+
+impl<T> ResolvedBlock<T> {
+    /// Local recipients followed by the remote ones, as returned by [use_remote_splits_generic].
+    pub fn into_recipients(self) -> Vec<T> {
+        let mut recipients = self.local;
+        recipients.extend(self.remote);
+        recipients
+    }
+}
+
+/// Resolves the recipients of a payment sent at playback position `timestamp`, given the local
+/// value block and the `<podcast:valueTimeSplit>` tags as `(start_time, duration, remote_block)`
+/// tuples.
+///
+/// A time split is active if `start_time <= timestamp < start_time + duration`. If several are,
+/// the first one is used. The splits of the local and remote recipients of the active time split
+/// are combined with [use_remote_splits]. If no time split is active, the local recipients are
+/// returned unchanged.
+///
+/// ## Example
+/// ```rust
+/// use chrono::Duration;
+/// use v4v::pc20::calc::{recipients_at_timestamp, RemoteBlock};
+///
+/// #[derive(Debug, Clone, PartialEq)]
+/// struct Recipient {
+///     name: &'static str,
+///     split: u64,
+/// }
+///
+/// impl v4v::pc20::calc::HasSplit for Recipient {
+///     fn get_split(&self) -> u64 {
+///         self.split
+///     }
+///
+///     fn set_split(&mut self, split: u64) {
+///         self.split = split;
+///     }
+/// }
+///
+/// let local = vec![Recipient { name: "host", split: 100 }];
+/// let time_splits = vec![(
+///     Duration::seconds(60),
+///     Duration::seconds(180),
+///     RemoteBlock {
+///         recipients: vec![Recipient { name: "musician", split: 100 }],
+///         remote_percentage: 90,
+///     },
+/// )];
+///
+/// let resolved = recipients_at_timestamp(&local, &time_splits, Duration::seconds(120));
+/// assert_eq!(resolved.active_time_split, Some(0));
+/// assert_eq!(resolved.local, vec![Recipient { name: "host", split: 1 }]);
+/// assert_eq!(resolved.remote, vec![Recipient { name: "musician", split: 9 }]);
+///
+/// let resolved = recipients_at_timestamp(&local, &time_splits, Duration::seconds(30));
+/// assert_eq!(resolved.active_time_split, None);
+/// assert_eq!(resolved.into_recipients(), local);
+/// ```
+pub fn recipients_at_timestamp<T: HasSplit + Clone>(
+    base_block: &[T],
+    time_splits: &[(chrono::Duration, chrono::Duration, RemoteBlock<T>)],
+    timestamp: chrono::Duration,
+) -> ResolvedBlock<T> {
+    let active = time_splits
+        .iter()
+        .enumerate()
+        .find(|(_, (start_time, duration, _))| {
+            // Intervals come from feed data, so one whose end overflows never matches.
+            start_time
+                .checked_add(duration)
+                .is_some_and(|end_time| *start_time <= timestamp && timestamp < end_time)
+        });
+
+    let Some((index, (_, _, remote_block))) = active else {
+        return ResolvedBlock {
+            local: base_block.to_vec(),
+            remote: vec![],
+            active_time_split: None,
+        };
+    };
+
+    let local_splits: Vec<u64> = base_block.iter().map(HasSplit::get_split).collect();
+    let remote_splits: Vec<u64> = remote_block
+        .recipients
+        .iter()
+        .map(HasSplit::get_split)
+        .collect();
+    let (new_local_splits, new_remote_splits) = use_remote_splits(
+        &local_splits,
+        &remote_splits,
+        remote_block.remote_percentage,
+    );
+
+    ResolvedBlock {
+        local: assign_splits(base_block, &new_local_splits)
+            .expect("use_remote_splits must return one split per local value"),
+        remote: assign_splits(&remote_block.recipients, &new_remote_splits)
+            .expect("use_remote_splits must return one split per remote value"),
+        active_time_split: Some(index),
+    }
+}
+
+/// Error returned when the number of splits doesn't match the number of values they are assigned
+/// to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LengthMismatchError {
+    /// Number of values.
+    pub num_values: usize,
+    /// Number of splits.
+    pub num_splits: usize,
+}
+
+impl std::fmt::Display for LengthMismatchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Got {} splits for {} values",
+            self.num_splits, self.num_values
+        )
     }
-    for (value, &split) in remote_values.iter().zip(new_remote_splits.iter()) {
-        let mut value = value.clone();
-        value.set_split(split);
-        result.push(value);
+}
+
+impl std::error::Error for LengthMismatchError {}
+
+/// Returns copies of `values` with their splits replaced by `splits`.
+///
+/// Unlike zipping the two lists, fails instead of silently dropping values at the tail if the
+/// lengths differ.
+pub fn assign_splits<T: HasSplit + Clone>(
+    values: &[T],
+    splits: &[u64],
+) -> Result<Vec<T>, LengthMismatchError> {
+    if values.len() != splits.len() {
+        return Err(LengthMismatchError {
+            num_values: values.len(),
+            num_splits: splits.len(),
+        });
     }
 
-    result
+    Ok(values
+        .iter()
+        .zip(splits)
+        .map(|(value, &split)| {
+            let mut value = value.clone();
+            value.set_split(split);
+            value
+        })
+        .collect())
+}
+
+/// Asserts that a distribution of sats upholds the invariants of [compute_sat_recipients].
+///
+/// Panics if the amounts don't add up to `total_sats`, or if some recipient got no sats even
+/// though there were enough sats to give every recipient at least one.
+///
+/// Intended for tests; available with the `testing` feature.
+///
+/// ## Example
+/// ```rust
+/// let result = v4v::pc20::calc::compute_sat_recipients(&[1, 99], 10);
+/// v4v::pc20::calc::assert_distribution_sums(&result, 10);
+/// ```
+#[cfg(feature = "testing")]
+#[track_caller]
+pub fn assert_distribution_sums(result: &[u64], total_sats: u64) {
+    let actual: u128 = result.iter().map(|&amount| amount as u128).sum();
+    assert!(
+        actual == total_sats as u128,
+        "distribution doesn't add up: expected sum {}, actual sum {} (amounts: {:?})",
+        total_sats,
+        actual,
+        result,
+    );
+
+    if total_sats >= result.len() as u64 {
+        if let Some(index) = result.iter().position(|&amount| amount == 0) {
+            panic!(
+                "recipient {} got no sats even though {} sats are enough for all {} recipients (amounts: {:?})",
+                index,
+                total_sats,
+                result.len(),
+                result,
+            );
+        }
+    }
+}
+
+/// How [splits_to_percentages] rounds percentages to whole numbers.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum RoundingMode {
+    /// Each percentage is rounded to the nearest whole number (halves are rounded up).
+    Nearest,
+    /// Each percentage is rounded up, never ending up below the exact percentage rounded down.
+    Up,
+    /// Each percentage is rounded down, never ending up above the exact percentage rounded up.
+    Down,
+    /// Percentages are rounded down and the remaining points go to the recipients with the
+    /// largest remainders ([Hamilton's method](https://en.wikipedia.org/wiki/Largest_remainder_method)).
+    #[default]
+    LargestRemainder,
+}
+
+/// Converts splits into whole percentages that add up to 100.
+///
+/// With [RoundingMode::Nearest], [RoundingMode::Up] and [RoundingMode::Down], every percentage
+/// is rounded independently, and then corrected one point per recipient until they add up to
+/// 100: excess points are taken back from the recipients with the smallest remainders, and
+/// missing points go to those with the largest remainders. Either way, every percentage stays
+/// between the exact percentage rounded down and rounded up. If all splits are zero, every
+/// recipient is weighed equally. An empty list results in an empty list.
+///
+/// ## Example
+/// ```rust
+/// use v4v::pc20::calc::{splits_to_percentages, RoundingMode};
+///
+/// let splits = vec![1, 1, 1];
+/// assert_eq!(splits_to_percentages(&splits, RoundingMode::LargestRemainder), vec![34, 33, 33]);
+/// assert_eq!(splits_to_percentages(&splits, RoundingMode::Up), vec![34, 33, 33]);
+/// assert_eq!(splits_to_percentages(&splits, RoundingMode::Down), vec![34, 33, 33]);
+/// ```
+pub fn splits_to_percentages(splits: &[u64], rounding_mode: RoundingMode) -> Vec<u64> {
+    if splits.is_empty() {
+        return vec![];
+    }
+
+    let all_zero = splits.iter().all(|&split| split == 0);
+    let weights: Vec<u128> = splits
+        .iter()
+        .map(|&split| if all_zero { 1 } else { split as u128 })
+        .collect();
+    let total_weight: u128 = weights.iter().sum();
+
+    // Indices by decreasing weight, then by increasing index.
+    let mut by_weight: Vec<usize> = (0..weights.len()).collect();
+    by_weight.sort_by(|&a, &b| weights[b].cmp(&weights[a]).then(a.cmp(&b)));
+
+    let mut percentages: Vec<u128> = weights
+        .iter()
+        .map(|&weight| {
+            let numerator = weight * 100;
+            match rounding_mode {
+                RoundingMode::Nearest => (2 * numerator + total_weight) / (2 * total_weight),
+                RoundingMode::Up => numerator.div_ceil(total_weight),
+                RoundingMode::Down | RoundingMode::LargestRemainder => numerator / total_weight,
+            }
+        })
+        .collect();
+
+    let sum: u128 = percentages.iter().sum();
+    let floor = |i: usize| weights[i] * 100 / total_weight;
+    let remainder = |i: usize| weights[i] * 100 % total_weight;
+
+    if sum < 100 {
+        // Missing points go to the rounded-down recipients with the largest remainders, ties going
+        // to larger splits.
+        let mut by_remainder: Vec<usize> = by_weight
+            .iter()
+            .copied()
+            .filter(|&i| percentages[i] == floor(i) && remainder(i) > 0)
+            .collect();
+        by_remainder.sort_by_key(|&i| std::cmp::Reverse(remainder(i)));
+        for &index in by_remainder.iter().take((100 - sum) as usize) {
+            percentages[index] += 1;
+        }
+    } else if sum > 100 {
+        // Excess points are taken back from the rounded-up recipients with the smallest
+        // remainders, ties going to smaller splits.
+        let mut by_remainder: Vec<usize> = by_weight
+            .iter()
+            .rev()
+            .copied()
+            .filter(|&i| percentages[i] > floor(i))
+            .collect();
+        by_remainder.sort_by_key(|&i| remainder(i));
+        for &index in by_remainder.iter().take((sum - 100) as usize) {
+            percentages[index] -= 1;
+        }
+    }
+
+    percentages.into_iter().map(|p| p as u64).collect()
 }
@@ -1,16 +1,14 @@
 pub use super::payments::MakePaymentArgs as ForwardPaymentArgs;
-use super::payments::{make_payment, PaymentInfo, PaymentRecipientInfo};
+use super::payments::{make_payment, make_payment_with, PaymentInfo, PaymentRecipientInfo};
+use super::provider::{AlbyProvider, InvoiceRequest, PaymentProvider};
 use crate::alby::{
-    api::{
-        invoices::{CreateInvoiceArgs as AlbyCreateInvoiceArgs, CreateInvoiceResponse},
-        RequestError,
-    },
+    api::{invoices::CreateInvoiceResponse, RequestError, RetryPolicy, Timeouts},
     webhooks::AlbyInvoice,
 };
 
 /// Arguments for creating an invoice for forwarding payments to multiple Podcasting 2.0
 /// recipients.
-#[derive(Debug, serde::Serialize, serde::Deserialize)]
+#[derive(Debug, Default, serde::Serialize, serde::Deserialize)]
 pub struct CreateInvoiceArgs<'a> {
     /// User agent.
     pub user_agent: &'a str,
@@ -20,6 +18,29 @@ pub struct CreateInvoiceArgs<'a> {
     pub payment_info: PaymentInfo,
     /// Recipients' information.
     pub recipients: Vec<PaymentRecipientInfo>,
+    /// Maximum number of sats the invoice may be for,
+    /// [DEFAULT_MAX_SATS](crate::pc20::payments::DEFAULT_MAX_SATS) if not set.
+    ///
+    /// Never deserialized, so that untrusted input can't raise the cap.
+    #[serde(skip)]
+    pub max_sats: Option<u64>,
+    /// Description included in the BOLT11 invoice, [PaymentInfo::message] if not set.
+    ///
+    /// The message is sent to the recipients in the bLIP-10 records either way.
+    #[serde(default)]
+    pub invoice_memo: Option<String>,
+    /// Base URL of the Alby API (e.g., of a mock server),
+    /// [DEFAULT_BASE_URL](crate::alby::api::DEFAULT_BASE_URL) if not set.
+    ///
+    /// Never deserialized, since it decides where the Alby token is sent.
+    #[serde(skip)]
+    pub base_url: Option<&'a str>,
+    /// Timeouts of the Alby request.
+    #[serde(skip)]
+    pub timeouts: Timeouts,
+    /// Policy for retrying the Alby request. Not retried if `None`.
+    #[serde(skip)]
+    pub retry: Option<RetryPolicy>,
 }
 
 /// Metadata fields for sat forwarding service using Alby API.
@@ -49,15 +70,50 @@ impl TryFrom<AlbyInvoice> for CreateInvoiceMetadata {
 }
 
 /// Creates an invoice for forwarding payments to multiple Podcasting 2.0 recipients.
+///
+/// The invoice amount is the sum of the recipients' sats. If there are no recipients, or they
+/// would all receive 0 sats, no invoice is created and [RequestError::InvalidArgs] is returned.
 pub async fn create_invoice(
     args: CreateInvoiceArgs<'_>,
 ) -> Result<CreateInvoiceResponse, RequestError> {
+    let provider = AlbyProvider {
+        user_agent: args.user_agent,
+        token: args.token,
+        base_url: args.base_url,
+        rate_limiter: None,
+        timeouts: args.timeouts,
+        retry: args.retry,
+    };
+
+    create_invoice_with(&provider, args).await
+}
+
+/// Like [create_invoice], but creates the invoice through `provider` instead of Alby.
+///
+/// The Alby-specific fields of [CreateInvoiceArgs] (`user_agent`, `token`, `base_url`,
+/// `timeouts`, and `retry`) are ignored; configure the provider instead.
+pub async fn create_invoice_with<P: PaymentProvider>(
+    provider: &P,
+    args: CreateInvoiceArgs<'_>,
+) -> Result<CreateInvoiceResponse, RequestError> {
+    let invoice = build_invoice_args(args)?;
+
+    provider.create_invoice(invoice).await
+}
+
+/// Builds the invoice request for [create_invoice].
+fn build_invoice_args(args: CreateInvoiceArgs<'_>) -> Result<InvoiceRequest, RequestError> {
     // `total_num_millisats` might be different from the sum of `num_sats` in `recipients` because
     // of how it may be calculated on the front end.
-    let total_sats = args
-        .recipients
-        .iter()
-        .fold(0, |acc, recipient| acc + recipient.num_sats);
+    let total_sats = args.recipients.iter().fold(0u64, |acc, recipient| {
+        acc.saturating_add(recipient.num_sats)
+    });
+    if total_sats == 0 {
+        return Err(RequestError::InvalidArgs(
+            "cannot create an invoice for 0 sats".to_string(),
+        ));
+    }
+    crate::pc20::payments::check_max_sats(total_sats, args.max_sats)?;
 
     let metadata_value = serde_json::json!(CreateInvoiceMetadata {
         forwarding_data: CreateInvoiceMetadataForwardingStruct {
@@ -66,28 +122,105 @@ pub async fn create_invoice(
         },
     });
 
-    let invoice_args = AlbyCreateInvoiceArgs {
-        user_agent: args.user_agent,
-        token: args.token,
+    Ok(InvoiceRequest {
         num_sats: total_sats,
         metadata: metadata_value,
-        description: args.payment_info.message.clone(),
+        description: args
+            .invoice_memo
+            .or_else(|| args.payment_info.message.clone()),
         payer_name: args.payment_info.sender_name.clone(),
-    };
-
-    crate::alby::api::invoices::create_invoice(invoice_args).await
+    })
 }
 
 /// Forwards payments to multiple Podcasting 2.0 recipients.
+///
+/// ## Cancellation
+///
+/// All keysends are sent in a single request to Alby. With [retry](ForwardPaymentArgs::retry) set to
+/// `None` (the default), that request is sent at most once, so dropping the future (e.g., because
+/// a webhook handler timed out, or when racing it against a cancellation signal with
+/// `tokio::select!`) never sends anything twice:
+///
+/// - If the future is dropped before the request has been sent, no keysends are sent.
+/// - If it is dropped after the request has been sent, Alby processes the keysends as usual, but
+///   the result is not observed.
+///
+/// The second case is indistinguishable from a timeout on the caller's side. Before retrying
+/// such a payout (e.g., with [FailedPayout]), check the outgoing payments in the Alby account,
+/// or set [boost_id](PaymentInfo::boost_id) so that the duplicates can be recognized by the
+/// recipients.
+///
+/// These guarantees don't hold with `retry` set: every attempt may have been processed by Alby,
+/// so the keysends can go out more than once, and dropping the future between attempts leaves
+/// any number of earlier attempts unobserved. See
+/// [Paying twice](RetryPolicy#paying-twice).
+///
+/// With [resolve_lightning_addresses](ForwardPaymentArgs::resolve_lightning_addresses) set,
+/// lightning addresses are resolved one after the other before the keysend request, and dropping
+/// the future then sends nothing. Recipients that don't support keysend are paid after the keysend
+/// request, one after the other, each with a few more requests (fetching, checking and paying the
+/// invoice). Dropping the future during these leaves the keysends sent and any of these invoices
+/// paid or not, unobserved.
 pub async fn forward_payments(args: ForwardPaymentArgs<'_>) -> Result<(), RequestError> {
-    make_payment(ForwardPaymentArgs {
-        user_agent: args.user_agent,
-        token: args.token,
-        payment_info: args.payment_info.clone(),
-        recipients: args.recipients.clone(),
-    })
-    .await
-    .map(|_| ())
+    make_payment(args).await.map(|_| ())
+}
+
+/// Like [forward_payments], but sends the keysends through `provider` instead of Alby; see
+/// [make_payment_with].
+pub async fn forward_payments_with<P: PaymentProvider>(
+    provider: &P,
+    args: ForwardPaymentArgs<'_>,
+) -> Result<(), RequestError> {
+    make_payment_with(provider, args).await.map(|_| ())
+}
+
+/// Payout that failed to be forwarded.
+///
+/// Can be persisted (e.g., in a database or a queue) and retried later by reconstructing
+/// [ForwardPaymentArgs] with [FailedPayout::to_forward_payment_args].
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone)]
+pub struct FailedPayout {
+    /// Payment information.
+    pub payment_info: PaymentInfo,
+    /// Recipients' information.
+    pub recipients: Vec<PaymentRecipientInfo>,
+    /// Reason of the most recent failure.
+    pub reason: String,
+    /// Number of attempts made so far.
+    pub num_attempts: u32,
+}
+
+impl FailedPayout {
+    /// Records the first failed attempt to forward a payout.
+    pub fn new(args: &ForwardPaymentArgs<'_>, error: &RequestError) -> Self {
+        Self {
+            payment_info: args.payment_info.clone(),
+            recipients: args.recipients.clone(),
+            reason: error.to_string(),
+            num_attempts: 1,
+        }
+    }
+
+    /// Records another failed attempt.
+    pub fn record_attempt(&mut self, error: &RequestError) {
+        self.reason = error.to_string();
+        self.num_attempts += 1;
+    }
+
+    /// Reconstructs the arguments needed to retry forwarding.
+    pub fn to_forward_payment_args<'a>(
+        &self,
+        user_agent: &'a str,
+        token: &'a str,
+    ) -> ForwardPaymentArgs<'a> {
+        ForwardPaymentArgs {
+            user_agent,
+            token,
+            payment_info: self.payment_info.clone(),
+            recipients: self.recipients.clone(),
+            ..Default::default()
+        }
+    }
 }
 
 /// Ensure forwarded amounts do not exceed a specified aomunt.
@@ -111,3 +244,160 @@ pub fn clip_recipients_at_amount(
 
     clipped_recipients
 }
+
+/// Comparison between the amount invoiced for forwarding and the amount the invoice settled for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SettlementReport {
+    /// Sum of the recipients' sats stored in the invoice metadata.
+    pub invoiced_sats: u64,
+    /// Sats actually received.
+    pub settled_sats: u64,
+}
+
+impl SettlementReport {
+    /// Compares the recipients' sats in the metadata with the settled amount of the invoice.
+    pub fn new(metadata: &CreateInvoiceMetadata, invoice: &AlbyInvoice) -> Self {
+        Self {
+            invoiced_sats: metadata
+                .forwarding_data
+                .recipients
+                .iter()
+                .map(|recipient| recipient.num_sats)
+                .sum(),
+            settled_sats: invoice.num_sats,
+        }
+    }
+
+    /// Settled minus invoiced sats: positive for overpayments, negative for underpayments.
+    pub fn delta(&self) -> i128 {
+        self.settled_sats as i128 - self.invoiced_sats as i128
+    }
+
+    /// Whether the invoice settled for more than invoiced.
+    pub fn is_overpaid(&self) -> bool {
+        self.settled_sats > self.invoiced_sats
+    }
+
+    /// Whether the invoice settled for less than invoiced.
+    pub fn is_underpaid(&self) -> bool {
+        self.settled_sats < self.invoiced_sats
+    }
+}
+
+/// Distributes `total_sats` among the recipients proportionally to their current amounts.
+///
+/// Can be used to pass an overpayment on to the recipients (or to scale down an underpayment)
+/// instead of [clipping](clip_recipients_at_amount) the recipients. Sats are distributed with
+/// [compute_sat_recipients](crate::pc20::calc::compute_sat_recipients).
+///
+/// ## Example
+/// ```rust
+/// use v4v::pc20::payments::PaymentRecipientInfo;
+///
+/// let recipients = vec![
+///     PaymentRecipientInfo { num_sats: 60, ..Default::default() },
+///     PaymentRecipientInfo { num_sats: 40, ..Default::default() },
+/// ];
+/// // The invoice settled for 110 sats instead of 100:
+/// let rescaled = v4v::pc20::forwarding::rescale_recipients_to_amount(110, &recipients);
+/// assert_eq!(rescaled[0].num_sats, 66);
+/// assert_eq!(rescaled[1].num_sats, 44);
+/// ```
+pub fn rescale_recipients_to_amount(
+    total_sats: u64,
+    recipients: &[PaymentRecipientInfo],
+) -> Vec<PaymentRecipientInfo> {
+    let splits: Vec<u64> = recipients
+        .iter()
+        .map(|recipient| recipient.num_sats)
+        .collect();
+
+    recipients
+        .iter()
+        .zip(crate::pc20::calc::compute_sat_recipients(
+            &splits, total_sats,
+        ))
+        .map(|(recipient, num_sats)| PaymentRecipientInfo {
+            num_sats,
+            ..recipient.clone()
+        })
+        .collect()
+}
+
+/// Arguments for relaying a received keysend payment onward to the recipients of a value block.
+///
+/// `onward_recipients`' [num_sats](PaymentRecipientInfo::num_sats) are their splits, and the
+/// sats actually received (`received_sats`) are distributed among them with
+/// [rescale_recipients_to_amount]. The received amount may differ from the boost's stated total,
+/// e.g., after fees upstream.
+///
+/// `payment_info` should describe the received boost. It is sent on as is, so the
+/// [boost_id](PaymentInfo::boost_id), the message and the stated
+/// [total_num_sats](PaymentInfo::total_num_sats) are preserved in the onward bLIP-10 records.
+///
+/// ## Example
+/// ```rust
+/// use v4v::pc20::payments::{PaymentInfo, PaymentRecipientInfo};
+///
+/// let payment_info = PaymentInfo { total_num_sats: 100, ..Default::default() };
+/// let onward_recipients = vec![
+///     PaymentRecipientInfo { num_sats: 90, ..Default::default() },
+///     PaymentRecipientInfo { num_sats: 10, ..Default::default() },
+/// ];
+/// // Only 50 of the stated 100 sats arrived:
+/// let args = v4v::pc20::forwarding::relay_keysend_args(
+///     "MyApp/1.0", "token", &payment_info, 50, &onward_recipients,
+/// );
+/// assert_eq!(args.recipients[0].num_sats, 45);
+/// assert_eq!(args.recipients[1].num_sats, 5);
+/// assert_eq!(args.payment_info.total_num_sats, 100);
+/// ```
+pub fn relay_keysend_args<'a>(
+    user_agent: &'a str,
+    token: &'a str,
+    payment_info: &PaymentInfo,
+    received_sats: u64,
+    onward_recipients: &[PaymentRecipientInfo],
+) -> ForwardPaymentArgs<'a> {
+    ForwardPaymentArgs {
+        user_agent,
+        token,
+        payment_info: payment_info.clone(),
+        recipients: rescale_recipients_to_amount(received_sats, onward_recipients),
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn build_invoice_args_memo_and_message() {
+        let args = |invoice_memo: Option<&str>| CreateInvoiceArgs {
+            payment_info: PaymentInfo {
+                message: Some("Great episode!".to_string()),
+                ..Default::default()
+            },
+            recipients: vec![PaymentRecipientInfo {
+                num_sats: 10,
+                ..Default::default()
+            }],
+            invoice_memo: invoice_memo.map(str::to_string),
+            ..Default::default()
+        };
+
+        let invoice_args = build_invoice_args(args(Some("Boost for My Podcast"))).unwrap();
+        assert_eq!(
+            invoice_args.description.as_deref(),
+            Some("Boost for My Podcast")
+        );
+        assert_eq!(
+            invoice_args.metadata["forwarding_data"]["payment_info"]["message"],
+            "Great episode!"
+        );
+
+        let invoice_args = build_invoice_args(args(None)).unwrap();
+        assert_eq!(invoice_args.description.as_deref(), Some("Great episode!"));
+    }
+}
@@ -0,0 +1,545 @@
+use crate::alby::{
+    api::{payments::PayInvoiceResponse, RateLimiter, RequestError, RetryPolicy, Timeouts},
+    types::{InvalidPubkeyError, KeysendAddress},
+};
+
+use super::provider::{AlbyProvider, PaymentProvider};
+
+/// Error returned when a lightning address can't be resolved into a keysend address.
+#[derive(Debug)]
+pub enum ResolveError {
+    /// The address is not of the form `name@domain`.
+    InvalidAddress(String),
+    /// Failed to send the request or read the response.
+    Request(reqwest::Error),
+    /// Unexpected status code.
+    UnexpectedStatus {
+        /// Status code.
+        status: reqwest::StatusCode,
+        /// Response body.
+        body: String,
+    },
+    /// Failed to parse the response body.
+    InvalidResponse(serde_json::Error),
+    /// The address doesn't support keysend payments.
+    NotKeysend(String),
+    /// The advertised node public key is invalid.
+    InvalidPubkey(InvalidPubkeyError),
+}
+
+impl std::fmt::Display for ResolveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ResolveError::InvalidAddress(address) => {
+                write!(f, "Invalid lightning address: {}", address)
+            }
+            ResolveError::Request(e) => write!(f, "Failed to send request: {}", e),
+            ResolveError::UnexpectedStatus { status, body } => {
+                write!(f, "Unexpected status code: {}. Body: {}", status, body)
+            }
+            ResolveError::InvalidResponse(e) => write!(f, "Failed to parse response body: {}", e),
+            ResolveError::NotKeysend(reason) => {
+                write!(f, "Lightning address doesn't support keysend: {}", reason)
+            }
+            ResolveError::InvalidPubkey(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for ResolveError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ResolveError::Request(e) => Some(e),
+            ResolveError::InvalidResponse(e) => Some(e),
+            ResolveError::InvalidPubkey(e) => Some(e),
+            ResolveError::InvalidAddress(_)
+            | ResolveError::UnexpectedStatus { .. }
+            | ResolveError::NotKeysend(_) => None,
+        }
+    }
+}
+
+/// Response of the `.well-known/keysend` endpoint.
+#[derive(Debug, serde::Deserialize)]
+struct KeysendResponse {
+    #[serde(default)]
+    status: Option<String>,
+    #[serde(default)]
+    reason: Option<String>,
+    #[serde(default)]
+    tag: Option<String>,
+    #[serde(default)]
+    pubkey: Option<String>,
+    #[serde(rename = "customData", default)]
+    custom_data: Vec<KeysendCustomData>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct KeysendCustomData {
+    #[serde(rename = "customKey")]
+    custom_key: String,
+    #[serde(rename = "customValue")]
+    custom_value: String,
+}
+
+/// Splits a lightning address into its name and domain.
+fn split_address(address: &str) -> Result<(&str, &str), ResolveError> {
+    let invalid = || ResolveError::InvalidAddress(address.to_string());
+
+    let (name, domain) = address.trim().split_once('@').ok_or_else(invalid)?;
+    let is_valid_name = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_.+".contains(c));
+    let is_valid_domain = !domain.is_empty()
+        && domain
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-.:".contains(c));
+    if !is_valid_name || !is_valid_domain {
+        return Err(invalid());
+    }
+
+    Ok((name, domain))
+}
+
+/// Resolves a lightning address (`name@domain`), e.g., a bLIP-10
+/// [reply_address](crate::pc20::tlv::Record::reply_address) containing "@", into the keysend
+/// address of the node and wallet behind it.
+///
+/// The keysend parameters are fetched from `https://domain/.well-known/keysend/name`, which
+/// wallets supporting keysend to lightning addresses (e.g., Alby) serve. Fails with
+/// [ResolveError::NotKeysend] if the wallet doesn't advertise keysend support.
+pub async fn resolve_lightning_address(
+    user_agent: &str,
+    address: &str,
+) -> Result<KeysendAddress, ResolveError> {
+    let (_, domain) = split_address(address)?;
+    resolve_lightning_address_at(user_agent, address, &format!("https://{}", domain)).await
+}
+
+/// Like [resolve_lightning_address], but fetches the keysend parameters from `base_url` instead
+/// of the address's domain, e.g., from a mock server or a gateway.
+pub async fn resolve_lightning_address_at(
+    user_agent: &str,
+    address: &str,
+    base_url: &str,
+) -> Result<KeysendAddress, ResolveError> {
+    let (name, _) = split_address(address)?;
+    let url = format!(
+        "{}/.well-known/keysend/{}",
+        base_url.trim_end_matches('/'),
+        name
+    );
+
+    let timeouts = Timeouts::default();
+    let client = reqwest::Client::builder()
+        .user_agent(user_agent)
+        .connect_timeout(timeouts.connect_timeout)
+        .timeout(timeouts.timeout)
+        .build()
+        .map_err(ResolveError::Request)?;
+
+    let response = client
+        .get(&url)
+        .send()
+        .await
+        .map_err(ResolveError::Request)?;
+    let status = response.status();
+    let body = response.text().await.map_err(ResolveError::Request)?;
+    if !status.is_success() {
+        return Err(ResolveError::UnexpectedStatus { status, body });
+    }
+
+    let response: KeysendResponse =
+        serde_json::from_str(&body).map_err(ResolveError::InvalidResponse)?;
+
+    if response
+        .status
+        .as_deref()
+        .is_some_and(|status| status.eq_ignore_ascii_case("ERROR"))
+    {
+        return Err(ResolveError::NotKeysend(
+            response
+                .reason
+                .unwrap_or_else(|| "unknown error".to_string()),
+        ));
+    }
+    if response.tag.as_deref() != Some("keysend") {
+        return Err(ResolveError::NotKeysend(format!(
+            "unexpected tag {:?}",
+            response.tag
+        )));
+    }
+    let pubkey = response
+        .pubkey
+        .ok_or_else(|| ResolveError::NotKeysend("no node public key".to_string()))?;
+
+    // A keysend address carries a single custom record.
+    match response.custom_data.first() {
+        Some(custom_data) => {
+            KeysendAddress::with_custom(&pubkey, &custom_data.custom_key, &custom_data.custom_value)
+        }
+        None => KeysendAddress::new(&pubkey),
+    }
+    .map_err(ResolveError::InvalidPubkey)
+}
+
+/// Arguments for [pay_via_lnurl].
+pub struct LnurlPayArgs<'a> {
+    /// User agent string.
+    pub user_agent: &'a str,
+    /// Bearer token for authentication with Alby. Never sent to the LNURL service.
+    pub token: &'a str,
+    /// Base URL of the Alby API (e.g., of a mock server),
+    /// [DEFAULT_BASE_URL](crate::alby::api::DEFAULT_BASE_URL) if `None`.
+    pub base_url: Option<&'a str>,
+    /// Recipient: a bech32-encoded LNURL (`lnurl1...`), an `lnurlp://` or `https://` URL, or a
+    /// lightning address (`name@domain`). Plain `http` URLs are only accepted for `.onion` hosts,
+    /// as required by LUD-01.
+    pub lnurl: &'a str,
+    /// Whether to accept a plain `http` URL for any host, e.g., for a local mock server.
+    pub allow_insecure_http: bool,
+    /// The amount of sats.
+    pub num_sats: u64,
+    /// Comment for the recipient, e.g., the boostagram message. Truncated to the length the
+    /// service allows, and dropped if it doesn't accept comments.
+    pub comment: Option<&'a str>,
+    /// Optional client-side rate limiter for paying the invoice. Off if `None`.
+    pub rate_limiter: Option<&'a RateLimiter>,
+    /// Timeouts of each request.
+    pub timeouts: Timeouts,
+    /// Policy for retrying failed Alby requests. Not retried if `None`.
+    pub retry: Option<RetryPolicy>,
+}
+
+/// Pay request of an LNURL-pay service (LUD-06).
+#[derive(Debug, serde::Deserialize)]
+struct PayRequestResponse {
+    #[serde(default)]
+    status: Option<String>,
+    #[serde(default)]
+    reason: Option<String>,
+    #[serde(default)]
+    tag: Option<String>,
+    #[serde(default)]
+    callback: Option<String>,
+    #[serde(rename = "minSendable", default)]
+    min_sendable_msat: u64,
+    #[serde(rename = "maxSendable", default)]
+    max_sendable_msat: u64,
+    /// Metadata the invoice's description hash has to commit to.
+    #[serde(default)]
+    metadata: Option<String>,
+    /// Maximum comment length (LUD-12), comments aren't accepted if 0.
+    #[serde(rename = "commentAllowed", default)]
+    comment_allowed: usize,
+}
+
+/// BOLT11 invoice fetched from an LNURL-pay service.
+pub(crate) struct LnurlInvoice {
+    /// The invoice.
+    pub(crate) invoice: String,
+    /// Hex-encoded sha256 hash of the pay request's metadata, which the invoice's description
+    /// hash has to equal (LUD-06).
+    pub(crate) description_hash: String,
+}
+
+/// Response of an LNURL-pay callback.
+#[derive(Debug, serde::Deserialize)]
+struct CallbackResponse {
+    #[serde(default)]
+    status: Option<String>,
+    #[serde(default)]
+    reason: Option<String>,
+    #[serde(default)]
+    pr: Option<String>,
+}
+
+/// Pays a recipient that doesn't accept keysend payments through LNURL-pay: fetches a BOLT11
+/// invoice for `num_sats` from the LNURL service and pays it via
+/// [pay_invoice](crate::alby::api::payments::pay_invoice).
+///
+/// The invoice is decoded via Alby before paying it, and rejected if its amount differs from
+/// `num_sats` or its description hash isn't the hash of the service's metadata (LUD-06).
+pub async fn pay_via_lnurl(args: LnurlPayArgs<'_>) -> Result<PayInvoiceResponse, RequestError> {
+    let url = lnurl_to_url(args.lnurl, args.allow_insecure_http)?;
+    let invoice = fetch_lnurl_invoice(
+        args.user_agent,
+        &url,
+        args.num_sats,
+        args.comment,
+        args.timeouts,
+    )
+    .await?;
+
+    let provider = AlbyProvider {
+        user_agent: args.user_agent,
+        token: args.token,
+        base_url: args.base_url,
+        rate_limiter: args.rate_limiter,
+        timeouts: args.timeouts,
+        retry: args.retry,
+    };
+    provider
+        .pay_invoice(&invoice.invoice, args.num_sats, &invoice.description_hash)
+        .await
+}
+
+/// Fetches a BOLT11 invoice for `num_sats` from the LNURL-pay service at `url` (the pay request).
+pub(crate) async fn fetch_lnurl_invoice(
+    user_agent: &str,
+    url: &url::Url,
+    num_sats: u64,
+    comment: Option<&str>,
+    timeouts: Timeouts,
+) -> Result<LnurlInvoice, RequestError> {
+    let num_msats = num_sats
+        .checked_mul(1000)
+        .ok_or_else(|| RequestError::InvalidArgs(format!("{} sats", num_sats)))?;
+
+    let client = reqwest::Client::builder()
+        .user_agent(user_agent)
+        .connect_timeout(timeouts.connect_timeout)
+        .timeout(timeouts.timeout)
+        .build()
+        .map_err(RequestError::ClientCreation)?;
+
+    let pay_request: PayRequestResponse = lnurl_get(&client, url.as_str()).await?;
+    check_lnurl_status(pay_request.status.as_deref(), pay_request.reason)?;
+    if pay_request.tag.as_deref() != Some("payRequest") {
+        return Err(RequestError::Lnurl(format!(
+            "unexpected tag {:?}",
+            pay_request.tag
+        )));
+    }
+    if num_msats < pay_request.min_sendable_msat || num_msats > pay_request.max_sendable_msat {
+        return Err(RequestError::InvalidArgs(format!(
+            "{} msats is outside of the range accepted by the recipient ({}-{} msats)",
+            num_msats, pay_request.min_sendable_msat, pay_request.max_sendable_msat
+        )));
+    }
+
+    let metadata = pay_request
+        .metadata
+        .ok_or_else(|| RequestError::Lnurl("no metadata".to_string()))?;
+    let callback = pay_request
+        .callback
+        .ok_or_else(|| RequestError::Lnurl("no callback".to_string()))?;
+    let mut callback = url::Url::parse(&callback)
+        .map_err(|e| RequestError::Lnurl(format!("invalid callback: {}", e)))?;
+    {
+        let mut query = callback.query_pairs_mut();
+        query.append_pair("amount", &num_msats.to_string());
+        match comment {
+            Some(comment) if pay_request.comment_allowed > 0 => {
+                let comment: String = comment.chars().take(pay_request.comment_allowed).collect();
+                query.append_pair("comment", &comment);
+            }
+            Some(_) => log::debug!("LNURL service doesn't accept comments, dropping comment"),
+            None => {}
+        }
+    }
+
+    let callback_response: CallbackResponse = lnurl_get(&client, callback.as_str()).await?;
+    check_lnurl_status(
+        callback_response.status.as_deref(),
+        callback_response.reason,
+    )?;
+    let invoice = callback_response
+        .pr
+        .ok_or_else(|| RequestError::Lnurl("no invoice".to_string()))?;
+
+    Ok(LnurlInvoice {
+        invoice,
+        description_hash: crate::hex::encode(&hmac_sha256::Hash::hash(metadata.as_bytes())),
+    })
+}
+
+/// URL of the LNURL-pay request of a lightning address (LUD-16), served at `base_url` (e.g., a
+/// mock server) instead of the address's domain if set.
+pub(crate) fn lightning_address_lnurlp_url(
+    address: &str,
+    base_url: Option<&str>,
+) -> Result<url::Url, RequestError> {
+    let (name, domain) = split_address(address)
+        .map_err(|_| RequestError::InvalidArgs(format!("invalid LNURL: {}", address)))?;
+    let url = match base_url {
+        Some(base_url) => format!(
+            "{}/.well-known/lnurlp/{}",
+            base_url.trim_end_matches('/'),
+            name
+        ),
+        None => format!("https://{}/.well-known/lnurlp/{}", domain, name),
+    };
+
+    url::Url::parse(&url)
+        .map_err(|_| RequestError::InvalidArgs(format!("invalid LNURL: {}", address)))
+}
+
+/// Fails if an LNURL response has the `ERROR` status.
+fn check_lnurl_status(status: Option<&str>, reason: Option<String>) -> Result<(), RequestError> {
+    match status {
+        Some(status) if status.eq_ignore_ascii_case("ERROR") => Err(RequestError::Lnurl(
+            reason.unwrap_or_else(|| "unknown error".to_string()),
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Sends a GET request to an LNURL service and parses the response.
+async fn lnurl_get<T: serde::de::DeserializeOwned>(
+    client: &reqwest::Client,
+    url: &str,
+) -> Result<T, RequestError> {
+    let response = client
+        .get(url)
+        .send()
+        .await
+        .map_err(RequestError::RequestSend)?;
+    let status = response.status();
+    let body = response
+        .text()
+        .await
+        .map_err(RequestError::ResponseBodyRead)?;
+    log::debug!("LNURL response ({}): {}", status, body);
+
+    if !status.is_success() {
+        return Err(RequestError::UnexpectedStatus { status, body });
+    }
+    serde_json::from_str(&body).map_err(|e| RequestError::ResponseParse(e, body))
+}
+
+/// Converts an LNURL, `lnurlp://` URL, or lightning address into the URL of the pay request.
+///
+/// Plain `http` is only accepted for `.onion` hosts (LUD-01), unless `allow_insecure_http` is set.
+fn lnurl_to_url(lnurl: &str, allow_insecure_http: bool) -> Result<url::Url, RequestError> {
+    let invalid = || RequestError::InvalidArgs(format!("invalid LNURL: {}", lnurl));
+
+    let lnurl = lnurl.trim();
+    let lnurl = match lnurl.get(..10) {
+        Some(prefix) if prefix.eq_ignore_ascii_case("lightning:") => &lnurl[10..],
+        _ => lnurl,
+    };
+
+    if lnurl.contains('@') && !lnurl.contains("://") {
+        return lightning_address_lnurlp_url(lnurl, None);
+    }
+
+    let url = if let Some(rest) = lnurl.strip_prefix("lnurlp://") {
+        format!("https://{}", rest)
+    } else if lnurl.to_ascii_lowercase().starts_with("lnurl1") {
+        let bytes = decode_bech32("lnurl", lnurl).ok_or_else(invalid)?;
+        String::from_utf8(bytes).map_err(|_| invalid())?
+    } else {
+        lnurl.to_string()
+    };
+
+    let url = url::Url::parse(&url).map_err(|_| invalid())?;
+    match url.scheme() {
+        "https" => Ok(url),
+        "http"
+            if allow_insecure_http
+                || url.host_str().is_some_and(|host| host.ends_with(".onion")) =>
+        {
+            Ok(url)
+        }
+        _ => Err(invalid()),
+    }
+}
+
+/// Decodes the data of a bech32 string with the given human-readable part.
+///
+/// Unlike BIP-173, the length isn't limited, since LNURLs are usually longer than 90 characters.
+fn decode_bech32(hrp: &str, s: &str) -> Option<Vec<u8>> {
+    const CHARSET: &[u8] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+    const GENERATOR: [u32; 5] = [0x3b6a57b2, 0x26508e6d, 0x1ea119fa, 0x3d4233dd, 0x2a1462b3];
+
+    if s.chars().any(|c| c.is_ascii_lowercase()) && s.chars().any(|c| c.is_ascii_uppercase()) {
+        return None;
+    }
+    let s = s.to_ascii_lowercase();
+    let (s_hrp, data) = s.rsplit_once('1')?;
+    if s_hrp != hrp || data.len() < 6 {
+        return None;
+    }
+    let data = data
+        .bytes()
+        .map(|c| CHARSET.iter().position(|&d| d == c).map(|i| i as u8))
+        .collect::<Option<Vec<u8>>>()?;
+
+    let values = hrp
+        .bytes()
+        .map(|c| c >> 5)
+        .chain(std::iter::once(0))
+        .chain(hrp.bytes().map(|c| c & 31))
+        .chain(data.iter().copied());
+    let mut checksum: u32 = 1;
+    for value in values {
+        let top = checksum >> 25;
+        checksum = ((checksum & 0x1ffffff) << 5) ^ u32::from(value);
+        for (i, generator) in GENERATOR.iter().enumerate() {
+            if (top >> i) & 1 == 1 {
+                checksum ^= generator;
+            }
+        }
+    }
+    if checksum != 1 {
+        return None;
+    }
+
+    // Converts from 5-bit groups to bytes, dropping the padding.
+    let mut bytes = Vec::new();
+    let (mut acc, mut bits) = (0u32, 0u32);
+    for &value in &data[..data.len() - 6] {
+        acc = (acc << 5) | u32::from(value);
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((acc >> bits) as u8);
+            acc &= (1 << bits) - 1;
+        }
+    }
+    if bits >= 5 || acc != 0 {
+        return None;
+    }
+
+    Some(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_bech32_lud01_example() {
+        // Example from LUD-01.
+        let lnurl = "LNURL1DP68GURN8GHJ7UM9WFMXJCM99E3K7MF0V9CXJ0M385EKVCENXC6R2C35XVUKXEFCV5MKVV34X5EKZD3EV56NYD3HXQURZEPEXEJXXEPNXSCRVWFNV9NXZCN9XQ6XYEFHVGCXXCMYXYMNSERXFQ5FNS";
+        assert_eq!(
+            decode_bech32("lnurl", lnurl).map(|bytes| String::from_utf8(bytes).unwrap()),
+            Some("https://service.com/api?q=3fc3645b439ce8e7f2553a69e5267081d96dcd340693afabe04be7b0ccd178df".to_string())
+        );
+
+        // Corrupted checksum.
+        let corrupted = lnurl.replace("FNS", "FNQ");
+        assert_eq!(decode_bech32("lnurl", &corrupted), None);
+    }
+
+    #[test]
+    fn lnurl_to_url_formats() {
+        assert_eq!(
+            lnurl_to_url("alice@example.com", false).unwrap().as_str(),
+            "https://example.com/.well-known/lnurlp/alice"
+        );
+        assert_eq!(
+            lnurl_to_url("lnurlp://example.com/pay/1", false)
+                .unwrap()
+                .as_str(),
+            "https://example.com/pay/1"
+        );
+        assert!(lnurl_to_url("ftp://example.com", false).is_err());
+        assert!(lnurl_to_url("lnurl1invalid", false).is_err());
+        assert!(lnurl_to_url("http://example.com/pay/1", false).is_err());
+        assert!(lnurl_to_url("http://example.com/pay/1", true).is_ok());
+        assert!(lnurl_to_url("http://example.onion/pay/1", false).is_ok());
+    }
+}
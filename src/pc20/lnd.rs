@@ -0,0 +1,354 @@
+use std::collections::HashMap;
+
+use base64::Engine;
+
+use super::provider::{InvoiceRequest, PaymentProvider};
+use crate::alby::{
+    api::{
+        invoices::CreateInvoiceResponse,
+        payments::{
+            KeysendResponse, MultiKeysendItemArgs, MultiKeysendItemResponse, MultiKeysendResponse,
+        },
+        ErrorResponse, RequestError, Timeouts,
+    },
+    types::Preimage,
+};
+
+/// Custom record type carrying the preimage of a keysend payment.
+pub const KEYSEND_PREIMAGE_RECORD: u64 = 5482373484;
+
+/// [PaymentProvider] using the REST API of an [LND](https://github.com/lightningnetwork/lnd)
+/// node.
+///
+/// Keysends are sent one by one with `POST /v2/router/send` and invoices are created with
+/// `POST /v1/invoices`. LND doesn't store invoice metadata or payer names, so those fields of
+/// [InvoiceRequest]s are dropped.
+///
+/// Available with the `lnd` feature.
+///
+/// ## Example
+/// ```rust
+/// let mut provider = v4v::pc20::lnd::LndProvider::new("https://localhost:8080", "0201036c6e64");
+/// provider.fee_limit_sats = Some(10);
+/// ```
+#[derive(Clone)]
+pub struct LndProvider {
+    /// Base URL of the REST API, e.g., `https://localhost:8080`.
+    pub base_url: String,
+    /// Hex-encoded macaroon, e.g., of `admin.macaroon`.
+    pub macaroon: String,
+    /// PEM-encoded TLS certificate of the node (`tls.cert`), trusted in addition to the system's
+    /// root certificates.
+    pub tls_cert_pem: Option<Vec<u8>>,
+    /// Maximum routing fee of a keysend in sats, 1% of the amount plus 10 sats if `None`.
+    pub fee_limit_sats: Option<u64>,
+    /// How long LND may try to route a keysend.
+    pub payment_timeout: std::time::Duration,
+    /// Expiry of created invoices.
+    pub invoice_expiry: std::time::Duration,
+    /// Timeouts of the requests. Keysend requests may additionally take up to
+    /// [payment_timeout](LndProvider::payment_timeout).
+    pub timeouts: Timeouts,
+}
+
+impl std::fmt::Debug for LndProvider {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LndProvider")
+            .field("base_url", &self.base_url)
+            .field("macaroon", &"<redacted>")
+            .field(
+                "tls_cert_pem",
+                &self.tls_cert_pem.as_ref().map(|_| "<cert>"),
+            )
+            .field("fee_limit_sats", &self.fee_limit_sats)
+            .field("payment_timeout", &self.payment_timeout)
+            .field("invoice_expiry", &self.invoice_expiry)
+            .field("timeouts", &self.timeouts)
+            .finish()
+    }
+}
+
+impl LndProvider {
+    /// Creates a provider for the REST API at `base_url`, authenticated with the hex-encoded
+    /// `macaroon`. Keysends time out after 60 seconds and invoices expire after an hour.
+    pub fn new(base_url: &str, macaroon: &str) -> Self {
+        Self {
+            base_url: base_url.to_string(),
+            macaroon: macaroon.to_string(),
+            tls_cert_pem: None,
+            fee_limit_sats: None,
+            payment_timeout: std::time::Duration::from_secs(60),
+            invoice_expiry: std::time::Duration::from_secs(3600),
+            timeouts: Timeouts::default(),
+        }
+    }
+
+    /// Builds the body of the `POST /v2/router/send` request for a keysend paying `preimage`.
+    ///
+    /// LND expects bytes base64-encoded, so the destination public key is converted from hex,
+    /// and the custom records are sent as a map from record type to the base64-encoded value,
+    /// together with the preimage under [KEYSEND_PREIMAGE_RECORD].
+    pub fn keysend_request(
+        &self,
+        keysend: &MultiKeysendItemArgs<'_>,
+        preimage: &Preimage,
+    ) -> Result<serde_json::Value, RequestError> {
+        let base64 = base64::engine::general_purpose::STANDARD;
+
+        let dest = crate::hex::decode(keysend.dest_pubkey).ok_or_else(|| {
+            RequestError::InvalidArgs(format!("invalid node public key {}", keysend.dest_pubkey))
+        })?;
+
+        let mut dest_custom_records = HashMap::new();
+        for (key, value) in &keysend.custom_records {
+            let key: u64 = key.parse().map_err(|_| {
+                RequestError::InvalidArgs(format!("custom record key {} is not a number", key))
+            })?;
+            if key == KEYSEND_PREIMAGE_RECORD {
+                return Err(RequestError::InvalidArgs(format!(
+                    "custom record key {} is reserved for the keysend preimage",
+                    key
+                )));
+            }
+            dest_custom_records.insert(key.to_string(), base64.encode(value.as_bytes()));
+        }
+        dest_custom_records.insert(
+            KEYSEND_PREIMAGE_RECORD.to_string(),
+            base64.encode(preimage.as_bytes()),
+        );
+
+        let fee_limit_sats = self.fee_limit_sats.unwrap_or(keysend.num_sats / 100 + 10);
+
+        Ok(serde_json::json!({
+            "dest": base64.encode(dest),
+            "amt": keysend.num_sats.to_string(),
+            "payment_hash": base64.encode(hmac_sha256::Hash::hash(preimage.as_bytes())),
+            "dest_custom_records": dest_custom_records,
+            "fee_limit_sat": fee_limit_sats.to_string(),
+            "timeout_seconds": self.payment_timeout.as_secs().max(1),
+            "no_inflight_updates": true,
+        }))
+    }
+
+    fn client(&self, timeout: std::time::Duration) -> Result<reqwest::Client, RequestError> {
+        let mut headers = reqwest::header::HeaderMap::new();
+        headers.insert(
+            "Grpc-Metadata-macaroon",
+            reqwest::header::HeaderValue::from_str(&self.macaroon)?,
+        );
+
+        let mut builder = reqwest::Client::builder()
+            .default_headers(headers)
+            .connect_timeout(self.timeouts.connect_timeout)
+            .timeout(timeout);
+        if let Some(tls_cert_pem) = &self.tls_cert_pem {
+            let certificate = reqwest::Certificate::from_pem(tls_cert_pem)
+                .map_err(RequestError::ClientCreation)?;
+            builder = builder.add_root_certificate(certificate);
+        }
+
+        builder.build().map_err(RequestError::ClientCreation)
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}{}", self.base_url.trim_end_matches('/'), path)
+    }
+
+    /// Sends a POST request and returns the response body if the status is successful.
+    async fn post(
+        &self,
+        client: &reqwest::Client,
+        path: &str,
+        body: &serde_json::Value,
+    ) -> Result<String, RequestError> {
+        let response = client
+            .post(self.url(path))
+            .body(body.to_string())
+            .send()
+            .await
+            .map_err(RequestError::RequestSend)?;
+
+        let status = response.status();
+        let body = response
+            .text()
+            .await
+            .map_err(RequestError::ResponseBodyRead)?;
+        log::debug!("LND response ({}): {}", status, body);
+
+        if !status.is_success() {
+            return Err(RequestError::UnexpectedStatus { status, body });
+        }
+        Ok(body)
+    }
+
+    async fn send_keysend(
+        &self,
+        client: &reqwest::Client,
+        keysend: &MultiKeysendItemArgs<'_>,
+        request: &serde_json::Value,
+    ) -> Result<MultiKeysendItemResponse, RequestError> {
+        let body = self.post(client, "/v2/router/send", request).await?;
+
+        // The response is a stream of JSON objects, one per line; the last one is the final
+        // state of the payment.
+        let last = body
+            .lines()
+            .rev()
+            .find(|line| !line.trim().is_empty())
+            .unwrap_or_default();
+        let update: PaymentUpdate =
+            serde_json::from_str(last).map_err(|e| RequestError::ResponseParse(e, body.clone()))?;
+
+        let payment = match update {
+            PaymentUpdate {
+                result: Some(payment),
+                ..
+            } if payment.status == "SUCCEEDED" => payment,
+            PaymentUpdate {
+                result: Some(payment),
+                ..
+            } => {
+                return Ok(item_error(format!(
+                    "payment {}: {}",
+                    payment.status.to_ascii_lowercase(),
+                    payment.failure_reason.unwrap_or_default()
+                )))
+            }
+            PaymentUpdate {
+                error: Some(error), ..
+            } => return Ok(item_error(error.message)),
+            PaymentUpdate { .. } => {
+                return Err(RequestError::Unexpected(format!(
+                    "Unexpected LND payment update: {}",
+                    last
+                )))
+            }
+        };
+
+        Ok(MultiKeysendItemResponse {
+            error: None,
+            keysend: Some(KeysendResponse {
+                num_sats: keysend.num_sats,
+                description: String::new(),
+                description_hash: String::new(),
+                dest_pubkey: keysend.dest_pubkey.to_string(),
+                fee_in_sats: payment.fee_sat.parse().unwrap_or_default(),
+                custom_records: keysend.custom_records.clone(),
+                payment_hash: payment.payment_hash,
+                payment_preimage: payment.payment_preimage,
+            }),
+        })
+    }
+}
+
+impl PaymentProvider for LndProvider {
+    /// Sends the keysends one by one.
+    ///
+    /// Fails only for invalid arguments (e.g., a custom record key that isn't a number), before
+    /// anything is sent; errors of the individual keysends (including timeouts) are reported per
+    /// keysend.
+    async fn multi_keysend(
+        &self,
+        keysends: Vec<MultiKeysendItemArgs<'_>>,
+    ) -> Result<MultiKeysendResponse, RequestError> {
+        let mut requests = Vec::with_capacity(keysends.len());
+        for keysend in &keysends {
+            let mut preimage = [0; 32];
+            getrandom::getrandom(&mut preimage)
+                .map_err(|e| RequestError::Unexpected(e.to_string()))?;
+            requests.push(self.keysend_request(keysend, &Preimage::from(preimage))?);
+        }
+        let client = self.client(self.timeouts.timeout + self.payment_timeout)?;
+
+        let mut responses = Vec::with_capacity(keysends.len());
+        for (keysend, request) in keysends.iter().zip(&requests) {
+            let response = self
+                .send_keysend(&client, keysend, request)
+                .await
+                .unwrap_or_else(|error| item_error(error.to_string()));
+            responses.push(response);
+        }
+
+        Ok(MultiKeysendResponse {
+            keysends: responses,
+        })
+    }
+
+    async fn create_invoice(
+        &self,
+        invoice: InvoiceRequest,
+    ) -> Result<CreateInvoiceResponse, RequestError> {
+        let request = serde_json::json!({
+            "value": invoice.num_sats.to_string(),
+            "memo": invoice.description.unwrap_or_default(),
+            "expiry": self.invoice_expiry.as_secs().to_string(),
+        });
+        let client = self.client(self.timeouts.timeout)?;
+        let created_at = chrono::Utc::now();
+
+        let body = self.post(&client, "/v1/invoices", &request).await?;
+        let response: AddInvoiceResponse = serde_json::from_str(&body)
+            .map_err(|e| RequestError::ResponseParse(e, body.clone()))?;
+
+        // Unlike other hashes, `r_hash` is bytes and thus base64-encoded.
+        let payment_hash = base64::engine::general_purpose::STANDARD
+            .decode(&response.r_hash)
+            .map_err(|_| {
+                RequestError::Unexpected(format!("Invalid payment hash {}", response.r_hash))
+            })?;
+
+        Ok(CreateInvoiceResponse {
+            expires_at: created_at
+                + chrono::Duration::from_std(self.invoice_expiry)
+                    .map_err(|e| RequestError::Unexpected(e.to_string()))?,
+            payment_hash: crate::hex::encode(&payment_hash),
+            payment_request: response.payment_request,
+        })
+    }
+}
+
+fn item_error(message: String) -> MultiKeysendItemResponse {
+    MultiKeysendItemResponse {
+        error: Some(ErrorResponse {
+            code: 0,
+            error: true,
+            message,
+        }),
+        keysend: None,
+    }
+}
+
+/// Update of a payment's state from `/v2/router/send`.
+#[derive(Debug, serde::Deserialize)]
+struct PaymentUpdate {
+    #[serde(default)]
+    result: Option<Payment>,
+    #[serde(default)]
+    error: Option<LndError>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct Payment {
+    status: String,
+    #[serde(default)]
+    payment_hash: String,
+    #[serde(default)]
+    payment_preimage: String,
+    /// Routing fee in sats, as a string (int64).
+    #[serde(default)]
+    fee_sat: String,
+    #[serde(default)]
+    failure_reason: Option<String>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct LndError {
+    #[serde(default)]
+    message: String,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct AddInvoiceResponse {
+    r_hash: String,
+    payment_request: String,
+}
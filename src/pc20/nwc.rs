@@ -0,0 +1,511 @@
+use std::{collections::HashMap, str::FromStr};
+
+use aes::cipher::{block_padding::Pkcs7, BlockDecryptMut, BlockEncryptMut, KeyIvInit};
+use base64::Engine;
+use futures_util::{SinkExt, StreamExt};
+use tokio_tungstenite::tungstenite::Message;
+
+use super::provider::{InvoiceRequest, PaymentProvider};
+use crate::alby::api::{
+    invoices::CreateInvoiceResponse,
+    payments::{
+        KeysendResponse, MultiKeysendItemArgs, MultiKeysendItemResponse, MultiKeysendResponse,
+    },
+    ErrorResponse, RequestError, Timeouts,
+};
+
+/// Kind of NIP-47 request events.
+const REQUEST_KIND: u32 = 23194;
+/// Kind of NIP-47 response events.
+const RESPONSE_KIND: u32 = 23195;
+
+type Socket =
+    tokio_tungstenite::WebSocketStream<tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>>;
+
+/// Error returned when a Nostr Wallet Connect connection string can't be parsed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NwcUriError {
+    /// The string is not a `nostr+walletconnect://` URI.
+    InvalidUri,
+    /// The wallet service's public key is not a hex-encoded Nostr public key.
+    InvalidWalletPubkey,
+    /// The URI has no `relay` parameter.
+    NoRelay,
+    /// The `secret` parameter is missing or not a hex-encoded secret key.
+    InvalidSecret,
+}
+
+impl std::fmt::Display for NwcUriError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            NwcUriError::InvalidUri => write!(f, "not a nostr+walletconnect:// URI"),
+            NwcUriError::InvalidWalletPubkey => {
+                write!(f, "wallet public key is not a hex-encoded Nostr public key")
+            }
+            NwcUriError::NoRelay => write!(f, "URI has no relay"),
+            NwcUriError::InvalidSecret => write!(f, "secret is missing or invalid"),
+        }
+    }
+}
+
+impl std::error::Error for NwcUriError {}
+
+/// [PaymentProvider] using a wallet service over Nostr Wallet Connect (NIP-47).
+///
+/// Keysends are sent one by one with `pay_keysend` and invoices are created with `make_invoice`.
+/// Requests are encrypted with NIP-04 and sent over the first relay of the connection string.
+/// Payer names of [InvoiceRequest]s are not supported by NIP-47 and are dropped.
+///
+/// Available with the `nwc` feature.
+///
+/// ## Example
+/// ```rust
+/// let provider = v4v::pc20::nwc::NwcProvider::from_uri(
+///     "nostr+walletconnect://b889ff5b1513b641e2a139f661a661364979c5beee91842f8f0ef42ab558e9d4?relay=wss%3A%2F%2Frelay.example.com&secret=71a8c14c1407c113601079c4302dab36460f0ccd0ad506f1f2dc73b5100e4f3c",
+/// )
+/// .unwrap();
+/// assert_eq!(provider.relay(), "wss://relay.example.com");
+/// ```
+#[derive(Debug, Clone)]
+pub struct NwcProvider {
+    wallet_pubkey: secp256k1::XOnlyPublicKey,
+    relay: String,
+    keypair: secp256k1::Keypair,
+    /// Timeouts for connecting to the relay and for each response of the wallet service.
+    pub timeouts: Timeouts,
+}
+
+impl NwcProvider {
+    /// Parses a `nostr+walletconnect://<wallet pubkey>?relay=<relay>&secret=<secret>` connection
+    /// string.
+    pub fn from_uri(uri: &str) -> Result<Self, NwcUriError> {
+        let url = url::Url::parse(uri.trim()).map_err(|_| NwcUriError::InvalidUri)?;
+        if url.scheme() != "nostr+walletconnect" && url.scheme() != "nostrwalletconnect" {
+            return Err(NwcUriError::InvalidUri);
+        }
+
+        // Both `scheme://pubkey` and `scheme:pubkey` are in use.
+        let wallet_pubkey = match url.host_str() {
+            Some(host) => host,
+            None => url.path(),
+        };
+        let wallet_pubkey = secp256k1::XOnlyPublicKey::from_str(wallet_pubkey)
+            .map_err(|_| NwcUriError::InvalidWalletPubkey)?;
+
+        let query: HashMap<_, _> = url.query_pairs().collect();
+        let relay = query
+            .get("relay")
+            .map(|relay| relay.to_string())
+            .ok_or(NwcUriError::NoRelay)?;
+        let keypair = query
+            .get("secret")
+            .and_then(|secret| secp256k1::Keypair::from_str(secret).ok())
+            .ok_or(NwcUriError::InvalidSecret)?;
+
+        Ok(Self {
+            wallet_pubkey,
+            relay,
+            keypair,
+            timeouts: Timeouts::default(),
+        })
+    }
+
+    /// URL of the relay used to reach the wallet service.
+    pub fn relay(&self) -> &str {
+        &self.relay
+    }
+
+    async fn connect(&self) -> Result<Socket, RequestError> {
+        let (socket, _) = tokio::time::timeout(
+            self.timeouts.connect_timeout,
+            tokio_tungstenite::connect_async(self.relay.as_str()),
+        )
+        .await
+        .map_err(|_| RequestError::Nwc("timed out connecting to the relay".to_string()))?
+        .map_err(|e| RequestError::Nwc(format!("failed to connect to the relay: {}", e)))?;
+
+        Ok(socket)
+    }
+
+    /// Sends a request to the wallet service and waits for its response.
+    async fn request(
+        &self,
+        socket: &mut Socket,
+        method: &str,
+        params: serde_json::Value,
+    ) -> Result<Result<serde_json::Value, NwcError>, RequestError> {
+        let content = serde_json::json!({"method": method, "params": params}).to_string();
+        let event = self.sign_event(
+            REQUEST_KIND,
+            vec![vec!["p".to_string(), self.wallet_pubkey.to_string()]],
+            self.encrypt(&content)?,
+        );
+        let subscription_id = format!("v4v-{}", &event.id[..16]);
+
+        let filter = serde_json::json!({
+            "kinds": [RESPONSE_KIND],
+            "authors": [self.wallet_pubkey.to_string()],
+            "#e": [event.id],
+        });
+        for message in [
+            serde_json::json!(["REQ", subscription_id, filter]),
+            serde_json::json!(["EVENT", event]),
+        ] {
+            socket
+                .send(Message::Text(message.to_string()))
+                .await
+                .map_err(|e| RequestError::Nwc(format!("failed to send to the relay: {}", e)))?;
+        }
+
+        let response = tokio::time::timeout(
+            self.timeouts.timeout,
+            self.wait_for_response(socket, &subscription_id, &event.id),
+        )
+        .await
+        .map_err(|_| RequestError::Nwc("timed out waiting for the wallet service".to_string()))??;
+
+        let close = serde_json::json!(["CLOSE", subscription_id]);
+        let _ = socket.send(Message::Text(close.to_string())).await;
+
+        let response: NwcResponse = serde_json::from_str(&response)
+            .map_err(|e| RequestError::ResponseParse(e, response.clone()))?;
+        Ok(match response.error {
+            Some(error) => Err(error),
+            None => Ok(response.result.unwrap_or_default()),
+        })
+    }
+
+    /// Reads relay messages until the wallet service responds to the request, returning the
+    /// decrypted content of the response.
+    async fn wait_for_response(
+        &self,
+        socket: &mut Socket,
+        subscription_id: &str,
+        request_id: &str,
+    ) -> Result<String, RequestError> {
+        while let Some(message) = socket.next().await {
+            let message = message
+                .map_err(|e| RequestError::Nwc(format!("failed to read from the relay: {}", e)))?;
+            let Message::Text(message) = message else {
+                continue;
+            };
+            let Ok(message) = serde_json::from_str::<Vec<serde_json::Value>>(&message) else {
+                continue;
+            };
+
+            match message.first().and_then(|kind| kind.as_str()) {
+                Some("OK")
+                    if message.get(1).and_then(|id| id.as_str()) == Some(request_id)
+                        && message.get(2).and_then(|ok| ok.as_bool()) == Some(false) =>
+                {
+                    return Err(RequestError::Nwc(format!(
+                        "relay rejected the request: {}",
+                        message.get(3).and_then(|m| m.as_str()).unwrap_or_default()
+                    )));
+                }
+                Some("CLOSED")
+                    if message.get(1).and_then(|id| id.as_str()) == Some(subscription_id) =>
+                {
+                    return Err(RequestError::Nwc(format!(
+                        "relay closed the subscription: {}",
+                        message.get(2).and_then(|m| m.as_str()).unwrap_or_default()
+                    )));
+                }
+                Some("EVENT")
+                    if message.get(1).and_then(|id| id.as_str()) == Some(subscription_id) =>
+                {
+                    let Some(event) = message
+                        .get(2)
+                        .and_then(|event| serde_json::from_value::<Event>(event.clone()).ok())
+                    else {
+                        continue;
+                    };
+                    if self.is_response_to(&event, request_id) {
+                        return self.decrypt(&event.content);
+                    }
+                    log::debug!("Ignoring unexpected NWC event {}", event.id);
+                }
+                _ => {}
+            }
+        }
+
+        Err(RequestError::Nwc(
+            "relay closed the connection before the wallet service responded".to_string(),
+        ))
+    }
+
+    /// Whether the event is a validly signed response of the wallet service to the request.
+    fn is_response_to(&self, event: &Event, request_id: &str) -> bool {
+        let id = event_id(
+            &event.pubkey,
+            event.created_at,
+            event.kind,
+            &event.tags,
+            &event.content,
+        );
+        let is_signed = crate::hex::encode(&id) == event.id
+            && secp256k1::schnorr::Signature::from_str(&event.sig)
+                .is_ok_and(|sig| sig.verify(&id, &self.wallet_pubkey).is_ok());
+
+        is_signed
+            && event.kind == RESPONSE_KIND
+            && event.pubkey == self.wallet_pubkey.to_string()
+            && event
+                .tags
+                .iter()
+                .any(|tag| tag.len() >= 2 && tag[0] == "e" && tag[1] == request_id)
+    }
+
+    fn sign_event(&self, kind: u32, tags: Vec<Vec<String>>, content: String) -> Event {
+        let pubkey = self.keypair.x_only_public_key().0.to_string();
+        let created_at = chrono::Utc::now().timestamp();
+        let id = event_id(&pubkey, created_at, kind, &tags, &content);
+
+        Event {
+            id: crate::hex::encode(&id),
+            pubkey,
+            created_at,
+            kind,
+            tags,
+            content,
+            sig: self.keypair.sign_schnorr_no_aux_rand(&id).to_string(),
+        }
+    }
+
+    /// NIP-04 shared secret with the wallet service.
+    fn shared_secret(&self) -> [u8; 32] {
+        let point = secp256k1::ecdh::shared_secret_point(
+            &self.wallet_pubkey.public_key(secp256k1::Parity::Even),
+            &secp256k1::SecretKey::from_keypair(&self.keypair),
+        );
+
+        let mut secret = [0; 32];
+        secret.copy_from_slice(&point[..32]);
+        secret
+    }
+
+    fn encrypt(&self, plaintext: &str) -> Result<String, RequestError> {
+        let mut iv = [0; 16];
+        getrandom::getrandom(&mut iv).map_err(|e| RequestError::Unexpected(e.to_string()))?;
+
+        let ciphertext =
+            cbc::Encryptor::<aes::Aes256>::new(&self.shared_secret().into(), &iv.into())
+                .encrypt_padded_vec_mut::<Pkcs7>(plaintext.as_bytes());
+
+        let base64 = base64::engine::general_purpose::STANDARD;
+        Ok(format!(
+            "{}?iv={}",
+            base64.encode(ciphertext),
+            base64.encode(iv)
+        ))
+    }
+
+    fn decrypt(&self, content: &str) -> Result<String, RequestError> {
+        let invalid = || RequestError::Nwc("failed to decrypt the response".to_string());
+
+        let base64 = base64::engine::general_purpose::STANDARD;
+        let (ciphertext, iv) = content.split_once("?iv=").ok_or_else(invalid)?;
+        let ciphertext = base64.decode(ciphertext).map_err(|_| invalid())?;
+        let iv: [u8; 16] = base64
+            .decode(iv)
+            .ok()
+            .and_then(|iv| iv.try_into().ok())
+            .ok_or_else(invalid)?;
+
+        let plaintext =
+            cbc::Decryptor::<aes::Aes256>::new(&self.shared_secret().into(), &iv.into())
+                .decrypt_padded_vec_mut::<Pkcs7>(&ciphertext)
+                .map_err(|_| invalid())?;
+        String::from_utf8(plaintext).map_err(|_| invalid())
+    }
+
+    async fn pay_keysend(
+        &self,
+        socket: &mut Socket,
+        keysend: &MultiKeysendItemArgs<'_>,
+    ) -> Result<MultiKeysendItemResponse, RequestError> {
+        let mut tlv_records = Vec::new();
+        for (key, value) in &keysend.custom_records {
+            let key: u64 = key.parse().map_err(|_| {
+                RequestError::InvalidArgs(format!("custom record key {} is not a number", key))
+            })?;
+            tlv_records.push(serde_json::json!({
+                "type": key,
+                "value": crate::hex::encode(value.as_bytes()),
+            }));
+        }
+        let num_millisats = keysend.num_sats.checked_mul(1000).ok_or_else(|| {
+            RequestError::InvalidArgs(format!("{} sats is too many", keysend.num_sats))
+        })?;
+
+        let params = serde_json::json!({
+            "amount": num_millisats,
+            "pubkey": keysend.dest_pubkey,
+            "tlv_records": tlv_records,
+        });
+        let result = match self.request(socket, "pay_keysend", params).await? {
+            Ok(result) => result,
+            Err(error) => {
+                return Ok(MultiKeysendItemResponse {
+                    error: Some(error.into()),
+                    keysend: None,
+                })
+            }
+        };
+
+        let result: PayKeysendResult = serde_json::from_value(result.clone())
+            .map_err(|e| RequestError::ResponseParse(e, result.to_string()))?;
+        let payment_hash = crate::alby::types::Preimage::from_hex(&result.preimage)
+            .map(|preimage| crate::hex::encode(&hmac_sha256::Hash::hash(preimage.as_bytes())))
+            .unwrap_or_default();
+
+        Ok(MultiKeysendItemResponse {
+            error: None,
+            keysend: Some(KeysendResponse {
+                num_sats: keysend.num_sats,
+                description: String::new(),
+                description_hash: String::new(),
+                dest_pubkey: keysend.dest_pubkey.to_string(),
+                fee_in_sats: result.fees_paid_msat.unwrap_or_default() / 1000,
+                custom_records: keysend.custom_records.clone(),
+                payment_hash,
+                payment_preimage: result.preimage,
+            }),
+        })
+    }
+}
+
+impl PaymentProvider for NwcProvider {
+    /// Sends the keysends one by one with `pay_keysend` over a single relay connection.
+    ///
+    /// Fails only if nothing could be sent; once a keysend has been attempted, errors of the
+    /// remaining keysends (including timeouts) are reported per keysend.
+    async fn multi_keysend(
+        &self,
+        keysends: Vec<MultiKeysendItemArgs<'_>>,
+    ) -> Result<MultiKeysendResponse, RequestError> {
+        let mut socket = self.connect().await?;
+
+        let mut responses = Vec::with_capacity(keysends.len());
+        for keysend in &keysends {
+            let response = match self.pay_keysend(&mut socket, keysend).await {
+                Ok(response) => response,
+                Err(error) => MultiKeysendItemResponse {
+                    error: Some(ErrorResponse {
+                        code: 0,
+                        error: true,
+                        message: error.to_string(),
+                    }),
+                    keysend: None,
+                },
+            };
+            responses.push(response);
+        }
+        let _ = socket.close(None).await;
+
+        Ok(MultiKeysendResponse {
+            keysends: responses,
+        })
+    }
+
+    async fn create_invoice(
+        &self,
+        invoice: InvoiceRequest,
+    ) -> Result<CreateInvoiceResponse, RequestError> {
+        let num_millisats = invoice.num_sats.checked_mul(1000).ok_or_else(|| {
+            RequestError::InvalidArgs(format!("{} sats is too many", invoice.num_sats))
+        })?;
+        let params = serde_json::json!({
+            "amount": num_millisats,
+            "description": invoice.description,
+            "metadata": invoice.metadata,
+        });
+
+        let mut socket = self.connect().await?;
+        let result = self.request(&mut socket, "make_invoice", params).await;
+        let _ = socket.close(None).await;
+
+        let result = result?.map_err(|error| RequestError::Nwc(error.to_string()))?;
+        let result: MakeInvoiceResult = serde_json::from_value(result.clone())
+            .map_err(|e| RequestError::ResponseParse(e, result.to_string()))?;
+
+        Ok(CreateInvoiceResponse {
+            expires_at: chrono::DateTime::from_timestamp(result.expires_at, 0).ok_or_else(
+                || RequestError::Nwc(format!("invalid expiration time {}", result.expires_at)),
+            )?,
+            payment_hash: result.payment_hash,
+            payment_request: result.invoice,
+        })
+    }
+}
+
+/// Nostr event (NIP-01).
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+struct Event {
+    id: String,
+    pubkey: String,
+    created_at: i64,
+    kind: u32,
+    tags: Vec<Vec<String>>,
+    content: String,
+    sig: String,
+}
+
+/// Hash of the serialized event, which is its ID.
+fn event_id(
+    pubkey: &str,
+    created_at: i64,
+    kind: u32,
+    tags: &[Vec<String>],
+    content: &str,
+) -> [u8; 32] {
+    let serialized = serde_json::json!([0, pubkey, created_at, kind, tags, content]).to_string();
+    hmac_sha256::Hash::hash(serialized.as_bytes())
+}
+
+/// Decrypted content of a response event.
+#[derive(Debug, serde::Deserialize)]
+struct NwcResponse {
+    #[serde(default)]
+    error: Option<NwcError>,
+    #[serde(default)]
+    result: Option<serde_json::Value>,
+}
+
+/// Error of the wallet service.
+#[derive(Debug, serde::Deserialize)]
+struct NwcError {
+    code: String,
+    #[serde(default)]
+    message: String,
+}
+
+impl std::fmt::Display for NwcError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}: {}", self.code, self.message)
+    }
+}
+
+impl From<NwcError> for ErrorResponse {
+    fn from(error: NwcError) -> Self {
+        ErrorResponse {
+            code: 0,
+            error: true,
+            message: error.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct PayKeysendResult {
+    preimage: String,
+    #[serde(rename = "fees_paid", default)]
+    fees_paid_msat: Option<u64>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct MakeInvoiceResult {
+    invoice: String,
+    payment_hash: String,
+    expires_at: i64,
+}
@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::{borrow::Cow, collections::HashMap};
 
 use chrono::Duration;
 use url::Url;
@@ -6,13 +6,20 @@ use uuid::Uuid;
 
 use crate::alby::{
     api::{
-        payments::{MultiKeysendItemArgs, MultiKeysendResponse},
-        RequestError,
+        payments::{KeysendResponse, MultiKeysendItemArgs, PayInvoiceResponse},
+        ErrorResponse, RateLimiter, RequestError, RetryPolicy, Timeouts,
     },
     types::KeysendAddress,
 };
 
-use super::tlv::Record;
+use super::{
+    lightning_address::{
+        fetch_lnurl_invoice, lightning_address_lnurlp_url, resolve_lightning_address,
+        resolve_lightning_address_at,
+    },
+    provider::{AlbyProvider, PaymentProvider},
+    tlv::{Record, RemoteFeedGuid, BLIP10_FIELDS, MAX_SENDER_ID_LEN, TLV_RECORD_KEY},
+};
 
 /// Action for Podcasting 2.0 payment.
 #[derive(
@@ -29,12 +36,66 @@ pub enum Action {
     Auto,
 }
 
+/// Address of a payment recipient.
+///
+/// Serialized as a [KeysendAddress] object, or as a string for a lightning address.
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(untagged)]
+pub enum RecipientAddress {
+    /// Keysend address of the recipient's node (and wallet).
+    Keysend(KeysendAddress),
+    /// Lightning address (`name@domain`) of the recipient.
+    ///
+    /// With [MakePaymentArgs::resolve_lightning_addresses] set, [make_payment] resolves it into a
+    /// keysend address (see [resolve_lightning_address]), so that the recipient gets a keysend with the bLIP-10 record like the other recipients. If the
+    /// address doesn't support keysend, it falls back to paying an invoice fetched through
+    /// LNURL-pay (see [PaymentProvider::pay_invoice]), which carries only the
+    /// [message](PaymentInfo::message) as a comment.
+    ///
+    /// Resolving adds a request to the recipient's domain before the keysends are sent, one
+    /// recipient after the other, and the fallback adds a few more (fetching the invoice, then
+    /// checking and paying it). Resolve addresses ahead of time, e.g., when the feed is parsed,
+    /// to avoid this latency on every payment.
+    LightningAddress(String),
+}
+
+impl Default for RecipientAddress {
+    fn default() -> Self {
+        RecipientAddress::Keysend(KeysendAddress::default())
+    }
+}
+
+impl From<KeysendAddress> for RecipientAddress {
+    fn from(address: KeysendAddress) -> Self {
+        RecipientAddress::Keysend(address)
+    }
+}
+
+impl RecipientAddress {
+    /// The keysend address, `None` for a lightning address.
+    pub fn keysend(&self) -> Option<&KeysendAddress> {
+        match self {
+            RecipientAddress::Keysend(address) => Some(address),
+            RecipientAddress::LightningAddress(_) => None,
+        }
+    }
+}
+
+impl std::fmt::Display for RecipientAddress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RecipientAddress::Keysend(address) => write!(f, "{}", address.pubkey),
+            RecipientAddress::LightningAddress(address) => write!(f, "{}", address),
+        }
+    }
+}
+
 /// Information describing a payment recipient.
 #[derive(Debug, Default, serde::Serialize, serde::Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct PaymentRecipientInfo {
-    /// Recipient's keysend address.
-    pub address: KeysendAddress,
+    /// Recipient's keysend or lightning address.
+    pub address: RecipientAddress,
     /// Number of sats to send.
     pub num_sats: u64,
     /// UUID of a payment sent out to a single recipient.
@@ -46,9 +107,42 @@ pub struct PaymentRecipientInfo {
     /// Recipient's name.
     #[serde(default)]
     pub name: Option<String>,
+    /// Overrides [PaymentInfo::feed_guid] for this recipient, which is then paid as part of the
+    /// value block of that (remote) feed.
+    ///
+    /// Used when a single payment pays recipients of both the local value block and a remote one
+    /// (e.g., referenced in a `<podcast:valueTimeSplit>` tag). If set, the bLIP-10 record of this
+    /// recipient is built from [PaymentInfo::for_remote_value_block], so that it identifies the
+    /// remote feed and item, and carries the local ones as the remote info. See
+    /// [merge_recipients_with_provenance].
+    #[serde(default)]
+    pub feed_guid: Option<Uuid>,
+    /// Overrides [PaymentInfo::item_guid] for this recipient. Ignored if
+    /// [feed_guid](PaymentRecipientInfo::feed_guid) is not set.
+    #[serde(default)]
+    pub item_guid: Option<String>,
+    /// Additional app-specific fields added to this recipient's bLIP-10 record, e.g., an internal
+    /// ID for correlating the payment.
+    ///
+    /// Fields using a bLIP-10 key (see [BLIP10_FIELDS]) are ignored, so standard fields always
+    /// take precedence.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub custom_fields: HashMap<String, serde_json::Value>,
+    /// Recipient's role in the value block (e.g., "host", "guest" or "app fee"), for reporting.
+    ///
+    /// Shown by [format_payout_plan] but not sent in the bLIP-10 record; add it to
+    /// [custom_fields](PaymentRecipientInfo::custom_fields) to send it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub role: Option<String>,
 }
 
 /// Information describing a boost/stream/auto payment.
+///
+/// When a payment is sent to a value block referenced in a `<podcast:valueTimeSplit>` tag, the
+/// feed and item identifiers describe the *referenced* (remote) feed and item whose value block is
+/// being paid, while the remote info fields describe the *referencing* feed and item that the
+/// listener is playing. Playback info (`timestamp` and `speed`) always describes the playback of
+/// the referencing item. See [PaymentInfo::for_remote_value_block].
 #[derive(Debug, Default, serde::Serialize, serde::Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct PaymentInfo {
@@ -127,6 +221,16 @@ pub struct PaymentInfo {
     /// UUID for the boost/stream/auto payment. If there are several recipients, the same identifier should be sent to all of them.
     #[serde(default)]
     pub boost_id: Option<Uuid>,
+    /// Fiat value of the payment at the time it was sent, for display only.
+    ///
+    /// Sent as `fiat_value` in the bLIP-10 record. bLIP-10 doesn't define this field, so only apps
+    /// that look for it will display it. It doesn't affect the amounts sent.
+    #[serde(default)]
+    pub fiat_value: Option<f64>,
+    /// Currency of [PaymentInfo::fiat_value] (e.g., "USD"), sent as `fiat_currency` in the
+    /// bLIP-10 record.
+    #[serde(default)]
+    pub fiat_currency: Option<String>,
 
     /// REMOTE INFO
     ///
@@ -140,6 +244,134 @@ pub struct PaymentInfo {
     /// Keysend address of the sender.
     #[serde(default)]
     pub reply_address: Option<KeysendAddress>,
+
+    /// Pre-serialized bLIP-10 record sent verbatim to every recipient instead of the one built
+    /// from the other fields, e.g., to include fields this crate doesn't model yet.
+    ///
+    /// This bypasses everything the crate does to the record: it is not validated, and
+    /// per-recipient fields ([receiver_name](PaymentRecipientInfo::name),
+    /// [custom_fields](PaymentRecipientInfo::custom_fields), etc.), message truncation and
+    /// [AmountEncoding] are not applied. Amount checks still use
+    /// [total_num_sats](PaymentInfo::total_num_sats).
+    ///
+    /// Never (de)serialized, so that it can only be set by the server and not, e.g., by a
+    /// front-end request body or through invoice metadata.
+    #[serde(skip)]
+    pub raw_boostagram: Option<String>,
+}
+
+impl PaymentInfo {
+    /// Payment information for paying the value block of a remote feed/item referenced in a
+    /// `<podcast:valueTimeSplit>` tag.
+    ///
+    /// `self` describes the payment in the context of the referencing feed and item (the ones
+    /// being played). The returned payment information identifies the remote feed and item by
+    /// `feed_guid` and `item_guid`, and moves the referencing feed's and item's GUIDs to
+    /// `remote_feed_guid` and `remote_item_guid`. Other feed and item fields (names, URL, and
+    /// PodcastIndex IDs) are cleared, since they describe the referencing feed and item; they can
+    /// be set afterwards if known. Playback info is kept as is.
+    ///
+    /// ## Example
+    /// ```rust
+    /// let local_feed_guid = uuid::Uuid::parse_str("917393e3-1b1e-5cef-ace4-edaa54e1f810").unwrap();
+    /// let remote_feed_guid = uuid::Uuid::parse_str("a3c3d5c2-8d0e-5b0a-b0a9-53c1b2e1b2a4").unwrap();
+    ///
+    /// let payment_info = v4v::pc20::payments::PaymentInfo {
+    ///     feed_guid: Some(local_feed_guid),
+    ///     item_guid: Some("local-item".to_string()),
+    ///     timestamp: Some(chrono::Duration::seconds(330)),
+    ///     ..Default::default()
+    /// };
+    ///
+    /// let remote_payment_info = payment_info
+    ///     .for_remote_value_block(Some(remote_feed_guid), Some("remote-item".to_string()));
+    ///
+    /// assert_eq!(remote_payment_info.feed_guid, Some(remote_feed_guid));
+    /// assert_eq!(remote_payment_info.item_guid, Some("remote-item".to_string()));
+    /// assert_eq!(remote_payment_info.remote_feed_guid, Some(local_feed_guid));
+    /// assert_eq!(remote_payment_info.remote_item_guid, Some("local-item".to_string()));
+    /// assert_eq!(remote_payment_info.timestamp, Some(chrono::Duration::seconds(330)));
+    /// ```
+    pub fn for_remote_value_block(
+        &self,
+        remote_feed_guid: Option<Uuid>,
+        remote_item_guid: Option<String>,
+    ) -> Self {
+        Self {
+            feed_guid: remote_feed_guid,
+            feed_name: None,
+            feed_pi_id: None,
+            feed_url: None,
+            item_guid: remote_item_guid,
+            item_name: None,
+            item_pi_id: None,
+            remote_feed_guid: self.feed_guid,
+            remote_item_guid: self.item_guid.clone(),
+            ..self.clone()
+        }
+    }
+}
+
+/// Where a recipient of a payment comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Provenance {
+    /// Recipient from the value block of the feed or item being played.
+    Local,
+    /// Recipient from a remote value block, e.g., one referenced in a `<podcast:valueTimeSplit>`
+    /// tag.
+    Remote,
+}
+
+/// Merges local and remote recipients into a single list that can be paid with [make_payment],
+/// tagging each recipient with its [provenance](Provenance).
+///
+/// Remote recipients get [feed_guid](PaymentRecipientInfo::feed_guid) and
+/// [item_guid](PaymentRecipientInfo::item_guid) set to the GUIDs of the remote feed and item, so
+/// that their bLIP-10 records identify the remote value block, and carry the local feed and item
+/// as the remote info. Typically used after
+/// [use_remote_splits](crate::pc20::calc::use_remote_splits).
+///
+/// ## Example
+/// ```rust
+/// use v4v::pc20::payments::{merge_recipients_with_provenance, PaymentRecipientInfo, Provenance};
+///
+/// let local = vec![PaymentRecipientInfo { num_sats: 10, ..Default::default() }];
+/// let remote = vec![PaymentRecipientInfo { num_sats: 90, ..Default::default() }];
+/// let remote_feed_guid = uuid::Uuid::parse_str("a3c3d5c2-8d0e-5b0a-b0a9-53c1b2e1b2a4").unwrap();
+///
+/// let merged = merge_recipients_with_provenance(
+///     local,
+///     remote,
+///     remote_feed_guid,
+///     Some("remote-item".to_string()),
+/// );
+/// assert_eq!(merged[0].1, Provenance::Local);
+/// assert_eq!(merged[0].0.feed_guid, None);
+/// assert_eq!(merged[1].1, Provenance::Remote);
+/// assert_eq!(merged[1].0.feed_guid, Some(remote_feed_guid));
+/// ```
+pub fn merge_recipients_with_provenance(
+    local: Vec<PaymentRecipientInfo>,
+    remote: Vec<PaymentRecipientInfo>,
+    remote_feed_guid: Uuid,
+    remote_item_guid: Option<String>,
+) -> Vec<(PaymentRecipientInfo, Provenance)> {
+    let local = local
+        .into_iter()
+        .map(|recipient| (recipient, Provenance::Local));
+
+    let remote = remote.into_iter().map(|recipient| {
+        (
+            PaymentRecipientInfo {
+                feed_guid: Some(remote_feed_guid),
+                item_guid: remote_item_guid.clone(),
+                ..recipient
+            },
+            Provenance::Remote,
+        )
+    });
+
+    local.chain(remote).collect()
 }
 
 /// Arguments for [make_payment].
@@ -153,90 +385,1758 @@ pub struct MakePaymentArgs<'a> {
     pub payment_info: PaymentInfo,
     /// Recipients' information.
     pub recipients: Vec<PaymentRecipientInfo>,
+    /// Maximum length of the boostagram message in bytes, [DEFAULT_MAX_MESSAGE_BYTES] if not set.
+    ///
+    /// Longer messages are truncated on a character boundary and end with an ellipsis (unless
+    /// [omit_truncation_ellipsis](MakePaymentArgs::omit_truncation_ellipsis) is set). Custom
+    /// records of a keysend have to fit in the onion packet (1300 bytes shared by all hops), so
+    /// long messages can make the payment fail. Set to [usize::MAX] to never truncate.
+    pub max_message_bytes: Option<usize>,
+    /// Whether to cut truncated messages without ending them with an ellipsis.
+    pub omit_truncation_ellipsis: bool,
+    /// How the total amount is encoded in the bLIP-10 record.
+    pub amount_encoding: AmountEncoding,
+    /// Whether to send the playback position ([PaymentInfo::timestamp]) in HH:MM:SS notation
+    /// (`time`) in addition to seconds (`ts`), since some apps display `time` preferentially.
+    pub emit_hhmmss: bool,
+    /// Whether to refuse sending a payment whose recipients would receive more sats than
+    /// [PaymentInfo::total_num_sats]. See [MakePaymentArgs::amount_report].
+    pub strict_amounts: bool,
+    /// Whether to pay either all recipients or none of them.
+    ///
+    /// By default, payments are best-effort: every recipient is included even if it gets no sats.
+    /// With this option, nothing is sent unless every recipient gets at least one sat and the
+    /// recipients don't receive more than [PaymentInfo::total_num_sats] (if set).
+    ///
+    /// Note that keysends to different recipients are independent payments, so this can't
+    /// guarantee that a payout is atomic if a keysend fails in flight, only that no payout is
+    /// started when it can't be complete.
+    pub require_all_or_nothing: bool,
+    /// Maximum number of sats the payment may involve, [DEFAULT_MAX_SATS] if not set.
+    ///
+    /// Both [PaymentInfo::total_num_sats] and the sum of the recipients' sats are checked, so that
+    /// absurd amounts (e.g., from a bug or untrusted input) are rejected before anything is sent.
+    pub max_sats: Option<u64>,
+    /// Optional client-side rate limiter for the Alby requests. Off if `None`.
+    ///
+    /// Share one limiter between concurrent payouts to keep their combined rate under Alby's
+    /// limits.
+    pub rate_limiter: Option<&'a RateLimiter>,
+    /// Timeouts of the Alby request. A multi-keysend to many recipients can take a while, so
+    /// consider a longer overall timeout for large payouts.
+    pub timeouts: Timeouts,
+    /// Base URL of the Alby API (e.g., of a mock server),
+    /// [DEFAULT_BASE_URL](crate::alby::api::DEFAULT_BASE_URL) if `None`.
+    pub base_url: Option<&'a str>,
+    /// Policy for retrying the Alby request if it fails. Not retried if `None`.
+    ///
+    /// **Retrying can pay the recipients twice**; see [RetryPolicy#paying-twice].
+    pub retry: Option<RetryPolicy>,
+    /// Base URL for resolving recipients' [lightning addresses](RecipientAddress::LightningAddress)
+    /// (e.g., of a mock server), the address's domain if `None`.
+    pub lightning_address_base_url: Option<&'a str>,
+    /// Whether to pay recipients with a [lightning address](RecipientAddress::LightningAddress).
+    /// If not set, the payment fails with [RequestError::InvalidArgs] if there are any.
+    ///
+    /// Paying them makes requests to any host named in their addresses, so only opt in if the
+    /// recipients are trusted (e.g., parsed from a feed, not taken from a request body), or the
+    /// requests could reach internal hosts.
+    pub resolve_lightning_addresses: bool,
 }
 
-/// Send a payment to multiple Podcasting 2.0 recipients using the Alby API.
-pub async fn make_payment(args: MakePaymentArgs<'_>) -> Result<MultiKeysendResponse, RequestError> {
-    let mut keysends: Vec<MultiKeysendItemArgs> = vec![];
+/// Default cap on the number of sats in a single payment or invoice: 100M sats (1 BTC).
+pub const DEFAULT_MAX_SATS: u64 = 100_000_000;
+
+/// Checks that `num_sats` doesn't exceed `max_sats` (or [DEFAULT_MAX_SATS]).
+pub(crate) fn check_max_sats(num_sats: u64, max_sats: Option<u64>) -> Result<(), RequestError> {
+    let max_sats = max_sats.unwrap_or(DEFAULT_MAX_SATS);
+    if num_sats > max_sats {
+        return Err(RequestError::InvalidArgs(format!(
+            "{} sats exceeds the maximum of {} sats",
+            num_sats, max_sats
+        )));
+    }
+
+    Ok(())
+}
+
+/// How the total amount of a payment is encoded in the bLIP-10 record.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum AmountEncoding {
+    /// Only `value_msat_total`, as specified in bLIP-10.
+    #[default]
+    Msat,
+    /// `value_msat_total` plus a non-standard `value_sat_total` field in sats.
+    ///
+    /// Some older receivers only read the amount in sats. The extra field makes every record a
+    /// few bytes larger and is ignored by spec-compliant receivers.
+    MsatAndSat,
+}
+
+impl<'a> MakePaymentArgs<'a> {
+    /// Returns a builder that validates the arguments before they are used.
+    ///
+    /// ## Example
+    /// ```rust
+    /// use v4v::{alby::types::KeysendAddress, pc20::payments::{MakePaymentArgs, PaymentRecipientInfo}};
+    ///
+    /// let args = MakePaymentArgs::builder()
+    ///     .user_agent("MyApp/1.0")
+    ///     .token("token")
+    ///     .recipient(PaymentRecipientInfo {
+    ///         address: KeysendAddress {
+    ///             pubkey: "02abc".to_string(),
+    ///             custom_data: None,
+    ///         }.into(),
+    ///         num_sats: 100,
+    ///         ..Default::default()
+    ///     })
+    ///     .build();
+    /// assert!(args.is_ok());
+    ///
+    /// let args = MakePaymentArgs::builder().user_agent("MyApp/1.0").token("token").build();
+    /// assert_eq!(args.unwrap_err(), v4v::pc20::payments::MakePaymentArgsError::NoRecipients);
+    /// ```
+    pub fn builder() -> MakePaymentArgsBuilder<'a> {
+        MakePaymentArgsBuilder::default()
+    }
+}
+
+/// Builder for [MakePaymentArgs], created with [MakePaymentArgs::builder].
+#[derive(Debug, Default)]
+pub struct MakePaymentArgsBuilder<'a> {
+    args: MakePaymentArgs<'a>,
+    default_app_name: Option<String>,
+    default_app_version: Option<String>,
+}
+
+impl<'a> MakePaymentArgsBuilder<'a> {
+    /// Sets the user agent. Required.
+    pub fn user_agent(mut self, user_agent: &'a str) -> Self {
+        self.args.user_agent = user_agent;
+        self
+    }
+
+    /// Sets the token. Required.
+    pub fn token(mut self, token: &'a str) -> Self {
+        self.args.token = token;
+        self
+    }
+
+    /// Sets the payment information.
+    pub fn payment_info(mut self, payment_info: PaymentInfo) -> Self {
+        self.args.payment_info = payment_info;
+        self
+    }
+
+    /// Replaces the recipients. At least one recipient is required.
+    pub fn recipients(mut self, recipients: Vec<PaymentRecipientInfo>) -> Self {
+        self.args.recipients = recipients;
+        self
+    }
+
+    /// Adds a recipient.
+    pub fn recipient(mut self, recipient: PaymentRecipientInfo) -> Self {
+        self.args.recipients.push(recipient);
+        self
+    }
+
+    /// Sets [MakePaymentArgs::max_message_bytes].
+    pub fn max_message_bytes(mut self, max_message_bytes: usize) -> Self {
+        self.args.max_message_bytes = Some(max_message_bytes);
+        self
+    }
+
+    /// Sets [MakePaymentArgs::omit_truncation_ellipsis].
+    pub fn omit_truncation_ellipsis(mut self, omit_truncation_ellipsis: bool) -> Self {
+        self.args.omit_truncation_ellipsis = omit_truncation_ellipsis;
+        self
+    }
+
+    /// Sets [MakePaymentArgs::strict_amounts].
+    pub fn strict_amounts(mut self, strict_amounts: bool) -> Self {
+        self.args.strict_amounts = strict_amounts;
+        self
+    }
+
+    /// Sets [MakePaymentArgs::require_all_or_nothing].
+    pub fn require_all_or_nothing(mut self, require_all_or_nothing: bool) -> Self {
+        self.args.require_all_or_nothing = require_all_or_nothing;
+        self
+    }
+
+    /// Sets [MakePaymentArgs::max_sats].
+    pub fn max_sats(mut self, max_sats: u64) -> Self {
+        self.args.max_sats = Some(max_sats);
+        self
+    }
+
+    /// Sets [MakePaymentArgs::rate_limiter].
+    pub fn rate_limiter(mut self, rate_limiter: &'a RateLimiter) -> Self {
+        self.args.rate_limiter = Some(rate_limiter);
+        self
+    }
+
+    /// Sets [MakePaymentArgs::timeouts].
+    pub fn timeouts(mut self, timeouts: Timeouts) -> Self {
+        self.args.timeouts = timeouts;
+        self
+    }
+
+    /// Sets [MakePaymentArgs::base_url].
+    pub fn base_url(mut self, base_url: &'a str) -> Self {
+        self.args.base_url = Some(base_url);
+        self
+    }
+
+    /// Sets [MakePaymentArgs::retry]. Read its documentation before opting in.
+    pub fn retry(mut self, retry: RetryPolicy) -> Self {
+        self.args.retry = Some(retry);
+        self
+    }
+
+    /// Sets [MakePaymentArgs::lightning_address_base_url].
+    pub fn lightning_address_base_url(mut self, lightning_address_base_url: &'a str) -> Self {
+        self.args.lightning_address_base_url = Some(lightning_address_base_url);
+        self
+    }
+
+    /// Sets [MakePaymentArgs::resolve_lightning_addresses]. Read its documentation before opting
+    /// in.
+    pub fn resolve_lightning_addresses(mut self, resolve_lightning_addresses: bool) -> Self {
+        self.args.resolve_lightning_addresses = resolve_lightning_addresses;
+        self
+    }
+
+    /// Sets [MakePaymentArgs::amount_encoding].
+    pub fn amount_encoding(mut self, amount_encoding: AmountEncoding) -> Self {
+        self.args.amount_encoding = amount_encoding;
+        self
+    }
+
+    /// Sets [MakePaymentArgs::emit_hhmmss].
+    pub fn emit_hhmmss(mut self, emit_hhmmss: bool) -> Self {
+        self.args.emit_hhmmss = emit_hhmmss;
+        self
+    }
+
+    /// Sets the app name and version sent in the bLIP-10 records unless
+    /// [PaymentInfo::app_name] and [PaymentInfo::app_version] are set, which take precedence.
+    ///
+    /// Use [app_info](crate::app_info) to take them from your crate's metadata.
+    ///
+    /// ## Example
+    /// ```rust
+    /// use v4v::pc20::payments::{MakePaymentArgs, PaymentInfo, PaymentRecipientInfo};
+    ///
+    /// let (name, version) = v4v::app_info!();
+    /// let args = MakePaymentArgs::builder()
+    ///     .user_agent("MyApp/1.0")
+    ///     .token("token")
+    ///     .recipient(PaymentRecipientInfo { num_sats: 10, ..Default::default() })
+    ///     .default_app(name, version)
+    ///     .build()
+    ///     .unwrap();
+    /// assert_eq!(args.payment_info.app_name.as_deref(), Some(name));
+    /// ```
+    pub fn default_app(mut self, name: &str, version: &str) -> Self {
+        self.default_app_name = Some(name.to_string());
+        self.default_app_version = Some(version.to_string());
+        self
+    }
+
+    /// Validates and returns the arguments.
+    pub fn build(mut self) -> Result<MakePaymentArgs<'a>, MakePaymentArgsError> {
+        if self.args.user_agent.is_empty() {
+            return Err(MakePaymentArgsError::MissingUserAgent);
+        }
+        if self.args.token.is_empty() {
+            return Err(MakePaymentArgsError::MissingToken);
+        }
+        if self.args.recipients.is_empty() {
+            return Err(MakePaymentArgsError::NoRecipients);
+        }
+
+        let payment_info = &mut self.args.payment_info;
+        if payment_info.app_name.is_none() {
+            payment_info.app_name = self.default_app_name;
+        }
+        if payment_info.app_version.is_none() {
+            payment_info.app_version = self.default_app_version;
+        }
+
+        Ok(self.args)
+    }
+}
+
+/// Conventionally expected field missing from [PaymentInfo], as reported by
+/// [PaymentInfo::validate_for_action].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValidationIssue {
+    /// None of [PaymentInfo::feed_guid], [PaymentInfo::feed_url] and [PaymentInfo::feed_pi_id]
+    /// is set, so recipients can't tell which podcast was paid.
+    MissingFeedIdentifier,
+    /// Neither [PaymentInfo::item_guid] nor [PaymentInfo::item_pi_id] is set.
+    MissingItemIdentifier,
+    /// [PaymentInfo::timestamp] is not set.
+    MissingTimestamp,
+    /// [PaymentInfo::total_num_sats] is zero.
+    ZeroAmount,
+    /// Public key of the recipient at the given index is not a valid node public key.
+    InvalidPubkey {
+        /// Index of the recipient.
+        index: usize,
+    },
+}
+
+impl std::fmt::Display for ValidationIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ValidationIssue::MissingFeedIdentifier => write!(f, "no feed identifier"),
+            ValidationIssue::MissingItemIdentifier => write!(f, "no item identifier"),
+            ValidationIssue::MissingTimestamp => write!(f, "no playback timestamp"),
+            ValidationIssue::ZeroAmount => write!(f, "total amount is zero"),
+            ValidationIssue::InvalidPubkey { index } => {
+                write!(f, "recipient {} has an invalid public key", index)
+            }
+        }
+    }
+}
+
+impl PaymentInfo {
+    /// Checks that the fields conventionally expected for [PaymentInfo::action] are present.
+    ///
+    /// This is advisory: bLIP-10 makes almost every field optional, and [make_payment] doesn't
+    /// call it. The expectations are:
+    ///
+    /// - every action: a feed identifier and a nonzero [total_num_sats](PaymentInfo::total_num_sats);
+    /// - [Action::Stream]: additionally, an item identifier and a
+    ///   [timestamp](PaymentInfo::timestamp), since streaming happens while an episode plays;
+    /// - [Action::Boost] and [Action::Auto]: nothing else.
+    ///
+    /// ## Example
+    /// ```rust
+    /// use v4v::pc20::payments::{Action, PaymentInfo, ValidationIssue};
+    ///
+    /// let payment_info = PaymentInfo {
+    ///     action: Action::Stream,
+    ///     feed_guid: Some(uuid::Uuid::nil()),
+    ///     total_num_sats: 10,
+    ///     ..Default::default()
+    /// };
+    /// assert_eq!(
+    ///     payment_info.validate_for_action(),
+    ///     Err(vec![ValidationIssue::MissingItemIdentifier, ValidationIssue::MissingTimestamp]),
+    /// );
+    /// ```
+    pub fn validate_for_action(&self) -> Result<(), Vec<ValidationIssue>> {
+        let mut issues = vec![];
+
+        if self.feed_guid.is_none() && self.feed_url.is_none() && self.feed_pi_id.is_none() {
+            issues.push(ValidationIssue::MissingFeedIdentifier);
+        }
+
+        if self.action == Action::Stream {
+            if self.item_guid.is_none() && self.item_pi_id.is_none() {
+                issues.push(ValidationIssue::MissingItemIdentifier);
+            }
+            if self.timestamp.is_none() {
+                issues.push(ValidationIssue::MissingTimestamp);
+            }
+        }
+
+        if self.total_num_sats == 0 {
+            issues.push(ValidationIssue::ZeroAmount);
+        }
+
+        if issues.is_empty() {
+            Ok(())
+        } else {
+            Err(issues)
+        }
+    }
+}
+
+/// Options for [canonicalize_recipients].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CanonicalizeOpts {
+    /// Treat [PaymentRecipientInfo::num_sats] as splits and divide them by their greatest common
+    /// divisor.
+    ///
+    /// Only meaningful when storing value blocks whose recipients' `num_sats` hold splits, not
+    /// amounts of a payment.
+    pub normalize_splits: bool,
+    /// Drop recipients whose `num_sats` is zero.
+    pub drop_zero_splits: bool,
+    /// Sort recipients by public key, then custom data, followed by lightning addresses.
+    pub sort: bool,
+}
+
+/// Validates and canonicalizes recipients, e.g., before persisting a parsed value block.
+///
+/// Public keys are always validated (see [KeysendAddress::new]) and lowercased; lightning
+/// addresses are kept as is. The other steps are set in `opts` and applied in this order: dropping
+/// zero splits, normalizing splits and sorting (keysend addresses first). If any public key is
+/// invalid, all the [ValidationIssue::InvalidPubkey] issues are returned, indexed by position in
+/// `recipients`.
+///
+/// ## Example
+/// ```rust
+/// use v4v::alby::types::KeysendAddress;
+/// use v4v::pc20::payments::{canonicalize_recipients, CanonicalizeOpts, PaymentRecipientInfo};
+///
+/// let recipient = |pubkey: &str, num_sats| PaymentRecipientInfo {
+///     address: KeysendAddress { pubkey: pubkey.to_string(), custom_data: None }.into(),
+///     num_sats,
+///     ..Default::default()
+/// };
+/// let recipients = vec![
+///     recipient("03AE9F91A0CB8FF43840E3C322C4C61F019D8C1C3CEA15A25CFC425AC605E61A4A", 90),
+///     recipient("02d5c1bf8b940dc9cadca86d1b0a3c37fbe39cee4c7e839e33bef9174531d27f52", 10),
+///     recipient("030a58b8653d32b99200a2334cfe913e51dc7d155aa0116c176657a4f1722677a3", 0),
+/// ];
+///
+/// let canonical = canonicalize_recipients(recipients, CanonicalizeOpts {
+///     normalize_splits: true,
+///     drop_zero_splits: true,
+///     sort: true,
+/// }).unwrap();
+/// assert_eq!(
+///     canonical.iter().map(|r| (r.address.to_string()[..8].to_string(), r.num_sats)).collect::<Vec<_>>(),
+///     vec![("02d5c1bf".to_string(), 1), ("03ae9f91".to_string(), 9)],
+/// );
+/// ```
+pub fn canonicalize_recipients(
+    recipients: Vec<PaymentRecipientInfo>,
+    opts: CanonicalizeOpts,
+) -> Result<Vec<PaymentRecipientInfo>, Vec<ValidationIssue>> {
+    let mut issues = vec![];
+    let mut recipients: Vec<PaymentRecipientInfo> = recipients
+        .into_iter()
+        .enumerate()
+        .map(|(index, mut recipient)| {
+            if let RecipientAddress::Keysend(address) = &mut recipient.address {
+                match KeysendAddress::new(&address.pubkey) {
+                    Ok(valid) => address.pubkey = valid.pubkey,
+                    Err(_) => issues.push(ValidationIssue::InvalidPubkey { index }),
+                }
+            }
+            recipient
+        })
+        .collect();
+    if !issues.is_empty() {
+        return Err(issues);
+    }
+
+    if opts.drop_zero_splits {
+        recipients.retain(|recipient| recipient.num_sats > 0);
+    }
+
+    if opts.normalize_splits {
+        let splits: Vec<u64> = recipients.iter().map(|r| r.num_sats).collect();
+        for (recipient, split) in recipients
+            .iter_mut()
+            .zip(crate::pc20::calc::normalize_splits(&splits))
+        {
+            recipient.num_sats = split;
+        }
+    }
+
+    if opts.sort {
+        recipients.sort_by(|a, b| a.address.cmp(&b.address));
+    }
+
+    Ok(recipients)
+}
+
+/// Error returned by [MakePaymentArgsBuilder::build].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MakePaymentArgsError {
+    /// User agent was not set.
+    MissingUserAgent,
+    /// Token was not set.
+    MissingToken,
+    /// There are no recipients, so nothing would be sent.
+    NoRecipients,
+}
+
+impl std::fmt::Display for MakePaymentArgsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            MakePaymentArgsError::MissingUserAgent => write!(f, "user agent is required"),
+            MakePaymentArgsError::MissingToken => write!(f, "token is required"),
+            MakePaymentArgsError::NoRecipients => write!(f, "at least one recipient is required"),
+        }
+    }
+}
+
+impl std::error::Error for MakePaymentArgsError {}
+
+/// Default for [MakePaymentArgs::max_message_bytes].
+pub const DEFAULT_MAX_MESSAGE_BYTES: usize = 500;
+
+/// Ellipsis appended to truncated messages.
+const ELLIPSIS: &str = "…";
+
+/// Truncates `message` to at most `max_len` bytes on a character boundary, ending it with an
+/// ellipsis if it was truncated and `with_ellipsis` is set.
+fn truncate_message(message: &str, max_len: usize, with_ellipsis: bool) -> String {
+    if message.len() <= max_len {
+        return message.to_string();
+    }
+
+    log::warn!(
+        "Truncating boostagram message of {} bytes to {} bytes",
+        message.len(),
+        max_len
+    );
+
+    let ellipsis = if with_ellipsis && max_len >= ELLIPSIS.len() {
+        ELLIPSIS
+    } else {
+        ""
+    };
+
+    let mut end = max_len - ellipsis.len();
+    while !message.is_char_boundary(end) {
+        end -= 1;
+    }
+
+    format!("{}{}", &message[..end], ellipsis)
+}
+
+/// Estimate of the keysends [make_payment] would send.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PayoutEstimate {
+    /// Number of keysends.
+    pub keysend_count: usize,
+    /// Total size of the custom record values (including the bLIP-10 records) in bytes.
+    pub total_tlv_bytes: usize,
+    /// Total number of sats sent to the recipients.
+    pub total_sats: u64,
+}
+
+/// The amounts involved in a payment.
+///
+/// They can legitimately differ: the recipients may receive less than the total the listener
+/// entered (e.g., because of fees), but receiving more is an overspend.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AmountReport {
+    /// [PaymentInfo::total_num_sats] as entered by the listener (0 if not set).
+    pub total_num_sats: u64,
+    /// Sum of the recipients' sats.
+    pub recipients_num_sats: u64,
+    /// `value_msat_total` sent in the bLIP-10 records.
+    pub total_num_millisats: u64,
+}
+
+impl AmountReport {
+    /// Whether the recipients would receive more sats than the total entered by the listener.
+    pub fn is_overspend(&self) -> bool {
+        self.total_num_sats != 0 && self.recipients_num_sats > self.total_num_sats
+    }
+
+    /// Sats of the total that the recipients don't receive (e.g., fees).
+    pub fn unallocated_sats(&self) -> u64 {
+        self.total_num_sats.saturating_sub(self.recipients_num_sats)
+    }
+}
+
+impl MakePaymentArgs<'_> {
+    /// Reports the amounts involved in the payment.
+    ///
+    /// ## Example
+    /// ```rust
+    /// use v4v::pc20::payments::{MakePaymentArgs, PaymentInfo, PaymentRecipientInfo};
+    ///
+    /// let args = MakePaymentArgs {
+    ///     payment_info: PaymentInfo { total_num_sats: 100, ..Default::default() },
+    ///     recipients: vec![PaymentRecipientInfo { num_sats: 95, ..Default::default() }],
+    ///     ..Default::default()
+    /// };
+    /// let report = args.amount_report();
+    /// assert_eq!(report.total_num_millisats, 100_000);
+    /// assert_eq!(report.unallocated_sats(), 5);
+    /// assert!(!report.is_overspend());
+    /// ```
+    pub fn amount_report(&self) -> AmountReport {
+        let recipients_num_sats = self
+            .recipients
+            .iter()
+            .fold(0u64, |acc, r| acc.saturating_add(r.num_sats));
+        let total_num_sats = self.payment_info.total_num_sats;
+
+        AmountReport {
+            total_num_sats,
+            recipients_num_sats,
+            total_num_millisats: match total_num_sats {
+                0 => recipients_num_sats.saturating_mul(1000),
+                _ => total_num_sats.saturating_mul(1000),
+            },
+        }
+    }
+
+    /// Estimates the keysends that [make_payment] would send, without sending them.
+    ///
+    /// Useful for checking the payout against the batch and size limits of the backend, and
+    /// deciding whether to split or trim it.
+    pub fn estimate(&self) -> Result<PayoutEstimate, RequestError> {
+        let keysends = build_keysends(self)?;
+
+        Ok(PayoutEstimate {
+            keysend_count: keysends.len(),
+            total_tlv_bytes: keysends
+                .iter()
+                .flat_map(|keysend| keysend.custom_records.values())
+                .map(|value| value.len())
+                .sum(),
+            total_sats: keysends.iter().map(|keysend| keysend.num_sats).sum(),
+        })
+    }
+
+    /// Splits the bLIP-10 records that [make_payment] would send into their shared and
+    /// per-recipient portions. See [split_tlv_records].
+    pub fn tlv_portions(&self) -> Result<TlvPortions, RequestError> {
+        let records = build_keysends(self)?
+            .iter()
+            .map(|keysend| {
+                serde_json::from_str(&keysend.custom_records[TLV_RECORD_KEY]).map_err(|e| {
+                    RequestError::Unexpected(format!("Failed to parse TLV record: {}", e))
+                })
+            })
+            .collect::<Result<Vec<serde_json::Value>, _>>()?;
+
+        Ok(split_tlv_records(&records))
+    }
+}
+
+/// bLIP-10 records of a payment split into the fields shared by all recipients and the ones
+/// specific to each recipient, as returned by [split_tlv_records].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TlvPortions {
+    /// Fields with the same value in every record.
+    pub shared: serde_json::Map<String, serde_json::Value>,
+    /// Remaining fields of each record, in the same order as the records.
+    pub per_recipient: Vec<serde_json::Map<String, serde_json::Value>>,
+}
+
+/// Splits bLIP-10 records sent to several recipients into the fields they all share (e.g., the
+/// message) and the fields specific to each recipient (e.g., `name`), so that backends able to
+/// send the shared portion once can avoid repeating it.
+///
+/// Merging [TlvPortions::shared] into each of [TlvPortions::per_recipient] gives back the original
+/// records. Records that aren't JSON objects are treated as empty.
+///
+/// Alby requires the full record in every keysend, so this mainly benefits backends talking to
+/// a node directly.
+///
+/// ## Example
+/// ```rust
+/// use serde_json::json;
+///
+/// let records = vec![
+///     json!({"action": "boost", "message": "Hi!", "name": "Host"}),
+///     json!({"action": "boost", "message": "Hi!", "name": "Guest"}),
+/// ];
+///
+/// let portions = v4v::pc20::payments::split_tlv_records(&records);
+/// assert_eq!(
+///     serde_json::Value::Object(portions.shared),
+///     json!({"action": "boost", "message": "Hi!"}),
+/// );
+/// assert_eq!(
+///     portions.per_recipient.into_iter().map(serde_json::Value::Object).collect::<Vec<_>>(),
+///     vec![json!({"name": "Host"}), json!({"name": "Guest"})],
+/// );
+/// ```
+pub fn split_tlv_records(records: &[serde_json::Value]) -> TlvPortions {
+    let empty = serde_json::Map::new();
+    let objects: Vec<&serde_json::Map<String, serde_json::Value>> = records
+        .iter()
+        .map(|record| record.as_object().unwrap_or(&empty))
+        .collect();
+
+    let Some((first, rest)) = objects.split_first() else {
+        return TlvPortions::default();
+    };
+
+    let shared: serde_json::Map<String, serde_json::Value> = first
+        .iter()
+        .filter(|(key, value)| rest.iter().all(|object| object.get(*key) == Some(*value)))
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect();
+
+    let per_recipient = objects
+        .iter()
+        .map(|object| {
+            object
+                .iter()
+                .filter(|(key, _)| !shared.contains_key(*key))
+                .map(|(key, value)| (key.clone(), value.clone()))
+                .collect()
+        })
+        .collect();
+
+    TlvPortions {
+        shared,
+        per_recipient,
+    }
+}
+
+/// Formats the recipients of a payment as an aligned table for human review.
+///
+/// Lists each recipient's name, role (if any recipient has one), public key (shortened) or
+/// lightning address, sats and share of the recipients' sats (see
+/// [splits_to_percentages](crate::pc20::calc::splits_to_percentages)), followed by `total_sats`.
+/// If the recipients receive less than `total_sats`, the difference is shown as unallocated.
+///
+/// ## Example
+/// ```rust
+/// use v4v::{alby::types::KeysendAddress, pc20::payments::{format_payout_plan, PaymentRecipientInfo}};
+///
+/// let recipients = vec![
+///     PaymentRecipientInfo {
+///         address: KeysendAddress {
+///             pubkey: "03ae9f91a0cb8ff43840e3c322c4c61f019d8c1c3cea15a25cfc425ac605e61a4a".to_string(),
+///             custom_data: None,
+///         }.into(),
+///         num_sats: 950,
+///         name: Some("Podcaster".to_string()),
+///         ..Default::default()
+///     },
+///     PaymentRecipientInfo {
+///         address: KeysendAddress {
+///             pubkey: "02d5c1bf8b940dc9cadca86d1b0a3c37fbe39cee4c7e839e33bef9174531d27f52".to_string(),
+///             custom_data: None,
+///         }.into(),
+///         num_sats: 50,
+///         name: Some("App".to_string()),
+///         ..Default::default()
+///     },
+/// ];
+///
+/// assert_eq!(
+///     format_payout_plan(&recipients, 1000),
+///     "\
+/// Name       Pubkey         Sats  Share
+/// Podcaster  03ae9f91…1a4a   950    95%
+/// App        02d5c1bf…7f52    50     5%
+/// Total                     1000   100%
+/// "
+/// );
+/// ```
+pub fn format_payout_plan(recipients: &[PaymentRecipientInfo], total_sats: u64) -> String {
+    const PUBKEY_PREFIX_LEN: usize = 8;
+    const PUBKEY_SUFFIX_LEN: usize = 4;
+
+    let shorten_pubkey = |pubkey: &str| {
+        let chars: Vec<char> = pubkey.chars().collect();
+        if chars.len() <= PUBKEY_PREFIX_LEN + PUBKEY_SUFFIX_LEN + 1 {
+            return pubkey.to_string();
+        }
+        let prefix: String = chars[..PUBKEY_PREFIX_LEN].iter().collect();
+        let suffix: String = chars[chars.len() - PUBKEY_SUFFIX_LEN..].iter().collect();
+        format!("{}…{}", prefix, suffix)
+    };
+
+    let sats: Vec<u64> = recipients.iter().map(|r| r.num_sats).collect();
+    let percentages = crate::pc20::calc::splits_to_percentages(
+        &sats,
+        crate::pc20::calc::RoundingMode::LargestRemainder,
+    );
+
+    let mut rows: Vec<[String; 5]> = vec![[
+        "Name".to_string(),
+        "Role".to_string(),
+        "Pubkey".to_string(),
+        "Sats".to_string(),
+        "Share".to_string(),
+    ]];
+    for (recipient, percentage) in recipients.iter().zip(percentages) {
+        rows.push([
+            recipient.name.clone().unwrap_or_else(|| "-".to_string()),
+            recipient.role.clone().unwrap_or_else(|| "-".to_string()),
+            match &recipient.address {
+                RecipientAddress::Keysend(address) => shorten_pubkey(&address.pubkey),
+                RecipientAddress::LightningAddress(address) => address.clone(),
+            },
+            recipient.num_sats.to_string(),
+            format!("{}%", percentage),
+        ]);
+    }
+
+    let recipients_sats: u64 = sats.iter().sum();
+    if recipients_sats < total_sats {
+        rows.push([
+            "Unallocated".to_string(),
+            String::new(),
+            String::new(),
+            (total_sats - recipients_sats).to_string(),
+            String::new(),
+        ]);
+    }
+    rows.push([
+        "Total".to_string(),
+        String::new(),
+        String::new(),
+        total_sats.to_string(),
+        if recipients.is_empty() {
+            String::new()
+        } else {
+            "100%".to_string()
+        },
+    ]);
+
+    let width = |column: usize| {
+        rows.iter()
+            .map(|row| row[column].chars().count())
+            .max()
+            .unwrap_or(0)
+    };
+    let widths = [width(0), width(1), width(2), width(3), width(4)];
+    // The role column is only shown if any recipient has a role.
+    let show_role = recipients.iter().any(|recipient| recipient.role.is_some());
+
+    let mut output = String::new();
+    for row in rows {
+        let role = if show_role {
+            format!("{:<role$}  ", row[1], role = widths[1])
+        } else {
+            String::new()
+        };
+        let line = format!(
+            "{:<name$}  {}{:<pubkey$}  {:>sats$}  {:>share$}",
+            row[0],
+            role,
+            row[2],
+            row[3],
+            row[4],
+            name = widths[0],
+            pubkey = widths[2],
+            sats = widths[3],
+            share = widths[4],
+        );
+        output.push_str(line.trim_end());
+        output.push('\n');
+    }
+
+    output
+}
+
+/// Builds the keysend items for [make_payment] without sending them, e.g., for a dry run.
+///
+/// Performs the same checks as [make_payment] and returns one item per recipient, in order. If a
+/// recipient's [custom data](KeysendAddress::custom_data) uses the bLIP-10 record key
+/// ([TLV_RECORD_KEY]), the bLIP-10 record takes precedence and the custom data is not sent.
+///
+/// Fails with [RequestError::InvalidArgs] if a recipient has a
+/// [lightning address](RecipientAddress::LightningAddress), since it has to be resolved first.
+///
+/// ## Example
+/// ```rust
+/// use v4v::pc20::payments::{build_keysends, MakePaymentArgs, PaymentInfo, PaymentRecipientInfo};
+/// use v4v::pc20::tlv::TLV_RECORD_KEY;
+///
+/// let args = MakePaymentArgs {
+///     payment_info: PaymentInfo {
+///         message: Some("Great episode!".to_string()),
+///         total_num_sats: 21,
+///         ..Default::default()
+///     },
+///     recipients: vec![PaymentRecipientInfo { num_sats: 21, ..Default::default() }],
+///     ..Default::default()
+/// };
+/// let keysends = build_keysends(&args).unwrap();
+/// let record: serde_json::Value =
+///     serde_json::from_str(&keysends[0].custom_records[TLV_RECORD_KEY]).unwrap();
+/// assert_eq!(record["message"], "Great episode!");
+/// ```
+pub fn build_keysends<'a>(
+    args: &'a MakePaymentArgs<'_>,
+) -> Result<Vec<MultiKeysendItemArgs<'a>>, RequestError> {
+    if let Some(recipient) = args
+        .recipients
+        .iter()
+        .find(|recipient| recipient.address.keysend().is_none())
+    {
+        return Err(RequestError::InvalidArgs(format!(
+            "lightning address {} has to be resolved into a keysend address first",
+            recipient.address
+        )));
+    }
+
+    build_keysend_items(args).map(|items| items.into_iter().map(|(_, item)| item).collect())
+}
+
+/// Checks the arguments of a payment before anything is sent, and returns its amount report.
+fn check_payment_args(args: &MakePaymentArgs<'_>) -> Result<AmountReport, RequestError> {
+    let amount_report = args.amount_report();
+    check_max_sats(
+        amount_report
+            .total_num_sats
+            .max(amount_report.recipients_num_sats),
+        args.max_sats,
+    )?;
+    if args.require_all_or_nothing {
+        if let Some(recipient) = args.recipients.iter().find(|r| r.num_sats == 0) {
+            return Err(RequestError::InvalidArgs(format!(
+                "recipient {} would receive no sats, so the payout can't be complete",
+                recipient.address
+            )));
+        }
+    }
+    if let Some(sender_id) = args.payment_info.sender_id.as_deref() {
+        if sender_id.len() > MAX_SENDER_ID_LEN {
+            return Err(RequestError::InvalidArgs(format!(
+                "sender ID is {} bytes long, more than the maximum of {} bytes",
+                sender_id.len(),
+                MAX_SENDER_ID_LEN
+            )));
+        }
+    }
+    if (args.strict_amounts || args.require_all_or_nothing) && amount_report.is_overspend() {
+        return Err(RequestError::InvalidArgs(format!(
+            "recipients would receive {} sats, more than the total of {} sats",
+            amount_report.recipients_num_sats, amount_report.total_num_sats
+        )));
+    }
+
+    Ok(amount_report)
+}
+
+/// Builds the keysend items of the recipients with a keysend address, along with the recipients'
+/// indices. Checks all the recipients, including those with a lightning address.
+fn build_keysend_items<'a>(
+    args: &'a MakePaymentArgs<'_>,
+) -> Result<Vec<(usize, MultiKeysendItemArgs<'a>)>, RequestError> {
+    let amount_report = check_payment_args(args)?;
+
+    let mut keysends: Vec<(usize, MultiKeysendItemArgs)> = vec![];
+
+    // The message is the same for every recipient, so it is truncated once.
+    let message = args.payment_info.message.as_deref().map(|message| {
+        truncate_message(
+            message,
+            args.max_message_bytes.unwrap_or(DEFAULT_MAX_MESSAGE_BYTES),
+            !args.omit_truncation_ellipsis,
+        )
+    });
+
+    for (index, recipient) in args.recipients.iter().enumerate() {
+        let Some(address) = recipient.address.keysend() else {
+            continue;
+        };
 
-    for recipient in args.recipients.iter() {
         let mut custom_records = HashMap::new();
-        if let Some(custom_data) = recipient.address.custom_data.as_ref() {
-            custom_records.insert(custom_data.0.clone(), custom_data.1.clone());
-        }
-
-        let tlv_record = Record {
-            action: args.payment_info.action.clone(),
-            feed_guid: args.payment_info.feed_guid,
-            feed_name: args.payment_info.feed_name.clone(),
-            feed_pi_id: args.payment_info.feed_pi_id,
-            feed_url: args.payment_info.feed_url.clone(),
-            item_guid: args.payment_info.item_guid.clone(),
-            item_name: args.payment_info.item_name.clone(),
-            item_pi_id: args.payment_info.item_pi_id,
-            timestamp_seconds: args.payment_info.timestamp,
-            timestamp_hhmmss: None,
-            speed: args.payment_info.speed,
-            app_name: args.payment_info.app_name.clone(),
-            app_version: args.payment_info.app_version.clone(),
-            sender_name: args.payment_info.sender_name.clone(),
-            sender_id: args.payment_info.sender_id.clone(),
-            receiver_name: recipient.name.clone(),
-            total_num_millisats: match args.payment_info.total_num_sats {
-                0 => Some(args.recipients.iter().map(|r| r.num_sats).sum::<u64>() * 1000),
-                _ => Some(args.payment_info.total_num_sats * 1000),
+        if let Some(custom_data) = address.custom_data.as_ref() {
+            // The bLIP-10 record always wins over colliding custom data.
+            if custom_data.0 != TLV_RECORD_KEY {
+                custom_records.insert(custom_data.0.clone(), custom_data.1.clone());
+            }
+        }
+
+        let tlv_record_string = match args.payment_info.raw_boostagram.as_ref() {
+            Some(raw_boostagram) => raw_boostagram.clone(),
+            None => build_tlv_record_string(args, recipient, message.as_deref(), amount_report)?,
+        };
+        custom_records.insert(TLV_RECORD_KEY.to_string(), tlv_record_string);
+
+        keysends.push((
+            index,
+            MultiKeysendItemArgs {
+                num_sats: recipient.num_sats,
+                dest_pubkey: address.pubkey.as_str(),
+                custom_records,
+            },
+        ));
+    }
+
+    Ok(keysends)
+}
+
+/// Serializes the bLIP-10 record of a single recipient for [build_keysends].
+fn build_tlv_record_string(
+    args: &MakePaymentArgs<'_>,
+    recipient: &PaymentRecipientInfo,
+    message: Option<&str>,
+    amount_report: AmountReport,
+) -> Result<String, RequestError> {
+    let payment_info = match recipient.feed_guid {
+        Some(feed_guid) => Cow::Owned(
+            args.payment_info
+                .for_remote_value_block(Some(feed_guid), recipient.item_guid.clone()),
+        ),
+        None => Cow::Borrowed(&args.payment_info),
+    };
+
+    let tlv_record = Record {
+        action: payment_info.action.clone(),
+        feed_guid: payment_info.feed_guid,
+        feed_name: payment_info.feed_name.clone(),
+        feed_pi_id: payment_info.feed_pi_id,
+        feed_url: payment_info.feed_url.clone(),
+        item_guid: payment_info.item_guid.clone(),
+        item_name: payment_info.item_name.clone(),
+        item_pi_id: payment_info.item_pi_id,
+        timestamp_seconds: payment_info.timestamp,
+        timestamp_hhmmss: payment_info.timestamp.filter(|_| args.emit_hhmmss),
+        speed: payment_info.speed,
+        app_name: payment_info.app_name.clone(),
+        app_version: payment_info.app_version.clone(),
+        sender_name: payment_info.sender_name.clone(),
+        sender_id: payment_info.sender_id.clone(),
+        receiver_name: recipient.name.clone(),
+        total_num_millisats: Some(amount_report.total_num_millisats),
+        message: message.map(str::to_string),
+        boost_link: payment_info.boost_link.clone(),
+        payment_signature: recipient.payment_signature.clone(),
+        payment_id: recipient.payment_id,
+        boost_id: payment_info.boost_id,
+        remote_feed_guid: payment_info.remote_feed_guid.map(RemoteFeedGuid::Uuid),
+        remote_item_guid: payment_info.remote_item_guid.clone(),
+        reply_address: args
+            .payment_info
+            .reply_address
+            .as_ref()
+            .map(|address| address.pubkey.clone()),
+        reply_custom_key: args
+            .payment_info
+            .reply_address
+            .as_ref()
+            .and_then(|address| {
+                address
+                    .custom_data
+                    .as_ref()
+                    .map(|custom_data| custom_data.0.clone())
+            }),
+        reply_custom_value: args
+            .payment_info
+            .reply_address
+            .as_ref()
+            .and_then(|address| {
+                address
+                    .custom_data
+                    .as_ref()
+                    .map(|custom_data| custom_data.1.clone())
+            }),
+    };
+
+    // Non-standard fields added on top of the bLIP-10 record.
+    let mut extra_fields = serde_json::Map::new();
+    for (key, value) in recipient.custom_fields.iter() {
+        // Standard fields always take precedence over custom ones.
+        if !BLIP10_FIELDS.iter().any(|(_, wire_key)| wire_key == key) {
+            extra_fields.insert(key.clone(), value.clone());
+        }
+    }
+    if let Some(fiat_value) = args.payment_info.fiat_value {
+        extra_fields.insert("fiat_value".to_string(), fiat_value.into());
+    }
+    if let Some(fiat_currency) = args.payment_info.fiat_currency.as_ref() {
+        extra_fields.insert("fiat_currency".to_string(), fiat_currency.clone().into());
+    }
+    if args.amount_encoding == AmountEncoding::MsatAndSat {
+        extra_fields.insert(
+            "value_sat_total".to_string(),
+            (amount_report.total_num_millisats / 1000).into(),
+        );
+    }
+
+    if extra_fields.is_empty() {
+        serde_json::to_string(&tlv_record)
+    } else {
+        serde_json::to_value(&tlv_record).and_then(|mut value| {
+            if let Some(object) = value.as_object_mut() {
+                for (key, value) in extra_fields {
+                    object.insert(key, value);
+                }
+            }
+            serde_json::to_string(&value)
+        })
+    }
+    .map_err(|error| RequestError::Unexpected(format!("Failed to serialize TLV record: {}", error)))
+}
+
+/// Outcome of the payment to a single recipient of [make_payment].
+#[derive(Debug)]
+pub struct PaymentOutcome {
+    /// Index of the recipient in [MakePaymentArgs::recipients].
+    pub recipient_index: usize,
+    /// The keysend response if the payment succeeded, the error otherwise.
+    ///
+    /// For a recipient paid through an invoice (see [RecipientAddress::LightningAddress]), the
+    /// response is built from the invoice payment, without custom records.
+    pub result: Result<KeysendResponse, ErrorResponse>,
+}
+
+impl PaymentOutcome {
+    /// Whether the payment succeeded.
+    pub fn is_success(&self) -> bool {
+        self.result.is_ok()
+    }
+}
+
+/// Send a payment to multiple Podcasting 2.0 recipients using the Alby API.
+///
+/// Returns an outcome for every recipient, in the order of [MakePaymentArgs::recipients]. The
+/// keysends are independent, so some of them can fail while others succeed; check every outcome,
+/// e.g., to retry only the failed recipients.
+///
+/// With [MakePaymentArgs::resolve_lightning_addresses] set, recipients with a
+/// [lightning address](RecipientAddress::LightningAddress) are resolved before the keysends are
+/// sent, which adds latency. Those that can't be paid via keysend are paid through an invoice
+/// after the keysends.
+///
+/// Dropping the returned future doesn't undo keysends that have already been submitted; see
+/// [forward_payments](crate::pc20::forwarding::forward_payments#cancellation).
+pub async fn make_payment(args: MakePaymentArgs<'_>) -> Result<Vec<PaymentOutcome>, RequestError> {
+    let provider = AlbyProvider {
+        user_agent: args.user_agent,
+        token: args.token,
+        base_url: args.base_url,
+        rate_limiter: args.rate_limiter,
+        timeouts: args.timeouts,
+        retry: args.retry,
+    };
+
+    make_payment_with(&provider, args).await
+}
+
+/// Like [make_payment], but sends the keysends through `provider` instead of Alby.
+///
+/// The Alby-specific fields of [MakePaymentArgs] (`token`, `rate_limiter`, `base_url`, and
+/// `retry`) are ignored; configure the provider instead. `user_agent` and `timeouts` are only used
+/// for resolving lightning addresses. Invoices of recipients that don't support keysend are paid
+/// with [PaymentProvider::pay_invoice].
+pub async fn make_payment_with<P: PaymentProvider>(
+    provider: &P,
+    mut args: MakePaymentArgs<'_>,
+) -> Result<Vec<PaymentOutcome>, RequestError> {
+    if !args.resolve_lightning_addresses {
+        if let Some(recipient) = args
+            .recipients
+            .iter()
+            .find(|recipient| recipient.address.keysend().is_none())
+        {
+            return Err(RequestError::InvalidArgs(format!(
+                "lightning address {} is only paid with resolve_lightning_addresses set",
+                recipient.address
+            )));
+        }
+    }
+    // Fail fast, before any request is sent to the recipients' domains.
+    check_payment_args(&args)?;
+    resolve_lightning_addresses(&mut args).await;
+
+    let (keysend_indices, keysends): (Vec<usize>, Vec<MultiKeysendItemArgs>) =
+        build_keysend_items(&args)?.into_iter().unzip();
+    let mut outcomes = Vec::with_capacity(args.recipients.len());
+
+    if !keysends.is_empty() {
+        let num_keysends = keysends.len();
+        let response = provider.multi_keysend(keysends).await?;
+
+        // Keysends are built one per recipient with a keysend address, in order.
+        if response.keysends.len() != num_keysends {
+            return Err(RequestError::Unexpected(format!(
+                "Expected {} keysend responses, got {}",
+                num_keysends,
+                response.keysends.len()
+            )));
+        }
+
+        outcomes.extend(keysend_indices.into_iter().zip(response.keysends).map(
+            |(recipient_index, item)| PaymentOutcome {
+                recipient_index,
+                result: item.into_result(),
             },
-            message: args.payment_info.message.clone(),
-            boost_link: args.payment_info.boost_link.clone(),
-            payment_signature: recipient.payment_signature.clone(),
-            payment_id: recipient.payment_id,
-            boost_id: args.payment_info.boost_id,
-            remote_feed_guid: args.payment_info.remote_feed_guid,
-            remote_item_guid: args.payment_info.remote_item_guid.clone(),
-            reply_address: args
-                .payment_info
-                .reply_address
-                .as_ref()
-                .map(|address| address.pubkey.clone()),
-            reply_custom_key: args
-                .payment_info
-                .reply_address
-                .as_ref()
-                .and_then(|address| {
-                    address
-                        .custom_data
-                        .as_ref()
-                        .map(|custom_data| custom_data.0.clone())
-                }),
-            reply_custom_value: args
-                .payment_info
-                .reply_address
-                .as_ref()
-                .and_then(|address| {
-                    address
-                        .custom_data
-                        .as_ref()
-                        .map(|custom_data| custom_data.1.clone())
-                }),
+        ));
+    }
+
+    // Recipients whose lightning address couldn't be resolved.
+    for (recipient_index, recipient) in args.recipients.iter().enumerate() {
+        if let RecipientAddress::LightningAddress(address) = &recipient.address {
+            let result = pay_lightning_address(provider, &args, address, recipient.num_sats)
+                .await
+                .map(invoice_keysend_response)
+                .map_err(|error| ErrorResponse {
+                    code: 0,
+                    error: true,
+                    message: error.to_string(),
+                });
+            outcomes.push(PaymentOutcome {
+                recipient_index,
+                result,
+            });
+        }
+    }
+
+    outcomes.sort_by_key(|outcome| outcome.recipient_index);
+    Ok(outcomes)
+}
+
+/// Replaces the lightning addresses of the recipients with the keysend addresses they resolve
+/// to. Addresses that can't be resolved are kept, to be paid through an invoice.
+async fn resolve_lightning_addresses(args: &mut MakePaymentArgs<'_>) {
+    for recipient in args.recipients.iter_mut() {
+        let RecipientAddress::LightningAddress(address) = &recipient.address else {
+            continue;
+        };
+
+        let resolved = match args.lightning_address_base_url {
+            Some(base_url) => {
+                resolve_lightning_address_at(args.user_agent, address, base_url).await
+            }
+            None => resolve_lightning_address(args.user_agent, address).await,
         };
+        match resolved {
+            Ok(keysend_address) => recipient.address = RecipientAddress::Keysend(keysend_address),
+            Err(error) => log::debug!(
+                "Failed to resolve {} into a keysend address, falling back to an invoice: {}",
+                address,
+                error
+            ),
+        }
+    }
+}
 
-        let tlv_record_string = serde_json::to_string(&tlv_record).map_err(|error| {
-            RequestError::Unexpected(format!("Failed to serialize TLV record: {}", error))
-        })?;
-        // bLIP-10 TLV record:
-        custom_records.insert("7629169".to_string(), tlv_record_string);
+/// Pays a recipient's lightning address through an invoice fetched via LNURL-pay.
+async fn pay_lightning_address<P: PaymentProvider>(
+    provider: &P,
+    args: &MakePaymentArgs<'_>,
+    address: &str,
+    num_sats: u64,
+) -> Result<PayInvoiceResponse, RequestError> {
+    let url = lightning_address_lnurlp_url(address, args.lightning_address_base_url)?;
+    let invoice = fetch_lnurl_invoice(
+        args.user_agent,
+        &url,
+        num_sats,
+        args.payment_info.message.as_deref(),
+        args.timeouts,
+    )
+    .await?;
 
-        keysends.push(MultiKeysendItemArgs {
-            num_sats: recipient.num_sats,
-            dest_pubkey: recipient.address.pubkey.as_str(),
-            custom_records,
-        });
+    provider
+        .pay_invoice(&invoice.invoice, num_sats, &invoice.description_hash)
+        .await
+}
+
+/// Keysend response describing an invoice payment, for [PaymentOutcome::result].
+fn invoice_keysend_response(response: PayInvoiceResponse) -> KeysendResponse {
+    KeysendResponse {
+        num_sats: response.num_sats,
+        description: response.description.unwrap_or_default(),
+        description_hash: String::new(),
+        dest_pubkey: response.dest_pubkey.unwrap_or_default(),
+        fee_in_sats: response.fee_in_sats,
+        custom_records: HashMap::new(),
+        payment_hash: response.payment_hash,
+        payment_preimage: response.payment_preimage,
     }
+}
 
-    crate::alby::api::payments::multi_keysend(crate::alby::api::payments::MultiKeysendArgs {
+/// Key of the custom field carrying the original [boost_id](Record::boost_id) in a reply sent
+/// with [reply_to_boost].
+///
+/// bLIP-10 has no field for this, so only apps that look for it will link the reply to the
+/// original boost.
+pub const REPLY_TO_BOOST_ID_KEY: &str = "reply_to_boost_uuid";
+
+/// Arguments for [reply_to_boost].
+#[derive(Debug, Default)]
+pub struct ReplyToBoostArgs<'a> {
+    /// User agent string.
+    pub user_agent: &'a str,
+    /// Alby token.
+    pub token: &'a str,
+    /// Message of the reply.
+    pub message: Option<String>,
+    /// Name of the replier, e.g., the podcaster.
+    pub sender_name: Option<String>,
+    /// Name of the replying app.
+    pub app_name: Option<String>,
+    /// Version of the replying app.
+    pub app_version: Option<String>,
+    /// [PaymentInfo::boost_id] of the reply. A random one is generated if `None`; set it to be
+    /// able to recognize a retried reply.
+    pub boost_id: Option<Uuid>,
+    /// Optional client-side rate limiter. Off if `None`.
+    pub rate_limiter: Option<&'a RateLimiter>,
+    /// Timeouts of the Alby request.
+    pub timeouts: Timeouts,
+    /// Base URL of the Alby API, [DEFAULT_BASE_URL](crate::alby::api::DEFAULT_BASE_URL) if
+    /// `None`.
+    pub base_url: Option<&'a str>,
+    /// Policy for retrying the Alby request; see [RetryPolicy#paying-twice] for the risk of paying
+    /// twice.
+    pub retry: Option<RetryPolicy>,
+}
+
+/// Sends a boost back to the sender of a received boost (a "reply boost"), using the reply
+/// fields of the received bLIP-10 record.
+///
+/// The reply is about the same feed and item as the received boost, carries a fresh
+/// [boost_id](ReplyToBoostArgs::boost_id), and references the original boost's ID under
+/// [REPLY_TO_BOOST_ID_KEY].
+///
+/// Fails with [RequestError::InvalidArgs] if the record has no keysend reply route (see
+/// [Record::reply_keysend_address]), e.g., if the reply address is a lightning address.
+pub async fn reply_to_boost(
+    received: &Record,
+    amount_sats: u64,
+    args: ReplyToBoostArgs<'_>,
+) -> Result<Vec<PaymentOutcome>, RequestError> {
+    make_payment(build_reply_args(received, amount_sats, args)?).await
+}
+
+fn build_reply_args<'a>(
+    received: &Record,
+    amount_sats: u64,
+    args: ReplyToBoostArgs<'a>,
+) -> Result<MakePaymentArgs<'a>, RequestError> {
+    let address = received.reply_keysend_address().ok_or_else(|| {
+        RequestError::InvalidArgs(if received.is_lightning_address_reply() {
+            "replying to a lightning address is not supported".to_string()
+        } else {
+            "received record has no keysend reply address".to_string()
+        })
+    })?;
+
+    let mut custom_fields = HashMap::new();
+    if let Some(boost_id) = received.boost_id {
+        custom_fields.insert(
+            REPLY_TO_BOOST_ID_KEY.to_string(),
+            boost_id.to_string().into(),
+        );
+    }
+
+    Ok(MakePaymentArgs {
         user_agent: args.user_agent,
         token: args.token,
-        keysends,
+        payment_info: PaymentInfo {
+            action: Action::Boost,
+            feed_guid: received.feed_guid,
+            feed_name: received.feed_name.clone(),
+            feed_pi_id: received.feed_pi_id,
+            feed_url: received.feed_url.clone(),
+            item_guid: received.item_guid.clone(),
+            item_name: received.item_name.clone(),
+            item_pi_id: received.item_pi_id,
+            app_name: args.app_name,
+            app_version: args.app_version,
+            sender_name: args.sender_name,
+            total_num_sats: amount_sats,
+            message: args.message,
+            boost_id: Some(args.boost_id.unwrap_or_else(Uuid::new_v4)),
+            ..Default::default()
+        },
+        recipients: vec![PaymentRecipientInfo {
+            address: address.into(),
+            num_sats: amount_sats,
+            name: received.sender_name.clone(),
+            custom_fields,
+            ..Default::default()
+        }],
+        rate_limiter: args.rate_limiter,
+        timeouts: args.timeouts,
+        base_url: args.base_url,
+        retry: args.retry,
+        ..Default::default()
     })
-    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn build_keysends_tlv_record_wins_over_colliding_custom_data() {
+        let args = MakePaymentArgs {
+            payment_info: PaymentInfo {
+                message: Some("Hello".to_string()),
+                total_num_sats: 10,
+                ..Default::default()
+            },
+            recipients: vec![PaymentRecipientInfo {
+                address: KeysendAddress {
+                    pubkey: "pubkey".to_string(),
+                    custom_data: Some((TLV_RECORD_KEY.to_string(), "wallet".to_string())),
+                }
+                .into(),
+                num_sats: 10,
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+
+        let keysends = build_keysends(&args).unwrap();
+        assert_eq!(keysends.len(), 1);
+        assert_eq!(keysends[0].custom_records.len(), 1);
+
+        let record: serde_json::Value =
+            serde_json::from_str(&keysends[0].custom_records[TLV_RECORD_KEY]).unwrap();
+        assert_eq!(record["message"], "Hello");
+    }
+
+    #[test]
+    fn build_keysends_truncates_long_message() {
+        let message = "Great episode! 🎉 ".repeat(250);
+        assert!(message.len() > 4000);
+
+        let args = MakePaymentArgs {
+            payment_info: PaymentInfo {
+                message: Some(message.clone()),
+                total_num_sats: 10,
+                ..Default::default()
+            },
+            recipients: vec![PaymentRecipientInfo {
+                num_sats: 10,
+                ..Default::default()
+            }],
+            max_message_bytes: Some(300),
+            ..Default::default()
+        };
+
+        let keysends = build_keysends(&args).unwrap();
+        let record: serde_json::Value =
+            serde_json::from_str(&keysends[0].custom_records[TLV_RECORD_KEY]).unwrap();
+        let truncated = record["message"].as_str().unwrap();
+        assert!(truncated.len() <= 300);
+        assert!(truncated.ends_with(ELLIPSIS));
+        assert!(message.starts_with(truncated.trim_end_matches(ELLIPSIS)));
+    }
+
+    #[test]
+    fn build_keysends_uses_recipient_remote_guids() {
+        let local_feed_guid = Uuid::parse_str("917393e3-1b1e-5cef-ace4-edaa54e1f810").unwrap();
+        let remote_feed_guid = Uuid::parse_str("a3c3d5c2-8d0e-5b0a-b0a9-53c1b2e1b2a4").unwrap();
+
+        let recipients = merge_recipients_with_provenance(
+            vec![PaymentRecipientInfo {
+                num_sats: 10,
+                ..Default::default()
+            }],
+            vec![PaymentRecipientInfo {
+                num_sats: 90,
+                ..Default::default()
+            }],
+            remote_feed_guid,
+            Some("remote-item".to_string()),
+        );
+
+        let args = MakePaymentArgs {
+            payment_info: PaymentInfo {
+                feed_guid: Some(local_feed_guid),
+                feed_name: Some("Local feed".to_string()),
+                item_guid: Some("local-item".to_string()),
+                timestamp: Some(Duration::seconds(330)),
+                ..Default::default()
+            },
+            recipients: recipients
+                .into_iter()
+                .map(|(recipient, _)| recipient)
+                .collect(),
+            ..Default::default()
+        };
+
+        let keysends = build_keysends(&args).unwrap();
+        let records: Vec<serde_json::Value> = keysends
+            .iter()
+            .map(|keysend| serde_json::from_str(&keysend.custom_records[TLV_RECORD_KEY]).unwrap())
+            .collect();
+
+        assert_eq!(records[0]["guid"], local_feed_guid.to_string());
+        assert_eq!(records[0]["episode_guid"], "local-item");
+        assert_eq!(records[0]["podcast"], "Local feed");
+        assert_eq!(records[0].get("remote_feed_guid"), None);
+        assert_eq!(records[0].get("remote_item_guid"), None);
+
+        assert_eq!(records[1]["guid"], remote_feed_guid.to_string());
+        assert_eq!(records[1]["episode_guid"], "remote-item");
+        assert_eq!(records[1].get("podcast"), None);
+        assert_eq!(records[1]["remote_feed_guid"], local_feed_guid.to_string());
+        assert_eq!(records[1]["remote_item_guid"], "local-item");
+        assert_eq!(records[1]["ts"], 330);
+    }
+
+    #[test]
+    fn build_keysends_amount_encoding() {
+        let records = |amount_encoding| {
+            let args = MakePaymentArgs {
+                payment_info: PaymentInfo {
+                    total_num_sats: 21,
+                    ..Default::default()
+                },
+                recipients: vec![PaymentRecipientInfo {
+                    num_sats: 21,
+                    ..Default::default()
+                }],
+                amount_encoding,
+                ..Default::default()
+            };
+            let keysends = build_keysends(&args).unwrap();
+            serde_json::from_str::<serde_json::Value>(&keysends[0].custom_records[TLV_RECORD_KEY])
+                .unwrap()
+        };
+
+        let record = records(AmountEncoding::Msat);
+        assert_eq!(record["value_msat_total"], 21000);
+        assert_eq!(record.get("value_sat_total"), None);
+
+        let record = records(AmountEncoding::MsatAndSat);
+        assert_eq!(record["value_msat_total"], 21000);
+        assert_eq!(record["value_sat_total"], 21);
+    }
+
+    #[test]
+    fn build_keysends_emit_hhmmss() {
+        let records = |emit_hhmmss| {
+            let args = MakePaymentArgs {
+                payment_info: PaymentInfo {
+                    timestamp: Some(Duration::seconds(3723)),
+                    ..Default::default()
+                },
+                recipients: vec![PaymentRecipientInfo {
+                    num_sats: 1,
+                    ..Default::default()
+                }],
+                emit_hhmmss,
+                ..Default::default()
+            };
+            let keysends = build_keysends(&args).unwrap();
+            serde_json::from_str::<serde_json::Value>(&keysends[0].custom_records[TLV_RECORD_KEY])
+                .unwrap()
+        };
+
+        let record = records(false);
+        assert_eq!(record["ts"], 3723);
+        assert_eq!(record.get("time"), None);
+
+        let record = records(true);
+        assert_eq!(record["ts"], 3723);
+        assert_eq!(record["time"], "01:02:03");
+    }
+
+    #[test]
+    fn build_keysends_value_msat_total_falls_back_to_recipients() {
+        let args = MakePaymentArgs {
+            recipients: vec![
+                PaymentRecipientInfo {
+                    num_sats: 7,
+                    ..Default::default()
+                },
+                PaymentRecipientInfo {
+                    num_sats: 3,
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+        let keysends = build_keysends(&args).unwrap();
+        for keysend in keysends {
+            let record: serde_json::Value =
+                serde_json::from_str(&keysend.custom_records[TLV_RECORD_KEY]).unwrap();
+            assert_eq!(record["value_msat_total"], 10000);
+        }
+    }
+
+    #[test]
+    fn build_keysends_custom_fields() {
+        let args = MakePaymentArgs {
+            payment_info: PaymentInfo {
+                message: Some("Hello".to_string()),
+                total_num_sats: 10,
+                ..Default::default()
+            },
+            recipients: vec![PaymentRecipientInfo {
+                num_sats: 10,
+                custom_fields: HashMap::from([
+                    ("internal_id".to_string(), serde_json::json!(42)),
+                    ("message".to_string(), serde_json::json!("Overridden")),
+                    ("episode".to_string(), serde_json::json!("Overridden")),
+                ]),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+
+        let keysends = build_keysends(&args).unwrap();
+        let record: serde_json::Value =
+            serde_json::from_str(&keysends[0].custom_records[TLV_RECORD_KEY]).unwrap();
+        assert_eq!(record["internal_id"], 42);
+        assert_eq!(record["message"], "Hello");
+        assert_eq!(record.get("episode"), None);
+
+        // Custom fields survive being stored and reloaded, e.g., in invoice metadata.
+        let recipient: PaymentRecipientInfo =
+            serde_json::from_value(serde_json::to_value(&args.recipients[0]).unwrap()).unwrap();
+        assert_eq!(recipient.custom_fields["internal_id"], 42);
+    }
+
+    #[test]
+    fn build_keysends_role_is_not_sent() {
+        let args = MakePaymentArgs {
+            payment_info: PaymentInfo {
+                total_num_sats: 10,
+                ..Default::default()
+            },
+            recipients: vec![PaymentRecipientInfo {
+                num_sats: 10,
+                role: Some("host".to_string()),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+
+        let keysends = build_keysends(&args).unwrap();
+        let record: serde_json::Value =
+            serde_json::from_str(&keysends[0].custom_records[TLV_RECORD_KEY]).unwrap();
+        assert_eq!(record.get("role"), None);
+
+        // The role survives being stored and reloaded, e.g., in invoice metadata.
+        let recipient: PaymentRecipientInfo =
+            serde_json::from_value(serde_json::to_value(&args.recipients[0]).unwrap()).unwrap();
+        assert_eq!(recipient.role.as_deref(), Some("host"));
+    }
+
+    #[test]
+    fn build_keysends_raw_boostagram() {
+        let raw_boostagram = r#"{"action":"boost","future_field":[1,2,3]}"#;
+        let args = MakePaymentArgs {
+            payment_info: PaymentInfo {
+                total_num_sats: 10,
+                message: Some("Ignored".to_string()),
+                raw_boostagram: Some(raw_boostagram.to_string()),
+                ..Default::default()
+            },
+            recipients: vec![PaymentRecipientInfo {
+                num_sats: 10,
+                name: Some("Ignored".to_string()),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+
+        let keysends = build_keysends(&args).unwrap();
+        assert_eq!(keysends[0].custom_records[TLV_RECORD_KEY], raw_boostagram);
+
+        // It can't be set by deserialized input.
+        let payment_info: PaymentInfo = serde_json::from_value(serde_json::json!({
+            "totalNumSats": 10,
+            "rawBoostagram": raw_boostagram,
+        }))
+        .unwrap();
+        assert_eq!(payment_info.raw_boostagram, None);
+    }
+
+    #[test]
+    fn build_keysends_fiat_value() {
+        let records = |fiat_value, fiat_currency| {
+            let args = MakePaymentArgs {
+                payment_info: PaymentInfo {
+                    total_num_sats: 1000,
+                    fiat_value,
+                    fiat_currency,
+                    ..Default::default()
+                },
+                recipients: vec![PaymentRecipientInfo {
+                    num_sats: 1000,
+                    ..Default::default()
+                }],
+                ..Default::default()
+            };
+            let keysends = build_keysends(&args).unwrap();
+            assert_eq!(keysends[0].num_sats, 1000);
+            serde_json::from_str::<serde_json::Value>(&keysends[0].custom_records[TLV_RECORD_KEY])
+                .unwrap()
+        };
+
+        let record = records(Some(0.65), Some("USD".to_string()));
+        assert_eq!(record["fiat_value"], 0.65);
+        assert_eq!(record["fiat_currency"], "USD");
+        assert_eq!(record["value_msat_total"], 1_000_000);
+
+        let record = records(None, None);
+        assert_eq!(record.get("fiat_value"), None);
+        assert_eq!(record.get("fiat_currency"), None);
+    }
+
+    #[test]
+    fn truncate_message_on_char_boundary() {
+        assert_eq!(truncate_message("Hello", 5, true), "Hello");
+        assert_eq!(truncate_message("Hello!", 5, true), "He…");
+        assert_eq!(truncate_message("Hello!", 5, false), "Hello");
+        // "🎉" takes 4 bytes, so it can't be cut in half.
+        assert_eq!(truncate_message("🎉🎉🎉", 9, true), "🎉…");
+        assert_eq!(truncate_message("🎉🎉🎉", 9, false), "🎉🎉");
+        assert_eq!(truncate_message("🎉🎉🎉", 6, true), "…");
+        assert_eq!(truncate_message("Hello", 2, true), "He");
+        assert_eq!(truncate_message("🎉", 2, true), "");
+    }
+
+    fn parse_record(json: &str) -> Record {
+        super::super::tlv::parse_records_stream(json.as_bytes())
+            .next()
+            .unwrap()
+            .unwrap()
+    }
+
+    #[test]
+    fn build_reply_args_to_keysend_address() {
+        let received = parse_record(
+            r#"{"action":"boost","guid":"917393e3-1b1e-5cef-ace4-edaa54e1f810","episode_guid":"ep-1","sender_name":"Alice","message":"Great show!","boost_uuid":"5ad37bc5-9f3f-4e3b-8a1d-3c5a0b6e6f7a","reply_address":"030a58b8653d32b99200a2334cfe913e51dc7d155aa0116c176657a4f1722677a3","reply_custom_key":"696969","reply_custom_value":"alice-wallet"}"#,
+        );
+
+        let args = build_reply_args(
+            &received,
+            21,
+            ReplyToBoostArgs {
+                message: Some("Thanks, Alice!".to_string()),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        assert_eq!(
+            args.payment_info.feed_guid.map(|guid| guid.to_string()),
+            Some("917393e3-1b1e-5cef-ace4-edaa54e1f810".to_string())
+        );
+        assert_eq!(args.payment_info.item_guid.as_deref(), Some("ep-1"));
+        assert_eq!(args.payment_info.total_num_sats, 21);
+        assert_eq!(args.payment_info.message.as_deref(), Some("Thanks, Alice!"));
+        assert!(args.payment_info.boost_id.is_some());
+        assert_ne!(args.payment_info.boost_id, received.boost_id);
+
+        assert_eq!(args.recipients.len(), 1);
+        let recipient = &args.recipients[0];
+        assert_eq!(
+            recipient.address,
+            RecipientAddress::Keysend(KeysendAddress {
+                pubkey: "030a58b8653d32b99200a2334cfe913e51dc7d155aa0116c176657a4f1722677a3"
+                    .to_string(),
+                custom_data: Some(("696969".to_string(), "alice-wallet".to_string())),
+            })
+        );
+        assert_eq!(recipient.num_sats, 21);
+        assert_eq!(recipient.name.as_deref(), Some("Alice"));
+        assert_eq!(
+            recipient.custom_fields[REPLY_TO_BOOST_ID_KEY],
+            "5ad37bc5-9f3f-4e3b-8a1d-3c5a0b6e6f7a"
+        );
+    }
+
+    #[test]
+    fn build_reply_args_without_reply_route() {
+        let no_reply_address = parse_record(r#"{"action":"boost","message":"Hi"}"#);
+        let lightning_address =
+            parse_record(r#"{"action":"boost","reply_address":"alice@example.com"}"#);
+
+        for received in [no_reply_address, lightning_address] {
+            match build_reply_args(&received, 21, ReplyToBoostArgs::default()) {
+                Err(RequestError::InvalidArgs(_)) => {}
+                other => panic!("expected InvalidArgs, got {:?}", other.map(|_| ())),
+            }
+        }
+    }
 }
@@ -0,0 +1,171 @@
+use std::future::Future;
+
+use crate::alby::api::{
+    invoices::{CreateInvoiceArgs, CreateInvoiceResponse, DecodeInvoiceArgs},
+    payments::{
+        MultiKeysendArgs, MultiKeysendItemArgs, MultiKeysendResponse, PayInvoiceArgs,
+        PayInvoiceResponse,
+    },
+    RateLimiter, RequestError, RetryPolicy, Timeouts,
+};
+
+/// Invoice to be created by a [PaymentProvider].
+#[derive(Debug, Clone, Default)]
+pub struct InvoiceRequest {
+    /// The amount of sats.
+    pub num_sats: u64,
+    /// Arbitrary metadata, e.g., the forwarding data of
+    /// [create_invoice](crate::pc20::forwarding::create_invoice).
+    pub metadata: serde_json::Value,
+    /// Arbitrary text (included in the BOLT11 invoice).
+    pub description: Option<String>,
+    /// Name of payer (not included in the BOLT11 invoice).
+    pub payer_name: Option<String>,
+}
+
+/// Backend that sends and receives payments, e.g., Alby or a self-hosted node.
+///
+/// The payment functions of this crate use Alby by default; implement this trait to use them with
+/// another backend, and pass it to
+/// [make_payment_with](crate::pc20::payments::make_payment_with),
+/// [forward_payments_with](crate::pc20::forwarding::forward_payments_with), or
+/// [create_invoice_with](crate::pc20::forwarding::create_invoice_with). Responses and errors use
+/// the types of the Alby API.
+pub trait PaymentProvider {
+    /// Sends keysend payments. Must return one response per keysend, in the same order.
+    fn multi_keysend(
+        &self,
+        keysends: Vec<MultiKeysendItemArgs<'_>>,
+    ) -> impl Future<Output = Result<MultiKeysendResponse, RequestError>> + Send;
+
+    /// Creates a BOLT11 invoice.
+    fn create_invoice(
+        &self,
+        invoice: InvoiceRequest,
+    ) -> impl Future<Output = Result<CreateInvoiceResponse, RequestError>> + Send;
+
+    /// Pays a BOLT11 invoice of `num_sats` fetched from an LNURL-pay service, e.g., through the
+    /// lightning address of a recipient that doesn't accept keysend payments.
+    ///
+    /// Must not pay the invoice if its amount differs from `num_sats`, or if its description hash
+    /// differs from `description_hash` (the hex-encoded sha256 hash of the pay request's
+    /// metadata, as required by LUD-06).
+    ///
+    /// Fails with [RequestError::InvalidArgs] by default, so that such recipients can't be paid
+    /// unless the provider implements it.
+    fn pay_invoice(
+        &self,
+        invoice: &str,
+        num_sats: u64,
+        description_hash: &str,
+    ) -> impl Future<Output = Result<PayInvoiceResponse, RequestError>> + Send {
+        let _ = (invoice, num_sats, description_hash);
+        async {
+            Err(RequestError::InvalidArgs(
+                "the payment provider can't pay invoices".to_string(),
+            ))
+        }
+    }
+}
+
+/// [PaymentProvider] using the token-based Alby API.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct AlbyProvider<'a> {
+    /// User agent string.
+    pub user_agent: &'a str,
+    /// Bearer token for authentication.
+    pub token: &'a str,
+    /// Base URL of the Alby API (e.g., of a mock server),
+    /// [DEFAULT_BASE_URL](crate::alby::api::DEFAULT_BASE_URL) if `None`.
+    pub base_url: Option<&'a str>,
+    /// Optional client-side rate limiter for keysends. Off if `None`.
+    pub rate_limiter: Option<&'a RateLimiter>,
+    /// Timeouts of the requests.
+    pub timeouts: Timeouts,
+    /// Policy for retrying failed requests. Not retried if `None`; see
+    /// [RetryPolicy#paying-twice] for the risk of paying twice.
+    pub retry: Option<RetryPolicy>,
+}
+
+impl PaymentProvider for AlbyProvider<'_> {
+    async fn multi_keysend(
+        &self,
+        keysends: Vec<MultiKeysendItemArgs<'_>>,
+    ) -> Result<MultiKeysendResponse, RequestError> {
+        crate::alby::api::payments::multi_keysend(MultiKeysendArgs {
+            user_agent: self.user_agent,
+            token: self.token,
+            base_url: self.base_url,
+            keysends,
+            rate_limiter: self.rate_limiter,
+            timeouts: self.timeouts,
+            retry: self.retry,
+        })
+        .await
+    }
+
+    async fn create_invoice(
+        &self,
+        invoice: InvoiceRequest,
+    ) -> Result<CreateInvoiceResponse, RequestError> {
+        crate::alby::api::invoices::create_invoice(CreateInvoiceArgs {
+            user_agent: self.user_agent,
+            token: self.token,
+            base_url: self.base_url,
+            num_sats: invoice.num_sats,
+            metadata: invoice.metadata,
+            description: invoice.description,
+            payer_name: invoice.payer_name,
+            timeouts: self.timeouts,
+            retry: self.retry,
+        })
+        .await
+    }
+
+    /// Decodes the invoice via Alby and pays it unless its amount differs from `num_sats` or its
+    /// description hash differs from `description_hash`.
+    async fn pay_invoice(
+        &self,
+        invoice: &str,
+        num_sats: u64,
+        description_hash: &str,
+    ) -> Result<PayInvoiceResponse, RequestError> {
+        let decoded = crate::alby::api::invoices::decode_invoice(DecodeInvoiceArgs {
+            user_agent: self.user_agent,
+            token: self.token,
+            base_url: self.base_url,
+            invoice,
+            timeouts: self.timeouts,
+            retry: self.retry,
+        })
+        .await?;
+        if decoded.num_sats != Some(num_sats) {
+            return Err(RequestError::Lnurl(format!(
+                "invoice amount ({:?} sats) differs from the requested amount ({} sats)",
+                decoded.num_sats, num_sats
+            )));
+        }
+        if !decoded
+            .description_hash
+            .as_deref()
+            .is_some_and(|hash| hash.eq_ignore_ascii_case(description_hash))
+        {
+            return Err(RequestError::Lnurl(format!(
+                "invoice description hash ({:?}) differs from the hash of the metadata ({})",
+                decoded.description_hash, description_hash
+            )));
+        }
+
+        crate::alby::api::payments::pay_invoice(PayInvoiceArgs {
+            user_agent: self.user_agent,
+            token: self.token,
+            base_url: self.base_url,
+            invoice,
+            num_sats: None,
+            rate_limiter: self.rate_limiter,
+            timeouts: self.timeouts,
+            retry: self.retry,
+        })
+        .await
+    }
+}
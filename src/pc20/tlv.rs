@@ -1,9 +1,31 @@
 use super::payments::Action;
+use crate::alby::types::KeysendAddress;
 use chrono::Duration;
 use serde_json::Value;
 use url::Url;
 use uuid::Uuid;
 
+/// Custom record key under which the bLIP-10 TLV record is sent.
+pub const TLV_RECORD_KEY: &str = "7629169";
+
+/// Maximum length of [sender_id](Record::sender_id) in bytes, as specified in bLIP-10 (32 bytes,
+/// hex-encoded).
+pub const MAX_SENDER_ID_LEN: usize = 64;
+
+/// Custom record key conventionally used to identify a wallet hosted at a node (e.g., an Alby or
+/// Fountain account), given as `customKey` in `<podcast:valueRecipient>` tags.
+///
+/// See [KeysendAddress::with_wallet_id].
+pub const WALLET_ID_KEY: &str = "696969";
+
+/// Custom record key carrying the payment preimage of a keysend, as defined by
+/// [bLIP-3](https://github.com/lightning/blips/blob/master/blip-0003.md).
+pub const KEYSEND_PREIMAGE_KEY: &str = "5482373484";
+
+/// Custom record key carrying a plain-text chat message, as introduced by
+/// [WhatSat](https://github.com/joostjager/whatsat) and used by many keysend apps.
+pub const KEYSEND_MESSAGE_KEY: &str = "34349334";
+
 /// bLIP-10 TLV record coming from an untrusted source.
 ///
 /// Apps may not conform to
@@ -127,8 +149,67 @@ struct UntrustedRecord {
     reply_custom_value: Value,
 }
 
+/// Names of [Record] fields and the keys they are serialized under, as specified in
+/// [bLIP-10](https://github.com/Podcastindex-org/podcast-namespace/blob/main/value/blip-0010.md#fields).
+pub const BLIP10_FIELDS: &[(&str, &str)] = &[
+    ("action", "action"),
+    ("feed_guid", "guid"),
+    ("feed_name", "podcast"),
+    ("feed_pi_id", "feedID"),
+    ("feed_url", "url"),
+    ("item_guid", "episode_guid"),
+    ("item_name", "episode"),
+    ("item_pi_id", "itemID"),
+    ("timestamp_seconds", "ts"),
+    ("timestamp_hhmmss", "time"),
+    ("speed", "speed"),
+    ("app_name", "app_name"),
+    ("app_version", "app_version"),
+    ("sender_name", "sender_name"),
+    ("sender_id", "sender_id"),
+    ("receiver_name", "name"),
+    ("total_num_millisats", "value_msat_total"),
+    ("message", "message"),
+    ("boost_link", "boost_link"),
+    ("payment_signature", "signature"),
+    ("payment_id", "uuid"),
+    ("boost_id", "boost_uuid"),
+    ("remote_feed_guid", "remote_feed_guid"),
+    ("remote_item_guid", "remote_item_guid"),
+    ("reply_address", "reply_address"),
+    ("reply_custom_key", "reply_custom_key"),
+    ("reply_custom_value", "reply_custom_value"),
+];
+
+/// Returns the mapping between [Record] field names and their bLIP-10 keys.
+///
+/// ## Example
+/// ```rust
+/// let map = v4v::pc20::tlv::blip10_field_map();
+/// assert!(map.contains(&("timestamp_seconds", "ts")));
+/// assert!(map.contains(&("total_num_millisats", "value_msat_total")));
+/// ```
+pub fn blip10_field_map() -> &'static [(&'static str, &'static str)] {
+    BLIP10_FIELDS
+}
+
 /// Well-formed bLIP-10 TLV record.
-#[derive(Debug, serde::Serialize, Clone)]
+///
+/// Deserializing a record is strict, for reading back records from trusted storage: malformed
+/// fields are errors rather than being dropped. Records from other apps should be parsed as
+/// untrusted instead, e.g., with [parse_tlv_from_custom_records].
+///
+/// ## Example
+/// ```rust
+/// use v4v::pc20::tlv::Record;
+///
+/// let json = r#"{"action":"stream","ts":330,"time":"00:05:30"}"#;
+/// let record: Record = serde_json::from_str(json).unwrap();
+/// assert_eq!(record.timestamp_seconds, Some(chrono::Duration::seconds(330)));
+///
+/// assert!(serde_json::from_str::<Record>(r#"{"action":"stream","time":"5 min"}"#).is_err());
+/// ```
+#[derive(Debug, Default, PartialEq, serde::Serialize, serde::Deserialize, Clone)]
 pub struct Record {
     /// ACTION
     pub action: Action,
@@ -165,15 +246,19 @@ pub struct Record {
     ///  Timestamp of when the payment was sent, in seconds, as an offset from zero (i.e. - playback position).
     #[serde(
         rename = "ts",
+        default,
         skip_serializing_if = "Option::is_none",
-        serialize_with = "serialize_duration_to_seconds"
+        serialize_with = "serialize_duration_to_seconds",
+        deserialize_with = "deserialize_seconds_strict"
     )]
     pub timestamp_seconds: Option<Duration>,
     /// Timestamp of when the payment was sent, in HH:MM:SS notation, as an offset from 00:00:00 (i.e. - playback position).
     #[serde(
         rename = "time",
+        default,
         skip_serializing_if = "Option::is_none",
-        serialize_with = "serialize_duration_to_timestamp"
+        serialize_with = "serialize_duration_to_timestamp",
+        deserialize_with = "deserialize_timestamp_strict"
     )]
     pub timestamp_hhmmss: Option<Duration>,
     /// Speed in which the podcast was playing, in decimal notation at the time the payment was sent. So 0.5 is half speed and 2 is double speed.
@@ -229,7 +314,7 @@ pub struct Record {
     ///
     /// Sometimes a payment will be sent to a feed's value block because a different feed referenced it in a <podcast:valueTimeSplit> tag. When that happens, this field will contain the guid of the referencing feed.
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub remote_feed_guid: Option<Uuid>,
+    pub remote_feed_guid: Option<RemoteFeedGuid>,
     /// Sometimes a payment will be sent to an episode's value block because a different feed referenced it in a <podcast:valueTimeSplit> tag. When that happens, this field will contain the guid of the referencing feed's `<item>`.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub remote_item_guid: Option<String>,
@@ -247,6 +332,276 @@ pub struct Record {
     pub reply_custom_value: Option<String>,
 }
 
+/// GUID of the referencing feed in [Record::remote_feed_guid].
+///
+/// bLIP-10 specifies a UUID, but some apps send arbitrary strings, which are preserved instead of
+/// being dropped. Both are (de)serialized as a plain string.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum RemoteFeedGuid {
+    /// A valid UUID.
+    Uuid(Uuid),
+    /// A string that is not a valid UUID.
+    Raw(String),
+}
+
+impl RemoteFeedGuid {
+    /// Parses `guid` as a UUID, keeping it as [RemoteFeedGuid::Raw] if it isn't one.
+    pub fn parse(guid: &str) -> Self {
+        match Uuid::parse_str(guid) {
+            Ok(uuid) => RemoteFeedGuid::Uuid(uuid),
+            Err(_) => RemoteFeedGuid::Raw(guid.to_string()),
+        }
+    }
+
+    /// The UUID, `None` for a [raw](RemoteFeedGuid::Raw) GUID.
+    pub fn as_uuid(&self) -> Option<Uuid> {
+        match self {
+            RemoteFeedGuid::Uuid(uuid) => Some(*uuid),
+            RemoteFeedGuid::Raw(_) => None,
+        }
+    }
+}
+
+impl From<Uuid> for RemoteFeedGuid {
+    fn from(uuid: Uuid) -> Self {
+        RemoteFeedGuid::Uuid(uuid)
+    }
+}
+
+impl std::fmt::Display for RemoteFeedGuid {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RemoteFeedGuid::Uuid(uuid) => write!(f, "{}", uuid),
+            RemoteFeedGuid::Raw(guid) => write!(f, "{}", guid),
+        }
+    }
+}
+
+impl serde::Serialize for RemoteFeedGuid {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for RemoteFeedGuid {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let guid: String = serde::Deserialize::deserialize(deserializer)?;
+        Ok(RemoteFeedGuid::parse(&guid))
+    }
+}
+
+impl Record {
+    /// Creates a builder for a record with only a few fields set; unset fields are `None` and the
+    /// action is [Action::Boost].
+    ///
+    /// ## Example
+    /// ```rust
+    /// use v4v::pc20::{payments::Action, tlv::Record};
+    ///
+    /// let record = Record::builder()
+    ///     .action(Action::Stream)
+    ///     .feed_guid(uuid::Uuid::parse_str("917393e3-1b1e-5cef-ace4-edaa54e1f810").unwrap())
+    ///     .item_guid("ep-1")
+    ///     .total_num_millisats(10_000)
+    ///     .build();
+    /// assert_eq!(
+    ///     serde_json::to_string(&record).unwrap(),
+    ///     r#"{"action":"stream","guid":"917393e3-1b1e-5cef-ace4-edaa54e1f810","episode_guid":"ep-1","value_msat_total":10000}"#,
+    /// );
+    /// ```
+    pub fn builder() -> RecordBuilder {
+        RecordBuilder::default()
+    }
+}
+
+/// Builder for [Record], created with [Record::builder].
+#[derive(Debug, Default)]
+pub struct RecordBuilder {
+    record: Record,
+}
+
+impl RecordBuilder {
+    /// Sets [Record::action].
+    pub fn action(mut self, action: Action) -> Self {
+        self.record.action = action;
+        self
+    }
+
+    /// Sets [Record::feed_guid].
+    pub fn feed_guid(mut self, feed_guid: Uuid) -> Self {
+        self.record.feed_guid = Some(feed_guid);
+        self
+    }
+
+    /// Sets [Record::feed_name].
+    pub fn feed_name(mut self, feed_name: &str) -> Self {
+        self.record.feed_name = Some(feed_name.to_string());
+        self
+    }
+
+    /// Sets [Record::feed_pi_id].
+    pub fn feed_pi_id(mut self, feed_pi_id: u64) -> Self {
+        self.record.feed_pi_id = Some(feed_pi_id);
+        self
+    }
+
+    /// Sets [Record::feed_url].
+    pub fn feed_url(mut self, feed_url: Url) -> Self {
+        self.record.feed_url = Some(feed_url);
+        self
+    }
+
+    /// Sets [Record::item_guid].
+    pub fn item_guid(mut self, item_guid: &str) -> Self {
+        self.record.item_guid = Some(item_guid.to_string());
+        self
+    }
+
+    /// Sets [Record::item_name].
+    pub fn item_name(mut self, item_name: &str) -> Self {
+        self.record.item_name = Some(item_name.to_string());
+        self
+    }
+
+    /// Sets [Record::item_pi_id].
+    pub fn item_pi_id(mut self, item_pi_id: u64) -> Self {
+        self.record.item_pi_id = Some(item_pi_id);
+        self
+    }
+
+    /// Sets [Record::timestamp_seconds].
+    pub fn timestamp_seconds(mut self, timestamp_seconds: Duration) -> Self {
+        self.record.timestamp_seconds = Some(timestamp_seconds);
+        self
+    }
+
+    /// Sets [Record::timestamp_hhmmss].
+    pub fn timestamp_hhmmss(mut self, timestamp_hhmmss: Duration) -> Self {
+        self.record.timestamp_hhmmss = Some(timestamp_hhmmss);
+        self
+    }
+
+    /// Sets [Record::speed].
+    pub fn speed(mut self, speed: f64) -> Self {
+        self.record.speed = Some(speed);
+        self
+    }
+
+    /// Sets [Record::app_name].
+    pub fn app_name(mut self, app_name: &str) -> Self {
+        self.record.app_name = Some(app_name.to_string());
+        self
+    }
+
+    /// Sets [Record::app_version].
+    pub fn app_version(mut self, app_version: &str) -> Self {
+        self.record.app_version = Some(app_version.to_string());
+        self
+    }
+
+    /// Sets [Record::sender_name].
+    pub fn sender_name(mut self, sender_name: &str) -> Self {
+        self.record.sender_name = Some(sender_name.to_string());
+        self
+    }
+
+    /// Sets [Record::sender_id].
+    pub fn sender_id(mut self, sender_id: &str) -> Self {
+        self.record.sender_id = Some(sender_id.to_string());
+        self
+    }
+
+    /// Sets [Record::receiver_name].
+    pub fn receiver_name(mut self, receiver_name: &str) -> Self {
+        self.record.receiver_name = Some(receiver_name.to_string());
+        self
+    }
+
+    /// Sets [Record::total_num_millisats].
+    pub fn total_num_millisats(mut self, total_num_millisats: u64) -> Self {
+        self.record.total_num_millisats = Some(total_num_millisats);
+        self
+    }
+
+    /// Sets [Record::message].
+    pub fn message(mut self, message: &str) -> Self {
+        self.record.message = Some(message.to_string());
+        self
+    }
+
+    /// Sets [Record::boost_link].
+    pub fn boost_link(mut self, boost_link: Url) -> Self {
+        self.record.boost_link = Some(boost_link);
+        self
+    }
+
+    /// Sets [Record::payment_signature].
+    pub fn payment_signature(mut self, payment_signature: &str) -> Self {
+        self.record.payment_signature = Some(payment_signature.to_string());
+        self
+    }
+
+    /// Sets [Record::payment_id].
+    pub fn payment_id(mut self, payment_id: Uuid) -> Self {
+        self.record.payment_id = Some(payment_id);
+        self
+    }
+
+    /// Sets [Record::boost_id].
+    pub fn boost_id(mut self, boost_id: Uuid) -> Self {
+        self.record.boost_id = Some(boost_id);
+        self
+    }
+
+    /// Sets [Record::remote_feed_guid].
+    pub fn remote_feed_guid(mut self, remote_feed_guid: Uuid) -> Self {
+        self.record.remote_feed_guid = Some(RemoteFeedGuid::Uuid(remote_feed_guid));
+        self
+    }
+
+    /// Sets [Record::remote_feed_guid] to a GUID that may not be a UUID (see
+    /// [RemoteFeedGuid::parse]), replacing any GUID set before.
+    pub fn remote_feed_guid_raw(mut self, remote_feed_guid_raw: &str) -> Self {
+        self.record.remote_feed_guid = Some(RemoteFeedGuid::parse(remote_feed_guid_raw));
+        self
+    }
+
+    /// Sets [Record::remote_item_guid].
+    pub fn remote_item_guid(mut self, remote_item_guid: &str) -> Self {
+        self.record.remote_item_guid = Some(remote_item_guid.to_string());
+        self
+    }
+
+    /// Sets [Record::reply_address].
+    pub fn reply_address(mut self, reply_address: &str) -> Self {
+        self.record.reply_address = Some(reply_address.to_string());
+        self
+    }
+
+    /// Sets [Record::reply_custom_key].
+    pub fn reply_custom_key(mut self, reply_custom_key: &str) -> Self {
+        self.record.reply_custom_key = Some(reply_custom_key.to_string());
+        self
+    }
+
+    /// Sets [Record::reply_custom_value].
+    pub fn reply_custom_value(mut self, reply_custom_value: &str) -> Self {
+        self.record.reply_custom_value = Some(reply_custom_value.to_string());
+        self
+    }
+
+    /// Builds the record.
+    pub fn build(self) -> Record {
+        self.record
+    }
+}
+
 fn json_value_to_string(value: Value) -> Option<String> {
     match value {
         Value::String(string) => {
@@ -270,9 +625,13 @@ fn json_value_to_u64(value: Value) -> Option<u64> {
     }
 }
 
+/// Only `http` and `https` URLs are accepted; other schemes (e.g., `file` or `javascript`) make
+/// no sense in a TLV record and could be dangerous to display or follow.
 fn json_value_to_url(value: Value) -> Option<Url> {
     match value {
-        Value::String(string) => Url::parse(&string).ok(),
+        Value::String(string) => Url::parse(&string)
+            .ok()
+            .filter(|url| matches!(url.scheme(), "http" | "https")),
         _ => None,
     }
 }
@@ -393,8 +752,70 @@ where
     }
 }
 
+/// Strictly deserialize seconds into [chrono::Duration], failing on anything but a number.
+fn deserialize_seconds_strict<'de, D>(deserializer: D) -> Result<Option<Duration>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let seconds: Option<f64> = serde::Deserialize::deserialize(deserializer)?;
+
+    seconds
+        .map(|seconds| {
+            // Out-of-range values saturate when cast, and are then rejected as out of bounds.
+            Some(seconds)
+                .filter(|seconds| seconds.is_finite())
+                .and_then(|seconds| Duration::try_milliseconds((seconds * 1000.0).round() as i64))
+                .ok_or_else(|| {
+                    serde::de::Error::custom(format!("invalid number of seconds: {}", seconds))
+                })
+        })
+        .transpose()
+}
+
+/// Strictly deserialize "HH:MM:SS" into [chrono::Duration].
+fn deserialize_timestamp_strict<'de, D>(deserializer: D) -> Result<Option<Duration>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let hhmmss: Option<String> = serde::Deserialize::deserialize(deserializer)?;
+    let Some(hhmmss) = hhmmss else {
+        return Ok(None);
+    };
+
+    let invalid = || serde::de::Error::custom(format!("invalid HH:MM:SS timestamp: {}", hhmmss));
+
+    let split: Vec<&str> = hhmmss.split(':').collect();
+    let [hours, minutes, seconds] = split.as_slice() else {
+        return Err(invalid());
+    };
+    let hours: i64 = hours.parse().map_err(|_| invalid())?;
+    let minutes: i64 = minutes.parse().map_err(|_| invalid())?;
+    let seconds: i64 = seconds.parse().map_err(|_| invalid())?;
+    if hours < 0 || !(0..60).contains(&minutes) || !(0..60).contains(&seconds) {
+        return Err(invalid());
+    }
+
+    Duration::try_hours(hours)
+        .zip(Duration::try_minutes(minutes))
+        .and_then(|(hours, minutes)| hours.checked_add(&minutes))
+        .zip(Duration::try_seconds(seconds))
+        .and_then(|(duration, seconds)| duration.checked_add(&seconds))
+        .map(Some)
+        .ok_or_else(invalid)
+}
+
 impl From<UntrustedRecord> for Record {
     fn from(record: UntrustedRecord) -> Self {
+        // Prefer whichever spelling holds a valid UUID, but keep the raw string if neither does.
+        let remote_feed_guid_snake =
+            json_value_to_string(record.remote_feed_guid).map(|guid| RemoteFeedGuid::parse(&guid));
+        let remote_feed_guid_camel = json_value_to_string(record.remote_feed_guid_camelcase)
+            .map(|guid| RemoteFeedGuid::parse(&guid));
+        let remote_feed_guid = match (remote_feed_guid_snake, remote_feed_guid_camel) {
+            (Some(RemoteFeedGuid::Raw(_)), Some(camel @ RemoteFeedGuid::Uuid(_))) => Some(camel),
+            (snake, camel) => snake.or(camel),
+        };
+
         Self {
             action: match record.action {
                 Value::String(string) => match string.as_str() {
@@ -418,7 +839,9 @@ impl From<UntrustedRecord> for Record {
             app_name: json_value_to_string(record.app_name),
             app_version: json_value_to_string(record.app_version),
             sender_name: json_value_to_string(record.sender_name),
-            sender_id: json_value_to_string(record.sender_id),
+            // Non-conforming IDs are dropped rather than trusted.
+            sender_id: json_value_to_string(record.sender_id)
+                .filter(|sender_id| sender_id.len() <= MAX_SENDER_ID_LEN),
             receiver_name: json_value_to_string(record.receiver_name),
             total_num_millisats: json_value_to_u64(record.total_num_millisats),
             message: json_value_to_string(record.message),
@@ -426,14 +849,7 @@ impl From<UntrustedRecord> for Record {
             payment_signature: json_value_to_string(record.payment_signature),
             payment_id: json_value_to_uuid(record.payment_id),
             boost_id: json_value_to_uuid(record.boost_id),
-            remote_feed_guid: match (
-                json_value_to_uuid(record.remote_feed_guid),
-                json_value_to_uuid(record.remote_feed_guid_camelcase),
-            ) {
-                (Some(guid), _) => Some(guid),
-                (_, Some(guid)) => Some(guid),
-                _ => None,
-            },
+            remote_feed_guid,
             remote_item_guid: match (
                 json_value_to_string(record.remote_item_guid),
                 json_value_to_string(record.remote_item_guid_camelcase),
@@ -450,6 +866,9 @@ impl From<UntrustedRecord> for Record {
 }
 
 /// Deserialize a bLIP-10 TLV record from an untrusted source.
+///
+/// The record can be either a JSON object or a string containing it (Alby sometimes sends the
+/// record stringified). A missing or malformed record is deserialized as `None`.
 pub fn deserialize_untrusted_tlv_record<'de, D>(deserializer: D) -> Result<Option<Record>, D::Error>
 where
     D: serde::Deserializer<'de>,
@@ -459,10 +878,258 @@ where
         Err(_) => return Ok(None),
     };
 
-    let untrusted_record: UntrustedRecord = match serde_json::from_value(value) {
-        Ok(untrusted_record) => untrusted_record,
-        Err(_) => return Ok(None),
+    let untrusted_record = match value {
+        Value::String(string) => serde_json::from_str::<UntrustedRecord>(&string),
+        value => serde_json::from_value(value),
+    };
+
+    Ok(untrusted_record.ok().map(Into::into))
+}
+
+/// Parses a bLIP-10 TLV record from a raw custom record value coming from an untrusted source.
+///
+/// The value can be either the JSON itself or base64-encoded JSON.
+pub(crate) fn parse_untrusted_tlv_record_str(value: &str) -> Option<Record> {
+    use base64::{engine::general_purpose::STANDARD, Engine as _};
+
+    let json = match serde_json::from_str::<Value>(value) {
+        Ok(json) => json,
+        Err(_) => {
+            let decoded = STANDARD.decode(value.trim()).ok()?;
+            serde_json::from_slice(&decoded).ok()?
+        }
     };
 
-    Ok(Some(untrusted_record.into()))
+    serde_json::from_value::<UntrustedRecord>(json)
+        .ok()
+        .map(Into::into)
+}
+
+/// Parses the bLIP-10 record from the custom records of a received keysend (e.g., as returned by
+/// LND or CLN), stored under [TLV_RECORD_KEY].
+///
+/// Returns `None` if the record is missing or isn't a JSON object (plain or base64-encoded).
+///
+/// ## Example
+/// ```rust
+/// let custom_records = std::collections::HashMap::from([
+///     ("7629169".to_string(), r#"{"action":"boost","message":"Hi!"}"#.to_string()),
+///     ("5482373484".to_string(), "preimage".to_string()),
+/// ]);
+/// let record = v4v::pc20::tlv::parse_tlv_from_custom_records(&custom_records).unwrap();
+/// assert_eq!(record.message, Some("Hi!".to_string()));
+/// ```
+pub fn parse_tlv_from_custom_records(
+    records: &std::collections::HashMap<String, String>,
+) -> Option<Record> {
+    records
+        .get(TLV_RECORD_KEY)
+        .and_then(|value| parse_untrusted_tlv_record_str(value))
+}
+
+/// Error for a single line of [parse_records_stream].
+#[derive(Debug)]
+pub enum ParseError {
+    /// Reading the line failed.
+    Io(std::io::Error),
+    /// The line is not a JSON object of the expected shape.
+    InvalidRecord {
+        /// 1-based line number.
+        line: usize,
+        /// Underlying error.
+        error: serde_json::Error,
+    },
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ParseError::Io(e) => write!(f, "failed to read record: {}", e),
+            ParseError::InvalidRecord { line, error } => {
+                write!(f, "invalid record on line {}: {}", line, error)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ParseError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ParseError::Io(e) => Some(e),
+            ParseError::InvalidRecord { error, .. } => Some(error),
+        }
+    }
+}
+
+/// Lazily parses newline-delimited JSON bLIP-10 records coming from an untrusted source, e.g.,
+/// logs of received payments.
+///
+/// Records are read one line at a time, so the input doesn't have to fit in memory. Blank lines
+/// are skipped. A malformed line yields an `Err` item, and parsing continues with the next line.
+///
+/// ## Example
+/// ```rust
+/// let input = "{\"action\":\"boost\",\"message\":\"Hi!\"}\nnot json\n\n{\"action\":\"stream\"}\n";
+///
+/// let results: Vec<_> = v4v::pc20::tlv::parse_records_stream(input.as_bytes()).collect();
+/// assert_eq!(results.len(), 3);
+/// assert_eq!(results[0].as_ref().unwrap().message, Some("Hi!".to_string()));
+/// assert!(matches!(
+///     results[1],
+///     Err(v4v::pc20::tlv::ParseError::InvalidRecord { line: 2, .. }),
+/// ));
+/// assert_eq!(results[2].as_ref().unwrap().action, v4v::pc20::payments::Action::Stream);
+/// ```
+pub fn parse_records_stream<R: std::io::Read>(
+    reader: R,
+) -> impl Iterator<Item = Result<Record, ParseError>> {
+    use std::io::BufRead;
+
+    std::io::BufReader::new(reader)
+        .lines()
+        .enumerate()
+        .filter_map(|(index, line)| match line {
+            Ok(line) if line.trim().is_empty() => None,
+            Ok(line) => Some(
+                serde_json::from_str::<UntrustedRecord>(&line)
+                    .map(Into::into)
+                    .map_err(|error| ParseError::InvalidRecord {
+                        line: index + 1,
+                        error,
+                    }),
+            ),
+            Err(e) => Some(Err(ParseError::Io(e))),
+        })
+}
+
+impl Record {
+    /// Returns the exact string whose sha256 hash is signed with Nostr, as specified in bLIP-10:
+    /// `[0,sender_id,ts,1,[],message]` serialized per NIP-01, without any whitespace.
+    ///
+    /// The timestamp is in whole seconds, and a missing message is signed as an empty string.
+    /// Returns `None` if [sender_id](Record::sender_id) or
+    /// [timestamp_seconds](Record::timestamp_seconds) is missing.
+    pub fn nostr_signing_payload(&self) -> Option<String> {
+        let sender_id = self.sender_id.as_deref()?;
+        let timestamp = self.timestamp_seconds?;
+
+        let payload = serde_json::json!([
+            0,
+            sender_id,
+            timestamp.num_seconds(),
+            1,
+            [],
+            self.message.as_deref().unwrap_or_default(),
+        ]);
+        Some(payload.to_string())
+    }
+
+    /// Reconstructs the sender's keysend address from the reply fields, so that a reply can be
+    /// paid back to them. This is the inverse of how
+    /// [make_payment](crate::pc20::payments::make_payment) writes
+    /// [PaymentInfo::reply_address](crate::pc20::payments::PaymentInfo::reply_address).
+    ///
+    /// Custom data is only set if both [reply_custom_key](Record::reply_custom_key) and
+    /// [reply_custom_value](Record::reply_custom_value) are present.
+    ///
+    /// Returns `None` if there is no reply address, if it is not a valid node public key, or if
+    /// it is a lightning address (contains "@"), which has to be resolved to a node first; check
+    /// [is_lightning_address_reply](Record::is_lightning_address_reply) to tell these apart.
+    pub fn reply_keysend_address(&self) -> Option<KeysendAddress> {
+        let reply_address = self.reply_address.as_deref()?;
+        if reply_address.contains('@') {
+            return None;
+        }
+
+        match (
+            self.reply_custom_key.as_deref(),
+            self.reply_custom_value.as_deref(),
+        ) {
+            (Some(key), Some(value)) => KeysendAddress::with_custom(reply_address, key, value),
+            _ => KeysendAddress::new(reply_address),
+        }
+        .ok()
+    }
+
+    /// Whether the reply address is a lightning address (e.g., `alice@example.com`) rather than a
+    /// node public key.
+    pub fn is_lightning_address_reply(&self) -> bool {
+        self.reply_address
+            .as_deref()
+            .is_some_and(|reply_address| reply_address.contains('@'))
+    }
+}
+
+/// Error returned when a Nostr signature of a bLIP-10 record cannot be checked.
+#[cfg(feature = "nostr")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VerifyError {
+    /// There is no bLIP-10 record to verify.
+    NoBoostagram,
+    /// The record has no signature.
+    NoSignature,
+    /// The record has no sender ID.
+    NoSenderId,
+    /// The record has no timestamp, which is part of the signed data.
+    NoTimestamp,
+    /// The sender ID is not a hex-encoded Nostr public key.
+    InvalidSenderId,
+    /// The signature is not a hex-encoded 64-byte Schnorr signature.
+    InvalidSignature,
+}
+
+#[cfg(feature = "nostr")]
+impl std::fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            VerifyError::NoBoostagram => write!(f, "no bLIP-10 record"),
+            VerifyError::NoSignature => write!(f, "record has no signature"),
+            VerifyError::NoSenderId => write!(f, "record has no sender ID"),
+            VerifyError::NoTimestamp => write!(f, "record has no timestamp"),
+            VerifyError::InvalidSenderId => {
+                write!(f, "sender ID is not a hex-encoded Nostr public key")
+            }
+            VerifyError::InvalidSignature => {
+                write!(f, "signature is not a hex-encoded Schnorr signature")
+            }
+        }
+    }
+}
+
+#[cfg(feature = "nostr")]
+impl std::error::Error for VerifyError {}
+
+#[cfg(feature = "nostr")]
+impl Record {
+    /// Verifies the Nostr signature of the record against the sender ID.
+    ///
+    /// As specified in bLIP-10, `[0,sender_id,ts,1,[],message]` is serialized per NIP-01, hashed
+    /// with sha256 and verified as a Schnorr signature by the public key in
+    /// [sender_id](Record::sender_id), which must be hex-encoded (not `npub`).
+    ///
+    /// Returns `Ok(false)` if the signature is well-formed but doesn't match.
+    ///
+    /// Available with the `nostr` feature.
+    pub fn verify_signature(&self) -> Result<bool, VerifyError> {
+        use std::str::FromStr;
+
+        let signature = self
+            .payment_signature
+            .as_deref()
+            .ok_or(VerifyError::NoSignature)?;
+        let sender_id = self.sender_id.as_deref().ok_or(VerifyError::NoSenderId)?;
+        self.timestamp_seconds.ok_or(VerifyError::NoTimestamp)?;
+
+        let pubkey = secp256k1::XOnlyPublicKey::from_str(sender_id)
+            .map_err(|_| VerifyError::InvalidSenderId)?;
+        let signature = secp256k1::schnorr::Signature::from_str(signature)
+            .map_err(|_| VerifyError::InvalidSignature)?;
+
+        let payload = self
+            .nostr_signing_payload()
+            .ok_or(VerifyError::NoTimestamp)?;
+        let hash = hmac_sha256::Hash::hash(payload.as_bytes());
+
+        Ok(signature.verify(&hash, &pubkey).is_ok())
+    }
 }
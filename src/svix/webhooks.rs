@@ -58,6 +58,17 @@ impl Webhook {
     }
 
     pub fn verify<HM: HeaderMap>(&self, payload: &[u8], headers: &HM) -> Result<(), WebhookError> {
+        self.verify_at(payload, headers, OffsetDateTime::now_utc().unix_timestamp())
+    }
+
+    /// Same as [Webhook::verify], but checks the timestamp against `now` (a Unix timestamp)
+    /// instead of the system clock.
+    pub fn verify_at<HM: HeaderMap>(
+        &self,
+        payload: &[u8],
+        headers: &HM,
+        now: i64,
+    ) -> Result<(), WebhookError> {
         let msg_id = Self::get_header(headers, SVIX_MSG_ID_KEY, UNBRANDED_MSG_ID_KEY, "id")?;
         let msg_signature = Self::get_header(
             headers,
@@ -73,7 +84,7 @@ impl Webhook {
         )
         .and_then(Self::parse_timestamp)?;
 
-        Self::verify_timestamp(msg_ts)?;
+        Self::verify_timestamp(msg_ts, now)?;
 
         let versioned_signature = self.sign(msg_id, msg_ts, payload)?;
         let expected_signature = versioned_signature
@@ -132,8 +143,7 @@ impl Webhook {
         str::parse::<i64>(hdr).map_err(|_| WebhookError::InvalidTimestamp)
     }
 
-    fn verify_timestamp(ts: i64) -> Result<(), WebhookError> {
-        let now = OffsetDateTime::now_utc().unix_timestamp();
+    fn verify_timestamp(ts: i64, now: i64) -> Result<(), WebhookError> {
         if now - ts > TOLERANCE_IN_SECONDS {
             Err(WebhookError::TimestampTooOldError)
         } else if ts > now + TOLERANCE_IN_SECONDS {
@@ -146,6 +156,9 @@ impl Webhook {
 
 /// Trait to abstract over the `HeaderMap` types from both v0.2 and v1.0 of the
 /// `http` crate.
+///
+/// Implemented for `http::HeaderMap` from both versions, so the header maps extracted by web
+/// frameworks (e.g., `axum::http::HeaderMap`) can be passed in directly.
 pub trait HeaderMap: private::HeaderMapSealed {}
 
 impl HeaderMap for http02::HeaderMap {}
@@ -192,7 +205,7 @@ mod tests {
     use time::OffsetDateTime;
 
     use super::{
-        Webhook, SVIX_MSG_ID_KEY, SVIX_MSG_SIGNATURE_KEY, SVIX_MSG_TIMESTAMP_KEY,
+        Webhook, WebhookError, SVIX_MSG_ID_KEY, SVIX_MSG_SIGNATURE_KEY, SVIX_MSG_TIMESTAMP_KEY,
         UNBRANDED_MSG_ID_KEY, UNBRANDED_MSG_SIGNATURE_KEY, UNBRANDED_MSG_TIMESTAMP_KEY,
     };
 
@@ -336,6 +349,32 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_verify_at_fixed_time() {
+        let secret = "whsec_C2FVsBQIhrscChlQIMV+b5sSYspob7oD".to_owned();
+        let msg_id = "msg_27UH4WbU6Z5A5EzD8u03UvzRbpk";
+        let payload = br#"{"email":"test@example.com","username":"test_user"}"#;
+        let wh = Webhook::new(&secret).unwrap();
+
+        let ts = 1_700_000_000;
+        let signature = wh.sign(msg_id, ts, payload).unwrap();
+        let mut headers = get_svix_headers(msg_id, &signature);
+        headers.insert(SVIX_MSG_TIMESTAMP_KEY, ts.to_string().parse().unwrap());
+
+        let tolerance = super::TOLERANCE_IN_SECONDS;
+        assert!(wh.verify_at(payload, &headers, ts).is_ok());
+        assert!(wh.verify_at(payload, &headers, ts + tolerance).is_ok());
+        assert!(wh.verify_at(payload, &headers, ts - tolerance).is_ok());
+        assert!(matches!(
+            wh.verify_at(payload, &headers, ts + tolerance + 1),
+            Err(WebhookError::TimestampTooOldError)
+        ));
+        assert!(matches!(
+            wh.verify_at(payload, &headers, ts - tolerance - 1),
+            Err(WebhookError::FutureTimestampError)
+        ));
+    }
+
     #[test]
     fn test_verify_with_multiple_signatures() {
         let secret = "whsec_C2FVsBQIhrscChlQIMV+b5sSYspob7oD".to_owned();
@@ -196,6 +196,12 @@ compute_sat_recipients_tests! {
         total_sats: 4,
         expected_sats: vec![1, 1, 1, 1, 0, 0, 0],
     },
+    case_36: TestCase {
+        // Leftover sats go to the largest splits, then to the earliest of the tied ones.
+        splits: vec![2, 1, 1, 1, 1],
+        total_sats: 8,
+        expected_sats: vec![3, 2, 1, 1, 1],
+    },
 }
 
 macro_rules! compute_sat_recipients_generic_tests {
@@ -521,6 +527,10 @@ macro_rules! use_remote_splits_tests {
                         expected_remote_splits: Vec<u64>,
                     }
                     assert_eq!(v4v::pc20::calc::use_remote_splits(&$value.local_splits, &$value.remote_splits, $value.remote_percentage), ($value.expected_local_splits, $value.expected_remote_splits));
+                    assert_eq!(
+                        v4v::pc20::calc::use_remote_splits_multi(&$value.local_splits, &[($value.remote_splits, $value.remote_percentage)]),
+                        ($value.expected_local_splits, vec![$value.expected_remote_splits]),
+                    );
                 }
             )*
         }
@@ -577,6 +587,78 @@ use_remote_splits_tests! {
         expected_local_splits: vec![0, 0, 0],
         expected_remote_splits: vec![4, 5, 6],
     },
+    case_8: TestCase {
+        local_splits: vec![10, 0, 30],
+        remote_splits: vec![7],
+        remote_percentage: 0,
+        expected_local_splits: vec![1, 0, 3],
+        expected_remote_splits: vec![0],
+    },
+}
+
+macro_rules! use_remote_splits_multi_tests {
+    ($($name:ident: $value:expr,)*) => {
+        paste::item! {
+            $(
+                #[test]
+                fn [<use_remote_splits_multi_ $name>]() {
+                    struct TestCase {
+                        local_splits: Vec<u64>,
+                        remotes: Vec<(Vec<u64>, u64)>,
+                        expected_local_splits: Vec<u64>,
+                        expected_remote_splits: Vec<Vec<u64>>,
+                    }
+                    assert_eq!(
+                        v4v::pc20::calc::use_remote_splits_multi(&$value.local_splits, &$value.remotes),
+                        ($value.expected_local_splits, $value.expected_remote_splits),
+                    );
+                }
+            )*
+        }
+    }
+}
+
+use_remote_splits_multi_tests! {
+    two_remotes: TestCase {
+        // Local recipients get 25% each, the first remote 30%, and the second remote 10% each.
+        local_splits: vec![50, 50],
+        remotes: vec![(vec![1], 30), (vec![1, 1], 20)],
+        expected_local_splits: vec![5, 5],
+        expected_remote_splits: vec![vec![6], vec![2, 2]],
+    },
+    uneven_remote_splits: TestCase {
+        // Local: 50% * 1/4 and 50% * 3/4. First remote: 30% * 2/3 and 30% * 1/3. Second remote:
+        // 20%. That's 12.5 : 37.5 : 20 : 10 : 20.
+        local_splits: vec![1, 3],
+        remotes: vec![(vec![2, 1], 30), (vec![5], 20)],
+        expected_local_splits: vec![5, 15],
+        expected_remote_splits: vec![vec![8, 4], vec![8]],
+    },
+    total_capped_at_100: TestCase {
+        local_splits: vec![50, 50],
+        remotes: vec![(vec![1], 60), (vec![1], 60), (vec![1], 10)],
+        expected_local_splits: vec![0, 0],
+        expected_remote_splits: vec![vec![3], vec![2], vec![0]],
+    },
+    empty_remote: TestCase {
+        // The empty remote's 20% goes to the others proportionally.
+        local_splits: vec![50, 50],
+        remotes: vec![(vec![1], 30), (vec![], 20)],
+        expected_local_splits: vec![5, 5],
+        expected_remote_splits: vec![vec![6], vec![]],
+    },
+    no_remotes: TestCase {
+        local_splits: vec![50, 50],
+        remotes: vec![],
+        expected_local_splits: vec![1, 1],
+        expected_remote_splits: vec![],
+    },
+    large_splits: TestCase {
+        local_splits: vec![u64::MAX, u64::MAX],
+        remotes: vec![(vec![u64::MAX - 1], 50)],
+        expected_local_splits: vec![1, 1],
+        expected_remote_splits: vec![vec![2]],
+    },
 }
 
 macro_rules! use_remote_splits_generic_tests {
@@ -620,3 +702,5158 @@ use_remote_splits_generic_tests! {
         expected_values: vec![MyStruct { split: 3 }, MyStruct { split: 2 }],
     },
 }
+
+#[test]
+fn failed_payout_round_trip() {
+    let args = v4v::pc20::forwarding::ForwardPaymentArgs {
+        user_agent: "v4v-tests",
+        token: "token",
+        payment_info: v4v::pc20::payments::PaymentInfo {
+            message: Some("Hello".to_string()),
+            total_num_sats: 100,
+            ..Default::default()
+        },
+        recipients: vec![v4v::pc20::payments::PaymentRecipientInfo {
+            address: v4v::alby::types::KeysendAddress {
+                pubkey: "pubkey".to_string(),
+                custom_data: None,
+            }
+            .into(),
+            num_sats: 100,
+            ..Default::default()
+        }],
+        ..Default::default()
+    };
+
+    let mut failed_payout = v4v::pc20::forwarding::FailedPayout::new(
+        &args,
+        &v4v::alby::api::RequestError::Unexpected("first".to_string()),
+    );
+    failed_payout.record_attempt(&v4v::alby::api::RequestError::Unexpected(
+        "second".to_string(),
+    ));
+    assert_eq!(failed_payout.num_attempts, 2);
+    assert_eq!(failed_payout.reason, "Unexpected error: second");
+
+    let serialized = serde_json::to_string(&failed_payout).unwrap();
+    let deserialized: v4v::pc20::forwarding::FailedPayout =
+        serde_json::from_str(&serialized).unwrap();
+
+    let retry_args = deserialized.to_forward_payment_args("v4v-tests", "token");
+    assert_eq!(retry_args.user_agent, "v4v-tests");
+    assert_eq!(retry_args.token, "token");
+    assert_eq!(retry_args.payment_info.message, Some("Hello".to_string()));
+    assert_eq!(retry_args.payment_info.total_num_sats, 100);
+    assert_eq!(retry_args.recipients.len(), 1);
+    assert_eq!(retry_args.recipients[0].address.to_string(), "pubkey");
+    assert_eq!(retry_args.recipients[0].num_sats, 100);
+}
+
+#[test]
+fn relay_keysend_args_received_less_than_stated() {
+    let boost_id = uuid::Uuid::parse_str("5ad37bc5-9f3f-4e3b-8a1d-3c5a0b6e6f7a").unwrap();
+    let payment_info = v4v::pc20::payments::PaymentInfo {
+        message: Some("Hello".to_string()),
+        total_num_sats: 100,
+        boost_id: Some(boost_id),
+        ..Default::default()
+    };
+    let onward_recipients = vec![
+        v4v::pc20::payments::PaymentRecipientInfo {
+            address: v4v::alby::types::KeysendAddress {
+                pubkey: "03ae9f91a0cb8ff43840e3c322c4c61f019d8c1c3cea15a25cfc425ac605e61a4a"
+                    .to_string(),
+                custom_data: None,
+            }
+            .into(),
+            num_sats: 60,
+            ..Default::default()
+        },
+        v4v::pc20::payments::PaymentRecipientInfo {
+            address: v4v::alby::types::KeysendAddress {
+                pubkey: "02d5c1bf8b940dc9cadca86d1b0a3c37fbe39cee4c7e839e33bef9174531d27f52"
+                    .to_string(),
+                custom_data: None,
+            }
+            .into(),
+            num_sats: 40,
+            ..Default::default()
+        },
+    ];
+
+    // Fees upstream left 70 of the stated 100 sats.
+    let args = v4v::pc20::forwarding::relay_keysend_args(
+        "v4v-tests",
+        "token",
+        &payment_info,
+        70,
+        &onward_recipients,
+    );
+    assert_eq!(
+        args.recipients
+            .iter()
+            .map(|recipient| recipient.num_sats)
+            .collect::<Vec<_>>(),
+        vec![42, 28]
+    );
+
+    let portions = args.tlv_portions().unwrap();
+    assert_eq!(portions.shared["boost_uuid"], boost_id.to_string());
+    assert_eq!(portions.shared["message"], "Hello");
+    assert_eq!(portions.shared["value_msat_total"], 100_000);
+}
+
+macro_rules! compute_sat_recipients_detailed_tests {
+    ($($name:ident: $value:expr,)*) => {
+        paste::item! {
+            $(
+                #[test]
+                fn [<compute_sat_recipients_detailed_ $name>]() {
+                    struct TestCase {
+                        splits: Vec<u64>,
+                        total_sats: u64,
+                        expected: v4v::pc20::calc::SatAllocation,
+                    }
+                    let allocation = v4v::pc20::calc::compute_sat_recipients_detailed(&$value.splits, $value.total_sats);
+                    assert_eq!(allocation, $value.expected);
+                    assert_eq!(allocation.amounts, v4v::pc20::calc::compute_sat_recipients(&$value.splits, $value.total_sats));
+                }
+            )*
+        }
+    }
+}
+
+compute_sat_recipients_detailed_tests! {
+    everyone_paid: TestCase {
+        splits: vec![60, 40],
+        total_sats: 1000,
+        expected: v4v::pc20::calc::SatAllocation {
+            amounts: vec![600, 400],
+            undistributed: 0,
+            num_zero_recipients: 0,
+        },
+    },
+    fewer_sats_than_recipients: TestCase {
+        splits: vec![50, 40, 3, 2, 2, 1, 2],
+        total_sats: 4,
+        expected: v4v::pc20::calc::SatAllocation {
+            amounts: vec![1, 1, 1, 1, 0, 0, 0],
+            undistributed: 0,
+            num_zero_recipients: 3,
+        },
+    },
+    no_recipients: TestCase {
+        splits: vec![],
+        total_sats: 100,
+        expected: v4v::pc20::calc::SatAllocation {
+            amounts: vec![],
+            undistributed: 100,
+            num_zero_recipients: 0,
+        },
+    },
+}
+
+macro_rules! compute_sat_recipients_capped_tests {
+    ($($name:ident: $value:expr,)*) => {
+        paste::item! {
+            $(
+                #[test]
+                fn [<compute_sat_recipients_capped_ $name>]() {
+                    struct TestCase {
+                        splits: Vec<u64>,
+                        total_sats: u64,
+                        caps: Vec<Option<u64>>,
+                        expected_sats: Vec<u64>,
+                        expected_shortfall: u64,
+                    }
+                    let sats = v4v::pc20::calc::compute_sat_recipients_capped(&$value.splits, $value.total_sats, &$value.caps);
+                    assert_eq!(sats, $value.expected_sats);
+
+                    let allocation = v4v::pc20::calc::compute_sat_recipients_capped_detailed(&$value.splits, $value.total_sats, &$value.caps);
+                    assert_eq!(allocation.amounts, $value.expected_sats);
+                    assert_eq!(allocation.undistributed, $value.expected_shortfall);
+                    assert_eq!(allocation.num_zero_recipients, sats.iter().filter(|&&s| s == 0).count());
+                }
+            )*
+        }
+    }
+}
+
+compute_sat_recipients_capped_tests! {
+    no_caps: TestCase {
+        splits: vec![50, 30, 20],
+        total_sats: 100,
+        caps: vec![None, None, None],
+        expected_sats: vec![50, 30, 20],
+        expected_shortfall: 0,
+    },
+    cap_not_reached: TestCase {
+        splits: vec![50, 30, 20],
+        total_sats: 100,
+        caps: vec![Some(50), None, None],
+        expected_sats: vec![50, 30, 20],
+        expected_shortfall: 0,
+    },
+    cascading_spillover: TestCase {
+        // The first recipient is capped at 40, and its overflow pushes the second one (36 of the
+        // remaining 60) over its cap of 35, whose overflow goes to the third one.
+        splits: vec![50, 30, 20],
+        total_sats: 100,
+        caps: vec![Some(40), Some(35), None],
+        expected_sats: vec![40, 35, 25],
+        expected_shortfall: 0,
+    },
+    spillover_split_proportionally: TestCase {
+        splits: vec![50, 30, 20],
+        total_sats: 100,
+        caps: vec![Some(0), None, None],
+        expected_sats: vec![0, 60, 40],
+        expected_shortfall: 0,
+    },
+    all_capped_with_shortfall: TestCase {
+        splits: vec![50, 30, 20],
+        total_sats: 100,
+        caps: vec![Some(40), Some(35), Some(5)],
+        expected_sats: vec![40, 35, 5],
+        expected_shortfall: 20,
+    },
+    missing_caps_are_uncapped: TestCase {
+        splits: vec![50, 50],
+        total_sats: 100,
+        caps: vec![Some(10)],
+        expected_sats: vec![10, 90],
+        expected_shortfall: 0,
+    },
+    no_recipients: TestCase {
+        splits: vec![],
+        total_sats: 100,
+        caps: vec![],
+        expected_sats: vec![],
+        expected_shortfall: 100,
+    },
+}
+
+macro_rules! compute_sat_recipients_mode_tests {
+    ($($name:ident: $value:expr,)*) => {
+        paste::item! {
+            $(
+                #[test]
+                fn [<compute_sat_recipients_mode_ $name>]() {
+                    use v4v::pc20::calc::{compute_sat_recipients_mode, SatRoundingMode};
+
+                    struct TestCase {
+                        splits: Vec<u64>,
+                        total_sats: u64,
+                        expected_largest_remainder: Vec<u64>,
+                        expected_current_heuristic: Vec<u64>,
+                    }
+                    assert_eq!(
+                        compute_sat_recipients_mode(&$value.splits, $value.total_sats, SatRoundingMode::LargestRemainder),
+                        $value.expected_largest_remainder,
+                    );
+                    assert_eq!(
+                        compute_sat_recipients_mode(&$value.splits, $value.total_sats, SatRoundingMode::CurrentHeuristic),
+                        $value.expected_current_heuristic,
+                    );
+                    assert_eq!(
+                        v4v::pc20::calc::compute_sat_recipients(&$value.splits, $value.total_sats),
+                        $value.expected_current_heuristic,
+                    );
+                }
+            )*
+        }
+    }
+}
+
+compute_sat_recipients_mode_tests! {
+    thirds: TestCase {
+        // The exact amounts are 3.3, 3.3 and 3.4 sats.
+        splits: vec![33, 33, 34],
+        total_sats: 10,
+        expected_largest_remainder: vec![3, 3, 4],
+        expected_current_heuristic: vec![3, 3, 4],
+    },
+    thirds_of_100: TestCase {
+        // The exact amounts are 33.33, 33.33 and 33.34 sats.
+        splits: vec![1, 1, 1],
+        total_sats: 100,
+        expected_largest_remainder: vec![34, 33, 33],
+        expected_current_heuristic: vec![34, 33, 33],
+    },
+    halves: TestCase {
+        // The exact amounts are 4, 3.5 and 2.5 sats.
+        splits: vec![40, 35, 25],
+        total_sats: 10,
+        expected_largest_remainder: vec![4, 4, 2],
+        expected_current_heuristic: vec![5, 3, 2],
+    },
+    remainders_follow_splits: TestCase {
+        // The exact amounts are 5.6, 2.8 and 1.6 sats.
+        splits: vec![56, 28, 16],
+        total_sats: 10,
+        expected_largest_remainder: vec![6, 3, 1],
+        expected_current_heuristic: vec![6, 3, 1],
+    },
+    everyone_gets_one_sat: TestCase {
+        // The exact amounts are 0.1 and 9.9 sats, so the first recipient takes a sat from the
+        // second one.
+        splits: vec![1, 99],
+        total_sats: 10,
+        expected_largest_remainder: vec![1, 9],
+        expected_current_heuristic: vec![1, 9],
+    },
+    not_enough_sats: TestCase {
+        splits: vec![1, 50, 49],
+        total_sats: 2,
+        expected_largest_remainder: vec![0, 1, 1],
+        expected_current_heuristic: vec![0, 1, 1],
+    },
+    all_zero_splits: TestCase {
+        splits: vec![0, 0, 0],
+        total_sats: 10,
+        expected_largest_remainder: vec![4, 3, 3],
+        expected_current_heuristic: vec![4, 3, 3],
+    },
+    no_recipients: TestCase {
+        splits: vec![],
+        total_sats: 10,
+        expected_largest_remainder: vec![],
+        expected_current_heuristic: vec![],
+    },
+}
+
+macro_rules! compute_sat_recipients_with_floor_tests {
+    ($($name:ident: $value:expr,)*) => {
+        paste::item! {
+            $(
+                #[test]
+                fn [<compute_sat_recipients_with_floor_ $name>]() {
+                    struct TestCase {
+                        splits: Vec<u64>,
+                        total_sats: u64,
+                        min_sat: u64,
+                        expected_sats: Vec<u64>,
+                    }
+                    let sats = v4v::pc20::calc::compute_sat_recipients_with_floor(&$value.splits, $value.total_sats, $value.min_sat);
+                    assert_eq!(sats, $value.expected_sats);
+                    if !$value.splits.is_empty() {
+                        assert_eq!(sats.iter().sum::<u64>(), $value.total_sats);
+                    }
+                    if $value.total_sats >= $value.min_sat {
+                        assert!(sats.iter().all(|&sats| sats == 0 || sats >= $value.min_sat));
+                    }
+                }
+            )*
+        }
+    }
+}
+
+compute_sat_recipients_with_floor_tests! {
+    floor_zero_skips_dust: TestCase {
+        splits: vec![1, 99, 0],
+        total_sats: 10,
+        min_sat: 0,
+        expected_sats: vec![0, 10, 0],
+    },
+    floor_one: TestCase {
+        splits: vec![1, 99, 0],
+        total_sats: 10,
+        min_sat: 1,
+        // Same as compute_sat_recipients: even zero splits get a sat when possible.
+        expected_sats: vec![1, 8, 1],
+    },
+    floor_met_by_everyone: TestCase {
+        splits: vec![60, 30, 10],
+        total_sats: 1000,
+        min_sat: 10,
+        expected_sats: vec![600, 300, 100],
+    },
+    lowest_split_dropped: TestCase {
+        splits: vec![60, 35, 5],
+        total_sats: 100,
+        min_sat: 10,
+        expected_sats: vec![64, 36, 0],
+    },
+    total_below_floor_times_recipients: TestCase {
+        // 25 sats can't give 10 sats to all three recipients, or even to the first two
+        // proportionally (30 would get 25 * 30/80 = 9).
+        splits: vec![50, 30, 20],
+        total_sats: 25,
+        min_sat: 10,
+        expected_sats: vec![25, 0, 0],
+    },
+    total_below_floor: TestCase {
+        splits: vec![40, 60],
+        total_sats: 5,
+        min_sat: 10,
+        expected_sats: vec![0, 5],
+    },
+    ties_keep_earlier_recipients: TestCase {
+        splits: vec![10, 10, 10],
+        total_sats: 25,
+        min_sat: 10,
+        expected_sats: vec![13, 12, 0],
+    },
+    all_zero_splits: TestCase {
+        splits: vec![0, 0, 0],
+        total_sats: 30,
+        min_sat: 10,
+        expected_sats: vec![10, 10, 10],
+    },
+    zero_total: TestCase {
+        splits: vec![50, 50],
+        total_sats: 0,
+        min_sat: 10,
+        expected_sats: vec![0, 0],
+    },
+    no_recipients: TestCase {
+        splits: vec![],
+        total_sats: 100,
+        min_sat: 10,
+        expected_sats: vec![],
+    },
+}
+
+macro_rules! compute_tiered_tests {
+    ($($name:ident: $value:expr,)*) => {
+        paste::item! {
+            $(
+                #[test]
+                fn [<compute_tiered_ $name>]() {
+                    use v4v::pc20::calc::TieredRecipient;
+                    struct TestCase {
+                        recipients: Vec<TieredRecipient>,
+                        total_sats: u64,
+                        expected_sats: Vec<u64>,
+                    }
+                    assert_eq!(v4v::pc20::calc::compute_tiered(&$value.recipients, $value.total_sats), $value.expected_sats);
+                }
+            )*
+        }
+    }
+}
+
+compute_tiered_tests! {
+    case_1: TestCase {
+        recipients: vec![
+            TieredRecipient { split: 10, tier: 0 },
+            TieredRecipient { split: 10, tier: 0 },
+            TieredRecipient { split: 80, tier: 1 },
+        ],
+        total_sats: 100,
+        expected_sats: vec![10, 10, 80],
+    },
+    case_2: TestCase {
+        // The total only covers the first tier.
+        recipients: vec![
+            TieredRecipient { split: 10, tier: 0 },
+            TieredRecipient { split: 10, tier: 0 },
+            TieredRecipient { split: 80, tier: 1 },
+        ],
+        total_sats: 2,
+        expected_sats: vec![1, 1, 0],
+    },
+    case_3: TestCase {
+        // The total only covers part of the first tier.
+        recipients: vec![
+            TieredRecipient { split: 80, tier: 1 },
+            TieredRecipient { split: 5, tier: 0 },
+            TieredRecipient { split: 15, tier: 0 },
+        ],
+        total_sats: 1,
+        expected_sats: vec![0, 0, 1],
+    },
+    case_4: TestCase {
+        recipients: vec![
+            TieredRecipient { split: 1, tier: 0 },
+            TieredRecipient { split: 1, tier: 0 },
+            TieredRecipient { split: 1, tier: 0 },
+            TieredRecipient { split: 97, tier: 1 },
+        ],
+        total_sats: 3,
+        expected_sats: vec![1, 1, 1, 0],
+    },
+    case_5: TestCase {
+        recipients: vec![
+            TieredRecipient { split: 1, tier: 0 },
+            TieredRecipient { split: 1, tier: 0 },
+            TieredRecipient { split: 1, tier: 0 },
+            TieredRecipient { split: 97, tier: 1 },
+        ],
+        total_sats: 10,
+        expected_sats: vec![1, 1, 1, 7],
+    },
+    case_6: TestCase {
+        recipients: vec![
+            TieredRecipient { split: 50, tier: 0 },
+            TieredRecipient { split: 30, tier: 1 },
+            TieredRecipient { split: 20, tier: 2 },
+        ],
+        total_sats: 60,
+        expected_sats: vec![30, 18, 12],
+    },
+    case_7: TestCase {
+        recipients: vec![
+            TieredRecipient { split: 0, tier: 0 },
+            TieredRecipient { split: 0, tier: 1 },
+        ],
+        total_sats: 3,
+        expected_sats: vec![2, 1],
+    },
+    case_8: TestCase {
+        recipients: vec![],
+        total_sats: 3,
+        expected_sats: vec![],
+    },
+    case_9: TestCase {
+        recipients: vec![
+            TieredRecipient { split: 10, tier: 0 },
+            TieredRecipient { split: 90, tier: 1 },
+        ],
+        total_sats: 0,
+        expected_sats: vec![0, 0],
+    },
+}
+
+#[test]
+fn make_payment_args_estimate() {
+    let recipient = |pubkey: &str, num_sats: u64| v4v::pc20::payments::PaymentRecipientInfo {
+        address: v4v::alby::types::KeysendAddress {
+            pubkey: pubkey.to_string(),
+            custom_data: Some(("696969".to_string(), "wallet".to_string())),
+        }
+        .into(),
+        num_sats,
+        ..Default::default()
+    };
+
+    let args = v4v::pc20::payments::MakePaymentArgs {
+        payment_info: v4v::pc20::payments::PaymentInfo {
+            message: Some("Hello".to_string()),
+            total_num_sats: 100,
+            ..Default::default()
+        },
+        recipients: vec![recipient("pubkey1", 60), recipient("pubkey2", 40)],
+        ..Default::default()
+    };
+
+    // Both recipients receive the same record since no recipient-specific fields are set.
+    let record = r#"{"action":"boost","value_msat_total":100000,"message":"Hello"}"#;
+    assert_eq!(
+        args.estimate().unwrap(),
+        v4v::pc20::payments::PayoutEstimate {
+            keysend_count: 2,
+            total_tlv_bytes: 2 * ("wallet".len() + record.len()),
+            total_sats: 100,
+        }
+    );
+}
+
+#[test]
+fn map_distribute() {
+    use v4v::pc20::calc::DistributeExt;
+
+    let boosts: Vec<(&[u64], u64)> = vec![(&[50, 50], 1000), (&[1, 50, 50], 1), (&[], 100)];
+    let distributions: Vec<Vec<u64>> = boosts.into_iter().map_distribute().collect();
+    assert_eq!(distributions, vec![vec![500, 500], vec![0, 1, 0], vec![]]);
+}
+
+#[cfg(feature = "testing")]
+#[test]
+fn assert_distribution_sums_holds_for_compute_sat_recipients() {
+    use v4v::pc20::calc::{assert_distribution_sums, compute_sat_recipients};
+
+    let cases: Vec<(&[u64], u64)> = vec![
+        (&[60, 40], 1000),
+        (&[1, 99], 10),
+        (&[1, 99], 1),
+        (&[0, 0, 0], 7),
+        (&[1, 2, 3, 4, 5, 6, 7, 8, 9, 10], 3),
+    ];
+    for (splits, total_sats) in cases {
+        assert_distribution_sums(&compute_sat_recipients(splits, total_sats), total_sats);
+    }
+}
+
+#[cfg(feature = "testing")]
+#[test]
+#[should_panic(expected = "expected sum 1000, actual sum 1000000")]
+fn assert_distribution_sums_catches_wrong_sum() {
+    v4v::pc20::calc::assert_distribution_sums(&[600_000, 400_000], 1000);
+}
+
+#[cfg(feature = "testing")]
+#[test]
+#[should_panic(expected = "recipient 0 got no sats")]
+fn assert_distribution_sums_catches_starved_recipient() {
+    v4v::pc20::calc::assert_distribution_sums(&[0, 10], 10);
+}
+
+fn alby_invoice_body(boostagram: serde_json::Value) -> serde_json::Value {
+    serde_json::json!({
+        "identifier": "MAYm6eWC7TKUyixZWAKUM3su",
+        "type": "incoming",
+        "memo": "Hello",
+        "state": "SETTLED",
+        "metadata": {},
+        "amount": 100,
+        "created_at": "2024-08-01T12:00:00.000Z",
+        "boostagram": boostagram,
+    })
+}
+
+#[test]
+fn extract_alby_invoices_single_object() {
+    let body = alby_invoice_body(serde_json::json!({"action": "boost", "message": "Hi"}));
+
+    let invoices = v4v::alby::webhooks::extract_alby_invoices(&body).unwrap();
+    assert_eq!(invoices.len(), 1);
+    assert_eq!(invoices[0].num_sats, 100);
+    assert_eq!(
+        invoices[0].boostagram.as_ref().unwrap().message.as_deref(),
+        Some("Hi")
+    );
+}
+
+#[test]
+fn extract_alby_invoices_array() {
+    let mut second = alby_invoice_body(serde_json::json!({"action": "stream"}));
+    second["amount"] = 5.into();
+    let body = serde_json::json!([
+        alby_invoice_body(serde_json::json!({"action": "boost", "message": "Hi"})),
+        second,
+    ]);
+
+    let invoices = v4v::alby::webhooks::extract_alby_invoices(&body).unwrap();
+    assert_eq!(invoices.len(), 2);
+    assert_eq!(invoices[0].num_sats, 100);
+    assert_eq!(invoices[1].num_sats, 5);
+    assert_eq!(
+        invoices[1].boostagram.as_ref().unwrap().action,
+        v4v::pc20::payments::Action::Stream
+    );
+
+    let body = serde_json::json!([alby_invoice_body(serde_json::json!({})), {"amount": 1}]);
+    assert!(matches!(
+        v4v::alby::webhooks::extract_alby_invoices(&body),
+        Err(v4v::alby::webhooks::ExtractError::InvalidBody(_))
+    ));
+}
+
+#[test]
+fn deserialize_untrusted_tlv_record_object_and_string() {
+    use v4v::alby::webhooks::extract_alby_invoice;
+
+    let boostagram = serde_json::json!({
+        "action": "stream",
+        "podcast": "Podcasting 2.0",
+        "value_msat_total": 2000,
+        "message": "Hi",
+    });
+
+    let from_object = extract_alby_invoice(&alby_invoice_body(boostagram.clone()))
+        .unwrap()
+        .boostagram
+        .unwrap();
+    let from_string = extract_alby_invoice(&alby_invoice_body(boostagram.to_string().into()))
+        .unwrap()
+        .boostagram
+        .unwrap();
+
+    for record in [&from_object, &from_string] {
+        assert_eq!(record.action, v4v::pc20::payments::Action::Stream);
+        assert_eq!(record.feed_name.as_deref(), Some("Podcasting 2.0"));
+        assert_eq!(record.total_num_millisats, Some(2000));
+        assert_eq!(record.message.as_deref(), Some("Hi"));
+    }
+    // Serializing the parsed record gives back the same fields.
+    assert_eq!(
+        serde_json::to_value(&from_string).unwrap(),
+        serde_json::to_value(&from_object).unwrap()
+    );
+    assert_eq!(serde_json::to_value(&from_object).unwrap(), boostagram);
+
+    // Base64-encoded records are only accepted in raw custom records, not in webhook bodies.
+    let base64 = {
+        use base64::{engine::general_purpose::STANDARD, Engine as _};
+        STANDARD.encode(boostagram.to_string())
+    };
+
+    for malformed in [
+        serde_json::Value::Null,
+        "not json".into(),
+        "42".into(),
+        42.into(),
+        base64.into(),
+    ] {
+        let invoice = extract_alby_invoice(&alby_invoice_body(malformed)).unwrap();
+        assert!(invoice.boostagram.is_none());
+    }
+
+    let mut body = alby_invoice_body(serde_json::Value::Null);
+    body.as_object_mut().unwrap().remove("boostagram");
+    assert!(extract_alby_invoice(&body).unwrap().boostagram.is_none());
+}
+
+macro_rules! untrusted_url_tests {
+    ($($name:ident: $value:expr,)*) => {
+        paste::item! {
+            $(
+                #[test]
+                fn [<untrusted_url_ $name>]() {
+                    struct TestCase {
+                        url: &'static str,
+                        expected_url: Option<&'static str>,
+                    }
+                    let body = alby_invoice_body(serde_json::json!({
+                        "action": "boost",
+                        "url": $value.url,
+                        "boost_link": $value.url,
+                    }));
+                    let invoice = v4v::alby::webhooks::extract_alby_invoice(&body).unwrap();
+                    let record = invoice.boostagram.unwrap();
+                    let expected_url = $value.expected_url.map(|url| url::Url::parse(url).unwrap());
+                    assert_eq!(record.feed_url, expected_url);
+                    assert_eq!(record.boost_link, expected_url);
+                }
+            )*
+        }
+    }
+}
+
+untrusted_url_tests! {
+    case_1: TestCase {
+        url: "https://example.com/feed.xml",
+        expected_url: Some("https://example.com/feed.xml"),
+    },
+    case_2: TestCase {
+        url: "http://example.com/feed.xml",
+        expected_url: Some("http://example.com/feed.xml"),
+    },
+    case_3: TestCase {
+        url: "file:///etc/passwd",
+        expected_url: None,
+    },
+    case_4: TestCase {
+        url: "javascript:alert(1)",
+        expected_url: None,
+    },
+}
+
+macro_rules! remote_feed_guid_tests {
+    ($($name:ident: $value:expr,)*) => {
+        paste::item! {
+            $(
+                #[test]
+                fn [<remote_feed_guid_ $name>]() {
+                    struct TestCase {
+                        boostagram: serde_json::Value,
+                        expected: Option<v4v::pc20::tlv::RemoteFeedGuid>,
+                    }
+                    let body = alby_invoice_body($value.boostagram);
+                    let invoice = v4v::alby::webhooks::extract_alby_invoice(&body).unwrap();
+                    let record = invoice.boostagram.unwrap();
+                    assert_eq!(record.remote_feed_guid, $value.expected);
+                }
+            )*
+        }
+    }
+}
+
+remote_feed_guid_tests! {
+    case_1: TestCase {
+        boostagram: serde_json::json!({
+            "action": "boost",
+            "remote_feed_guid": "917393e3-1b1e-5cef-ace4-edaa54e1f810",
+        }),
+        expected: Some(v4v::pc20::tlv::RemoteFeedGuid::Uuid(
+            uuid::Uuid::parse_str("917393e3-1b1e-5cef-ace4-edaa54e1f810").unwrap(),
+        )),
+    },
+    case_2: TestCase {
+        boostagram: serde_json::json!({
+            "action": "boost",
+            "remoteFeedGuid": "not-a-uuid",
+        }),
+        expected: Some(v4v::pc20::tlv::RemoteFeedGuid::Raw("not-a-uuid".to_string())),
+    },
+    case_3: TestCase {
+        boostagram: serde_json::json!({
+            "action": "boost",
+            "remote_feed_guid": "not-a-uuid",
+            "remoteFeedGuid": "917393e3-1b1e-5cef-ace4-edaa54e1f810",
+        }),
+        expected: Some(v4v::pc20::tlv::RemoteFeedGuid::Uuid(
+            uuid::Uuid::parse_str("917393e3-1b1e-5cef-ace4-edaa54e1f810").unwrap(),
+        )),
+    },
+    case_4: TestCase {
+        boostagram: serde_json::json!({
+            "action": "boost",
+        }),
+        expected: None,
+    },
+}
+
+#[test]
+fn parse_tlv_from_lnd_custom_records() {
+    use v4v::pc20::tlv::parse_tlv_from_custom_records;
+
+    // Custom records of an LND invoice HTLC, with values decoded as UTF-8.
+    let mut custom_records = std::collections::HashMap::from([
+        (
+            "5482373484".to_string(),
+            "b3f6c1c1ef8b1d4ccad8f3e7a2a4f38f8c5c2e07e1f9d1a83c6f2d7b5e9a0c41".to_string(),
+        ),
+        ("34349334".to_string(), "Keysend message".to_string()),
+        ("696969".to_string(), "wallet-id".to_string()),
+        (
+            "7629169".to_string(),
+            serde_json::json!({
+                "podcast": "Podcasting 2.0",
+                "episode": "Episode 150",
+                "action": "boost",
+                "ts": 33,
+                "app_name": "Fountain",
+                "value_msat_total": 100000,
+                "sender_name": "Alice",
+                "message": "Great show!",
+            })
+            .to_string(),
+        ),
+    ]);
+
+    let record = parse_tlv_from_custom_records(&custom_records).unwrap();
+    assert_eq!(record.action, v4v::pc20::payments::Action::Boost);
+    assert_eq!(record.feed_name.as_deref(), Some("Podcasting 2.0"));
+    assert_eq!(
+        record.timestamp_seconds,
+        Some(chrono::Duration::seconds(33))
+    );
+    assert_eq!(record.total_num_millisats, Some(100_000));
+    assert_eq!(record.message.as_deref(), Some("Great show!"));
+
+    custom_records.insert("7629169".to_string(), "42".to_string());
+    assert!(parse_tlv_from_custom_records(&custom_records).is_none());
+
+    custom_records.remove("7629169");
+    assert!(parse_tlv_from_custom_records(&custom_records).is_none());
+}
+
+macro_rules! extract_incoming_keysend_tests {
+    ($($name:ident: $value:expr,)*) => {
+        paste::item! {
+            $(
+                #[test]
+                fn [<extract_incoming_keysend_ $name>]() {
+                    struct TestCase {
+                        custom_records: serde_json::Value,
+                        boostagram: serde_json::Value,
+                        expected_message: Option<&'static str>,
+                    }
+                    let body = serde_json::json!({
+                        "identifier": "MAYm6eWC7TKUyixZWAKUM3su",
+                        "type": "incoming",
+                        "state": "SETTLED",
+                        "amount": 100,
+                        "keysend": true,
+                        "created_at": "2024-08-01T12:00:00.000Z",
+                        "custom_records": $value.custom_records,
+                        "boostagram": $value.boostagram,
+                    });
+                    let keysend = v4v::alby::webhooks::extract_incoming_keysend(&body).unwrap();
+                    assert_eq!(keysend.num_sats, 100);
+                    let message = keysend.boostagram.and_then(|record| record.message);
+                    assert_eq!(message.as_deref(), $value.expected_message);
+                }
+            )*
+        }
+    }
+}
+
+extract_incoming_keysend_tests! {
+    // JSON in the custom record.
+    case_1: TestCase {
+        custom_records: serde_json::json!({"7629169": r#"{"action":"boost","message":"Hi!"}"#}),
+        boostagram: serde_json::Value::Null,
+        expected_message: Some("Hi!"),
+    },
+    // Base64-encoded JSON in the custom record.
+    case_2: TestCase {
+        custom_records: serde_json::json!({"7629169": "eyJhY3Rpb24iOiJib29zdCIsIm1lc3NhZ2UiOiJIaSEifQ=="}),
+        boostagram: serde_json::Value::Null,
+        expected_message: Some("Hi!"),
+    },
+    // Custom record takes precedence over the parsed boostagram.
+    case_3: TestCase {
+        custom_records: serde_json::json!({"7629169": r#"{"action":"boost","message":"Hi!"}"#}),
+        boostagram: serde_json::json!({"action": "boost", "message": "Bye!"}),
+        expected_message: Some("Hi!"),
+    },
+    // Falls back to the parsed boostagram.
+    case_4: TestCase {
+        custom_records: serde_json::json!({"34349334": "abc"}),
+        boostagram: serde_json::json!({"action": "boost", "message": "Bye!"}),
+        expected_message: Some("Bye!"),
+    },
+    // Garbage is ignored.
+    case_5: TestCase {
+        custom_records: serde_json::json!({"7629169": "not a record"}),
+        boostagram: serde_json::Value::Null,
+        expected_message: None,
+    },
+}
+
+#[test]
+fn extract_incoming_keysend_rejects_non_keysend() {
+    let mut body = alby_invoice_body(serde_json::Value::Null);
+    body["keysend"] = serde_json::Value::Bool(false);
+    assert!(matches!(
+        v4v::alby::webhooks::extract_incoming_keysend(&body),
+        Err(v4v::alby::webhooks::ExtractError::NotKeysend)
+    ));
+}
+
+macro_rules! keysend_address_new_tests {
+    ($($name:ident: $value:expr,)*) => {
+        paste::item! {
+            $(
+                #[test]
+                fn [<keysend_address_new_ $name>]() {
+                    struct TestCase {
+                        pubkey: &'static str,
+                        expected_pubkey: Option<&'static str>,
+                    }
+                    let address = v4v::alby::types::KeysendAddress::new($value.pubkey);
+                    assert_eq!(address.as_ref().ok().map(|a| a.pubkey.as_str()), $value.expected_pubkey);
+                    if let Err(e) = address {
+                        assert_eq!(e.pubkey, $value.pubkey);
+                    }
+                }
+            )*
+        }
+    }
+}
+
+keysend_address_new_tests! {
+    case_1: TestCase {
+        pubkey: "030a58b8653d32b99200a2334cfe913e51dc7d155aa0116c176657a4f1722677a3",
+        expected_pubkey: Some("030a58b8653d32b99200a2334cfe913e51dc7d155aa0116c176657a4f1722677a3"),
+    },
+    case_2: TestCase {
+        pubkey: "030A58B8653D32B99200A2334CFE913E51DC7D155AA0116C176657A4F1722677A3",
+        expected_pubkey: Some("030a58b8653d32b99200a2334cfe913e51dc7d155aa0116c176657a4f1722677a3"),
+    },
+    case_3: TestCase {
+        // Wrong prefix.
+        pubkey: "040a58b8653d32b99200a2334cfe913e51dc7d155aa0116c176657a4f1722677a3",
+        expected_pubkey: None,
+    },
+    case_4: TestCase {
+        // Too short.
+        pubkey: "030a58b8653d32b99200a2334cfe913e51dc7d155aa0116c176657a4f1722677",
+        expected_pubkey: None,
+    },
+    case_5: TestCase {
+        // Not hex.
+        pubkey: "030a58b8653d32b99200a2334cfe913e51dc7d155aa0116c176657a4f1722677zz",
+        expected_pubkey: None,
+    },
+    case_6: TestCase {
+        pubkey: "",
+        expected_pubkey: None,
+    },
+}
+
+macro_rules! diff_distributions_tests {
+    ($($name:ident: $value:expr,)*) => {
+        paste::item! {
+            $(
+                #[test]
+                fn [<diff_distributions_ $name>]() {
+                    struct TestCase {
+                        before: Vec<u64>,
+                        after: Vec<u64>,
+                        expected_deltas: Vec<i64>,
+                        expected_total_moved: u64,
+                        expected_net_change: i128,
+                    }
+                    let diff = v4v::pc20::calc::DistributionDiff::new(&$value.before, &$value.after);
+                    assert_eq!(v4v::pc20::calc::diff_distributions(&$value.before, &$value.after), $value.expected_deltas);
+                    assert_eq!(diff.deltas, $value.expected_deltas);
+                    assert_eq!(diff.total_moved(), $value.expected_total_moved);
+                    assert_eq!(diff.net_change(), $value.expected_net_change);
+                    assert_eq!(diff.is_unchanged(), $value.expected_deltas.iter().all(|&delta| delta == 0));
+                }
+            )*
+        }
+    }
+}
+
+diff_distributions_tests! {
+    unchanged: TestCase {
+        before: vec![600, 400],
+        after: vec![600, 400],
+        expected_deltas: vec![0, 0],
+        expected_total_moved: 0,
+        expected_net_change: 0,
+    },
+    gain_and_loss: TestCase {
+        // A split change moved sats from the first recipient to the third.
+        before: vec![500, 300, 200],
+        after: vec![400, 300, 300],
+        expected_deltas: vec![-100, 0, 100],
+        expected_total_moved: 100,
+        expected_net_change: 0,
+    },
+    larger_total: TestCase {
+        before: vec![60, 40],
+        after: vec![66, 44],
+        expected_deltas: vec![6, 4],
+        expected_total_moved: 0,
+        expected_net_change: 10,
+    },
+    different_lengths: TestCase {
+        // A recipient was added.
+        before: vec![100],
+        after: vec![90, 10],
+        expected_deltas: vec![-10, 10],
+        expected_total_moved: 10,
+        expected_net_change: 0,
+    },
+    extremes: TestCase {
+        before: vec![u64::MAX, 0],
+        after: vec![0, u64::MAX],
+        expected_deltas: vec![i64::MIN, i64::MAX],
+        expected_total_moved: i64::MAX as u64,
+        expected_net_change: -1,
+    },
+}
+
+macro_rules! preview_percentages_tests {
+    ($($name:ident: $value:expr,)*) => {
+        paste::item! {
+            $(
+                #[test]
+                fn [<preview_percentages_ $name>]() {
+                    struct TestCase {
+                        recipients: Vec<v4v::pc20::calc::GenericRecipient>,
+                        expected: Result<Vec<f64>, v4v::pc20::calc::RecipientsToSplitsError>,
+                    }
+                    let percentages = v4v::pc20::calc::preview_percentages(&$value.recipients);
+                    assert_eq!(percentages, $value.expected);
+                    if let Ok(percentages) = percentages {
+                        if !percentages.is_empty() {
+                            assert!((percentages.iter().sum::<f64>() - 100.0).abs() < 1e-9);
+                        }
+                    }
+                }
+            )*
+        }
+    }
+}
+
+preview_percentages_tests! {
+    shares_and_fee: TestCase {
+        recipients: vec![
+            v4v::pc20::calc::GenericRecipient::ShareBased { num_shares: 50 },
+            v4v::pc20::calc::GenericRecipient::ShareBased { num_shares: 50 },
+            v4v::pc20::calc::GenericRecipient::PercentageBased { percentage: 1 },
+        ],
+        expected: Ok(vec![49.5, 49.5, 1.0]),
+    },
+    uneven_shares: TestCase {
+        recipients: vec![
+            v4v::pc20::calc::GenericRecipient::ShareBased { num_shares: 3 },
+            v4v::pc20::calc::GenericRecipient::ShareBased { num_shares: 1 },
+            v4v::pc20::calc::GenericRecipient::PercentageBased { percentage: 20 },
+        ],
+        expected: Ok(vec![60.0, 20.0, 20.0]),
+    },
+    only_fees: TestCase {
+        recipients: vec![
+            v4v::pc20::calc::GenericRecipient::PercentageBased { percentage: 30 },
+            v4v::pc20::calc::GenericRecipient::PercentageBased { percentage: 10 },
+        ],
+        expected: Ok(vec![75.0, 25.0]),
+    },
+    zero_shares: TestCase {
+        recipients: vec![
+            v4v::pc20::calc::GenericRecipient::ShareBased { num_shares: 0 },
+            v4v::pc20::calc::GenericRecipient::ShareBased { num_shares: 0 },
+        ],
+        expected: Ok(vec![50.0, 50.0]),
+    },
+    zero_shares_and_fee: TestCase {
+        recipients: vec![
+            v4v::pc20::calc::GenericRecipient::ShareBased { num_shares: 0 },
+            v4v::pc20::calc::GenericRecipient::ShareBased { num_shares: 0 },
+            v4v::pc20::calc::GenericRecipient::PercentageBased { percentage: 10 },
+        ],
+        expected: Ok(vec![45.0, 45.0, 10.0]),
+    },
+    empty: TestCase {
+        recipients: vec![],
+        expected: Ok(vec![]),
+    },
+    fee_exceeds_100: TestCase {
+        recipients: vec![
+            v4v::pc20::calc::GenericRecipient::PercentageBased { percentage: 60 },
+            v4v::pc20::calc::GenericRecipient::PercentageBased { percentage: 41 },
+        ],
+        expected: Err(v4v::pc20::calc::RecipientsToSplitsError::TotalFeeExceeds100),
+    },
+    fee_is_100_with_shares: TestCase {
+        recipients: vec![
+            v4v::pc20::calc::GenericRecipient::ShareBased { num_shares: 1 },
+            v4v::pc20::calc::GenericRecipient::PercentageBased { percentage: 100 },
+        ],
+        expected: Err(v4v::pc20::calc::RecipientsToSplitsError::FeeIs100ButNonFeeRecipientsExist),
+    },
+}
+
+macro_rules! normalize_splits_tests {
+    ($($name:ident: $value:expr,)*) => {
+        paste::item! {
+            $(
+                #[test]
+                fn [<normalize_splits_ $name>]() {
+                    struct TestCase {
+                        splits: Vec<u64>,
+                        expected: Vec<u64>,
+                    }
+                    assert_eq!(v4v::pc20::calc::normalize_splits(&$value.splits), $value.expected);
+                }
+            )*
+        }
+    }
+}
+
+normalize_splits_tests! {
+    fee_splits: TestCase {
+        splits: vec![9900, 9900, 200],
+        expected: vec![99, 99, 2],
+    },
+    zeros_stay: TestCase {
+        splits: vec![0, 30, 0, 45],
+        expected: vec![0, 2, 0, 3],
+    },
+    all_zero: TestCase {
+        splits: vec![0, 0, 0],
+        expected: vec![0, 0, 0],
+    },
+    single: TestCase {
+        splits: vec![250],
+        expected: vec![1],
+    },
+    coprime: TestCase {
+        splits: vec![3, 5],
+        expected: vec![3, 5],
+    },
+    empty: TestCase {
+        splits: vec![],
+        expected: vec![],
+    },
+}
+
+macro_rules! splits_equivalent_tests {
+    ($($name:ident: $value:expr,)*) => {
+        paste::item! {
+            $(
+                #[test]
+                fn [<splits_equivalent_ $name>]() {
+                    struct TestCase {
+                        a: Vec<u64>,
+                        b: Vec<u64>,
+                        expected: bool,
+                    }
+                    assert_eq!(v4v::pc20::calc::splits_equivalent(&$value.a, &$value.b), $value.expected);
+                    assert_eq!(v4v::pc20::calc::splits_equivalent(&$value.b, &$value.a), $value.expected);
+                }
+            )*
+        }
+    }
+}
+
+splits_equivalent_tests! {
+    case_1: TestCase {
+        a: vec![2, 2],
+        b: vec![1, 1],
+        expected: true,
+    },
+    case_2: TestCase {
+        a: vec![60, 30, 10],
+        b: vec![6, 3, 1],
+        expected: true,
+    },
+    case_3: TestCase {
+        a: vec![60, 40],
+        b: vec![40, 60],
+        expected: false,
+    },
+    case_4: TestCase {
+        a: vec![1, 1],
+        b: vec![1, 1, 1],
+        expected: false,
+    },
+    case_5: TestCase {
+        a: vec![0, 5],
+        b: vec![0, 1],
+        expected: true,
+    },
+    case_6: TestCase {
+        a: vec![],
+        b: vec![],
+        expected: true,
+    },
+    case_7: TestCase {
+        a: vec![u64::MAX, u64::MAX],
+        b: vec![1, 1],
+        expected: true,
+    },
+}
+
+macro_rules! value_blocks_equivalent_tests {
+    ($($name:ident: $value:expr,)*) => {
+        paste::item! {
+            $(
+                #[test]
+                fn [<value_blocks_equivalent_ $name>]() {
+                    struct TestCase {
+                        a: Vec<(&'static str, u64)>,
+                        b: Vec<(&'static str, u64)>,
+                        expected: bool,
+                    }
+                    assert_eq!(v4v::pc20::calc::value_blocks_equivalent(&$value.a, &$value.b), $value.expected);
+                }
+            )*
+        }
+    }
+}
+
+value_blocks_equivalent_tests! {
+    case_1: TestCase {
+        a: vec![("alice", 2), ("bob", 2)],
+        b: vec![("alice", 1), ("bob", 1)],
+        expected: true,
+    },
+    case_2: TestCase {
+        a: vec![("alice", 90), ("bob", 10)],
+        b: vec![("bob", 1), ("alice", 9)],
+        expected: true,
+    },
+    case_3: TestCase {
+        a: vec![("alice", 90), ("bob", 10)],
+        b: vec![("alice", 1), ("bob", 9)],
+        expected: false,
+    },
+    case_4: TestCase {
+        a: vec![("alice", 1), ("bob", 1)],
+        b: vec![("alice", 1), ("carol", 1)],
+        expected: false,
+    },
+}
+
+#[test]
+fn make_payment_args_builder_validation() {
+    use v4v::pc20::payments::{MakePaymentArgs, MakePaymentArgsError, PaymentRecipientInfo};
+
+    let recipient = PaymentRecipientInfo {
+        num_sats: 10,
+        ..Default::default()
+    };
+
+    assert_eq!(
+        MakePaymentArgs::builder()
+            .token("token")
+            .recipient(recipient.clone())
+            .build()
+            .unwrap_err(),
+        MakePaymentArgsError::MissingUserAgent
+    );
+    assert_eq!(
+        MakePaymentArgs::builder()
+            .user_agent("test")
+            .recipient(recipient.clone())
+            .build()
+            .unwrap_err(),
+        MakePaymentArgsError::MissingToken
+    );
+
+    let args = MakePaymentArgs::builder()
+        .user_agent("test")
+        .token("token")
+        .recipients(vec![recipient.clone(), recipient.clone()])
+        .max_message_bytes(100)
+        .build()
+        .unwrap();
+    assert_eq!(args.recipients.len(), 2);
+    assert_eq!(args.max_message_bytes, Some(100));
+    assert_eq!(args.timeouts, v4v::alby::api::Timeouts::default());
+
+    let timeouts = v4v::alby::api::Timeouts {
+        connect_timeout: std::time::Duration::from_secs(2),
+        timeout: std::time::Duration::from_secs(60),
+    };
+    let args = MakePaymentArgs::builder()
+        .user_agent("test")
+        .token("token")
+        .recipient(recipient)
+        .timeouts(timeouts)
+        .build()
+        .unwrap();
+    assert_eq!(args.timeouts, timeouts);
+}
+
+#[cfg(feature = "nostr")]
+fn signed_boostagram(message: &str, signed_message: &str) -> serde_json::Value {
+    let keypair = secp256k1::Keypair::from_secret_bytes([7; 32]).unwrap();
+    let sender_id = keypair.x_only_public_key().0.to_string();
+    let payload = serde_json::json!([0, sender_id, 60, 1, [], signed_message]).to_string();
+    let signature = keypair.sign_schnorr_no_aux_rand(&hmac_sha256::Hash::hash(payload.as_bytes()));
+
+    serde_json::json!({
+        "action": "boost",
+        "ts": 60,
+        "message": message,
+        "sender_id": sender_id,
+        "signature": signature.to_string(),
+    })
+}
+
+#[test]
+fn nostr_signing_payload_golden() {
+    use v4v::alby::webhooks::extract_alby_invoice;
+
+    let boostagram = serde_json::json!({
+        "action": "boost",
+        "ts": 60.7,
+        "message": "Hi \"there\"\n🎧",
+        "sender_id": "abc",
+    });
+    let record = extract_alby_invoice(&alby_invoice_body(boostagram))
+        .unwrap()
+        .boostagram
+        .unwrap();
+    assert_eq!(
+        record.nostr_signing_payload().as_deref(),
+        Some(r#"[0,"abc",60,1,[],"Hi \"there\"\n🎧"]"#)
+    );
+
+    let boostagram = serde_json::json!({"action": "boost", "ts": 60, "sender_id": "abc"});
+    let record = extract_alby_invoice(&alby_invoice_body(boostagram))
+        .unwrap()
+        .boostagram
+        .unwrap();
+    assert_eq!(
+        record.nostr_signing_payload().as_deref(),
+        Some(r#"[0,"abc",60,1,[],""]"#)
+    );
+
+    let boostagram = serde_json::json!({"action": "boost", "sender_id": "abc"});
+    let record = extract_alby_invoice(&alby_invoice_body(boostagram))
+        .unwrap()
+        .boostagram
+        .unwrap();
+    assert_eq!(record.nostr_signing_payload(), None);
+}
+
+#[cfg(feature = "nostr")]
+#[test]
+fn verify_boostagram_signature() {
+    use v4v::alby::webhooks::extract_alby_invoice;
+    use v4v::pc20::tlv::VerifyError;
+
+    let body = alby_invoice_body(signed_boostagram("Hi!", "Hi!"));
+    let invoice = extract_alby_invoice(&body).unwrap();
+    assert_eq!(invoice.verify_boostagram_signature(), Ok(true));
+
+    // Message was tampered with.
+    let body = alby_invoice_body(signed_boostagram("Bye!", "Hi!"));
+    let invoice = extract_alby_invoice(&body).unwrap();
+    assert_eq!(invoice.verify_boostagram_signature(), Ok(false));
+
+    let mut boostagram = signed_boostagram("Hi!", "Hi!");
+    boostagram["signature"] = serde_json::Value::Null;
+    let invoice = extract_alby_invoice(&alby_invoice_body(boostagram)).unwrap();
+    assert_eq!(
+        invoice.verify_boostagram_signature(),
+        Err(VerifyError::NoSignature)
+    );
+
+    let mut boostagram = signed_boostagram("Hi!", "Hi!");
+    boostagram["sender_id"] = serde_json::json!("npub1xyz");
+    let invoice = extract_alby_invoice(&alby_invoice_body(boostagram)).unwrap();
+    assert_eq!(
+        invoice.verify_boostagram_signature(),
+        Err(VerifyError::InvalidSenderId)
+    );
+
+    let invoice = extract_alby_invoice(&alby_invoice_body(serde_json::Value::Null)).unwrap();
+    assert_eq!(
+        invoice.verify_boostagram_signature(),
+        Err(VerifyError::NoBoostagram)
+    );
+}
+
+macro_rules! splits_to_percentages_tests {
+    ($($name:ident: $value:expr,)*) => {
+        paste::item! {
+            $(
+                #[test]
+                fn [<splits_to_percentages_ $name>]() {
+                    use v4v::pc20::calc::{splits_to_percentages, RoundingMode};
+
+                    struct TestCase {
+                        splits: Vec<u64>,
+                        expected_nearest: Vec<u64>,
+                        expected_up: Vec<u64>,
+                        expected_down: Vec<u64>,
+                        expected_largest_remainder: Vec<u64>,
+                    }
+                    let splits = $value.splits;
+                    assert_eq!(splits_to_percentages(&splits, RoundingMode::Nearest), $value.expected_nearest);
+                    assert_eq!(splits_to_percentages(&splits, RoundingMode::Up), $value.expected_up);
+                    assert_eq!(splits_to_percentages(&splits, RoundingMode::Down), $value.expected_down);
+                    assert_eq!(splits_to_percentages(&splits, RoundingMode::LargestRemainder), $value.expected_largest_remainder);
+                    assert_eq!(splits_to_percentages(&splits, RoundingMode::default()), $value.expected_largest_remainder);
+                }
+            )*
+        }
+    }
+}
+
+splits_to_percentages_tests! {
+    case_1: TestCase {
+        splits: vec![60, 40],
+        expected_nearest: vec![60, 40],
+        expected_up: vec![60, 40],
+        expected_down: vec![60, 40],
+        expected_largest_remainder: vec![60, 40],
+    },
+    case_2: TestCase {
+        splits: vec![1, 1, 1],
+        expected_nearest: vec![34, 33, 33],
+        expected_up: vec![34, 33, 33],
+        expected_down: vec![34, 33, 33],
+        expected_largest_remainder: vec![34, 33, 33],
+    },
+    case_3: TestCase {
+        // 14.2857..%, 28.5714..%, 57.1428..%
+        splits: vec![1, 2, 4],
+        expected_nearest: vec![14, 29, 57],
+        expected_up: vec![14, 29, 57],
+        expected_down: vec![14, 29, 57],
+        expected_largest_remainder: vec![14, 29, 57],
+    },
+    case_4: TestCase {
+        // 0.5%, 0.5%, 99%
+        splits: vec![1, 1, 198],
+        expected_nearest: vec![1, 0, 99],
+        expected_up: vec![1, 0, 99],
+        expected_down: vec![1, 0, 99],
+        expected_largest_remainder: vec![1, 0, 99],
+    },
+    case_5: TestCase {
+        splits: vec![0, 0],
+        expected_nearest: vec![50, 50],
+        expected_up: vec![50, 50],
+        expected_down: vec![50, 50],
+        expected_largest_remainder: vec![50, 50],
+    },
+    case_6: TestCase {
+        splits: vec![],
+        expected_nearest: vec![],
+        expected_up: vec![],
+        expected_down: vec![],
+        expected_largest_remainder: vec![],
+    },
+}
+
+#[test]
+fn splits_to_percentages_within_floor_and_ceil() {
+    use v4v::pc20::calc::{splits_to_percentages, RoundingMode};
+
+    let cases: Vec<Vec<u64>> = vec![
+        vec![1, 1, 1],
+        vec![1, 2, 4],
+        vec![1, 1, 198],
+        vec![1, 1, 1, 1, 1, 1, 1],
+        vec![3, 3, 3, 991],
+        vec![995, 1, 1, 1, 1, 1],
+        vec![1; 150],
+        (1..=40).collect(),
+        vec![u64::MAX, 1, 1],
+    ];
+
+    for splits in cases {
+        let total: u128 = splits.iter().map(|&split| split as u128).sum();
+        for mode in [
+            RoundingMode::Nearest,
+            RoundingMode::Up,
+            RoundingMode::Down,
+            RoundingMode::LargestRemainder,
+        ] {
+            let percentages = splits_to_percentages(&splits, mode);
+            assert_eq!(
+                percentages.iter().sum::<u64>(),
+                100,
+                "{:?} {:?}",
+                mode,
+                splits
+            );
+            for (&split, &percentage) in splits.iter().zip(&percentages) {
+                let numerator = split as u128 * 100;
+                let (floor, ceil) = (numerator / total, numerator.div_ceil(total));
+                assert!(
+                    (floor..=ceil).contains(&(percentage as u128)),
+                    "{:?} {:?}: {} not in [{}, {}]",
+                    mode,
+                    splits,
+                    percentage,
+                    floor,
+                    ceil
+                );
+            }
+        }
+    }
+}
+
+macro_rules! settlement_report_tests {
+    ($($name:ident: $value:expr,)*) => {
+        paste::item! {
+            $(
+                #[test]
+                fn [<settlement_report_ $name>]() {
+                    use v4v::pc20::forwarding::{
+                        rescale_recipients_to_amount, CreateInvoiceMetadata, SettlementReport,
+                    };
+
+                    struct TestCase {
+                        invoiced_sats: Vec<u64>,
+                        settled_sats: u64,
+                        expected_delta: i128,
+                        expected_sats: Vec<u64>,
+                    }
+                    let recipients: Vec<serde_json::Value> = $value
+                        .invoiced_sats
+                        .iter()
+                        .map(|num_sats| serde_json::json!({
+                            "address": {"pubkey": "pubkey"},
+                            "numSats": num_sats,
+                        }))
+                        .collect();
+                    let mut body = alby_invoice_body(serde_json::Value::Null);
+                    body["amount"] = serde_json::json!($value.settled_sats);
+                    body["metadata"] = serde_json::json!({
+                        "payment_info": {"totalNumSats": 0},
+                        "recipients": recipients,
+                    });
+
+                    let invoice = v4v::alby::webhooks::extract_alby_invoice(&body).unwrap();
+                    let metadata = CreateInvoiceMetadata::try_from(invoice.clone()).unwrap();
+                    let report = SettlementReport::new(&metadata, &invoice);
+                    assert_eq!(report.delta(), $value.expected_delta);
+                    assert_eq!(report.is_overpaid(), $value.expected_delta > 0);
+                    assert_eq!(report.is_underpaid(), $value.expected_delta < 0);
+
+                    let rescaled = rescale_recipients_to_amount(
+                        report.settled_sats,
+                        &metadata.forwarding_data.recipients,
+                    );
+                    let rescaled_sats: Vec<u64> = rescaled.iter().map(|r| r.num_sats).collect();
+                    assert_eq!(rescaled_sats, $value.expected_sats);
+                }
+            )*
+        }
+    }
+}
+
+settlement_report_tests! {
+    // Exact settlement.
+    case_1: TestCase {
+        invoiced_sats: vec![60, 40],
+        settled_sats: 100,
+        expected_delta: 0,
+        expected_sats: vec![60, 40],
+    },
+    // Underpayment.
+    case_2: TestCase {
+        invoiced_sats: vec![60, 40],
+        settled_sats: 50,
+        expected_delta: -50,
+        expected_sats: vec![30, 20],
+    },
+    // Overpayment is distributed proportionally.
+    case_3: TestCase {
+        invoiced_sats: vec![60, 40],
+        settled_sats: 103,
+        expected_delta: 3,
+        expected_sats: vec![62, 41],
+    },
+}
+
+#[test]
+fn blip10_field_map_matches_record_serialization() {
+    let record = v4v::pc20::tlv::Record::builder()
+        .action(v4v::pc20::payments::Action::Boost)
+        .feed_guid(uuid::Uuid::nil())
+        .feed_name("feed")
+        .feed_pi_id(1)
+        .feed_url(url::Url::parse("https://example.com/feed.xml").unwrap())
+        .item_guid("item")
+        .item_name("item")
+        .item_pi_id(1)
+        .timestamp_seconds(chrono::Duration::seconds(1))
+        .timestamp_hhmmss(chrono::Duration::seconds(1))
+        .speed(1.0)
+        .app_name("app")
+        .app_version("1.0")
+        .sender_name("sender")
+        .sender_id("sender")
+        .receiver_name("receiver")
+        .total_num_millisats(1000)
+        .message("message")
+        .boost_link(url::Url::parse("https://example.com").unwrap())
+        .payment_signature("signature")
+        .payment_id(uuid::Uuid::nil())
+        .boost_id(uuid::Uuid::nil())
+        .remote_feed_guid(uuid::Uuid::nil())
+        .remote_item_guid("remote")
+        .reply_address("address")
+        .reply_custom_key("key")
+        .reply_custom_value("value")
+        .build();
+
+    // Destructured without `..` so that adding a field to `Record` requires updating this test.
+    let v4v::pc20::tlv::Record {
+        action: _,
+        feed_guid: Some(_),
+        feed_name: Some(_),
+        feed_pi_id: Some(_),
+        feed_url: Some(_),
+        item_guid: Some(_),
+        item_name: Some(_),
+        item_pi_id: Some(_),
+        timestamp_seconds: Some(_),
+        timestamp_hhmmss: Some(_),
+        speed: Some(_),
+        app_name: Some(_),
+        app_version: Some(_),
+        sender_name: Some(_),
+        sender_id: Some(_),
+        receiver_name: Some(_),
+        total_num_millisats: Some(_),
+        message: Some(_),
+        boost_link: Some(_),
+        payment_signature: Some(_),
+        payment_id: Some(_),
+        boost_id: Some(_),
+        remote_feed_guid: Some(_),
+        remote_item_guid: Some(_),
+        reply_address: Some(_),
+        reply_custom_key: Some(_),
+        reply_custom_value: Some(_),
+    } = &record
+    else {
+        panic!("every field should be set: {:?}", record);
+    };
+
+    let serialized = serde_json::to_value(&record).unwrap();
+    let mut keys: Vec<&str> = serialized
+        .as_object()
+        .unwrap()
+        .keys()
+        .map(|key| key.as_str())
+        .collect();
+    keys.sort_unstable();
+
+    let mut expected_keys: Vec<&str> = v4v::pc20::tlv::blip10_field_map()
+        .iter()
+        .map(|(_, key)| *key)
+        .collect();
+    expected_keys.sort_unstable();
+
+    assert_eq!(keys, expected_keys);
+}
+
+#[test]
+fn assign_splits_rejects_length_mismatch() {
+    use v4v::pc20::calc::{assign_splits, LengthMismatchError};
+
+    #[derive(Debug, PartialEq, Clone)]
+    struct MyStruct {
+        split: u64,
+    }
+
+    impl v4v::pc20::calc::HasSplit for MyStruct {
+        fn get_split(&self) -> u64 {
+            self.split
+        }
+
+        fn set_split(&mut self, split: u64) {
+            self.split = split;
+        }
+    }
+
+    let values = vec![MyStruct { split: 1 }, MyStruct { split: 2 }];
+
+    assert_eq!(
+        assign_splits(&values, &[3]),
+        Err(LengthMismatchError {
+            num_values: 2,
+            num_splits: 1,
+        })
+    );
+    assert_eq!(
+        assign_splits(&values, &[3, 4, 5]),
+        Err(LengthMismatchError {
+            num_values: 2,
+            num_splits: 3,
+        })
+    );
+    assert_eq!(
+        assign_splits(&values, &[3, 4]),
+        Ok(vec![MyStruct { split: 3 }, MyStruct { split: 4 }])
+    );
+}
+
+macro_rules! recipients_to_sats_tests {
+    ($($name:ident: $value:expr,)*) => {
+        paste::item! {
+            $(
+                #[test]
+                fn [<recipients_to_sats_ $name>]() {
+                    struct TestCase {
+                        recipients: Vec<v4v::pc20::calc::GenericRecipient>,
+                        total_sats: u64,
+                        expected_sats: Result<Vec<u64>, v4v::pc20::calc::RecipientsToSplitsError>,
+                    }
+                    assert_eq!(v4v::pc20::calc::recipients_to_sats(&$value.recipients, $value.total_sats), $value.expected_sats);
+                }
+            )*
+        }
+    }
+}
+
+recipients_to_sats_tests! {
+    case_1: TestCase {
+        recipients: vec![
+            v4v::pc20::calc::GenericRecipient::ShareBased { num_shares: 50 },
+            v4v::pc20::calc::GenericRecipient::ShareBased { num_shares: 50 },
+        ],
+        total_sats: 100,
+        expected_sats: Ok(vec![50, 50]),
+    },
+    case_2: TestCase {
+        recipients: vec![
+            v4v::pc20::calc::GenericRecipient::ShareBased { num_shares: 50 },
+            v4v::pc20::calc::GenericRecipient::ShareBased { num_shares: 50 },
+            v4v::pc20::calc::GenericRecipient::PercentageBased { percentage: 1 },
+        ],
+        total_sats: 200,
+        expected_sats: Ok(vec![99, 99, 2]),
+    },
+    case_3: TestCase {
+        recipients: vec![
+            v4v::pc20::calc::GenericRecipient::ShareBased { num_shares: 50 },
+            v4v::pc20::calc::GenericRecipient::ShareBased { num_shares: 50 },
+            v4v::pc20::calc::GenericRecipient::PercentageBased { percentage: 1 },
+        ],
+        total_sats: 3,
+        expected_sats: Ok(vec![1, 1, 1]),
+    },
+    case_4: TestCase {
+        recipients: vec![
+            v4v::pc20::calc::GenericRecipient::PercentageBased { percentage: 2 },
+            v4v::pc20::calc::GenericRecipient::PercentageBased { percentage: 1 },
+        ],
+        total_sats: 30,
+        expected_sats: Ok(vec![20, 10]),
+    },
+    case_5: TestCase {
+        recipients: vec![
+            v4v::pc20::calc::GenericRecipient::ShareBased { num_shares: 50 },
+            v4v::pc20::calc::GenericRecipient::PercentageBased { percentage: 100 },
+        ],
+        total_sats: 100,
+        expected_sats: Err(v4v::pc20::calc::RecipientsToSplitsError::FeeIs100ButNonFeeRecipientsExist),
+    },
+    case_6: TestCase {
+        recipients: vec![
+            v4v::pc20::calc::GenericRecipient::PercentageBased { percentage: 60 },
+            v4v::pc20::calc::GenericRecipient::PercentageBased { percentage: 50 },
+        ],
+        total_sats: 100,
+        expected_sats: Err(v4v::pc20::calc::RecipientsToSplitsError::TotalFeeExceeds100),
+    },
+}
+
+macro_rules! minimum_satisfying_amount_tests {
+    ($($name:ident: $value:expr,)*) => {
+        paste::item! {
+            $(
+                #[test]
+                fn [<minimum_satisfying_amount_ $name>]() {
+                    struct TestCase {
+                        splits: Vec<u64>,
+                        expected_amount: u64,
+                    }
+                    let amount = v4v::pc20::calc::minimum_satisfying_amount(&$value.splits);
+                    assert_eq!(amount, $value.expected_amount);
+
+                    // At the minimum amount, every recipient with a non-zero split gets a sat.
+                    if amount > 0 && amount < 1_000_000 {
+                        let sats = v4v::pc20::calc::compute_sat_recipients(&$value.splits, amount);
+                        for (split, sats) in $value.splits.iter().zip(sats) {
+                            if *split > 0 {
+                                assert!(sats >= 1);
+                            }
+                        }
+                    }
+                }
+            )*
+        }
+    }
+}
+
+minimum_satisfying_amount_tests! {
+    case_1: TestCase {
+        splits: vec![1, 99],
+        expected_amount: 100,
+    },
+    case_2: TestCase {
+        splits: vec![50, 50],
+        expected_amount: 2,
+    },
+    case_3: TestCase {
+        splits: vec![3, 4],
+        expected_amount: 3,
+    },
+    case_4: TestCase {
+        splits: vec![0, 10, 90],
+        expected_amount: 10,
+    },
+    case_5: TestCase {
+        splits: vec![100],
+        expected_amount: 1,
+    },
+    case_6: TestCase {
+        splits: vec![0, 0],
+        expected_amount: 0,
+    },
+    case_7: TestCase {
+        splits: vec![],
+        expected_amount: 0,
+    },
+    case_8: TestCase {
+        splits: vec![1, u64::MAX],
+        expected_amount: u64::MAX,
+    },
+}
+
+#[test]
+fn verify_signature_at_fixed_time() {
+    use base64::{engine::general_purpose::STANDARD, Engine as _};
+
+    let secret = "whsec_MfKQ9r8GKYqrTwjUPD8ILPZIo2LaLaSw";
+    let payload = br#"{"type":"incoming","state":"SETTLED"}"#;
+    let msg_id = "msg_p5jXN8AQM9LWM0D4loKWxJek";
+    let received_at = chrono::DateTime::parse_from_rfc3339("2024-08-01T12:00:00Z")
+        .unwrap()
+        .with_timezone(&chrono::Utc);
+    let timestamp = received_at.timestamp();
+
+    let key = STANDARD
+        .decode(secret.trim_start_matches("whsec_"))
+        .unwrap();
+    let to_sign = format!(
+        "{msg_id}.{timestamp}.{}",
+        std::str::from_utf8(payload).unwrap()
+    );
+    let signature = STANDARD.encode(hmac_sha256::HMAC::mac(to_sign.as_bytes(), &key));
+
+    let mut headers = http1::HeaderMap::new();
+    headers.insert("svix-id", msg_id.parse().unwrap());
+    headers.insert("svix-timestamp", timestamp.to_string().parse().unwrap());
+    headers.insert("svix-signature", format!("v1,{signature}").parse().unwrap());
+
+    let verify = |now| v4v::alby::webhooks::verify_signature_at(secret, payload, &headers, now);
+    assert!(verify(received_at).is_ok());
+    assert!(verify(received_at + chrono::Duration::minutes(5)).is_ok());
+    assert!(verify(received_at + chrono::Duration::minutes(6)).is_err());
+    assert!(verify(received_at - chrono::Duration::minutes(6)).is_err());
+}
+
+macro_rules! amount_report_tests {
+    ($($name:ident: $value:expr,)*) => {
+        paste::item! {
+            $(
+                #[test]
+                fn [<amount_report_ $name>]() {
+                    struct TestCase {
+                        total_num_sats: u64,
+                        recipients_num_sats: Vec<u64>,
+                        expected_total_num_millisats: u64,
+                        expected_overspend: bool,
+                    }
+                    let args = v4v::pc20::payments::MakePaymentArgs {
+                        payment_info: v4v::pc20::payments::PaymentInfo {
+                            total_num_sats: $value.total_num_sats,
+                            ..Default::default()
+                        },
+                        recipients: $value
+                            .recipients_num_sats
+                            .iter()
+                            .map(|&num_sats| v4v::pc20::payments::PaymentRecipientInfo {
+                                num_sats,
+                                ..Default::default()
+                            })
+                            .collect(),
+                        strict_amounts: true,
+                        ..Default::default()
+                    };
+
+                    let report = args.amount_report();
+                    assert_eq!(report.total_num_sats, $value.total_num_sats);
+                    assert_eq!(report.recipients_num_sats, $value.recipients_num_sats.iter().sum::<u64>());
+                    assert_eq!(report.total_num_millisats, $value.expected_total_num_millisats);
+                    assert_eq!(report.is_overspend(), $value.expected_overspend);
+
+                    let estimate = args.estimate();
+                    if $value.expected_overspend {
+                        assert!(matches!(estimate, Err(v4v::alby::api::RequestError::InvalidArgs(_))));
+                    } else {
+                        assert!(estimate.is_ok());
+                    }
+                }
+            )*
+        }
+    }
+}
+
+amount_report_tests! {
+    // Recipients receive exactly the total.
+    case_1: TestCase {
+        total_num_sats: 100,
+        recipients_num_sats: vec![60, 40],
+        expected_total_num_millisats: 100_000,
+        expected_overspend: false,
+    },
+    // Recipients receive less than the total (fees).
+    case_2: TestCase {
+        total_num_sats: 100,
+        recipients_num_sats: vec![50, 40],
+        expected_total_num_millisats: 100_000,
+        expected_overspend: false,
+    },
+    // Recipients receive more than the total.
+    case_3: TestCase {
+        total_num_sats: 100,
+        recipients_num_sats: vec![60, 50],
+        expected_total_num_millisats: 100_000,
+        expected_overspend: true,
+    },
+    // Total not set, so it is the recipients' sum.
+    case_4: TestCase {
+        total_num_sats: 0,
+        recipients_num_sats: vec![60, 50],
+        expected_total_num_millisats: 110_000,
+        expected_overspend: false,
+    },
+}
+
+#[test]
+fn format_payout_plan_unallocated_and_missing_names() {
+    use v4v::pc20::payments::{format_payout_plan, PaymentRecipientInfo};
+
+    let recipients = vec![
+        PaymentRecipientInfo {
+            address: v4v::alby::types::KeysendAddress {
+                pubkey: "short".to_string(),
+                custom_data: None,
+            }
+            .into(),
+            num_sats: 1,
+            ..Default::default()
+        },
+        PaymentRecipientInfo {
+            address: v4v::alby::types::KeysendAddress {
+                pubkey: "02d5c1bf8b940dc9cadca86d1b0a3c37fbe39cee4c7e839e33bef9174531d27f52"
+                    .to_string(),
+                custom_data: None,
+            }
+            .into(),
+            num_sats: 2,
+            name: Some("Host".to_string()),
+            ..Default::default()
+        },
+    ];
+
+    assert_eq!(
+        format_payout_plan(&recipients, 10),
+        "\
+Name         Pubkey         Sats  Share
+-            short             1    33%
+Host         02d5c1bf…7f52     2    67%
+Unallocated                    7
+Total                         10   100%
+"
+    );
+
+    assert_eq!(
+        format_payout_plan(&[], 10),
+        "\
+Name         Pubkey  Sats  Share
+Unallocated            10
+Total                  10
+"
+    );
+}
+
+#[test]
+fn format_payout_plan_with_roles() {
+    use v4v::pc20::payments::{format_payout_plan, PaymentRecipientInfo};
+
+    let recipients = vec![
+        PaymentRecipientInfo {
+            address: v4v::alby::types::KeysendAddress {
+                pubkey: "host".to_string(),
+                custom_data: None,
+            }
+            .into(),
+            num_sats: 9,
+            name: Some("Alice".to_string()),
+            role: Some("host".to_string()),
+            ..Default::default()
+        },
+        PaymentRecipientInfo {
+            address: v4v::alby::types::KeysendAddress {
+                pubkey: "app".to_string(),
+                custom_data: None,
+            }
+            .into(),
+            num_sats: 1,
+            name: Some("App".to_string()),
+            ..Default::default()
+        },
+    ];
+
+    assert_eq!(
+        format_payout_plan(&recipients, 10),
+        "\
+Name   Role  Pubkey  Sats  Share
+Alice  host  host       9    90%
+App    -     app        1    10%
+Total                  10   100%
+"
+    );
+}
+
+#[test]
+fn require_all_or_nothing() {
+    use v4v::pc20::payments::{MakePaymentArgs, PaymentInfo, PaymentRecipientInfo};
+
+    let args = |total_num_sats: u64, require_all_or_nothing: bool| {
+        let splits = [1, 49, 50];
+        MakePaymentArgs {
+            payment_info: PaymentInfo {
+                total_num_sats,
+                ..Default::default()
+            },
+            recipients: v4v::pc20::calc::compute_sat_recipients(&splits, total_num_sats)
+                .into_iter()
+                .enumerate()
+                .map(|(i, num_sats)| PaymentRecipientInfo {
+                    address: v4v::alby::types::KeysendAddress {
+                        pubkey: format!("pubkey{}", i),
+                        custom_data: None,
+                    }
+                    .into(),
+                    num_sats,
+                    ..Default::default()
+                })
+                .collect(),
+            require_all_or_nothing,
+            ..Default::default()
+        }
+    };
+
+    // Not enough sats for every recipient: nothing is sent.
+    assert!(matches!(
+        args(2, true).estimate(),
+        Err(v4v::alby::api::RequestError::InvalidArgs(_))
+    ));
+    // Best-effort by default.
+    assert_eq!(args(2, false).estimate().unwrap().keysend_count, 3);
+    // Enough sats for everyone.
+    assert_eq!(args(3, true).estimate().unwrap().keysend_count, 3);
+
+    // Recipients would receive more than the total.
+    let mut overspending_args = args(100, true);
+    overspending_args.recipients[0].num_sats += 1;
+    assert!(matches!(
+        overspending_args.estimate(),
+        Err(v4v::alby::api::RequestError::InvalidArgs(_))
+    ));
+}
+
+macro_rules! max_sats_tests {
+    ($($name:ident: $value:expr,)*) => {
+        paste::item! {
+            $(
+                #[test]
+                fn [<max_sats_ $name>]() {
+                    struct TestCase {
+                        total_num_sats: u64,
+                        recipients_num_sats: Vec<u64>,
+                        max_sats: Option<u64>,
+                        expected_ok: bool,
+                    }
+                    let args = v4v::pc20::payments::MakePaymentArgs {
+                        payment_info: v4v::pc20::payments::PaymentInfo {
+                            total_num_sats: $value.total_num_sats,
+                            ..Default::default()
+                        },
+                        recipients: $value
+                            .recipients_num_sats
+                            .iter()
+                            .map(|&num_sats| v4v::pc20::payments::PaymentRecipientInfo {
+                                num_sats,
+                                ..Default::default()
+                            })
+                            .collect(),
+                        max_sats: $value.max_sats,
+                        ..Default::default()
+                    };
+                    match args.estimate() {
+                        Ok(_) => assert!($value.expected_ok),
+                        Err(v4v::alby::api::RequestError::InvalidArgs(_)) => assert!(!$value.expected_ok),
+                        Err(e) => panic!("unexpected error: {}", e),
+                    }
+                }
+            )*
+        }
+    }
+}
+
+max_sats_tests! {
+    case_1: TestCase {
+        total_num_sats: v4v::pc20::payments::DEFAULT_MAX_SATS,
+        recipients_num_sats: vec![v4v::pc20::payments::DEFAULT_MAX_SATS],
+        max_sats: None,
+        expected_ok: true,
+    },
+    case_2: TestCase {
+        total_num_sats: v4v::pc20::payments::DEFAULT_MAX_SATS + 1,
+        recipients_num_sats: vec![1],
+        max_sats: None,
+        expected_ok: false,
+    },
+    case_3: TestCase {
+        total_num_sats: 0,
+        recipients_num_sats: vec![u64::MAX, u64::MAX],
+        max_sats: None,
+        expected_ok: false,
+    },
+    case_4: TestCase {
+        total_num_sats: 1000,
+        recipients_num_sats: vec![600, 400],
+        max_sats: Some(1000),
+        expected_ok: true,
+    },
+    case_5: TestCase {
+        total_num_sats: 1000,
+        recipients_num_sats: vec![600, 401],
+        max_sats: Some(1000),
+        expected_ok: false,
+    },
+    case_6: TestCase {
+        total_num_sats: u64::MAX,
+        recipients_num_sats: vec![1],
+        max_sats: Some(u64::MAX),
+        expected_ok: true,
+    },
+}
+
+#[tokio::test]
+async fn request_timeout_yields_request_send() {
+    // Accepts connections but never responds.
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let base_url = format!("http://{}/", listener.local_addr().unwrap());
+    std::thread::spawn(move || {
+        let _connections: Vec<_> = listener.incoming().collect();
+    });
+
+    let result = v4v::alby::api::account::get_balance(v4v::alby::api::account::GetBalanceArgs {
+        user_agent: "test",
+        token: "token",
+        base_url: Some(&base_url),
+        timeouts: v4v::alby::api::Timeouts {
+            timeout: std::time::Duration::from_micros(500),
+            ..Default::default()
+        },
+        retry: None,
+    })
+    .await;
+
+    match result {
+        Err(v4v::alby::api::RequestError::RequestSend(e)) => assert!(e.is_timeout()),
+        other => panic!("expected a timeout, got {:?}", other.map(|r| r.balance)),
+    }
+}
+
+/// Starts an HTTP server that responds with the given statuses and bodies in turn (repeating the
+/// last one), returning its base URL and the request lines (e.g., `GET /balance HTTP/1.1`) of the
+/// requests received.
+fn spawn_mock_server(
+    responses: Vec<(u16, &'static str)>,
+) -> (String, std::sync::Arc<std::sync::Mutex<Vec<String>>>) {
+    use std::io::{Read, Write};
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let base_url = format!("http://{}", listener.local_addr().unwrap());
+    let requests = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+
+    let received = requests.clone();
+    std::thread::spawn(move || {
+        for mut stream in listener.incoming().flatten() {
+            let mut request = Vec::new();
+            let mut buf = [0; 1024];
+            while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+                match stream.read(&mut buf) {
+                    Ok(0) | Err(_) => break,
+                    Ok(n) => request.extend_from_slice(&buf[..n]),
+                }
+            }
+
+            let i = {
+                let mut received = received.lock().unwrap();
+                let request = String::from_utf8_lossy(&request);
+                received.push(request.lines().next().unwrap_or_default().to_string());
+                received.len() - 1
+            };
+            let (status, body) = responses[i.min(responses.len() - 1)];
+            let response = format!(
+                "HTTP/1.1 {} Status\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                status,
+                body.len(),
+                body
+            );
+            let _ = stream.write_all(response.as_bytes());
+        }
+    });
+
+    (base_url, requests)
+}
+
+const MOCK_INTERNAL_SERVER_ERROR: &str = r#"{"code": 500, "error": true, "message": "oops"}"#;
+const MOCK_BAD_REQUEST: &str = r#"{"code": 400, "error": true, "message": "invalid destination"}"#;
+
+#[tokio::test]
+async fn retry_stops_after_max_attempts() {
+    let (base_url, requests) = spawn_mock_server(vec![(500, MOCK_INTERNAL_SERVER_ERROR)]);
+
+    let result = v4v::alby::api::account::get_balance(v4v::alby::api::account::GetBalanceArgs {
+        user_agent: "test",
+        token: "token",
+        base_url: Some(&base_url),
+        timeouts: Default::default(),
+        retry: Some(v4v::alby::api::RetryPolicy {
+            max_attempts: 3,
+            base_delay: std::time::Duration::from_millis(1),
+            max_delay: std::time::Duration::from_millis(1),
+            ..Default::default()
+        }),
+    })
+    .await;
+
+    assert!(matches!(
+        result,
+        Err(v4v::alby::api::RequestError::InternalServerError(_))
+    ));
+    assert_eq!(requests.lock().unwrap().len(), 3);
+}
+
+#[tokio::test]
+async fn retry_succeeds_after_internal_server_error() {
+    let (base_url, requests) = spawn_mock_server(vec![
+        (500, MOCK_INTERNAL_SERVER_ERROR),
+        (200, r#"{"balance": 21, "currency": "BTC", "unit": "sat"}"#),
+    ]);
+
+    let result = v4v::alby::api::account::get_balance(v4v::alby::api::account::GetBalanceArgs {
+        user_agent: "test",
+        token: "token",
+        base_url: Some(&base_url),
+        timeouts: Default::default(),
+        retry: Some(v4v::alby::api::RetryPolicy {
+            max_attempts: 3,
+            base_delay: std::time::Duration::from_millis(1),
+            max_delay: std::time::Duration::from_millis(1),
+            ..Default::default()
+        }),
+    })
+    .await;
+
+    assert_eq!(result.unwrap().balance, 21);
+    assert_eq!(requests.lock().unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn no_retry_by_default() {
+    let (base_url, requests) = spawn_mock_server(vec![(500, MOCK_INTERNAL_SERVER_ERROR)]);
+
+    let result = v4v::pc20::payments::make_payment(v4v::pc20::payments::MakePaymentArgs {
+        user_agent: "test",
+        token: "token",
+        recipients: vec![v4v::pc20::payments::PaymentRecipientInfo {
+            num_sats: 1,
+            ..Default::default()
+        }],
+        base_url: Some(&base_url),
+        ..Default::default()
+    })
+    .await;
+
+    assert!(matches!(
+        result,
+        Err(v4v::alby::api::RequestError::InternalServerError(_))
+    ));
+    assert_eq!(requests.lock().unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn pay_invoice_success() {
+    let (base_url, requests) = spawn_mock_server(vec![(
+        200,
+        r#"{
+            "amount": 21,
+            "description": "Coffee",
+            "destination": "030a58b8653d32b99200a2334cfe913e51dc7d155aa0116c176657a4f1722677a3",
+            "fee": 1,
+            "payment_hash": "66687aadf862bd776c8fc18b8e9f8e20089714856ee233b3902a591d0d5f2925",
+            "payment_preimage": "0000000000000000000000000000000000000000000000000000000000000000",
+            "payment_request": "lnbc210n1..."
+        }"#,
+    )]);
+
+    let response =
+        v4v::alby::api::payments::pay_invoice(v4v::alby::api::payments::PayInvoiceArgs {
+            user_agent: "test",
+            token: "token",
+            base_url: Some(&base_url),
+            invoice: "lnbc210n1...",
+            num_sats: None,
+            rate_limiter: None,
+            timeouts: Default::default(),
+            retry: None,
+        })
+        .await
+        .unwrap();
+
+    assert_eq!(response.num_sats, 21);
+    assert_eq!(response.fee_in_sats, 1);
+    assert_eq!(
+        response.payment_hash,
+        "66687aadf862bd776c8fc18b8e9f8e20089714856ee233b3902a591d0d5f2925"
+    );
+    assert!(response
+        .preimage()
+        .unwrap()
+        .verifies(&response.payment_hash));
+    assert_eq!(requests.lock().unwrap().len(), 1);
+}
+
+/// Decoded invoice of the mock LNURL-pay service, committing to the metadata `[]`.
+const MOCK_DECODED_INVOICE_21: &str = r#"{
+    "amount": 21,
+    "description": "Boost",
+    "description_hash": "4f53cda18c2baa0c0354bb5f9a3ecbe5ed12ab4d8e11ba873c2f11161202b945",
+    "payment_hash": "66687aadf862bd776c8fc18b8e9f8e20089714856ee233b3902a591d0d5f2925",
+    "created_at": 1700000000,
+    "expiry": 3600
+}"#;
+
+const MOCK_PAID_INVOICE_21: &str = r#"{
+    "amount": 21,
+    "description": "Boost",
+    "destination": "030a58b8653d32b99200a2334cfe913e51dc7d155aa0116c176657a4f1722677a3",
+    "fee": 0,
+    "payment_hash": "66687aadf862bd776c8fc18b8e9f8e20089714856ee233b3902a591d0d5f2925",
+    "payment_preimage": "0000000000000000000000000000000000000000000000000000000000000000",
+    "payment_request": "lnbc210n1mock"
+}"#;
+
+/// Spawns a mock LNURL-pay service (pay request, then callback) and returns its LNURL.
+fn spawn_mock_lnurl_service(
+    pay_request: &str,
+    callback_response: &'static str,
+) -> (String, std::sync::Arc<std::sync::Mutex<Vec<String>>>) {
+    let (callback_url, callback_requests) = spawn_mock_server(vec![(200, callback_response)]);
+    let pay_request = pay_request
+        .replace("CALLBACK", &format!("{}/callback?k1=abc", callback_url))
+        .leak();
+    let (lnurl, _) = spawn_mock_server(vec![(200, pay_request)]);
+
+    (lnurl, callback_requests)
+}
+
+#[tokio::test]
+async fn pay_via_lnurl_forwards_comment() {
+    let (lnurl, callback_requests) = spawn_mock_lnurl_service(
+        r#"{"tag": "payRequest", "callback": "CALLBACK", "minSendable": 1000, "maxSendable": 100000000, "metadata": "[]", "commentAllowed": 10}"#,
+        r#"{"pr": "lnbc210n1mock", "routes": []}"#,
+    );
+    let (base_url, alby_requests) = spawn_mock_server(vec![
+        (200, MOCK_DECODED_INVOICE_21),
+        (200, MOCK_PAID_INVOICE_21),
+    ]);
+
+    let response =
+        v4v::pc20::lightning_address::pay_via_lnurl(v4v::pc20::lightning_address::LnurlPayArgs {
+            user_agent: "test",
+            token: "token",
+            base_url: Some(&base_url),
+            lnurl: &lnurl,
+            allow_insecure_http: true,
+            num_sats: 21,
+            comment: Some("Great episode!"),
+            rate_limiter: None,
+            timeouts: Default::default(),
+            retry: None,
+        })
+        .await
+        .unwrap();
+
+    assert_eq!(response.num_sats, 21);
+    assert_eq!(
+        callback_requests.lock().unwrap().clone(),
+        vec!["GET /callback?k1=abc&amount=21000&comment=Great+epis HTTP/1.1".to_string()]
+    );
+    assert_eq!(
+        alby_requests.lock().unwrap().clone(),
+        vec![
+            "GET /decode/bolt11/lnbc210n1mock HTTP/1.1".to_string(),
+            "POST /payments/bolt11 HTTP/1.1".to_string(),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn pay_via_lnurl_drops_comment_if_not_allowed() {
+    let (lnurl, callback_requests) = spawn_mock_lnurl_service(
+        r#"{"tag": "payRequest", "callback": "CALLBACK", "minSendable": 1000, "maxSendable": 100000000, "metadata": "[]"}"#,
+        r#"{"pr": "lnbc210n1mock", "routes": []}"#,
+    );
+    let (base_url, _) = spawn_mock_server(vec![
+        (200, MOCK_DECODED_INVOICE_21),
+        (200, MOCK_PAID_INVOICE_21),
+    ]);
+
+    v4v::pc20::lightning_address::pay_via_lnurl(v4v::pc20::lightning_address::LnurlPayArgs {
+        user_agent: "test",
+        token: "token",
+        base_url: Some(&base_url),
+        lnurl: &lnurl,
+        allow_insecure_http: true,
+        num_sats: 21,
+        comment: Some("Great episode!"),
+        rate_limiter: None,
+        timeouts: Default::default(),
+        retry: None,
+    })
+    .await
+    .unwrap();
+
+    assert_eq!(
+        callback_requests.lock().unwrap().clone(),
+        vec!["GET /callback?k1=abc&amount=21000 HTTP/1.1".to_string()]
+    );
+}
+
+macro_rules! pay_via_lnurl_error_tests {
+    ($($name:ident: $value:expr,)*) => {
+        paste::item! {
+            $(
+                #[tokio::test]
+                async fn [<pay_via_lnurl_ $name>]() {
+                    struct TestCase {
+                        pay_request: &'static str,
+                        callback_response: &'static str,
+                        decoded_invoice: &'static str,
+                        expected: fn(&v4v::alby::api::RequestError) -> bool,
+                    }
+                    let (lnurl, _) = spawn_mock_lnurl_service(
+                        $value.pay_request,
+                        $value.callback_response,
+                    );
+                    let (base_url, alby_requests) = spawn_mock_server(vec![
+                        (200, $value.decoded_invoice),
+                        (200, MOCK_PAID_INVOICE_21),
+                    ]);
+
+                    let result = v4v::pc20::lightning_address::pay_via_lnurl(
+                        v4v::pc20::lightning_address::LnurlPayArgs {
+                            user_agent: "test",
+                            token: "token",
+                            base_url: Some(&base_url),
+                            lnurl: &lnurl,
+                            allow_insecure_http: true,
+                            num_sats: 21,
+                            comment: None,
+                            rate_limiter: None,
+                            timeouts: Default::default(),
+                            retry: None,
+                        },
+                    )
+                    .await;
+
+                    let error = result.unwrap_err();
+                    assert!(($value.expected)(&error), "{:?}", error);
+                    assert!(!alby_requests
+                        .lock()
+                        .unwrap()
+                        .iter()
+                        .any(|request| request.starts_with("POST /payments/bolt11")));
+                }
+            )*
+        }
+    }
+}
+
+pay_via_lnurl_error_tests! {
+    amount_out_of_range: TestCase {
+        pay_request: r#"{"tag": "payRequest", "callback": "CALLBACK", "minSendable": 100000, "maxSendable": 100000000, "metadata": "[]"}"#,
+        callback_response: r#"{"pr": "lnbc210n1mock", "routes": []}"#,
+        decoded_invoice: MOCK_DECODED_INVOICE_21,
+        expected: |e| matches!(e, v4v::alby::api::RequestError::InvalidArgs(_)),
+    },
+    callback_error: TestCase {
+        pay_request: r#"{"tag": "payRequest", "callback": "CALLBACK", "minSendable": 1000, "maxSendable": 100000000, "metadata": "[]"}"#,
+        callback_response: r#"{"status": "ERROR", "reason": "no route"}"#,
+        decoded_invoice: MOCK_DECODED_INVOICE_21,
+        expected: |e| matches!(e, v4v::alby::api::RequestError::Lnurl(reason) if reason == "no route"),
+    },
+    not_pay_request: TestCase {
+        pay_request: r#"{"tag": "withdrawRequest", "callback": "CALLBACK"}"#,
+        callback_response: r#"{"pr": "lnbc210n1mock", "routes": []}"#,
+        decoded_invoice: MOCK_DECODED_INVOICE_21,
+        expected: |e| matches!(e, v4v::alby::api::RequestError::Lnurl(_)),
+    },
+    invoice_amount_mismatch: TestCase {
+        pay_request: r#"{"tag": "payRequest", "callback": "CALLBACK", "minSendable": 1000, "maxSendable": 100000000, "metadata": "[]"}"#,
+        callback_response: r#"{"pr": "lnbc210n1mock", "routes": []}"#,
+        decoded_invoice: r#"{"amount": 2100, "payment_hash": "66687aadf862bd776c8fc18b8e9f8e20089714856ee233b3902a591d0d5f2925", "created_at": 1700000000, "expiry": 3600}"#,
+        expected: |e| matches!(e, v4v::alby::api::RequestError::Lnurl(_)),
+    },
+    description_hash_mismatch: TestCase {
+        pay_request: r#"{"tag": "payRequest", "callback": "CALLBACK", "minSendable": 1000, "maxSendable": 100000000, "metadata": "[[\"text/plain\",\"Alice\"]]"}"#,
+        callback_response: r#"{"pr": "lnbc210n1mock", "routes": []}"#,
+        decoded_invoice: MOCK_DECODED_INVOICE_21,
+        expected: |e| matches!(e, v4v::alby::api::RequestError::Lnurl(_)),
+    },
+    missing_description_hash: TestCase {
+        pay_request: r#"{"tag": "payRequest", "callback": "CALLBACK", "minSendable": 1000, "maxSendable": 100000000, "metadata": "[]"}"#,
+        callback_response: r#"{"pr": "lnbc210n1mock", "routes": []}"#,
+        decoded_invoice: r#"{"amount": 21, "payment_hash": "66687aadf862bd776c8fc18b8e9f8e20089714856ee233b3902a591d0d5f2925", "created_at": 1700000000, "expiry": 3600}"#,
+        expected: |e| matches!(e, v4v::alby::api::RequestError::Lnurl(_)),
+    },
+    missing_metadata: TestCase {
+        pay_request: r#"{"tag": "payRequest", "callback": "CALLBACK", "minSendable": 1000, "maxSendable": 100000000}"#,
+        callback_response: r#"{"pr": "lnbc210n1mock", "routes": []}"#,
+        decoded_invoice: MOCK_DECODED_INVOICE_21,
+        expected: |e| matches!(e, v4v::alby::api::RequestError::Lnurl(reason) if reason == "no metadata"),
+    },
+}
+
+macro_rules! decode_invoice_tests {
+    ($($name:ident: $value:expr,)*) => {
+        paste::item! {
+            $(
+                #[tokio::test]
+                async fn [<decode_invoice_ $name>]() {
+                    struct TestCase {
+                        response: &'static str,
+                        expected: v4v::alby::api::invoices::DecodedInvoice,
+                    }
+                    let (base_url, _) = spawn_mock_server(vec![(200, $value.response)]);
+
+                    let decoded = v4v::alby::api::invoices::decode_invoice(
+                        v4v::alby::api::invoices::DecodeInvoiceArgs {
+                            user_agent: "test",
+                            token: "token",
+                            base_url: Some(&base_url),
+                            invoice: "lnbc1mock",
+                            timeouts: Default::default(),
+                            retry: None,
+                        },
+                    )
+                    .await
+                    .unwrap();
+
+                    assert_eq!(decoded, $value.expected);
+                }
+            )*
+        }
+    }
+}
+
+decode_invoice_tests! {
+    with_amount: TestCase {
+        response: r#"{
+            "amount": 21,
+            "description": "Coffee",
+            "payment_hash": "66687aadf862bd776c8fc18b8e9f8e20089714856ee233b3902a591d0d5f2925",
+            "created_at": 1700000000,
+            "expiry": 3600
+        }"#,
+        expected: v4v::alby::api::invoices::DecodedInvoice {
+            num_sats: Some(21),
+            description: Some("Coffee".to_string()),
+            description_hash: None,
+            payment_hash: "66687aadf862bd776c8fc18b8e9f8e20089714856ee233b3902a591d0d5f2925".to_string(),
+            expires_at: chrono::DateTime::from_timestamp(1700003600, 0).unwrap(),
+        },
+    },
+    zero_amount: TestCase {
+        response: r#"{
+            "amount": 0,
+            "description": "",
+            "payment_hash": "66687aadf862bd776c8fc18b8e9f8e20089714856ee233b3902a591d0d5f2925",
+            "created_at": 1700000000,
+            "expiry": 86400
+        }"#,
+        expected: v4v::alby::api::invoices::DecodedInvoice {
+            num_sats: None,
+            description: None,
+            description_hash: None,
+            payment_hash: "66687aadf862bd776c8fc18b8e9f8e20089714856ee233b3902a591d0d5f2925".to_string(),
+            expires_at: chrono::DateTime::from_timestamp(1700086400, 0).unwrap(),
+        },
+    },
+}
+
+#[tokio::test]
+async fn decode_invoice_rejects_non_bech32() {
+    let (base_url, requests) = spawn_mock_server(vec![(200, MOCK_DECODED_INVOICE_21)]);
+
+    let result =
+        v4v::alby::api::invoices::decode_invoice(v4v::alby::api::invoices::DecodeInvoiceArgs {
+            user_agent: "test",
+            token: "token",
+            base_url: Some(&base_url),
+            invoice: "../../invoices?x=",
+            timeouts: Default::default(),
+            retry: None,
+        })
+        .await;
+
+    assert!(matches!(
+        result,
+        Err(v4v::alby::api::RequestError::InvalidArgs(_))
+    ));
+    assert!(requests.lock().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn list_invoices_pages() {
+    let page_1 = serde_json::json!([
+        alby_invoice_body(serde_json::json!({"action": "boost", "message": "Hi"})),
+        alby_invoice_body(serde_json::json!({"action": "stream"})),
+    ])
+    .to_string();
+    let page_2 =
+        serde_json::json!([alby_invoice_body(serde_json::json!({"action": "boost"}))]).to_string();
+    let (base_url, requests) = spawn_mock_server(vec![
+        (200, page_1.leak()),
+        (200, page_2.leak()),
+        (200, "[]"),
+    ]);
+
+    let list_invoices = |page| {
+        v4v::alby::api::invoices::list_invoices(v4v::alby::api::invoices::ListInvoicesArgs {
+            user_agent: "test",
+            token: "token",
+            base_url: Some(&base_url),
+            page: Some(page),
+            items: Some(2),
+            created_after: chrono::DateTime::from_timestamp(1722470400, 0),
+            created_before: None,
+            timeouts: Default::default(),
+            retry: None,
+        })
+    };
+
+    let invoices = list_invoices(1).await.unwrap();
+    assert_eq!(invoices.len(), 2);
+    assert_eq!(
+        invoices[0].boostagram.as_ref().unwrap().message.as_deref(),
+        Some("Hi")
+    );
+
+    let invoices = list_invoices(2).await.unwrap();
+    assert_eq!(invoices.len(), 1);
+
+    let invoices = list_invoices(3).await.unwrap();
+    assert!(invoices.is_empty());
+
+    assert_eq!(
+        requests.lock().unwrap()[1],
+        "GET /invoices?page=2&items=2&q%5Bcreated_at_gt%5D=1722470400 HTTP/1.1"
+    );
+}
+
+#[tokio::test]
+async fn get_invoice_found_and_not_found() {
+    let mut invoice = alby_invoice_body(serde_json::json!({"action": "boost"}));
+    invoice["metadata"] = serde_json::to_value(
+        v4v::pc20::forwarding::CreateInvoiceMetadataForwardingStruct {
+            payment_info: v4v::pc20::payments::PaymentInfo {
+                total_num_sats: 100,
+                ..Default::default()
+            },
+            recipients: vec![],
+        },
+    )
+    .unwrap();
+    let (base_url, requests) = spawn_mock_server(vec![
+        (200, invoice.to_string().leak()),
+        (
+            404,
+            r#"{"code": 404, "error": true, "message": "Not found"}"#,
+        ),
+    ]);
+
+    let get_invoice = || {
+        v4v::alby::api::invoices::get_invoice(v4v::alby::api::invoices::GetInvoiceArgs {
+            user_agent: "test",
+            token: "token",
+            base_url: Some(&base_url),
+            payment_hash: "66687aadf862bd776c8fc18b8e9f8e20089714856ee233b3902a591d0d5f2925",
+            timeouts: Default::default(),
+            retry: None,
+        })
+    };
+
+    let invoice = get_invoice().await.unwrap();
+    assert_eq!(invoice.num_sats, 100);
+    let metadata = v4v::pc20::forwarding::CreateInvoiceMetadata::try_from(invoice).unwrap();
+    assert_eq!(metadata.forwarding_data.payment_info.total_num_sats, 100);
+
+    assert!(matches!(
+        get_invoice().await,
+        Err(v4v::alby::api::RequestError::NotFound { .. })
+    ));
+
+    assert_eq!(
+        requests.lock().unwrap()[0],
+        "GET /invoices/66687aadf862bd776c8fc18b8e9f8e20089714856ee233b3902a591d0d5f2925 HTTP/1.1"
+    );
+}
+
+const MOCK_MIXED_MULTI_KEYSEND: &str = r#"{
+    "keysends": [
+        {
+            "keysend": {
+                "amount": 7,
+                "description": "",
+                "description_hash": "",
+                "destination": "030a58b8653d32b99200a2334cfe913e51dc7d155aa0116c176657a4f1722677a3",
+                "fee": 0,
+                "custom_records": {},
+                "payment_hash": "66687aadf862bd776c8fc18b8e9f8e20089714856ee233b3902a591d0d5f2925",
+                "payment_preimage": "0000000000000000000000000000000000000000000000000000000000000000"
+            },
+            "error": {}
+        },
+        {
+            "keysend": {},
+            "error": {"code": 1, "error": true, "message": "no route"}
+        },
+        {
+            "error": {"code": 1, "error": true, "message": "no route"}
+        }
+    ]
+}"#;
+
+#[test]
+fn multi_keysend_response_mixed() {
+    let response: v4v::alby::api::payments::MultiKeysendResponse =
+        serde_json::from_str(MOCK_MIXED_MULTI_KEYSEND).unwrap();
+
+    let results: Vec<_> = response
+        .keysends
+        .into_iter()
+        .map(|item| item.into_result())
+        .collect();
+    assert_eq!(results[0].as_ref().unwrap().num_sats, 7);
+    assert_eq!(results[1].as_ref().unwrap_err().message, "no route");
+    assert_eq!(results[2].as_ref().unwrap_err().message, "no route");
+}
+
+#[tokio::test]
+async fn make_payment_mixed_outcomes() {
+    let (base_url, _) = spawn_mock_server(vec![(200, MOCK_MIXED_MULTI_KEYSEND)]);
+
+    let outcomes = v4v::pc20::payments::make_payment(v4v::pc20::payments::MakePaymentArgs {
+        user_agent: "test",
+        token: "token",
+        recipients: vec![
+            v4v::pc20::payments::PaymentRecipientInfo {
+                num_sats: 7,
+                ..Default::default()
+            },
+            v4v::pc20::payments::PaymentRecipientInfo {
+                num_sats: 2,
+                ..Default::default()
+            },
+            v4v::pc20::payments::PaymentRecipientInfo {
+                num_sats: 1,
+                ..Default::default()
+            },
+        ],
+        base_url: Some(&base_url),
+        ..Default::default()
+    })
+    .await
+    .unwrap();
+
+    let failed: Vec<_> = outcomes
+        .iter()
+        .filter(|outcome| !outcome.is_success())
+        .map(|outcome| outcome.recipient_index)
+        .collect();
+    assert_eq!(failed, vec![1, 2]);
+}
+
+#[tokio::test]
+async fn make_payment_rejects_mismatched_response() {
+    let (base_url, _) = spawn_mock_server(vec![(200, MOCK_MIXED_MULTI_KEYSEND)]);
+
+    let result = v4v::pc20::payments::make_payment(v4v::pc20::payments::MakePaymentArgs {
+        user_agent: "test",
+        token: "token",
+        recipients: vec![v4v::pc20::payments::PaymentRecipientInfo {
+            num_sats: 7,
+            ..Default::default()
+        }],
+        base_url: Some(&base_url),
+        ..Default::default()
+    })
+    .await;
+
+    assert!(matches!(
+        result,
+        Err(v4v::alby::api::RequestError::Unexpected(_))
+    ));
+}
+
+#[tokio::test]
+async fn batching_sender_drops_sats_on_unparsable_response() {
+    let (base_url, requests) = spawn_mock_server(vec![(200, "not json")]);
+
+    let mut sender = v4v::pc20::batching::BatchingSender::new(
+        "test",
+        "token",
+        v4v::pc20::batching::BatchingConfig {
+            base_url: Some(base_url),
+            ..Default::default()
+        },
+    );
+    sender.push(
+        Default::default(),
+        &[v4v::pc20::payments::PaymentRecipientInfo {
+            num_sats: 7,
+            ..Default::default()
+        }],
+    );
+
+    let result = sender.flush().await;
+    assert!(matches!(
+        result,
+        Err(v4v::alby::api::RequestError::ResponseParse(..))
+    ));
+    assert_eq!(requests.lock().unwrap().len(), 1);
+
+    // Alby may have sent the keysend, so it must not be sent again.
+    assert!(sender.pending().is_empty());
+    let unconfirmed = sender.take_unconfirmed();
+    assert_eq!(
+        unconfirmed.iter().map(|r| r.num_sats).collect::<Vec<_>>(),
+        vec![7]
+    );
+    assert!(sender.take_unconfirmed().is_empty());
+}
+
+#[tokio::test]
+async fn batching_sender_requeues_sats_on_rejection() {
+    let (base_url, _) = spawn_mock_server(vec![(400, MOCK_BAD_REQUEST)]);
+
+    let mut sender = v4v::pc20::batching::BatchingSender::new(
+        "test",
+        "token",
+        v4v::pc20::batching::BatchingConfig {
+            base_url: Some(base_url),
+            ..Default::default()
+        },
+    );
+    sender.push(
+        Default::default(),
+        &[v4v::pc20::payments::PaymentRecipientInfo {
+            num_sats: 7,
+            ..Default::default()
+        }],
+    );
+
+    let result = sender.flush().await;
+    assert!(matches!(
+        result,
+        Err(v4v::alby::api::RequestError::BadRequest(_))
+    ));
+    assert_eq!(sender.pending_sats(), 7);
+    assert!(sender.take_unconfirmed().is_empty());
+}
+
+/// Provider that records the keysends and invoices instead of sending them.
+#[derive(Default)]
+struct RecordingProvider {
+    keysends: std::sync::Mutex<Vec<(u64, String)>>,
+    invoices: std::sync::Mutex<Vec<v4v::pc20::provider::InvoiceRequest>>,
+    paid_invoices: std::sync::Mutex<Vec<(String, u64, String)>>,
+}
+
+impl v4v::pc20::provider::PaymentProvider for RecordingProvider {
+    async fn multi_keysend(
+        &self,
+        keysends: Vec<v4v::alby::api::payments::MultiKeysendItemArgs<'_>>,
+    ) -> Result<v4v::alby::api::payments::MultiKeysendResponse, v4v::alby::api::RequestError> {
+        let mut recorded = self.keysends.lock().unwrap();
+        for keysend in &keysends {
+            recorded.push((keysend.num_sats, keysend.dest_pubkey.to_string()));
+        }
+
+        let items = keysends
+            .iter()
+            .map(|keysend| {
+                serde_json::json!({
+                    "keysend": {
+                        "amount": keysend.num_sats,
+                        "description": "",
+                        "description_hash": "",
+                        "destination": keysend.dest_pubkey,
+                        "fee": 0,
+                        "custom_records": {},
+                        "payment_hash": "66687aadf862bd776c8fc18b8e9f8e20089714856ee233b3902a591d0d5f2925",
+                        "payment_preimage": "0000000000000000000000000000000000000000000000000000000000000000"
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        Ok(serde_json::from_value(serde_json::json!({ "keysends": items })).unwrap())
+    }
+
+    async fn create_invoice(
+        &self,
+        invoice: v4v::pc20::provider::InvoiceRequest,
+    ) -> Result<v4v::alby::api::invoices::CreateInvoiceResponse, v4v::alby::api::RequestError> {
+        self.invoices.lock().unwrap().push(invoice);
+
+        Ok(v4v::alby::api::invoices::CreateInvoiceResponse {
+            expires_at: chrono::DateTime::from_timestamp(1700003600, 0).unwrap(),
+            payment_hash: "66687aadf862bd776c8fc18b8e9f8e20089714856ee233b3902a591d0d5f2925"
+                .to_string(),
+            payment_request: "lnbc210n1mock".to_string(),
+        })
+    }
+
+    async fn pay_invoice(
+        &self,
+        invoice: &str,
+        num_sats: u64,
+        description_hash: &str,
+    ) -> Result<v4v::alby::api::payments::PayInvoiceResponse, v4v::alby::api::RequestError> {
+        self.paid_invoices.lock().unwrap().push((
+            invoice.to_string(),
+            num_sats,
+            description_hash.to_string(),
+        ));
+
+        Ok(serde_json::from_str(MOCK_PAID_INVOICE_21).unwrap())
+    }
+}
+
+#[tokio::test]
+async fn make_payment_with_custom_provider() {
+    let provider = RecordingProvider::default();
+    let pubkey = "030a58b8653d32b99200a2334cfe913e51dc7d155aa0116c176657a4f1722677a3";
+
+    let outcomes = v4v::pc20::payments::make_payment_with(
+        &provider,
+        v4v::pc20::payments::MakePaymentArgs {
+            recipients: vec![
+                v4v::pc20::payments::PaymentRecipientInfo {
+                    address: v4v::alby::types::KeysendAddress::new(pubkey)
+                        .unwrap()
+                        .into(),
+                    num_sats: 7,
+                    ..Default::default()
+                },
+                v4v::pc20::payments::PaymentRecipientInfo {
+                    address: v4v::alby::types::KeysendAddress::new(pubkey)
+                        .unwrap()
+                        .into(),
+                    num_sats: 3,
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+
+    assert!(outcomes.iter().all(|outcome| outcome.is_success()));
+    assert_eq!(
+        provider.keysends.lock().unwrap().clone(),
+        vec![(7, pubkey.to_string()), (3, pubkey.to_string())]
+    );
+}
+
+#[tokio::test]
+async fn make_payment_with_lightning_addresses() {
+    let provider = RecordingProvider::default();
+    let pubkey = "030a58b8653d32b99200a2334cfe913e51dc7d155aa0116c176657a4f1722677a3";
+    let (callback_url, callback_requests) =
+        spawn_mock_server(vec![(200, r#"{"pr": "lnbc210n1mock", "routes": []}"#)]);
+    let pay_request = format!(
+        r#"{{"tag": "payRequest", "callback": "{}/callback", "minSendable": 1000, "maxSendable": 100000000, "metadata": "[]", "commentAllowed": 20}}"#,
+        callback_url
+    )
+    .leak();
+    let (base_url, requests) = spawn_mock_server(vec![
+        (404, r#"{"status": "ERROR", "reason": "not found"}"#),
+        (
+            200,
+            r#"{"status": "OK", "tag": "keysend", "pubkey": "030a58b8653d32b99200a2334cfe913e51dc7d155aa0116c176657a4f1722677a3"}"#,
+        ),
+        (200, pay_request),
+    ]);
+
+    let recipient = |address: v4v::pc20::payments::RecipientAddress, num_sats| {
+        v4v::pc20::payments::PaymentRecipientInfo {
+            address,
+            num_sats,
+            ..Default::default()
+        }
+    };
+    let outcomes = v4v::pc20::payments::make_payment_with(
+        &provider,
+        v4v::pc20::payments::MakePaymentArgs {
+            payment_info: v4v::pc20::payments::PaymentInfo {
+                message: Some("Great episode!".to_string()),
+                ..Default::default()
+            },
+            recipients: vec![
+                recipient(
+                    v4v::pc20::payments::RecipientAddress::LightningAddress(
+                        "bob@example.com".to_string(),
+                    ),
+                    21,
+                ),
+                recipient(
+                    v4v::alby::types::KeysendAddress::new(pubkey)
+                        .unwrap()
+                        .into(),
+                    7,
+                ),
+                recipient(
+                    v4v::pc20::payments::RecipientAddress::LightningAddress(
+                        "alice@example.com".to_string(),
+                    ),
+                    3,
+                ),
+            ],
+            lightning_address_base_url: Some(&base_url),
+            resolve_lightning_addresses: true,
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(
+        outcomes
+            .iter()
+            .map(|outcome| (outcome.recipient_index, outcome.is_success()))
+            .collect::<Vec<_>>(),
+        vec![(0, true), (1, true), (2, true)]
+    );
+    assert_eq!(outcomes[0].result.as_ref().unwrap().num_sats, 21);
+    assert_eq!(
+        requests.lock().unwrap().clone(),
+        vec![
+            "GET /.well-known/keysend/bob HTTP/1.1".to_string(),
+            "GET /.well-known/keysend/alice HTTP/1.1".to_string(),
+            "GET /.well-known/lnurlp/bob HTTP/1.1".to_string(),
+        ]
+    );
+    assert_eq!(
+        provider.keysends.lock().unwrap().clone(),
+        vec![(7, pubkey.to_string()), (3, pubkey.to_string())]
+    );
+    assert_eq!(
+        callback_requests.lock().unwrap().clone(),
+        vec!["GET /callback?amount=21000&comment=Great+episode%21 HTTP/1.1".to_string()]
+    );
+    assert_eq!(
+        provider.paid_invoices.lock().unwrap().clone(),
+        vec![(
+            "lnbc210n1mock".to_string(),
+            21,
+            "4f53cda18c2baa0c0354bb5f9a3ecbe5ed12ab4d8e11ba873c2f11161202b945".to_string()
+        )]
+    );
+}
+
+#[tokio::test]
+async fn make_payment_with_lightning_addresses_not_opted_in() {
+    let provider = RecordingProvider::default();
+    let (base_url, requests) = spawn_mock_server(vec![(404, "{}")]);
+
+    let result = v4v::pc20::payments::make_payment_with(
+        &provider,
+        v4v::pc20::payments::MakePaymentArgs {
+            recipients: vec![v4v::pc20::payments::PaymentRecipientInfo {
+                address: v4v::pc20::payments::RecipientAddress::LightningAddress(
+                    "alice@127.0.0.1:8080".to_string(),
+                ),
+                num_sats: 21,
+                ..Default::default()
+            }],
+            lightning_address_base_url: Some(&base_url),
+            ..Default::default()
+        },
+    )
+    .await;
+
+    assert!(matches!(
+        result,
+        Err(v4v::alby::api::RequestError::InvalidArgs(_))
+    ));
+    assert!(requests.lock().unwrap().is_empty());
+    assert!(provider.keysends.lock().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn make_payment_with_lightning_addresses_over_max_sats() {
+    let provider = RecordingProvider::default();
+    let (base_url, requests) = spawn_mock_server(vec![(404, "{}")]);
+
+    let result = v4v::pc20::payments::make_payment_with(
+        &provider,
+        v4v::pc20::payments::MakePaymentArgs {
+            recipients: vec![v4v::pc20::payments::PaymentRecipientInfo {
+                address: v4v::pc20::payments::RecipientAddress::LightningAddress(
+                    "alice@127.0.0.1:8080".to_string(),
+                ),
+                num_sats: u64::MAX,
+                ..Default::default()
+            }],
+            lightning_address_base_url: Some(&base_url),
+            resolve_lightning_addresses: true,
+            ..Default::default()
+        },
+    )
+    .await;
+
+    assert!(matches!(
+        result,
+        Err(v4v::alby::api::RequestError::InvalidArgs(_))
+    ));
+    assert!(requests.lock().unwrap().is_empty());
+    assert!(provider.keysends.lock().unwrap().is_empty());
+}
+
+#[test]
+fn build_keysends_unresolved_lightning_address() {
+    let args = v4v::pc20::payments::MakePaymentArgs {
+        recipients: vec![v4v::pc20::payments::PaymentRecipientInfo {
+            address: v4v::pc20::payments::RecipientAddress::LightningAddress(
+                "alice@example.com".to_string(),
+            ),
+            num_sats: 21,
+            ..Default::default()
+        }],
+        ..Default::default()
+    };
+
+    assert!(matches!(
+        v4v::pc20::payments::build_keysends(&args),
+        Err(v4v::alby::api::RequestError::InvalidArgs(_))
+    ));
+}
+
+#[tokio::test]
+async fn create_invoice_with_custom_provider() {
+    let provider = RecordingProvider::default();
+
+    let response = v4v::pc20::forwarding::create_invoice_with(
+        &provider,
+        v4v::pc20::forwarding::CreateInvoiceArgs {
+            payment_info: v4v::pc20::payments::PaymentInfo {
+                message: Some("Great episode!".to_string()),
+                sender_name: Some("Alice".to_string()),
+                ..Default::default()
+            },
+            recipients: vec![v4v::pc20::payments::PaymentRecipientInfo {
+                num_sats: 21,
+                ..Default::default()
+            }],
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(response.payment_request, "lnbc210n1mock");
+    let invoices = provider.invoices.lock().unwrap();
+    assert_eq!(invoices.len(), 1);
+    assert_eq!(invoices[0].num_sats, 21);
+    assert_eq!(invoices[0].description.as_deref(), Some("Great episode!"));
+    assert_eq!(invoices[0].payer_name.as_deref(), Some("Alice"));
+}
+
+#[test]
+fn build_keysends_boost_with_message() {
+    let args = v4v::pc20::payments::MakePaymentArgs {
+        payment_info: v4v::pc20::payments::PaymentInfo {
+            action: v4v::pc20::payments::Action::Boost,
+            feed_guid: Some(uuid::Uuid::parse_str("917393e3-1b1e-5cef-ace4-edaa54e1f810").unwrap()),
+            item_guid: Some("ep-1".to_string()),
+            message: Some("Great episode!".to_string()),
+            sender_name: Some("Alice".to_string()),
+            total_num_sats: 100,
+            ..Default::default()
+        },
+        recipients: vec![v4v::pc20::payments::PaymentRecipientInfo {
+            address: v4v::alby::types::KeysendAddress::new(
+                "030a58b8653d32b99200a2334cfe913e51dc7d155aa0116c176657a4f1722677a3",
+            )
+            .unwrap()
+            .into(),
+            num_sats: 100,
+            name: Some("Host".to_string()),
+            ..Default::default()
+        }],
+        ..Default::default()
+    };
+
+    let keysends = v4v::pc20::payments::build_keysends(&args).unwrap();
+    assert_eq!(keysends.len(), 1);
+    assert_eq!(keysends[0].num_sats, 100);
+    assert_eq!(
+        keysends[0].dest_pubkey,
+        "030a58b8653d32b99200a2334cfe913e51dc7d155aa0116c176657a4f1722677a3"
+    );
+    assert_eq!(
+        keysends[0].custom_records[v4v::pc20::tlv::TLV_RECORD_KEY],
+        r#"{"action":"boost","guid":"917393e3-1b1e-5cef-ace4-edaa54e1f810","episode_guid":"ep-1","sender_name":"Alice","name":"Host","value_msat_total":100000,"message":"Great episode!"}"#
+    );
+}
+
+macro_rules! max_message_bytes_tests {
+    ($($name:ident: $value:expr,)*) => {
+        paste::item! {
+            $(
+                #[test]
+                fn [<max_message_bytes_ $name>]() {
+                    struct TestCase {
+                        message: String,
+                        max_message_bytes: Option<usize>,
+                        expected: String,
+                    }
+                    let args = v4v::pc20::payments::MakePaymentArgs {
+                        payment_info: v4v::pc20::payments::PaymentInfo {
+                            message: Some($value.message),
+                            ..Default::default()
+                        },
+                        recipients: vec![v4v::pc20::payments::PaymentRecipientInfo {
+                            num_sats: 1,
+                            ..Default::default()
+                        }],
+                        max_message_bytes: $value.max_message_bytes,
+                        ..Default::default()
+                    };
+
+                    let keysends = v4v::pc20::payments::build_keysends(&args).unwrap();
+                    let record: serde_json::Value = serde_json::from_str(
+                        &keysends[0].custom_records[v4v::pc20::tlv::TLV_RECORD_KEY],
+                    )
+                    .unwrap();
+                    assert_eq!(record["message"], $value.expected);
+                }
+            )*
+        }
+    }
+}
+
+max_message_bytes_tests! {
+    default_fits: TestCase {
+        message: "a".repeat(500),
+        max_message_bytes: None,
+        expected: "a".repeat(500),
+    },
+    // 497 + 4 bytes: the emoji doesn't fit alongside the 3-byte ellipsis.
+    default_emoji_at_boundary: TestCase {
+        message: format!("{}🎉", "a".repeat(497)),
+        max_message_bytes: None,
+        expected: format!("{}…", "a".repeat(497)),
+    },
+    // The emoji straddles the cut at byte 497, so it is dropped entirely.
+    default_emoji_straddles_boundary: TestCase {
+        message: format!("{}🎉🎉", "a".repeat(495)),
+        max_message_bytes: None,
+        expected: format!("{}…", "a".repeat(495)),
+    },
+    only_emoji: TestCase {
+        message: "🎉".repeat(10),
+        max_message_bytes: Some(11),
+        expected: "🎉🎉…".to_string(),
+    },
+    unlimited: TestCase {
+        message: "🎉".repeat(1000),
+        max_message_bytes: Some(usize::MAX),
+        expected: "🎉".repeat(1000),
+    },
+}
+
+/// Valid Nostr hex pubkey (64 characters).
+const NOSTR_HEX_PUBKEY: &str = "3bf0c63fcb93463407af97a5e5ee64fa883d107ef9e558472c4eb9aaaefa459d";
+
+macro_rules! sender_id_tests {
+    ($($name:ident: $value:expr,)*) => {
+        paste::item! {
+            $(
+                #[test]
+                fn [<sender_id_ $name>]() {
+                    struct TestCase {
+                        sender_id: String,
+                        expected_valid: bool,
+                    }
+
+                    // Received records drop non-conforming IDs.
+                    let boostagram = serde_json::json!({"action": "boost", "sender_id": $value.sender_id});
+                    let record = v4v::alby::webhooks::extract_alby_invoice(&alby_invoice_body(boostagram))
+                        .unwrap()
+                        .boostagram
+                        .unwrap();
+                    assert_eq!(
+                        record.sender_id,
+                        $value.expected_valid.then(|| $value.sender_id.clone())
+                    );
+
+                    // Sent records reject them.
+                    let args = v4v::pc20::payments::MakePaymentArgs {
+                        payment_info: v4v::pc20::payments::PaymentInfo {
+                            sender_id: Some($value.sender_id.clone()),
+                            ..Default::default()
+                        },
+                        recipients: vec![v4v::pc20::payments::PaymentRecipientInfo {
+                            num_sats: 1,
+                            ..Default::default()
+                        }],
+                        ..Default::default()
+                    };
+                    let result = v4v::pc20::payments::build_keysends(&args);
+                    if $value.expected_valid {
+                        assert!(result.is_ok());
+                    } else {
+                        assert!(matches!(
+                            result,
+                            Err(v4v::alby::api::RequestError::InvalidArgs(_))
+                        ));
+                    }
+                }
+            )*
+        }
+    }
+}
+
+sender_id_tests! {
+    nostr_hex_pubkey: TestCase {
+        sender_id: NOSTR_HEX_PUBKEY.to_string(),
+        expected_valid: true,
+    },
+    short: TestCase {
+        sender_id: "user-1".to_string(),
+        expected_valid: true,
+    },
+    too_long: TestCase {
+        sender_id: format!("{}0", NOSTR_HEX_PUBKEY),
+        expected_valid: false,
+    },
+}
+
+#[test]
+fn record_serialize_deserialize_round_trip() {
+    let uuid = |s| uuid::Uuid::parse_str(s).unwrap();
+    let record = v4v::pc20::tlv::Record::builder()
+        .action(v4v::pc20::payments::Action::Stream)
+        .feed_guid(uuid("917393e3-1b1e-5cef-ace4-edaa54e1f810"))
+        .feed_name("Podcasting 2.0")
+        .feed_pi_id(920666)
+        .feed_url(url::Url::parse("https://mp3s.nashownotes.com/pc20rss.xml").unwrap())
+        .item_guid("ep-1")
+        .item_name("Episode 1")
+        .item_pi_id(42)
+        .timestamp_seconds(chrono::Duration::seconds(330))
+        .timestamp_hhmmss(chrono::Duration::seconds(3930))
+        .speed(1.5)
+        .app_name("MyApp")
+        .app_version("1.0")
+        .sender_name("Alice")
+        .sender_id(NOSTR_HEX_PUBKEY)
+        .receiver_name("Host")
+        .total_num_millisats(21_000)
+        .message("Great episode!")
+        .boost_link(url::Url::parse("https://example.com/ep-1?t=330").unwrap())
+        .payment_signature("signature")
+        .payment_id(uuid("5ad37bc5-9f3f-4e3b-8a1d-3c5a0b6e6f7a"))
+        .boost_id(uuid("6ad37bc5-9f3f-4e3b-8a1d-3c5a0b6e6f7a"))
+        .remote_feed_guid(uuid("7ad37bc5-9f3f-4e3b-8a1d-3c5a0b6e6f7a"))
+        .remote_item_guid("remote-item")
+        .reply_address("030a58b8653d32b99200a2334cfe913e51dc7d155aa0116c176657a4f1722677a3")
+        .reply_custom_key("696969")
+        .reply_custom_value("alice-wallet")
+        .build();
+
+    let json = serde_json::to_string(&record).unwrap();
+    let deserialized: v4v::pc20::tlv::Record = serde_json::from_str(&json).unwrap();
+    assert_eq!(deserialized, record);
+
+    let minimal = v4v::pc20::tlv::Record::default();
+    let json = serde_json::to_string(&minimal).unwrap();
+    assert_eq!(json, r#"{"action":"boost"}"#);
+    let deserialized: v4v::pc20::tlv::Record = serde_json::from_str(&json).unwrap();
+    assert_eq!(deserialized, minimal);
+}
+
+macro_rules! record_deserialize_strict_tests {
+    ($($name:ident: $value:expr,)*) => {
+        paste::item! {
+            $(
+                #[test]
+                fn [<record_deserialize_strict_ $name>]() {
+                    struct TestCase {
+                        json: &'static str,
+                    }
+                    assert!(serde_json::from_str::<v4v::pc20::tlv::Record>($value.json).is_err());
+                }
+            )*
+        }
+    }
+}
+
+record_deserialize_strict_tests! {
+    unknown_action: TestCase {
+        json: r#"{"action":"zap"}"#,
+    },
+    missing_action: TestCase {
+        json: r#"{"message":"Hi"}"#,
+    },
+    invalid_guid: TestCase {
+        json: r#"{"action":"boost","guid":"not-a-uuid"}"#,
+    },
+    string_seconds: TestCase {
+        json: r#"{"action":"boost","ts":"330"}"#,
+    },
+    invalid_timestamp: TestCase {
+        json: r#"{"action":"boost","time":"00:65:00"}"#,
+    },
+    negative_item_id: TestCase {
+        json: r#"{"action":"boost","itemID":-1}"#,
+    },
+    timestamp_out_of_bounds: TestCase {
+        json: r#"{"action":"boost","time":"9999999999999999:00:00"}"#,
+    },
+    seconds_out_of_bounds: TestCase {
+        json: r#"{"action":"boost","ts":-1e300}"#,
+    },
+}
+
+#[test]
+fn record_raw_remote_feed_guid_round_trip() {
+    let record = v4v::pc20::tlv::Record::builder()
+        .remote_feed_guid(uuid::Uuid::nil())
+        .remote_feed_guid_raw("not-a-uuid")
+        .build();
+
+    let json = serde_json::to_string(&record).unwrap();
+    assert_eq!(
+        json,
+        r#"{"action":"boost","remote_feed_guid":"not-a-uuid"}"#
+    );
+    let deserialized: v4v::pc20::tlv::Record = serde_json::from_str(&json).unwrap();
+    assert_eq!(deserialized, record);
+}
+
+#[tokio::test]
+async fn resolve_lightning_address_keysend() {
+    let (base_url, requests) = spawn_mock_server(vec![(
+        200,
+        r#"{
+            "status": "OK",
+            "tag": "keysend",
+            "pubkey": "030a58b8653d32b99200a2334cfe913e51dc7d155aa0116c176657a4f1722677a3",
+            "customData": [{"customKey": "696969", "customValue": "017rsl75kNnSke4mMHYE"}]
+        }"#,
+    )]);
+
+    let address = v4v::pc20::lightning_address::resolve_lightning_address_at(
+        "test",
+        "alice@getalby.com",
+        &base_url,
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(
+        address.pubkey,
+        "030a58b8653d32b99200a2334cfe913e51dc7d155aa0116c176657a4f1722677a3"
+    );
+    assert_eq!(address.wallet_id(), Some("017rsl75kNnSke4mMHYE"));
+    assert_eq!(
+        requests.lock().unwrap()[0],
+        "GET /.well-known/keysend/alice HTTP/1.1"
+    );
+}
+
+macro_rules! resolve_lightning_address_error_tests {
+    ($($name:ident: $value:expr,)*) => {
+        paste::item! {
+            $(
+                #[tokio::test]
+                async fn [<resolve_lightning_address_ $name>]() {
+                    struct TestCase {
+                        address: &'static str,
+                        response: (u16, &'static str),
+                        expected: fn(&v4v::pc20::lightning_address::ResolveError) -> bool,
+                    }
+                    let (base_url, _) = spawn_mock_server(vec![$value.response]);
+
+                    let result = v4v::pc20::lightning_address::resolve_lightning_address_at(
+                        "test",
+                        $value.address,
+                        &base_url,
+                    )
+                    .await;
+
+                    let error = result.unwrap_err();
+                    assert!(($value.expected)(&error), "{:?}", error);
+                }
+            )*
+        }
+    }
+}
+
+resolve_lightning_address_error_tests! {
+    pay_request_only: TestCase {
+        address: "alice@example.com",
+        response: (200, r#"{"tag": "payRequest", "callback": "https://example.com/cb", "minSendable": 1000, "maxSendable": 1000000, "metadata": "[]"}"#),
+        expected: |e| matches!(e, v4v::pc20::lightning_address::ResolveError::NotKeysend(_)),
+    },
+    error_status: TestCase {
+        address: "alice@example.com",
+        response: (200, r#"{"status": "ERROR", "reason": "keysend not supported"}"#),
+        expected: |e| matches!(e, v4v::pc20::lightning_address::ResolveError::NotKeysend(reason) if reason == "keysend not supported"),
+    },
+    not_found: TestCase {
+        address: "alice@example.com",
+        response: (404, "Not found"),
+        expected: |e| matches!(e, v4v::pc20::lightning_address::ResolveError::UnexpectedStatus { .. }),
+    },
+    invalid_pubkey: TestCase {
+        address: "alice@example.com",
+        response: (200, r#"{"tag": "keysend", "pubkey": "not-a-pubkey"}"#),
+        expected: |e| matches!(e, v4v::pc20::lightning_address::ResolveError::InvalidPubkey(_)),
+    },
+    invalid_address: TestCase {
+        address: "alice",
+        response: (200, "{}"),
+        expected: |e| matches!(e, v4v::pc20::lightning_address::ResolveError::InvalidAddress(_)),
+    },
+    path_in_address: TestCase {
+        address: "../admin@example.com",
+        response: (200, "{}"),
+        expected: |e| matches!(e, v4v::pc20::lightning_address::ResolveError::InvalidAddress(_)),
+    },
+}
+
+#[tokio::test]
+async fn create_invoice_rejects_absurd_amount() {
+    let result = v4v::pc20::forwarding::create_invoice(v4v::pc20::forwarding::CreateInvoiceArgs {
+        user_agent: "test",
+        token: "token",
+        recipients: vec![v4v::pc20::payments::PaymentRecipientInfo {
+            num_sats: u64::MAX,
+            ..Default::default()
+        }],
+        ..Default::default()
+    })
+    .await;
+
+    assert!(matches!(
+        result,
+        Err(v4v::alby::api::RequestError::InvalidArgs(_))
+    ));
+}
+
+#[test]
+fn create_invoice_args_ignore_deserialized_server_settings() {
+    let args: v4v::pc20::forwarding::CreateInvoiceArgs = serde_json::from_str(
+        r#"{
+            "user_agent": "test",
+            "token": "token",
+            "payment_info": {"totalNumSats": 21},
+            "recipients": [],
+            "base_url": "https://attacker.example.com",
+            "max_sats": 18446744073709551615
+        }"#,
+    )
+    .unwrap();
+
+    assert_eq!(args.base_url, None);
+    assert_eq!(args.max_sats, None);
+}
+
+#[tokio::test]
+async fn create_invoice_rejects_zero_amount() {
+    for recipients in [
+        vec![],
+        vec![
+            v4v::pc20::payments::PaymentRecipientInfo::default(),
+            v4v::pc20::payments::PaymentRecipientInfo::default(),
+        ],
+    ] {
+        let result =
+            v4v::pc20::forwarding::create_invoice(v4v::pc20::forwarding::CreateInvoiceArgs {
+                user_agent: "test",
+                token: "token",
+                recipients,
+                ..Default::default()
+            })
+            .await;
+
+        assert!(matches!(
+            result,
+            Err(v4v::alby::api::RequestError::InvalidArgs(_))
+        ));
+    }
+}
+
+macro_rules! alby_invoice_kind_state_tests {
+    ($($name:ident: $value:expr,)*) => {
+        paste::item! {
+            $(
+                #[test]
+                fn [<alby_invoice_kind_state_ $name>]() {
+                    struct TestCase {
+                        kind: &'static str,
+                        state: &'static str,
+                        expected_kind: v4v::alby::webhooks::InvoiceKind,
+                        expected_state: v4v::alby::webhooks::InvoiceState,
+                        expected_settled: bool,
+                        expected_incoming: bool,
+                    }
+                    let mut body = alby_invoice_body(serde_json::Value::Null);
+                    body["type"] = $value.kind.into();
+                    body["state"] = $value.state.into();
+
+                    let invoice = v4v::alby::webhooks::extract_alby_invoice(&body).unwrap();
+                    assert_eq!(invoice.kind, $value.expected_kind);
+                    assert_eq!(invoice.state, $value.expected_state);
+                    assert_eq!(invoice.kind.as_str(), $value.kind);
+                    assert_eq!(invoice.state.to_string(), $value.state);
+                    assert_eq!(invoice.is_settled(), $value.expected_settled);
+                    assert_eq!(invoice.is_incoming(), $value.expected_incoming);
+                }
+            )*
+        }
+    }
+}
+
+alby_invoice_kind_state_tests! {
+    incoming_settled: TestCase {
+        kind: "incoming",
+        state: "SETTLED",
+        expected_kind: v4v::alby::webhooks::InvoiceKind::Incoming,
+        expected_state: v4v::alby::webhooks::InvoiceState::Settled,
+        expected_settled: true,
+        expected_incoming: true,
+    },
+    outgoing_created: TestCase {
+        kind: "outgoing",
+        state: "CREATED",
+        expected_kind: v4v::alby::webhooks::InvoiceKind::Outgoing,
+        expected_state: v4v::alby::webhooks::InvoiceState::Created,
+        expected_settled: false,
+        expected_incoming: false,
+    },
+    unknown: TestCase {
+        kind: "internal",
+        state: "EXPIRED",
+        expected_kind: v4v::alby::webhooks::InvoiceKind::Unknown("internal".to_string()),
+        expected_state: v4v::alby::webhooks::InvoiceState::Unknown("EXPIRED".to_string()),
+        expected_settled: false,
+        expected_incoming: false,
+    },
+}
+
+macro_rules! peek_event_type_tests {
+    ($($name:ident: $value:expr,)*) => {
+        paste::item! {
+            $(
+                #[test]
+                fn [<peek_event_type_ $name>]() {
+                    struct TestCase {
+                        body: serde_json::Value,
+                        expected_type: Option<&'static str>,
+                    }
+                    assert_eq!(
+                        v4v::alby::webhooks::peek_event_type(&$value.body).as_deref(),
+                        $value.expected_type
+                    );
+                }
+            )*
+        }
+    }
+}
+
+peek_event_type_tests! {
+    case_1: TestCase {
+        body: alby_invoice_body(serde_json::Value::Null),
+        expected_type: Some("incoming"),
+    },
+    case_2: TestCase {
+        body: serde_json::json!({"event_type": "invoice.settled", "event": "outgoing"}),
+        expected_type: None,
+    },
+    case_3: TestCase {
+        body: serde_json::json!({"type": 1}),
+        expected_type: None,
+    },
+    case_4: TestCase {
+        body: serde_json::json!([]),
+        expected_type: None,
+    },
+}
+
+macro_rules! compute_sat_recipients_weighted_tests {
+    ($($name:ident: $value:expr,)*) => {
+        paste::item! {
+            $(
+                #[test]
+                fn [<compute_sat_recipients_weighted_ $name>]() {
+                    struct TestCase {
+                        weights: Vec<f64>,
+                        total_sats: u64,
+                        expected_sats: Result<Vec<u64>, v4v::pc20::calc::CalcError>,
+                    }
+                    assert_eq!(
+                        v4v::pc20::calc::compute_sat_recipients_weighted(&$value.weights, $value.total_sats),
+                        $value.expected_sats
+                    );
+                }
+            )*
+        }
+    }
+}
+
+compute_sat_recipients_weighted_tests! {
+    // Same as integer splits [1, 1].
+    case_1: TestCase {
+        weights: vec![2.5, 2.5],
+        total_sats: 101,
+        expected_sats: Ok(v4v::pc20::calc::compute_sat_recipients(&[1, 1], 101)),
+    },
+    case_2: TestCase {
+        weights: vec![0.5, 1.5, 2.0],
+        total_sats: 1000,
+        expected_sats: Ok(vec![125, 375, 500]),
+    },
+    // Tiny weights still get at least one sat.
+    case_3: TestCase {
+        weights: vec![1e-300, 99.0],
+        total_sats: 10,
+        expected_sats: Ok(vec![1, 9]),
+    },
+    case_4: TestCase {
+        weights: vec![0.0, 0.0],
+        total_sats: 10,
+        expected_sats: Ok(vec![5, 5]),
+    },
+    case_5: TestCase {
+        weights: vec![1.0, -1.0],
+        total_sats: 10,
+        expected_sats: Err(v4v::pc20::calc::CalcError::InvalidWeight { index: 1 }),
+    },
+    case_6: TestCase {
+        weights: vec![f64::NAN, 1.0],
+        total_sats: 10,
+        expected_sats: Err(v4v::pc20::calc::CalcError::InvalidWeight { index: 0 }),
+    },
+    case_7: TestCase {
+        weights: vec![1.0, f64::INFINITY],
+        total_sats: 10,
+        expected_sats: Err(v4v::pc20::calc::CalcError::InvalidWeight { index: 1 }),
+    },
+    case_8: TestCase {
+        weights: vec![],
+        total_sats: 10,
+        expected_sats: Ok(vec![]),
+    },
+}
+
+#[test]
+fn make_payment_args_builder_default_app() {
+    use v4v::pc20::payments::{MakePaymentArgs, PaymentInfo, PaymentRecipientInfo};
+
+    let builder = || {
+        MakePaymentArgs::builder()
+            .user_agent("test")
+            .token("token")
+            .recipient(PaymentRecipientInfo {
+                num_sats: 10,
+                ..Default::default()
+            })
+            .default_app("DefaultApp", "1.0")
+    };
+
+    let args = builder().build().unwrap();
+    assert_eq!(args.payment_info.app_name.as_deref(), Some("DefaultApp"));
+    assert_eq!(args.payment_info.app_version.as_deref(), Some("1.0"));
+
+    // Per-payment values take precedence, regardless of the order of the builder calls.
+    let args = builder()
+        .payment_info(PaymentInfo {
+            app_name: Some("OtherApp".to_string()),
+            ..Default::default()
+        })
+        .build()
+        .unwrap();
+    assert_eq!(args.payment_info.app_name.as_deref(), Some("OtherApp"));
+    assert_eq!(args.payment_info.app_version.as_deref(), Some("1.0"));
+}
+
+macro_rules! compute_refund_tests {
+    ($($name:ident: $value:expr,)*) => {
+        paste::item! {
+            $(
+                #[test]
+                fn [<compute_refund_ $name>]() {
+                    struct TestCase {
+                        original_amounts: Vec<u64>,
+                        refund_sats: u64,
+                        expected_refunds: Vec<u64>,
+                    }
+                    assert_eq!(
+                        v4v::pc20::calc::compute_refund(&$value.original_amounts, $value.refund_sats),
+                        $value.expected_refunds
+                    );
+                }
+            )*
+        }
+    }
+}
+
+compute_refund_tests! {
+    // Full refund.
+    case_1: TestCase {
+        original_amounts: vec![600, 300, 100],
+        refund_sats: 1000,
+        expected_refunds: vec![600, 300, 100],
+    },
+    // Partial refund.
+    case_2: TestCase {
+        original_amounts: vec![600, 300, 100],
+        refund_sats: 100,
+        expected_refunds: vec![60, 30, 10],
+    },
+    // Partial refund with rounding.
+    case_3: TestCase {
+        original_amounts: vec![1, 1, 1],
+        refund_sats: 2,
+        expected_refunds: vec![1, 1, 0],
+    },
+    // Party that received nothing refunds nothing.
+    case_4: TestCase {
+        original_amounts: vec![0, 500, 500],
+        refund_sats: 1000,
+        expected_refunds: vec![0, 500, 500],
+    },
+    case_5: TestCase {
+        original_amounts: vec![600, 400],
+        refund_sats: 0,
+        expected_refunds: vec![0, 0],
+    },
+    case_6: TestCase {
+        original_amounts: vec![0, 1000],
+        refund_sats: 1,
+        expected_refunds: vec![0, 1],
+    },
+}
+
+#[tokio::test]
+async fn rate_limiter_allows_burst_then_paces() {
+    let rate_limiter = v4v::alby::api::RateLimiter::new(10.0);
+
+    let start = std::time::Instant::now();
+    for _ in 0..10 {
+        rate_limiter.acquire().await;
+    }
+    assert!(start.elapsed() < std::time::Duration::from_millis(50));
+
+    rate_limiter.acquire().await;
+    assert!(start.elapsed() >= std::time::Duration::from_millis(90));
+}
+
+macro_rules! reply_keysend_address_tests {
+    ($($name:ident: $value:expr,)*) => {
+        paste::item! {
+            $(
+                #[test]
+                fn [<reply_keysend_address_ $name>]() {
+                    struct TestCase {
+                        boostagram: serde_json::Value,
+                        expected_pubkey: Option<&'static str>,
+                        expected_custom_data: Option<(&'static str, &'static str)>,
+                        expected_lightning_address: bool,
+                    }
+                    let body = alby_invoice_body($value.boostagram);
+                    let invoice = v4v::alby::webhooks::extract_alby_invoice(&body).unwrap();
+                    let record = invoice.boostagram.unwrap();
+                    let address = record.reply_keysend_address();
+                    assert_eq!(address.as_ref().map(|address| address.pubkey.as_str()), $value.expected_pubkey);
+                    assert_eq!(
+                        address.and_then(|address| address.custom_data),
+                        $value.expected_custom_data.map(|(key, value)| (key.to_string(), value.to_string())),
+                    );
+                    assert_eq!(record.is_lightning_address_reply(), $value.expected_lightning_address);
+                }
+            )*
+        }
+    }
+}
+
+reply_keysend_address_tests! {
+    node_with_custom_data: TestCase {
+        boostagram: serde_json::json!({
+            "action": "boost",
+            "reply_address": "030a58b8653d32b99200a2334cfe913e51dc7d155aa0116c176657a4f1722677a3",
+            "reply_custom_key": "696969",
+            "reply_custom_value": "017rsl75kNnSke4mMHYE",
+        }),
+        expected_pubkey: Some("030a58b8653d32b99200a2334cfe913e51dc7d155aa0116c176657a4f1722677a3"),
+        expected_custom_data: Some(("696969", "017rsl75kNnSke4mMHYE")),
+        expected_lightning_address: false,
+    },
+    node_without_custom_value: TestCase {
+        boostagram: serde_json::json!({
+            "action": "boost",
+            "reply_address": "030a58b8653d32b99200a2334cfe913e51dc7d155aa0116c176657a4f1722677a3",
+            "reply_custom_key": "696969",
+        }),
+        expected_pubkey: Some("030a58b8653d32b99200a2334cfe913e51dc7d155aa0116c176657a4f1722677a3"),
+        expected_custom_data: None,
+        expected_lightning_address: false,
+    },
+    lightning_address: TestCase {
+        boostagram: serde_json::json!({
+            "action": "boost",
+            "reply_address": "alice@example.com",
+        }),
+        expected_pubkey: None,
+        expected_custom_data: None,
+        expected_lightning_address: true,
+    },
+    invalid_pubkey: TestCase {
+        boostagram: serde_json::json!({
+            "action": "boost",
+            "reply_address": "not-a-pubkey",
+        }),
+        expected_pubkey: None,
+        expected_custom_data: None,
+        expected_lightning_address: false,
+    },
+    no_reply_address: TestCase {
+        boostagram: serde_json::json!({"action": "boost"}),
+        expected_pubkey: None,
+        expected_custom_data: None,
+        expected_lightning_address: false,
+    },
+}
+
+macro_rules! validate_for_action_tests {
+    ($($name:ident: $value:expr,)*) => {
+        paste::item! {
+            $(
+                #[test]
+                fn [<validate_for_action_ $name>]() {
+                    use v4v::pc20::payments::ValidationIssue;
+
+                    struct TestCase {
+                        payment_info: v4v::pc20::payments::PaymentInfo,
+                        expected: Result<(), Vec<ValidationIssue>>,
+                    }
+                    assert_eq!($value.payment_info.validate_for_action(), $value.expected);
+                }
+            )*
+        }
+    }
+}
+
+validate_for_action_tests! {
+    boost_ok: TestCase {
+        payment_info: v4v::pc20::payments::PaymentInfo {
+            feed_url: Some(url::Url::parse("https://example.com/feed.xml").unwrap()),
+            total_num_sats: 100,
+            ..Default::default()
+        },
+        expected: Ok(()),
+    },
+    boost_empty: TestCase {
+        payment_info: Default::default(),
+        expected: Err(vec![
+            ValidationIssue::MissingFeedIdentifier,
+            ValidationIssue::ZeroAmount,
+        ]),
+    },
+    stream_ok: TestCase {
+        payment_info: v4v::pc20::payments::PaymentInfo {
+            action: v4v::pc20::payments::Action::Stream,
+            feed_pi_id: Some(1),
+            item_guid: Some("item".to_string()),
+            timestamp: Some(chrono::Duration::seconds(30)),
+            total_num_sats: 1,
+            ..Default::default()
+        },
+        expected: Ok(()),
+    },
+    stream_without_item: TestCase {
+        payment_info: v4v::pc20::payments::PaymentInfo {
+            action: v4v::pc20::payments::Action::Stream,
+            feed_guid: Some(uuid::Uuid::nil()),
+            timestamp: Some(chrono::Duration::seconds(30)),
+            total_num_sats: 1,
+            ..Default::default()
+        },
+        expected: Err(vec![ValidationIssue::MissingItemIdentifier]),
+    },
+    auto_ok_without_item: TestCase {
+        payment_info: v4v::pc20::payments::PaymentInfo {
+            action: v4v::pc20::payments::Action::Auto,
+            feed_guid: Some(uuid::Uuid::nil()),
+            total_num_sats: 1,
+            ..Default::default()
+        },
+        expected: Ok(()),
+    },
+}
+
+#[test]
+fn parse_records_stream_continues_after_bad_lines() {
+    use v4v::pc20::tlv::{parse_records_stream, ParseError};
+
+    let mut input = b"{\"action\":\"boost\",\"value_msat_total\":1000}\n".to_vec();
+    input.extend_from_slice(b"\xff\xfe\n");
+    input.extend_from_slice(b"{\"action\":\n");
+    input.extend_from_slice(b"{\"action\":\"auto\",\"value_msat_total\":2000}");
+
+    let results: Vec<_> = parse_records_stream(input.as_slice()).collect();
+    assert_eq!(results.len(), 4);
+    assert_eq!(results[0].as_ref().unwrap().total_num_millisats, Some(1000));
+    assert!(matches!(results[1], Err(ParseError::Io(_))));
+    assert!(matches!(
+        results[2],
+        Err(ParseError::InvalidRecord { line: 3, .. })
+    ));
+    assert_eq!(results[3].as_ref().unwrap().total_num_millisats, Some(2000));
+}
+
+#[test]
+fn keysend_custom_record_helpers() {
+    use v4v::pc20::tlv::{KEYSEND_MESSAGE_KEY, KEYSEND_PREIMAGE_KEY};
+
+    let body = serde_json::json!({
+        "identifier": "MAYm6eWC7TKUyixZWAKUM3su",
+        "type": "incoming",
+        "state": "SETTLED",
+        "amount": 21,
+        "created_at": "2024-08-01T12:00:00.000Z",
+        "custom_records": {
+            KEYSEND_MESSAGE_KEY: "Hello",
+            KEYSEND_PREIMAGE_KEY: "00",
+        },
+    });
+    let keysend = v4v::alby::webhooks::extract_incoming_keysend(&body).unwrap();
+    assert_eq!(keysend.keysend_message(), Some("Hello"));
+    assert_eq!(keysend.custom_record(KEYSEND_PREIMAGE_KEY), Some("00"));
+    assert!(keysend.boostagram.is_none());
+
+    let address = v4v::alby::types::KeysendAddress::with_custom(
+        "030a58b8653d32b99200a2334cfe913e51dc7d155aa0116c176657a4f1722677a3",
+        "112111100",
+        "wallet",
+    )
+    .unwrap();
+    assert_eq!(address.wallet_id(), None);
+}
+
+macro_rules! compute_sat_recipients_ranked_tests {
+    ($($name:ident: $value:expr,)*) => {
+        paste::item! {
+            $(
+                #[test]
+                fn [<compute_sat_recipients_ranked_ $name>]() {
+                    struct TestCase {
+                        splits: Vec<u64>,
+                        total_sats: u64,
+                        expected: Vec<(usize, u64)>,
+                    }
+                    let ranked = v4v::pc20::calc::compute_sat_recipients_ranked(&$value.splits, $value.total_sats);
+                    assert_eq!(ranked, $value.expected);
+
+                    // Mapping back to the input order gives the unranked distribution.
+                    let mut unranked = vec![0; $value.splits.len()];
+                    for (index, sats) in ranked {
+                        unranked[index] = sats;
+                    }
+                    assert_eq!(unranked, v4v::pc20::calc::compute_sat_recipients(&$value.splits, $value.total_sats));
+                }
+            )*
+        }
+    }
+}
+
+compute_sat_recipients_ranked_tests! {
+    case_1: TestCase {
+        splits: vec![10, 60, 30],
+        total_sats: 100,
+        expected: vec![(1, 60), (2, 30), (0, 10)],
+    },
+    case_2: TestCase {
+        splits: vec![25, 50, 25],
+        total_sats: 100,
+        expected: vec![(1, 50), (0, 25), (2, 25)],
+    },
+    case_3: TestCase {
+        splits: vec![1, 99],
+        total_sats: 1,
+        expected: vec![(1, 1), (0, 0)],
+    },
+    case_4: TestCase {
+        splits: vec![],
+        total_sats: 100,
+        expected: vec![],
+    },
+}
+
+macro_rules! preview_distribution_tests {
+    ($($name:ident: $value:expr,)*) => {
+        paste::item! {
+            $(
+                #[test]
+                fn [<preview_distribution_ $name>]() {
+                    use v4v::pc20::calc::OutcomeReason::*;
+
+                    struct TestCase {
+                        splits: Vec<u64>,
+                        total_sats: u64,
+                        expected: Vec<(u64, v4v::pc20::calc::OutcomeReason)>,
+                    }
+                    let preview = v4v::pc20::calc::preview_distribution(&$value.splits, $value.total_sats);
+                    assert_eq!(
+                        preview.iter().map(|outcome| (outcome.sats, outcome.reason)).collect::<Vec<_>>(),
+                        $value.expected,
+                    );
+                }
+            )*
+        }
+    }
+}
+
+preview_distribution_tests! {
+    all_paid: TestCase {
+        splits: vec![1, 99],
+        total_sats: 10,
+        expected: vec![(1, Paid), (9, Paid)],
+    },
+    low_split_dropped: TestCase {
+        splits: vec![50, 40, 3, 2, 2, 1, 2],
+        total_sats: 4,
+        expected: vec![
+            (1, Paid),
+            (1, Paid),
+            (1, Paid),
+            (1, Paid),
+            (0, DroppedLowSplit),
+            (0, DroppedLowSplit),
+            (0, DroppedLowSplit),
+        ],
+    },
+    insufficient_total: TestCase {
+        splits: vec![60, 40],
+        total_sats: 0,
+        expected: vec![(0, DroppedInsufficient), (0, DroppedInsufficient)],
+    },
+}
+
+macro_rules! fiat_to_sats_tests {
+    ($($name:ident: $value:expr,)*) => {
+        paste::item! {
+            $(
+                #[test]
+                fn [<fiat_to_sats_ $name>]() {
+                    struct TestCase {
+                        fiat_amount: f64,
+                        sats_per_unit: f64,
+                        expected: u64,
+                    }
+                    assert_eq!(v4v::pc20::calc::fiat_to_sats($value.fiat_amount, $value.sats_per_unit), $value.expected);
+                }
+            )*
+        }
+    }
+}
+
+fiat_to_sats_tests! {
+    exact: TestCase {
+        fiat_amount: 0.25,
+        sats_per_unit: 1000.0,
+        expected: 250,
+    },
+    rounds_down: TestCase {
+        fiat_amount: 1.0,
+        sats_per_unit: 1500.4,
+        expected: 1500,
+    },
+    rounds_up: TestCase {
+        fiat_amount: 1.0,
+        sats_per_unit: 1500.6,
+        expected: 1501,
+    },
+    tie_to_even_down: TestCase {
+        fiat_amount: 0.5,
+        sats_per_unit: 5.0,
+        expected: 2,
+    },
+    tie_to_even_up: TestCase {
+        fiat_amount: 0.5,
+        sats_per_unit: 7.0,
+        expected: 4,
+    },
+    half_sat: TestCase {
+        fiat_amount: 0.5,
+        sats_per_unit: 1.0,
+        expected: 0,
+    },
+    zero: TestCase {
+        fiat_amount: 0.0,
+        sats_per_unit: 1500.0,
+        expected: 0,
+    },
+    negative: TestCase {
+        fiat_amount: -1.0,
+        sats_per_unit: 1500.0,
+        expected: 0,
+    },
+    nan: TestCase {
+        fiat_amount: f64::NAN,
+        sats_per_unit: 1500.0,
+        expected: 0,
+    },
+    saturates: TestCase {
+        fiat_amount: f64::INFINITY,
+        sats_per_unit: 1500.0,
+        expected: u64::MAX,
+    },
+}
+
+macro_rules! preimage_tests {
+    ($($name:ident: $value:expr,)*) => {
+        paste::item! {
+            $(
+                #[test]
+                fn [<preimage_ $name>]() {
+                    struct TestCase {
+                        preimage: &'static str,
+                        payment_hash: &'static str,
+                        expected: Option<bool>,
+                    }
+                    let preimage = v4v::alby::types::Preimage::from_hex($value.preimage);
+                    assert_eq!(preimage.as_ref().ok().map(|preimage| preimage.verifies($value.payment_hash)), $value.expected);
+                    if let Ok(preimage) = preimage {
+                        assert_eq!(preimage.to_hex(), $value.preimage.to_ascii_lowercase());
+                    }
+                }
+            )*
+        }
+    }
+}
+
+preimage_tests! {
+    matching: TestCase {
+        preimage: "0000000000000000000000000000000000000000000000000000000000000000",
+        payment_hash: "66687aadf862bd776c8fc18b8e9f8e20089714856ee233b3902a591d0d5f2925",
+        expected: Some(true),
+    },
+    matching_uppercase: TestCase {
+        preimage: "000102030405060708090A0B0C0D0E0F101112131415161718191A1B1C1D1E1F",
+        payment_hash: "630DCD2966C4336691125448BBB25B4FF412A49C732DB2C8ABC1B8581BD710DD",
+        expected: Some(true),
+    },
+    not_matching: TestCase {
+        preimage: "0000000000000000000000000000000000000000000000000000000000000000",
+        payment_hash: "630dcd2966c4336691125448bbb25b4ff412a49c732db2c8abc1b8581bd710dd",
+        expected: Some(false),
+    },
+    too_short: TestCase {
+        preimage: "00",
+        payment_hash: "66687aadf862bd776c8fc18b8e9f8e20089714856ee233b3902a591d0d5f2925",
+        expected: None,
+    },
+    not_hex: TestCase {
+        preimage: "zz00000000000000000000000000000000000000000000000000000000000000",
+        payment_hash: "66687aadf862bd776c8fc18b8e9f8e20089714856ee233b3902a591d0d5f2925",
+        expected: None,
+    },
+    sign_prefix: TestCase {
+        preimage: "+f00000000000000000000000000000000000000000000000000000000000000",
+        payment_hash: "66687aadf862bd776c8fc18b8e9f8e20089714856ee233b3902a591d0d5f2925",
+        expected: None,
+    },
+}
+
+#[test]
+fn compute_sat_recipients_strict_rejects_missing_recipients() {
+    use v4v::pc20::calc::{compute_sat_recipients, compute_sat_recipients_strict, CalcError};
+
+    assert_eq!(compute_sat_recipients(&[], 100), Vec::<u64>::new());
+    assert_eq!(
+        compute_sat_recipients_strict(&[], 100),
+        Err(CalcError::NoRecipients)
+    );
+    assert_eq!(compute_sat_recipients_strict(&[], 0), Ok(Vec::new()));
+    assert_eq!(compute_sat_recipients_strict(&[0, 0], 3), Ok(vec![2, 1]));
+}
+
+macro_rules! recipients_at_timestamp_tests {
+    ($($name:ident: $value:expr,)*) => {
+        paste::item! {
+            $(
+                #[test]
+                fn [<recipients_at_timestamp_ $name>]() {
+                    #[derive(Debug, PartialEq, Clone)]
+                    struct MyStruct {
+                        split: u64,
+                    }
+                    impl v4v::pc20::calc::HasSplit for MyStruct {
+                        fn get_split(&self) -> u64 {
+                            self.split
+                        }
+
+                        fn set_split(&mut self, split: u64) {
+                            self.split = split;
+                        }
+                    }
+
+                    struct TestCase {
+                        timestamp_seconds: i64,
+                        expected_active_time_split: Option<usize>,
+                        expected_local_splits: Vec<u64>,
+                        expected_remote_splits: Vec<u64>,
+                    }
+
+                    let local = vec![MyStruct { split: 50 }, MyStruct { split: 50 }];
+                    let time_splits = vec![
+                        (
+                            chrono::Duration::seconds(60),
+                            chrono::Duration::seconds(60),
+                            v4v::pc20::calc::RemoteBlock {
+                                recipients: vec![MyStruct { split: 1 }],
+                                remote_percentage: 90,
+                            },
+                        ),
+                        (
+                            chrono::Duration::seconds(300),
+                            chrono::Duration::seconds(60),
+                            v4v::pc20::calc::RemoteBlock {
+                                recipients: vec![MyStruct { split: 1 }, MyStruct { split: 3 }],
+                                remote_percentage: 50,
+                            },
+                        ),
+                    ];
+
+                    let resolved = v4v::pc20::calc::recipients_at_timestamp(
+                        &local,
+                        &time_splits,
+                        chrono::Duration::seconds($value.timestamp_seconds),
+                    );
+                    assert_eq!(resolved.active_time_split, $value.expected_active_time_split);
+                    assert_eq!(resolved.local.iter().map(|r| r.split).collect::<Vec<_>>(), $value.expected_local_splits);
+                    assert_eq!(resolved.remote.iter().map(|r| r.split).collect::<Vec<_>>(), $value.expected_remote_splits);
+                }
+            )*
+        }
+    }
+}
+
+recipients_at_timestamp_tests! {
+    before_first: TestCase {
+        timestamp_seconds: 59,
+        expected_active_time_split: None,
+        expected_local_splits: vec![50, 50],
+        expected_remote_splits: vec![],
+    },
+    start_of_first: TestCase {
+        timestamp_seconds: 60,
+        expected_active_time_split: Some(0),
+        expected_local_splits: vec![1, 1],
+        expected_remote_splits: vec![18],
+    },
+    end_of_first: TestCase {
+        timestamp_seconds: 120,
+        expected_active_time_split: None,
+        expected_local_splits: vec![50, 50],
+        expected_remote_splits: vec![],
+    },
+    inside_second: TestCase {
+        timestamp_seconds: 330,
+        expected_active_time_split: Some(1),
+        expected_local_splits: vec![2, 2],
+        expected_remote_splits: vec![1, 3],
+    },
+}
+
+#[test]
+fn recipients_at_timestamp_overflowing_interval() {
+    #[derive(Debug, PartialEq, Clone)]
+    struct MyStruct {
+        split: u64,
+    }
+    impl v4v::pc20::calc::HasSplit for MyStruct {
+        fn get_split(&self) -> u64 {
+            self.split
+        }
+
+        fn set_split(&mut self, split: u64) {
+            self.split = split;
+        }
+    }
+
+    let local = vec![MyStruct { split: 50 }, MyStruct { split: 50 }];
+    let time_splits = vec![(
+        chrono::Duration::MAX - chrono::Duration::seconds(10),
+        chrono::Duration::seconds(60),
+        v4v::pc20::calc::RemoteBlock {
+            recipients: vec![MyStruct { split: 1 }],
+            remote_percentage: 90,
+        },
+    )];
+
+    let resolved = v4v::pc20::calc::recipients_at_timestamp(
+        &local,
+        &time_splits,
+        chrono::Duration::MAX - chrono::Duration::seconds(5),
+    );
+    assert_eq!(resolved.active_time_split, None);
+    assert_eq!(
+        resolved.local.iter().map(|r| r.split).collect::<Vec<_>>(),
+        vec![50, 50]
+    );
+    assert!(resolved.remote.is_empty());
+}
+
+macro_rules! split_tlv_records_tests {
+    ($($name:ident: $value:expr,)*) => {
+        paste::item! {
+            $(
+                #[test]
+                fn [<split_tlv_records_ $name>]() {
+                    struct TestCase {
+                        records: Vec<serde_json::Value>,
+                        expected_shared: serde_json::Value,
+                        expected_per_recipient: Vec<serde_json::Value>,
+                    }
+                    let portions = v4v::pc20::payments::split_tlv_records(&$value.records);
+                    assert_eq!(serde_json::Value::Object(portions.shared.clone()), $value.expected_shared);
+                    assert_eq!(
+                        portions.per_recipient.iter().cloned().map(serde_json::Value::Object).collect::<Vec<_>>(),
+                        $value.expected_per_recipient,
+                    );
+
+                    // Merging the portions gives back the records.
+                    for (record, mut object) in $value.records.iter().zip(portions.per_recipient) {
+                        if record.is_object() {
+                            object.extend(portions.shared.clone());
+                            assert_eq!(&serde_json::Value::Object(object), record);
+                        }
+                    }
+                }
+            )*
+        }
+    }
+}
+
+split_tlv_records_tests! {
+    empty: TestCase {
+        records: vec![],
+        expected_shared: serde_json::json!({}),
+        expected_per_recipient: vec![],
+    },
+    single: TestCase {
+        records: vec![serde_json::json!({"action": "boost", "name": "Host"})],
+        expected_shared: serde_json::json!({"action": "boost", "name": "Host"}),
+        expected_per_recipient: vec![serde_json::json!({})],
+    },
+    different_values_and_missing_keys: TestCase {
+        records: vec![
+            serde_json::json!({"action": "boost", "message": "Hi!", "name": "Host", "uuid": "a"}),
+            serde_json::json!({"action": "boost", "message": "Hi!", "name": "Guest"}),
+        ],
+        expected_shared: serde_json::json!({"action": "boost", "message": "Hi!"}),
+        expected_per_recipient: vec![
+            serde_json::json!({"name": "Host", "uuid": "a"}),
+            serde_json::json!({"name": "Guest"}),
+        ],
+    },
+    not_an_object: TestCase {
+        records: vec![
+            serde_json::json!({"action": "boost"}),
+            serde_json::json!(null),
+        ],
+        expected_shared: serde_json::json!({}),
+        expected_per_recipient: vec![
+            serde_json::json!({"action": "boost"}),
+            serde_json::json!({}),
+        ],
+    },
+}
+
+#[test]
+fn make_payment_args_tlv_portions() {
+    let args = v4v::pc20::payments::MakePaymentArgs {
+        payment_info: v4v::pc20::payments::PaymentInfo {
+            message: Some("Hello".to_string()),
+            total_num_sats: 10,
+            ..Default::default()
+        },
+        recipients: vec![
+            v4v::pc20::payments::PaymentRecipientInfo {
+                num_sats: 5,
+                name: Some("Host".to_string()),
+                ..Default::default()
+            },
+            v4v::pc20::payments::PaymentRecipientInfo {
+                num_sats: 5,
+                name: Some("Guest".to_string()),
+                ..Default::default()
+            },
+        ],
+        ..Default::default()
+    };
+
+    let portions = args.tlv_portions().unwrap();
+    assert_eq!(portions.shared["message"], "Hello");
+    assert_eq!(portions.shared["value_msat_total"], 10_000);
+    assert_eq!(
+        portions
+            .per_recipient
+            .iter()
+            .map(|object| serde_json::Value::Object(object.clone()))
+            .collect::<Vec<_>>(),
+        vec![
+            serde_json::json!({"name": "Host"}),
+            serde_json::json!({"name": "Guest"}),
+        ]
+    );
+}
+
+macro_rules! canonicalize_recipients_tests {
+    ($($name:ident: $value:expr,)*) => {
+        paste::item! {
+            $(
+                #[test]
+                fn [<canonicalize_recipients_ $name>]() {
+                    use v4v::pc20::payments::{CanonicalizeOpts, PaymentRecipientInfo, ValidationIssue};
+
+                    struct TestCase {
+                        recipients: Vec<(&'static str, u64)>,
+                        opts: CanonicalizeOpts,
+                        expected: Result<Vec<(&'static str, u64)>, Vec<ValidationIssue>>,
+                    }
+                    let recipients = $value
+                        .recipients
+                        .iter()
+                        .map(|&(pubkey, num_sats)| PaymentRecipientInfo {
+                            address: v4v::alby::types::KeysendAddress {
+                                pubkey: pubkey.to_string(),
+                                custom_data: None,
+                            }.into(),
+                            num_sats,
+                            ..Default::default()
+                        })
+                        .collect();
+                    let result = v4v::pc20::payments::canonicalize_recipients(recipients, $value.opts);
+                    let expected = $value.expected.map(|recipients| {
+                        recipients
+                            .into_iter()
+                            .map(|(pubkey, num_sats)| (pubkey.to_string(), num_sats))
+                            .collect::<Vec<_>>()
+                    });
+                    assert_eq!(
+                        result.map(|recipients| {
+                            recipients
+                                .into_iter()
+                                .map(|r| (r.address.to_string(), r.num_sats))
+                                .collect::<Vec<_>>()
+                        }),
+                        expected,
+                    );
+                }
+            )*
+        }
+    }
+}
+
+canonicalize_recipients_tests! {
+    no_options: TestCase {
+        recipients: vec![
+            ("03AE9F91A0CB8FF43840E3C322C4C61F019D8C1C3CEA15A25CFC425AC605E61A4A", 90),
+            ("02d5c1bf8b940dc9cadca86d1b0a3c37fbe39cee4c7e839e33bef9174531d27f52", 0),
+        ],
+        opts: CanonicalizeOpts::default(),
+        expected: Ok(vec![
+            ("03ae9f91a0cb8ff43840e3c322c4c61f019d8c1c3cea15a25cfc425ac605e61a4a", 90),
+            ("02d5c1bf8b940dc9cadca86d1b0a3c37fbe39cee4c7e839e33bef9174531d27f52", 0),
+        ]),
+    },
+    normalize_splits: TestCase {
+        recipients: vec![
+            ("03ae9f91a0cb8ff43840e3c322c4c61f019d8c1c3cea15a25cfc425ac605e61a4a", 90),
+            ("02d5c1bf8b940dc9cadca86d1b0a3c37fbe39cee4c7e839e33bef9174531d27f52", 0),
+            ("030a58b8653d32b99200a2334cfe913e51dc7d155aa0116c176657a4f1722677a3", 30),
+        ],
+        opts: CanonicalizeOpts {
+            normalize_splits: true,
+            ..Default::default()
+        },
+        expected: Ok(vec![
+            ("03ae9f91a0cb8ff43840e3c322c4c61f019d8c1c3cea15a25cfc425ac605e61a4a", 3),
+            ("02d5c1bf8b940dc9cadca86d1b0a3c37fbe39cee4c7e839e33bef9174531d27f52", 0),
+            ("030a58b8653d32b99200a2334cfe913e51dc7d155aa0116c176657a4f1722677a3", 1),
+        ]),
+    },
+    drop_zero_splits: TestCase {
+        recipients: vec![
+            ("03ae9f91a0cb8ff43840e3c322c4c61f019d8c1c3cea15a25cfc425ac605e61a4a", 90),
+            ("02d5c1bf8b940dc9cadca86d1b0a3c37fbe39cee4c7e839e33bef9174531d27f52", 0),
+        ],
+        opts: CanonicalizeOpts {
+            drop_zero_splits: true,
+            ..Default::default()
+        },
+        expected: Ok(vec![
+            ("03ae9f91a0cb8ff43840e3c322c4c61f019d8c1c3cea15a25cfc425ac605e61a4a", 90),
+        ]),
+    },
+    sort: TestCase {
+        recipients: vec![
+            ("03ae9f91a0cb8ff43840e3c322c4c61f019d8c1c3cea15a25cfc425ac605e61a4a", 90),
+            ("02d5c1bf8b940dc9cadca86d1b0a3c37fbe39cee4c7e839e33bef9174531d27f52", 10),
+        ],
+        opts: CanonicalizeOpts {
+            sort: true,
+            ..Default::default()
+        },
+        expected: Ok(vec![
+            ("02d5c1bf8b940dc9cadca86d1b0a3c37fbe39cee4c7e839e33bef9174531d27f52", 10),
+            ("03ae9f91a0cb8ff43840e3c322c4c61f019d8c1c3cea15a25cfc425ac605e61a4a", 90),
+        ]),
+    },
+    invalid_pubkeys: TestCase {
+        recipients: vec![
+            ("not-a-pubkey", 90),
+            ("02d5c1bf8b940dc9cadca86d1b0a3c37fbe39cee4c7e839e33bef9174531d27f52", 10),
+            ("", 10),
+        ],
+        opts: CanonicalizeOpts::default(),
+        expected: Err(vec![
+            ValidationIssue::InvalidPubkey { index: 0 },
+            ValidationIssue::InvalidPubkey { index: 2 },
+        ]),
+    },
+}
+
+#[test]
+fn recipients_to_splits_error_is_std_error() {
+    fn to_splits(
+        recipients: &[v4v::pc20::calc::GenericRecipient],
+    ) -> Result<Vec<u64>, Box<dyn std::error::Error>> {
+        Ok(v4v::pc20::calc::fee_recipients_to_splits(recipients)?)
+    }
+
+    let error =
+        to_splits(&[v4v::pc20::calc::GenericRecipient::PercentageBased { percentage: 101 }])
+            .unwrap_err();
+    assert_eq!(error.to_string(), "Total fees exceeds 100%");
+
+    let errors = [v4v::pc20::calc::RecipientsToSplitsError::TotalFeeExceeds100; 2];
+    assert_eq!(errors[0], errors[1]);
+}
+
+#[cfg(feature = "nwc")]
+mod nwc {
+    use aes::cipher::{block_padding::Pkcs7, BlockDecryptMut, BlockEncryptMut, KeyIvInit};
+    use base64::Engine;
+    use futures_util::{SinkExt, StreamExt};
+    use std::str::FromStr;
+    use tokio_tungstenite::tungstenite::Message;
+
+    const WALLET_SECRET: &str = "71a8c14c1407c113601079c4302dab36460f0ccd0ad506f1f2dc73b5100e4f3c";
+    const CLIENT_SECRET: &str = "3b0c4f3e5f1c6a8d2e7b9a0c1d2e3f405162738495a6b7c8d9e0f1a2b3c4d5e6";
+    const DEST_PUBKEY: &str = "030a58b8653d32b99200a2334cfe913e51dc7d155aa0116c176657a4f1722677a3";
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+
+    fn shared_secret(secret: &str, pubkey: &str) -> [u8; 32] {
+        let pubkey = secp256k1::XOnlyPublicKey::from_str(pubkey).unwrap();
+        let point = secp256k1::ecdh::shared_secret_point(
+            &pubkey.public_key(secp256k1::Parity::Even),
+            &secp256k1::SecretKey::from_str(secret).unwrap(),
+        );
+        point[..32].try_into().unwrap()
+    }
+
+    /// Signed and NIP-04-encrypted response event of the mock wallet service.
+    fn wallet_response(
+        request: &serde_json::Value,
+        content: &serde_json::Value,
+    ) -> serde_json::Value {
+        let keypair = secp256k1::Keypair::from_str(WALLET_SECRET).unwrap();
+        let pubkey = keypair.x_only_public_key().0.to_string();
+        let client_pubkey = request["pubkey"].as_str().unwrap();
+
+        let key = shared_secret(WALLET_SECRET, client_pubkey);
+        let iv = [7u8; 16];
+        let ciphertext = cbc::Encryptor::<aes::Aes256>::new(&key.into(), &iv.into())
+            .encrypt_padded_vec_mut::<Pkcs7>(content.to_string().as_bytes());
+        let base64 = base64::engine::general_purpose::STANDARD;
+        let content = format!("{}?iv={}", base64.encode(ciphertext), base64.encode(iv));
+
+        let tags = serde_json::json!([["p", client_pubkey], ["e", request["id"]]]);
+        let id = hmac_sha256::Hash::hash(
+            serde_json::json!([0, pubkey, 1700000000, 23195, tags, content])
+                .to_string()
+                .as_bytes(),
+        );
+        serde_json::json!({
+            "id": hex(&id),
+            "pubkey": pubkey,
+            "created_at": 1700000000,
+            "kind": 23195,
+            "tags": tags,
+            "content": content,
+            "sig": keypair.sign_schnorr_no_aux_rand(&id).to_string(),
+        })
+    }
+
+    fn decrypt_request(request: &serde_json::Value) -> serde_json::Value {
+        let key = shared_secret(WALLET_SECRET, request["pubkey"].as_str().unwrap());
+        let (ciphertext, iv) = request["content"]
+            .as_str()
+            .unwrap()
+            .split_once("?iv=")
+            .unwrap();
+        let base64 = base64::engine::general_purpose::STANDARD;
+        let iv: [u8; 16] = base64.decode(iv).unwrap().try_into().unwrap();
+        let plaintext = cbc::Decryptor::<aes::Aes256>::new(&key.into(), &iv.into())
+            .decrypt_padded_vec_mut::<Pkcs7>(&base64.decode(ciphertext).unwrap())
+            .unwrap();
+        serde_json::from_slice(&plaintext).unwrap()
+    }
+
+    /// Spawns a relay with a wallet service that answers requests with `respond`, returning the
+    /// connection string and the decrypted requests.
+    async fn spawn_mock_relay(
+        respond: fn(&serde_json::Value) -> serde_json::Value,
+    ) -> (
+        String,
+        std::sync::Arc<std::sync::Mutex<Vec<serde_json::Value>>>,
+    ) {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let relay = format!("ws://{}", listener.local_addr().unwrap());
+        let requests = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+
+        let received = requests.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let received = received.clone();
+                tokio::spawn(async move {
+                    let mut socket = tokio_tungstenite::accept_async(stream).await.unwrap();
+                    let mut subscription_id = String::new();
+                    while let Some(Ok(Message::Text(message))) = socket.next().await {
+                        let message: Vec<serde_json::Value> =
+                            serde_json::from_str(&message).unwrap();
+                        match message[0].as_str().unwrap() {
+                            "REQ" => subscription_id = message[1].as_str().unwrap().to_string(),
+                            "EVENT" => {
+                                let event = &message[1];
+                                let request = decrypt_request(event);
+                                let response = wallet_response(event, &respond(&request));
+                                received.lock().unwrap().push(request);
+
+                                let ok = serde_json::json!(["OK", event["id"], true, ""]);
+                                let event = serde_json::json!(["EVENT", subscription_id, response]);
+                                socket.send(Message::Text(ok.to_string())).await.unwrap();
+                                socket.send(Message::Text(event.to_string())).await.unwrap();
+                            }
+                            _ => {}
+                        }
+                    }
+                });
+            }
+        });
+
+        let wallet_pubkey = secp256k1::Keypair::from_str(WALLET_SECRET)
+            .unwrap()
+            .x_only_public_key()
+            .0;
+        let uri = format!(
+            "nostr+walletconnect://{}?relay={}&secret={}",
+            wallet_pubkey, relay, CLIENT_SECRET
+        );
+        (uri, requests)
+    }
+
+    #[tokio::test]
+    async fn make_payment_over_nwc() {
+        let (uri, requests) = spawn_mock_relay(|request| {
+            if request["params"]["amount"] == 3000 {
+                serde_json::json!({
+                    "result_type": "pay_keysend",
+                    "error": {"code": "INSUFFICIENT_BALANCE", "message": "not enough sats"},
+                })
+            } else {
+                serde_json::json!({
+                    "result_type": "pay_keysend",
+                    "result": {
+                        "preimage": "0000000000000000000000000000000000000000000000000000000000000000",
+                        "fees_paid": 1000,
+                    },
+                })
+            }
+        })
+        .await;
+        let provider = v4v::pc20::nwc::NwcProvider::from_uri(&uri).unwrap();
+
+        let outcomes = v4v::pc20::payments::make_payment_with(
+            &provider,
+            v4v::pc20::payments::MakePaymentArgs {
+                payment_info: v4v::pc20::payments::PaymentInfo {
+                    action: v4v::pc20::payments::Action::Boost,
+                    message: Some("Hi!".to_string()),
+                    ..Default::default()
+                },
+                recipients: vec![
+                    v4v::pc20::payments::PaymentRecipientInfo {
+                        address: v4v::alby::types::KeysendAddress::with_custom(
+                            DEST_PUBKEY,
+                            "696969",
+                            "wallet",
+                        )
+                        .unwrap()
+                        .into(),
+                        num_sats: 7,
+                        ..Default::default()
+                    },
+                    v4v::pc20::payments::PaymentRecipientInfo {
+                        address: v4v::alby::types::KeysendAddress::new(DEST_PUBKEY)
+                            .unwrap()
+                            .into(),
+                        num_sats: 3,
+                        ..Default::default()
+                    },
+                ],
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+
+        let keysend = outcomes[0].result.as_ref().unwrap();
+        assert_eq!(keysend.fee_in_sats, 1);
+        assert_eq!(
+            keysend.payment_hash,
+            "66687aadf862bd776c8fc18b8e9f8e20089714856ee233b3902a591d0d5f2925"
+        );
+        assert_eq!(
+            outcomes[1].result.as_ref().unwrap_err().message,
+            "INSUFFICIENT_BALANCE: not enough sats"
+        );
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0]["method"], "pay_keysend");
+        assert_eq!(requests[0]["params"]["amount"], 7000);
+        assert_eq!(requests[0]["params"]["pubkey"], DEST_PUBKEY);
+
+        // Custom records are sent as hex-encoded TLV records.
+        let tlv_records = requests[0]["params"]["tlv_records"].as_array().unwrap();
+        assert!(tlv_records.contains(&serde_json::json!({"type": 696969, "value": hex(b"wallet")})));
+        let boostagram = tlv_records
+            .iter()
+            .find(|record| record["type"] == 7629169)
+            .unwrap();
+        assert!(boostagram["value"]
+            .as_str()
+            .unwrap()
+            .contains(&hex(br#""message":"Hi!""#)));
+    }
+
+    #[tokio::test]
+    async fn create_invoice_over_nwc() {
+        let (uri, requests) = spawn_mock_relay(|_| {
+            serde_json::json!({
+                "result_type": "make_invoice",
+                "result": {
+                    "type": "incoming",
+                    "invoice": "lnbc210n1mock",
+                    "payment_hash": "66687aadf862bd776c8fc18b8e9f8e20089714856ee233b3902a591d0d5f2925",
+                    "amount": 21000,
+                    "created_at": 1700000000,
+                    "expires_at": 1700003600,
+                },
+            })
+        })
+        .await;
+        let provider = v4v::pc20::nwc::NwcProvider::from_uri(&uri).unwrap();
+
+        let response = v4v::pc20::forwarding::create_invoice_with(
+            &provider,
+            v4v::pc20::forwarding::CreateInvoiceArgs {
+                payment_info: v4v::pc20::payments::PaymentInfo {
+                    message: Some("Great episode!".to_string()),
+                    ..Default::default()
+                },
+                recipients: vec![v4v::pc20::payments::PaymentRecipientInfo {
+                    num_sats: 21,
+                    ..Default::default()
+                }],
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(response.payment_request, "lnbc210n1mock");
+        assert_eq!(
+            response.expires_at,
+            chrono::DateTime::from_timestamp(1700003600, 0).unwrap()
+        );
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests[0]["method"], "make_invoice");
+        assert_eq!(requests[0]["params"]["amount"], 21000);
+        assert_eq!(requests[0]["params"]["description"], "Great episode!");
+    }
+
+    #[test]
+    fn nwc_uri_errors() {
+        use v4v::pc20::nwc::{NwcProvider, NwcUriError};
+
+        let pubkey = "b889ff5b1513b641e2a139f661a661364979c5beee91842f8f0ef42ab558e9d4";
+        assert_eq!(
+            NwcProvider::from_uri("https://example.com").unwrap_err(),
+            NwcUriError::InvalidUri
+        );
+        assert_eq!(
+            NwcProvider::from_uri("nostr+walletconnect://npub1xyz?relay=wss://r.example.com")
+                .unwrap_err(),
+            NwcUriError::InvalidWalletPubkey
+        );
+        assert_eq!(
+            NwcProvider::from_uri(&format!(
+                "nostr+walletconnect://{}?secret={}",
+                pubkey, CLIENT_SECRET
+            ))
+            .unwrap_err(),
+            NwcUriError::NoRelay
+        );
+        assert_eq!(
+            NwcProvider::from_uri(&format!(
+                "nostr+walletconnect://{}?relay=wss://r.example.com",
+                pubkey
+            ))
+            .unwrap_err(),
+            NwcUriError::InvalidSecret
+        );
+    }
+}
+
+#[cfg(feature = "lnd")]
+mod lnd {
+    use base64::Engine;
+
+    const DEST_PUBKEY: &str = "030a58b8653d32b99200a2334cfe913e51dc7d155aa0116c176657a4f1722677a3";
+    const PREIMAGE: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+    fn boost_args() -> v4v::pc20::payments::MakePaymentArgs<'static> {
+        v4v::pc20::payments::MakePaymentArgs {
+            payment_info: v4v::pc20::payments::PaymentInfo {
+                action: v4v::pc20::payments::Action::Boost,
+                message: Some("Hi!".to_string()),
+                ..Default::default()
+            },
+            recipients: vec![v4v::pc20::payments::PaymentRecipientInfo {
+                address: v4v::alby::types::KeysendAddress::with_custom(
+                    DEST_PUBKEY,
+                    "696969",
+                    "wallet",
+                )
+                .unwrap()
+                .into(),
+                num_sats: 7,
+                ..Default::default()
+            }],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn lnd_keysend_request() {
+        let base64 = base64::engine::general_purpose::STANDARD;
+        let provider = v4v::pc20::lnd::LndProvider::new("https://localhost:8080", "00");
+        let args = boost_args();
+        let keysends = v4v::pc20::payments::build_keysends(&args).unwrap();
+        let preimage = v4v::alby::types::Preimage::from_hex(PREIMAGE).unwrap();
+
+        let request = provider.keysend_request(&keysends[0], &preimage).unwrap();
+
+        assert_eq!(request["amt"], "7");
+        assert_eq!(request["fee_limit_sat"], "10");
+        assert_eq!(
+            base64.decode(request["dest"].as_str().unwrap()).unwrap()[0],
+            0x03
+        );
+        assert_eq!(
+            base64
+                .decode(request["payment_hash"].as_str().unwrap())
+                .unwrap(),
+            hmac_sha256::Hash::hash(&[0; 32])
+        );
+
+        let records = request["dest_custom_records"].as_object().unwrap();
+        assert_eq!(records.len(), 3);
+        assert_eq!(records["5482373484"], base64.encode([0; 32]));
+        assert_eq!(records["696969"], base64.encode("wallet"));
+        let tlv: serde_json::Value =
+            serde_json::from_slice(&base64.decode(records["7629169"].as_str().unwrap()).unwrap())
+                .unwrap();
+        assert_eq!(tlv["action"], "boost");
+        assert_eq!(tlv["message"], "Hi!");
+    }
+
+    #[test]
+    fn lnd_keysend_request_rejects_invalid_record_key() {
+        let provider = v4v::pc20::lnd::LndProvider::new("https://localhost:8080", "00");
+        let preimage = v4v::alby::types::Preimage::from_hex(PREIMAGE).unwrap();
+        let keysend = v4v::alby::api::payments::MultiKeysendItemArgs {
+            num_sats: 7,
+            dest_pubkey: DEST_PUBKEY,
+            custom_records: std::collections::HashMap::from([(
+                "podcast".to_string(),
+                "value".to_string(),
+            )]),
+        };
+
+        assert!(matches!(
+            provider.keysend_request(&keysend, &preimage),
+            Err(v4v::alby::api::RequestError::InvalidArgs(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn make_payment_over_lnd() {
+        let (base_url, requests) = super::spawn_mock_server(vec![
+            (
+                200,
+                r#"{"result": {"status": "SUCCEEDED", "payment_hash": "66687aadf862bd776c8fc18b8e9f8e20089714856ee233b3902a591d0d5f2925", "payment_preimage": "0000000000000000000000000000000000000000000000000000000000000000", "fee_sat": "1"}}"#,
+            ),
+            (
+                200,
+                r#"{"result": {"status": "FAILED", "failure_reason": "FAILURE_REASON_NO_ROUTE"}}"#,
+            ),
+        ]);
+        let provider = v4v::pc20::lnd::LndProvider::new(&base_url, "00");
+        let mut args = boost_args();
+        args.recipients
+            .push(v4v::pc20::payments::PaymentRecipientInfo {
+                address: v4v::alby::types::KeysendAddress::new(DEST_PUBKEY)
+                    .unwrap()
+                    .into(),
+                num_sats: 3,
+                ..Default::default()
+            });
+
+        let outcomes = v4v::pc20::payments::make_payment_with(&provider, args)
+            .await
+            .unwrap();
+
+        let keysend = outcomes[0].result.as_ref().unwrap();
+        assert_eq!(keysend.fee_in_sats, 1);
+        assert!(keysend.preimage().unwrap().verifies(&keysend.payment_hash));
+        assert_eq!(
+            outcomes[1].result.as_ref().unwrap_err().message,
+            "payment failed: FAILURE_REASON_NO_ROUTE"
+        );
+        assert_eq!(
+            requests.lock().unwrap().clone(),
+            vec!["POST /v2/router/send HTTP/1.1".to_string(); 2]
+        );
+    }
+
+    #[tokio::test]
+    async fn create_invoice_over_lnd() {
+        let (base_url, requests) = super::spawn_mock_server(vec![(
+            200,
+            r#"{"r_hash": "ZmiKrfhivXdsj8GLjp+OIAiXFIVu4jOzkCpZHQ1fKSU=", "payment_request": "lnbc210n1mock", "add_index": "1"}"#,
+        )]);
+        let provider = v4v::pc20::lnd::LndProvider::new(&base_url, "00");
+
+        let response = v4v::pc20::forwarding::create_invoice_with(
+            &provider,
+            v4v::pc20::forwarding::CreateInvoiceArgs {
+                recipients: vec![v4v::pc20::payments::PaymentRecipientInfo {
+                    num_sats: 21,
+                    ..Default::default()
+                }],
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(response.payment_request, "lnbc210n1mock");
+        assert_eq!(
+            response.payment_hash,
+            "66688aadf862bd776c8fc18b8e9f8e20089714856ee233b3902a591d0d5f2925"
+        );
+        assert_eq!(
+            requests.lock().unwrap().clone(),
+            vec!["POST /v1/invoices HTTP/1.1".to_string()]
+        );
+    }
+}